@@ -0,0 +1,29 @@
+//! Rendering benchmarks: one template, and the multi-language merges whose
+//! cost grows with the parser's. Each iteration starts from a fresh
+//! registry, so parsed fragments are not memoized across iterations.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use nix_flake_generator_core::Generator;
+
+const COMBINATIONS: &[&[&str]] = &[
+    &["rust"],
+    &["rust", "go", "node"],
+    &["rust", "go", "node", "python", "haskell", "java"],
+];
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    for languages in COMBINATIONS {
+        group.bench_function(languages.join(","), |b| {
+            b.iter_batched(
+                || Generator::new().languages(languages.iter().copied()),
+                |generator| generator.render().unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+//! Check every embedded template before it is compiled in: the metadata must
+//! parse and describe the template, a `.nix` file must exist next to it
+//! unless the template extends another, that file must parse as a flake, and
+//! the extra packages must be listed in it one per line. Optional groups
+//! must not be empty, and neither they nor the template's own hook may end
+//! the strings their hooks and apps go into; the same goes for the groups in
+//! `groups/` that any template can take.
+//! Broken templates fail the build with the offending file and position
+//! instead of shipping.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const TEMPLATE_DIR: &str = "../nix-parser/src/templates";
+const GROUPS_DIR: &str = "../nix-parser/src/templates/groups";
+
+#[derive(Deserialize)]
+struct TemplateMetadata {
+    template: TemplateInfo,
+    #[serde(default)]
+    groups: BTreeMap<String, TemplateGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TemplateInfo {
+    description: String,
+    extends: Option<String>,
+    #[serde(default)]
+    extra_packages: Vec<String>,
+    #[serde(default)]
+    shell_hook: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TemplateGroup {
+    packages: Vec<String>,
+    #[serde(default)]
+    shell_hook: Vec<String>,
+    #[serde(default)]
+    apps: BTreeMap<String, String>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={TEMPLATE_DIR}");
+    println!("cargo:rerun-if-changed={GROUPS_DIR}");
+
+    let mut problems: Vec<String> =
+        toml_files(TEMPLATE_DIR).iter().filter_map(|path| check(path).err()).collect();
+    let groups = toml_files(GROUPS_DIR);
+    problems.extend(groups.iter().filter_map(|path| check_shared_group(path).err()));
+    if !problems.is_empty() {
+        fail(&problems);
+    }
+}
+
+fn toml_files(dir: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| fail(&[format!("{dir}: {e}")]))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn check(toml_path: &Path) -> Result<(), String> {
+    let display = toml_path.display();
+    let metadata = fs::read_to_string(toml_path).map_err(|e| format!("{display}: {e}"))?;
+    let metadata: TemplateMetadata =
+        toml::from_str(&metadata).map_err(|e| format!("{display}: {e}"))?;
+    if metadata.template.description.trim().is_empty() {
+        return Err(format!("{display}: template.description is empty"));
+    }
+    if metadata.template.shell_hook.iter().any(|line| line.contains("''")) {
+        return Err(format!("{display}: template.shell-hook has ''"));
+    }
+    for (name, group) in &metadata.groups {
+        check_group(group).map_err(|problem| format!("{display}: group '{name}' {problem}"))?;
+    }
+    if let Some(base) = &metadata.template.extends {
+        // The derived flake is checked when the registry applies the delta
+        let base_path = toml_path.with_file_name(format!("{base}.toml"));
+        if !base_path.exists() {
+            return Err(format!("{display}: extends unknown template '{base}'"));
+        }
+        return Ok(());
+    }
+
+    let nix_path = toml_path.with_extension("nix");
+    let display = nix_path.display();
+    let content = fs::read_to_string(&nix_path).map_err(|e| format!("{display}: {e}"))?;
+    nix_parser::validate_flake(&content).map_err(|e| match e {
+        nix_parser::ParseError::Syntax { line, column, .. }
+        | nix_parser::ParseError::TrailingInput { line, column, .. } => {
+            format!("{display}:{line}:{column}: {e}")
+        }
+        nix_parser::ParseError::Analysis(_) => format!("{display}: {e}"),
+    })?;
+
+    for package in &metadata.template.extra_packages {
+        if !content.lines().any(|line| line.trim() == package) {
+            return Err(format!("{display}: extra package '{package}' is not listed on its own line"));
+        }
+    }
+    Ok(())
+}
+
+fn check_shared_group(toml_path: &Path) -> Result<(), String> {
+    let display = toml_path.display();
+    let group = fs::read_to_string(toml_path).map_err(|e| format!("{display}: {e}"))?;
+    let group: TemplateGroup = toml::from_str(&group).map_err(|e| format!("{display}: {e}"))?;
+    check_group(&group).map_err(|problem| format!("{display}: group {problem}"))
+}
+
+fn check_group(group: &TemplateGroup) -> Result<(), &'static str> {
+    if group.packages.is_empty() {
+        return Err("has no packages");
+    }
+    // The lines and commands go into '' strings, which '' would end
+    if group.shell_hook.iter().chain(group.apps.values()).any(|line| line.contains("''")) {
+        return Err("has '' in a shell hook or app");
+    }
+    Ok(())
+}
+
+fn fail(problems: &[String]) -> ! {
+    eprintln!("Invalid embedded templates:");
+    for problem in problems {
+        eprintln!("  {problem}");
+    }
+    process::exit(1);
+}
@@ -0,0 +1,700 @@
+//! Templates derived from another template.
+//!
+//! A template whose metadata says `extends = "go"` has no `.nix` file of its
+//! own; its flake is the base template's with the `[delta]` table applied:
+//!
+//! ```toml
+//! [template]
+//! description = "Go with gRPC tooling"
+//! extends = "go"
+//!
+//! [delta]
+//! packages = ["protobuf", "protoc-gen-go", "protoc-gen-go-grpc"]
+//!
+//! [delta.variables]
+//! goVersion = "23"
+//!
+//! [delta.env]
+//! GOFLAGS = "-mod=mod"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Changes a derived template makes to the template it extends.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplateDelta {
+    /// Added to the devShell's packages
+    pub packages: Vec<String>,
+    /// Bindings given a new value, written as Nix expressions
+    pub variables: BTreeMap<String, String>,
+    /// Environment variables set in the devShell
+    pub env: BTreeMap<String, String>,
+}
+
+impl TemplateDelta {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.variables.is_empty() && self.env.is_empty()
+    }
+}
+
+/// Apply `delta` to the flake of the base template. The result must still be
+/// a valid flake; otherwise the reason is returned.
+pub fn apply(base: &str, delta: &TemplateDelta) -> Result<String, String> {
+    let mut content = base.to_string();
+
+    for (name, value) in &delta.variables {
+        content = replace_assignment(&content, name, value)
+            .ok_or_else(|| format!("the base template does not bind {name}"))?;
+    }
+    if !delta.packages.is_empty() {
+        content = add_packages(&content, &delta.packages)
+            .ok_or("the base template has no devShell packages list")?;
+    }
+    if !delta.env.is_empty() {
+        content = add_env(&content, &delta.env)
+            .ok_or("the base template has no devShell packages list to add env after")?;
+    }
+
+    nix_parser::validate_flake(&content).map_err(|e| e.to_string())?;
+    Ok(content)
+}
+
+/// Replace the value of the first `key = ...;` assignment starting a line.
+pub(crate) fn replace_assignment(content: &str, key: &str, value: &str) -> Option<String> {
+    let pattern = format!("{key} = ");
+    let start = line_starting(content, &pattern)? + pattern.len();
+    let end = start + statement_end(&content[start..])?;
+
+    Some(format!("{}{}{}", &content[..start], value, &content[end..]))
+}
+
+/// Span of the first `key = ...;` assignment starting a line, from the start
+/// of its line to the end of the line holding the `;`.
+fn assignment_lines(content: &str, key: &str) -> Option<(usize, usize)> {
+    let pattern = format!("{key} = ");
+    let start = line_starting(content, &pattern)?;
+    let end = start + pattern.len() + statement_end(&content[start + pattern.len()..])?;
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[end..].find('\n').map_or(content.len(), |i| end + i + 1);
+    Some((line_start, line_end))
+}
+
+/// Remove the first `key = ...;` assignment starting a line, with its lines.
+pub(crate) fn remove_assignment(content: &str, key: &str) -> Option<String> {
+    let (start, end) = assignment_lines(content, key)?;
+    Some(format!("{}{}", &content[..start], &content[end..]))
+}
+
+/// Add `key = value;` on the line after the first `after = ...;` assignment
+/// starting a line, indented like it.
+pub(crate) fn insert_assignment(
+    content: &str,
+    after: &str,
+    key: &str,
+    value: &str,
+) -> Option<String> {
+    let (start, end) = assignment_lines(content, after)?;
+    let indent: String =
+        content[start..].chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+    let line = format!("{indent}{key} = {value};\n");
+    Some(format!("{}{}{}", &content[..end], line, &content[end..]))
+}
+
+/// Add packages to the first `packages = ... [ ... ]` list.
+pub(crate) fn add_packages(content: &str, packages: &[String]) -> Option<String> {
+    let (open, close) = packages_list(content)?;
+    let before_close = content[..close].trim_end_matches([' ', '\t']);
+
+    if before_close.ends_with('\n') {
+        // One package per line, indented like the existing ones
+        let line_start = content[open..close].rfind('\n').map(|i| open + i + 1)?;
+        let indent = format!("{}  ", &content[line_start..close]);
+        let lines: String = packages.iter().map(|p| format!("{indent}{p}\n")).collect();
+        return Some(format!("{}{}{}", &content[..line_start], lines, &content[line_start..]));
+    }
+
+    let inline: String = packages.iter().map(|p| format!(" {p}")).collect();
+    Some(format!("{}{}{}", before_close, inline, &content[before_close.len()..]))
+}
+
+/// Append packages that are only added on darwin to the devShell packages,
+/// as `++ pkgs.lib.optionals pkgs.stdenv.isDarwin (with pkgs; [ ... ])`.
+pub(crate) fn add_darwin_packages(content: &str, packages: &[String]) -> Option<String> {
+    let (_, close) = packages_list(content)?;
+    let end = close + 1;
+    let before_close = content[..close].trim_end_matches([' ', '\t']);
+
+    if before_close.ends_with('\n') {
+        let indent = &content[before_close.len()..close];
+        let mut block = format!(
+            "\n{indent}++ pkgs.lib.optionals pkgs.stdenv.isDarwin (\n{indent}  with pkgs;\n{indent}  [\n"
+        );
+        for package in packages {
+            block.push_str(&format!("{indent}    {package}\n"));
+        }
+        block.push_str(&format!("{indent}  ]\n{indent})"));
+        return Some(format!("{}{}{}", &content[..end], block, &content[end..]));
+    }
+
+    let inline = format!(
+        " ++ pkgs.lib.optionals pkgs.stdenv.isDarwin (with pkgs; [ {} ])",
+        packages.join(" ")
+    );
+    Some(format!("{}{}{}", &content[..end], inline, &content[end..]))
+}
+
+/// Set environment variables in the devShell's `env` block, adding the block
+/// after the packages list when there is none.
+pub(crate) fn add_env(content: &str, env: &BTreeMap<String, String>) -> Option<String> {
+    let (close, statement, indent) = after_packages(content)?;
+
+    if let Some(block) = content[close..].find("env = {").map(|i| close + i) {
+        let open = block + "env = ".len();
+        let end = open + matching(&content[open..], '{', '}')?;
+        let end_line = content[..end].rfind('\n').map_or(0, |i| i + 1);
+        let lines: String = env
+            .iter()
+            .map(|(key, value)| format!("{indent}  {key} = {};\n", nix_string(value)))
+            .collect();
+        return Some(format!("{}{}{}", &content[..end_line], lines, &content[end_line..]));
+    }
+
+    let mut block = format!("\n\n{indent}env = {{\n");
+    for (key, value) in env {
+        block.push_str(&format!("{indent}  {key} = {};\n", nix_string(value)));
+    }
+    block.push_str(&format!("{indent}}};"));
+    Some(format!("{}{}{}", &content[..statement], block, &content[statement..]))
+}
+
+/// Add derivations to the devShell's `inputsFrom`, placed after the packages
+/// list when it has none.
+pub(crate) fn add_inputs_from(content: &str, derivations: &[String]) -> Option<String> {
+    let (close, statement, indent) = after_packages(content)?;
+
+    if let Some(list) = content[close..].find("inputsFrom = [").map(|i| close + i) {
+        let open = list + "inputsFrom = ".len();
+        let end = open + matching(&content[open..], '[', ']')?;
+        let before_close = content[..end].trim_end();
+        let added: String = derivations.iter().map(|d| format!(" {d}")).collect();
+        return Some(format!("{before_close}{added} {}", &content[end..]));
+    }
+
+    let block = format!("\n\n{indent}inputsFrom = [ {} ];", derivations.join(" "));
+    Some(format!("{}{}{}", &content[..statement], block, &content[statement..]))
+}
+
+/// Whether the flake sets its own `nixConfig`, as an attribute set or as
+/// `nixConfig.<name> = ...;` lines.
+pub(crate) fn has_nix_config(content: &str) -> bool {
+    line_starting(content, "nixConfig = {").is_some()
+        || line_starting(content, "nixConfig.").is_some()
+}
+
+/// Add values to the setting `name` of the flake's own `nixConfig`: appended
+/// to its list or space-separated string, skipping the ones it already has,
+/// or set as a new list when the setting is missing. `None` when there is no
+/// `nixConfig`, or the setting's value is neither a list nor a string.
+pub(crate) fn add_nix_config(content: &str, name: &str, values: &[String]) -> Option<String> {
+    let (setting, scope) = match line_starting(content, "nixConfig = {") {
+        Some(start) => {
+            let open = start + "nixConfig = ".len();
+            (name.to_string(), open + 1..open + matching(&content[open..], '{', '}')?)
+        }
+        None => (format!("nixConfig.{name}"), 0..content.len()),
+    };
+
+    let pattern = format!("{setting} = ");
+    if let Some(at) = line_starting(&content[scope.clone()], &pattern) {
+        let start = scope.start + at + pattern.len();
+        let end = start + statement_end(&content[start..])?;
+        let merged = merged_setting(&content[start..end], values)?;
+        return Some(format!("{}{}{}", &content[..start], merged, &content[end..]));
+    }
+
+    let quoted: Vec<String> = values.iter().map(|value| nix_string(value)).collect();
+    let line = format!("{setting} = [ {} ];", quoted.join(" "));
+    if scope.start > 0 {
+        // Before the `}` closing the set, on a line of its own unless the set
+        // is written on one line
+        let close = scope.end;
+        let end_line = content[..close].rfind('\n').map_or(0, |i| i + 1);
+        if !content[end_line..close].trim().is_empty() {
+            let before_close = content[..close].trim_end();
+            return Some(format!("{before_close} {line} {}", &content[close..]));
+        }
+        let indent = &content[end_line..close];
+        return Some(format!("{}{indent}  {line}\n{}", &content[..end_line], &content[end_line..]));
+    }
+
+    // After the first `nixConfig.<name> = ...;` statement, indented like it
+    let start = line_starting(content, "nixConfig.")?;
+    let end = start + statement_end(&content[start..])?;
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[end..].find('\n').map_or(content.len(), |i| end + i + 1);
+    let indent = &content[line_start..start];
+    Some(format!("{}{indent}{line}\n{}", &content[..line_end], &content[line_end..]))
+}
+
+/// A `nixConfig` setting's value with `values` added: as entries of a list,
+/// on lines of their own when the list has them, or as words of a string.
+fn merged_setting(value: &str, values: &[String]) -> Option<String> {
+    let value = value.trim_end();
+    if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        let words: Vec<&str> = inner.split_whitespace().collect();
+        let added: String = values
+            .iter()
+            .filter(|value| !words.contains(&value.as_str()))
+            .map(|value| format!(" {}", nix_parser::escape_string(value)))
+            .collect();
+        return Some(format!("\"{inner}{added}\""));
+    }
+
+    let list = value.strip_prefix('[')?.strip_suffix(']')?;
+    let added: Vec<String> = values
+        .iter()
+        .map(|value| nix_string(value))
+        .filter(|quoted| !list.contains(quoted.as_str()))
+        .collect();
+    if added.is_empty() {
+        return Some(value.to_string());
+    }
+
+    let close = value.len() - 1;
+    let before_close = value[..close].trim_end();
+    let close_indent = &value[value[..close].rfind('\n').map_or(0, |i| i + 1)..close];
+    if !list.contains('\n') || !close_indent.trim().is_empty() {
+        return Some(format!("{before_close} {} ]", added.join(" ")));
+    }
+    let item_indent = if before_close.ends_with('[') {
+        format!("{close_indent}  ")
+    } else {
+        let last_line = &before_close[before_close.rfind('\n').map_or(0, |i| i + 1)..];
+        last_line.chars().take_while(|c| *c == ' ' || *c == '\t').collect()
+    };
+    let lines: String = added.iter().map(|quoted| format!("\n{item_indent}{quoted}")).collect();
+    Some(format!("{before_close}{lines}\n{close_indent}]"))
+}
+
+/// Add a flake input next to the nixpkgs one, written the same way
+/// (`inputs.<name>.url = ...;` or `<name>.url = ...;` in an `inputs` set).
+pub(crate) fn add_input(content: &str, name: &str, url: &str) -> Option<String> {
+    let nixpkgs = content.find("nixpkgs.url = ")?;
+    let line_start = content[..nixpkgs].rfind('\n').map_or(0, |i| i + 1);
+    let prefix = &content[line_start..nixpkgs];
+    let line_end = nixpkgs + content[nixpkgs..].find('\n')? + 1;
+    let line = format!("{prefix}{name}.url = {};\n", nix_string(url));
+    Some(format!("{}{}{}", &content[..line_end], line, &content[line_end..]))
+}
+
+/// Add a non-flake input, such as a source tree, next to the nixpkgs one:
+/// `inputs.<name> = { url = ...; flake = false; };`.
+pub(crate) fn add_source_input(content: &str, name: &str, url: &str) -> Option<String> {
+    let nixpkgs = content.find("nixpkgs.url = ")?;
+    let line_start = content[..nixpkgs].rfind('\n').map_or(0, |i| i + 1);
+    let prefix = &content[line_start..nixpkgs];
+    let line_end = nixpkgs + content[nixpkgs..].find('\n')? + 1;
+    let line = format!("{prefix}{name} = {{ url = {}; flake = false; }};\n", nix_string(url));
+    Some(format!("{}{}{}", &content[..line_end], line, &content[line_end..]))
+}
+
+/// Make nested inputs follow top-level ones, next to the nixpkgs input and
+/// written the same way: `inputs.<a>.inputs.<b>.follows = "<b>";` for the
+/// pair `(["a", "b"], "b")`. Paths that already follow something are skipped.
+pub fn add_follows(content: &str, follows: &[(Vec<String>, String)]) -> Option<String> {
+    let nixpkgs = content.find("nixpkgs.url = ")?;
+    let line_start = content[..nixpkgs].rfind('\n').map_or(0, |i| i + 1);
+    let prefix = &content[line_start..nixpkgs];
+    let line_end = nixpkgs + content[nixpkgs..].find('\n')? + 1;
+
+    let mut lines = String::new();
+    for (path, target) in follows {
+        let attribute = path
+            .iter()
+            .map(|name| attribute_name(name))
+            .collect::<Vec<_>>()
+            .join(".inputs.");
+        if content.contains(&format!("{attribute}.follows")) {
+            continue;
+        }
+        lines.push_str(&format!("{prefix}{attribute}.follows = {};\n", nix_string(target)));
+    }
+    Some(format!("{}{}{}", &content[..line_end], lines, &content[line_end..]))
+}
+
+/// Replace the entry `old` of the devShell's packages list with `new`.
+pub(crate) fn replace_package(content: &str, old: &str, new: &str) -> Option<String> {
+    let start = package_offset(content, old)?;
+    Some(format!("{}{}{}", &content[..start], new, &content[start + old.len()..]))
+}
+
+/// Whether the devShell's packages list has the entry `name`.
+pub(crate) fn has_package(content: &str, name: &str) -> bool {
+    package_offset(content, name).is_some()
+}
+
+/// Remove the entry `name` from the devShell's packages list, with its line
+/// when it has one to itself.
+pub(crate) fn remove_package(content: &str, name: &str) -> Option<String> {
+    let mut start = package_offset(content, name)?;
+    let mut end = start + name.len();
+
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[end..].find('\n').map(|i| end + i);
+    let alone = content[line_start..start].trim().is_empty()
+        && line_end.is_some_and(|line_end| content[end..line_end].trim().is_empty());
+    match line_end {
+        Some(line_end) if alone => {
+            start = line_start;
+            end = line_end + 1;
+        }
+        // Inline, so the space before it goes too
+        _ => start = content[..start].trim_end_matches([' ', '\t']).len(),
+    }
+    Some(format!("{}{}", &content[..start], &content[end..]))
+}
+
+/// Append lines to the devShell's shell hook, adding a `shellHook` after the
+/// packages list when there is none.
+pub(crate) fn add_shell_hook(content: &str, lines: &[String]) -> Option<String> {
+    let indented = |indent: &str| -> String {
+        lines
+            .iter()
+            .map(|line| {
+                if line.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("{indent}{line}\n")
+                }
+            })
+            .collect()
+    };
+
+    if let Some(hook) = content.find("shellHook = ''") {
+        let body = hook + "shellHook = ''".len();
+        let close = body + closing_quote(&content[body..])?;
+        let line_start = content[..close].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &content[line_start..close];
+        if !indent.trim().is_empty() {
+            return None;
+        }
+        let lines = indented(&format!("{indent}  "));
+        return Some(format!("{}{}{}", &content[..line_start], lines, &content[line_start..]));
+    }
+
+    let (_, statement, indent) = after_packages(content)?;
+    let block = format!(
+        "\n\n{indent}shellHook = ''\n{}{indent}'';",
+        indented(&format!("{indent}  "))
+    );
+    Some(format!("{}{}{}", &content[..statement], block, &content[statement..]))
+}
+
+/// Add `apps` running each command inside the devShell after the
+/// `devShells = forEachSupportedSystem (...)` output. The apps enter the
+/// shell with `nix develop`, so its hooks and environment apply.
+pub(crate) fn add_apps(content: &str, apps: &BTreeMap<String, String>) -> Option<String> {
+    let (end, indent) = after_dev_shells(content)?;
+
+    let mut block = format!("\n\n{indent}apps = forEachSupportedSystem (\n");
+    block.push_str(&format!("{indent}  {{ pkgs }}:\n{indent}  {{\n"));
+    for (name, command) in apps {
+        let inner = format!("{indent}    ");
+        block.push_str(&format!("{inner}{} = {{\n", attribute_name(name)));
+        block.push_str(&format!("{inner}  type = \"app\";\n"));
+        block.push_str(&format!(
+            "{inner}  program = toString (\n{inner}    pkgs.writeShellScript {} ''\n",
+            nix_string(name)
+        ));
+        block.push_str(&format!(
+            "{inner}      exec nix --extra-experimental-features \"nix-command flakes\" \
+             develop ${{self}} --command {command} \"$@\"\n"
+        ));
+        block.push_str(&format!("{inner}    ''\n{inner}  );\n{inner}}};\n"));
+    }
+    block.push_str(&format!("{indent}  }}\n{indent});"));
+    Some(format!("{}{}{}", &content[..end], block, &content[end..]))
+}
+
+/// Add a `dev-image` package: the default devShell as a container image that
+/// enters it, built by `dockerTools.buildNixShellImage`. Flakes that already
+/// have `packages` outputs are left to the caller.
+pub(crate) fn add_dev_image(content: &str) -> Option<String> {
+    if content.contains("packages = forEachSupportedSystem") {
+        return None;
+    }
+    let (end, indent) = after_dev_shells(content)?;
+    let block = format!(
+        "\n\n{indent}packages = forEachSupportedSystem (\n\
+         {indent}  {{ pkgs }}:\n\
+         {indent}  {{\n\
+         {indent}    dev-image = pkgs.dockerTools.buildNixShellImage {{\n\
+         {indent}      name = \"dev-shell\";\n\
+         {indent}      tag = \"latest\";\n\
+         {indent}      drv = self.devShells.${{pkgs.system}}.default;\n\
+         {indent}    }};\n\
+         {indent}  }}\n\
+         {indent});"
+    );
+    Some(format!("{}{}{}", &content[..end], block, &content[end..]))
+}
+
+/// The end of the `devShells = forEachSupportedSystem ...;` statement and
+/// its indentation, for outputs added next to it.
+fn after_dev_shells(content: &str) -> Option<(usize, &str)> {
+    let pattern = "devShells = forEachSupportedSystem";
+    let shells = content
+        .match_indices(pattern)
+        .map(|(i, _)| i)
+        .find(|&i| content[..i].trim_end_matches([' ', '\t']).ends_with('\n'))?;
+    let line_start = content[..shells].rfind('\n').map_or(0, |i| i + 1);
+    let value = shells + "devShells =".len();
+    let end = value + statement_end(&content[value..])? + 1;
+    Some((end, &content[line_start..shells]))
+}
+
+/// The value of the `default` devShell, as written after `default =`.
+pub(crate) fn default_shell(content: &str) -> Option<&str> {
+    let (_, value, end) = default_shell_statement(content)?;
+    Some(&content[value..end])
+}
+
+/// Replace the `default` devShell with the named ones, each given as the
+/// value of a `default` devShell, indented the same way.
+pub(crate) fn replace_default_shell(content: &str, shells: &[(String, &str)]) -> Option<String> {
+    let (line_start, _, end) = default_shell_statement(content)?;
+    let indent = &content[line_start..content[line_start..].find("default")? + line_start];
+    let statements: Vec<String> = shells
+        .iter()
+        .map(|(name, value)| format!("{indent}{} ={value};", attribute_name(name)))
+        .collect();
+    Some(format!("{}{}{}", &content[..line_start], statements.join("\n"), &content[end + 1..]))
+}
+
+/// Start of the line of the `default = ...;` binding in `devShells`, start
+/// of its value and offset of its `;`.
+fn default_shell_statement(content: &str) -> Option<(usize, usize, usize)> {
+    let shells = content.find("devShells")?;
+    let binding = content[shells..]
+        .match_indices("default =")
+        .map(|(i, _)| shells + i)
+        .find(|&i| content[..i].trim_end_matches([' ', '\t']).ends_with('\n'))?;
+    let line_start = content[..binding].rfind('\n').map_or(0, |i| i + 1);
+    let value = binding + "default =".len();
+    let end = value + statement_end(&content[value..])?;
+    Some((line_start, value, end))
+}
+
+/// `name` as an attribute name, quoted unless it is a plain identifier.
+fn attribute_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+    if plain { name.to_string() } else { nix_string(name) }
+}
+
+/// The `]` closing the devShell packages list, the end of its statement and
+/// the indentation of the `packages =` line.
+fn after_packages(content: &str) -> Option<(usize, usize, &str)> {
+    let (_, close) = packages_list(content)?;
+    let packages_line = content[..close].rfind("packages =")?;
+    let line_start = content[..packages_line].rfind('\n').map_or(0, |i| i + 1);
+    let statement = close + 1 + statement_end(&content[close + 1..])? + 1;
+    Some((close, statement, &content[line_start..packages_line]))
+}
+
+/// Offset of the `''` closing the indented string `text` is the body of.
+fn closing_quote(text: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find("''").map(|i| from + i) {
+        // `'''`, `''$` and `''\` are escapes, not the closing quote
+        match text[i + 2..].chars().next() {
+            Some('\'' | '$' | '\\') => from = i + 3,
+            _ => return Some(i),
+        }
+    }
+    None
+}
+
+/// Offset of the entry `name` in the devShell's packages list.
+fn package_offset(content: &str, name: &str) -> Option<usize> {
+    let (open, close) = packages_list(content)?;
+    let list = &content[open + 1..close];
+    let separator = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let start = list.match_indices(name).map(|(i, _)| i).find(|&i| {
+        separator(list[..i].chars().next_back())
+            && separator(list[i + name.len()..].chars().next())
+    })?;
+    Some(open + 1 + start)
+}
+
+/// Byte offsets of the `[` and `]` of the first devShell packages list.
+fn packages_list(content: &str) -> Option<(usize, usize)> {
+    let from = content.find("mkShell").unwrap_or(0);
+    let binding = from + content[from..].find("packages =")?;
+    let open = binding + content[binding..].find('[')?;
+    let close = open + matching(&content[open..], '[', ']')?;
+    Some((open, close))
+}
+
+/// Offset of the first `pattern` that starts a line, after its indentation.
+fn line_starting(content: &str, pattern: &str) -> Option<usize> {
+    content
+        .match_indices(pattern)
+        .map(|(i, _)| i)
+        .find(|&i| content[..i].trim_end_matches([' ', '\t']).ends_with('\n'))
+}
+
+/// Offset of the bracket closing the one `text` starts with.
+fn matching(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Offset of the `;` ending the value at the start of `text`.
+fn statement_end(text: &str) -> Option<usize> {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth -= 1,
+            ';' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn nix_string(value: &str) -> String {
+    format!("\"{}\"", nix_parser::escape_string(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAKE: &str = r#"{
+  description = "Test";
+
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+
+  outputs =
+    { self, nixpkgs }:
+    let
+      goVersion = 24; # Change this to update the whole stack
+      pkgs = import nixpkgs { system = "x86_64-linux"; };
+    in
+    {
+      devShells.default = pkgs.mkShell {
+        packages = with pkgs; [
+          go
+          gotools
+        ];
+      };
+    };
+}
+"#;
+
+    #[test]
+    fn test_apply_delta() {
+        let delta = TemplateDelta {
+            packages: vec!["protobuf".to_string()],
+            variables: BTreeMap::from([("goVersion".to_string(), "23".to_string())]),
+            env: BTreeMap::from([("GOFLAGS".to_string(), "-mod=mod".to_string())]),
+        };
+        let content = apply(FLAKE, &delta).unwrap();
+
+        assert!(content.contains("goVersion = 23; # Change this to update the whole stack"));
+        assert!(content.contains("          gotools\n          protobuf\n        ];"));
+        assert!(content.contains("        env = {\n          GOFLAGS = \"-mod=mod\";\n        };"));
+    }
+
+    #[test]
+    fn test_add_darwin_packages() {
+        let content = add_darwin_packages(FLAKE, &["libiconv".to_string()]).unwrap();
+        assert!(content.contains(
+            "          gotools\n        ]\n        ++ pkgs.lib.optionals pkgs.stdenv.isDarwin (\n          with pkgs;\n          [\n            libiconv\n          ]\n        );"
+        ));
+        assert!(nix_parser::validate_flake(&content).is_ok());
+    }
+
+    #[test]
+    fn test_replace_package() {
+        let content = replace_package(FLAKE, "go", "self.inputs.go-src").unwrap();
+        assert!(content.contains("          self.inputs.go-src\n          gotools\n"));
+        assert!(replace_package(FLAKE, "got", "x").is_none());
+
+        let content = add_source_input(&content, "go-src", "github:golang/go").unwrap();
+        assert!(content.contains(
+            "  inputs.go-src = { url = \"github:golang/go\"; flake = false; };\n"
+        ));
+        assert!(nix_parser::validate_flake(&content).is_ok());
+    }
+
+    #[test]
+    fn test_add_follows() {
+        let follows = [
+            (vec!["devenv".to_string(), "nixpkgs".to_string()], "nixpkgs".to_string()),
+            (
+                vec!["devenv".to_string(), "cachix".to_string(), "nixpkgs".to_string()],
+                "nixpkgs".to_string(),
+            ),
+        ];
+        let content = add_follows(FLAKE, &follows).unwrap();
+        assert!(content.contains(
+            "  inputs.devenv.inputs.nixpkgs.follows = \"nixpkgs\";\n  inputs.devenv.inputs.cachix.inputs.nixpkgs.follows = \"nixpkgs\";\n"
+        ));
+        assert!(nix_parser::validate_flake(&content).is_ok());
+        assert_eq!(add_follows(&content, &follows).unwrap(), content);
+    }
+
+    #[test]
+    fn test_remove_package() {
+        let content = remove_package(FLAKE, "go").unwrap();
+        assert!(content.contains("packages = with pkgs; [\n          gotools\n        ];"));
+
+        let inline = FLAKE.replace("[\n          go\n          gotools\n        ]", "[ go gotools ]");
+        let content = remove_package(&inline, "gotools").unwrap();
+        assert!(content.contains("packages = with pkgs; [ go ];"));
+        assert!(remove_package(FLAKE, "gopls").is_none());
+    }
+
+    #[test]
+    fn test_insert_and_remove_assignment() {
+        let content = insert_assignment(FLAKE, "goVersion", "goPrivate", "\"example.com\"").unwrap();
+        assert!(content.contains(
+            "goVersion = 24; # Change this to update the whole stack\n      goPrivate = \"example.com\";\n      pkgs = "
+        ));
+        let content = remove_assignment(&content, "goVersion").unwrap();
+        assert!(content.contains("    let\n      goPrivate = \"example.com\";\n"));
+        assert!(nix_parser::validate_flake(&content).is_ok());
+    }
+
+    #[test]
+    fn test_apply_rejects_unknown_variable() {
+        let delta = TemplateDelta {
+            variables: BTreeMap::from([("javaVersion".to_string(), "17".to_string())]),
+            ..Default::default()
+        };
+        assert!(apply(FLAKE, &delta).unwrap_err().contains("javaVersion"));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::delta::TemplateDelta;
+use crate::error::{Result, TemplateError};
+use crate::registry::{Template, TemplateGroup};
+use rust_embed::RustEmbed;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+// Each `templates-*` feature embeds one group; the nix template is always
+// included, which also keeps rust-embed from embedding everything when no
+// group is enabled. Groups any template can take are always included.
+#[derive(RustEmbed)]
+#[folder = "../nix-parser/src/templates/"]
+#[include = "nix.*"]
+#[include = "groups/*"]
+#[cfg_attr(feature = "templates-systems", include = "rust.*")]
+#[cfg_attr(feature = "templates-systems", include = "rust-toolchain.*")]
+#[cfg_attr(feature = "templates-systems", include = "c-cpp.*")]
+#[cfg_attr(feature = "templates-systems", include = "go.*")]
+#[cfg_attr(feature = "templates-systems", include = "zig.*")]
+#[cfg_attr(feature = "templates-systems", include = "nim.*")]
+#[cfg_attr(feature = "templates-systems", include = "vlang.*")]
+#[cfg_attr(feature = "templates-systems", include = "swift.*")]
+#[cfg_attr(feature = "templates-jvm", include = "java.*")]
+#[cfg_attr(feature = "templates-jvm", include = "kotlin.*")]
+#[cfg_attr(feature = "templates-jvm", include = "scala.*")]
+#[cfg_attr(feature = "templates-jvm", include = "clojure.*")]
+#[cfg_attr(feature = "templates-dotnet", include = "csharp.*")]
+#[cfg_attr(feature = "templates-web", include = "node.*")]
+#[cfg_attr(feature = "templates-web", include = "bun.*")]
+#[cfg_attr(feature = "templates-web", include = "elm.*")]
+#[cfg_attr(feature = "templates-web", include = "php.*")]
+#[cfg_attr(feature = "templates-web", include = "ruby.*")]
+#[cfg_attr(feature = "templates-functional", include = "haskell.*")]
+#[cfg_attr(feature = "templates-functional", include = "ocaml.*")]
+#[cfg_attr(feature = "templates-functional", include = "elixir.*")]
+#[cfg_attr(feature = "templates-functional", include = "gleam.*")]
+#[cfg_attr(feature = "templates-scripting", include = "python.*")]
+#[cfg_attr(feature = "templates-scripting", include = "r.*")]
+#[cfg_attr(feature = "templates-scripting", include = "shell.*")]
+#[cfg_attr(feature = "templates-scripting", include = "latex.*")]
+#[cfg_attr(feature = "templates-infra", include = "hashi.*")]
+#[cfg_attr(feature = "templates-infra", include = "pulumi.*")]
+#[cfg_attr(feature = "templates-infra", include = "opa.*")]
+#[cfg_attr(feature = "templates-infra", include = "protobuf.*")]
+#[cfg_attr(feature = "templates-infra", include = "cue.*")]
+#[cfg_attr(feature = "templates-infra", include = "dhall.*")]
+#[cfg_attr(feature = "templates-infra", include = "nickel.*")]
+struct Templates;
+
+/// Directory of the groups offered to every template, one `<name>.toml` each
+const GROUPS_DIR: &str = "groups/";
+
+#[derive(Deserialize)]
+pub struct TemplateMetadata {
+    pub template: TemplateInfo,
+    #[serde(default)]
+    pub delta: TemplateDelta,
+    #[serde(default)]
+    pub groups: BTreeMap<String, TemplateGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TemplateInfo {
+    pub description: String,
+    /// Base template; such templates have no `.nix` file of their own
+    pub extends: Option<String>,
+    /// Shell command printing the version of the template's toolchain
+    pub version_command: Option<String>,
+    /// Packages that are optional tooling rather than the core toolchain
+    #[serde(default)]
+    pub extra_packages: Vec<String>,
+    /// Whether the template works without a C compiler
+    #[serde(default)]
+    pub no_cc: bool,
+    /// Packages only added to the devShell on darwin
+    #[serde(default)]
+    pub darwin_packages: Vec<String>,
+    /// Lines added to the devShell's shell hook
+    #[serde(default)]
+    pub shell_hook: Vec<String>,
+}
+
+/// Every template compiled into the binary. A `.toml` without a matching
+/// `.nix` (unless it extends another template), or metadata that doesn't
+/// parse, is an error rather than a template that silently goes missing.
+pub fn load() -> Result<Vec<Template>> {
+    let mut templates = Vec::new();
+
+    for file_path in Templates::iter() {
+        if file_path.starts_with(GROUPS_DIR) {
+            continue;
+        }
+        let Some(template_name) = file_path.strip_suffix(".toml") else {
+            continue;
+        };
+
+        let metadata: TemplateMetadata = toml::from_str(&read(&file_path)?).map_err(|source| {
+            TemplateError::InvalidMetadata {
+                path: PathBuf::from(file_path.as_ref()),
+                source,
+            }
+        })?;
+        let flake_content = match metadata.template.extends {
+            Some(_) => String::new(),
+            None => read(&format!("{template_name}.nix"))?,
+        };
+
+        let mut additional_files = HashMap::new();
+        // Special case for rust-toolchain template - add sample rust-toolchain.toml
+        if template_name == "rust-toolchain" {
+            additional_files.insert(
+                "rust-toolchain.toml".to_string(),
+                r#"[toolchain]
+channel = "stable"
+components = ["rustfmt", "rust-analyzer"]
+"#
+                .to_string(),
+            );
+        }
+
+        templates.push(Template {
+            name: template_name.to_string(),
+            description: metadata.template.description,
+            flake_content,
+            additional_files,
+            extends: metadata.template.extends,
+            delta: metadata.delta,
+            extra_packages: metadata.template.extra_packages,
+            no_cc: metadata.template.no_cc,
+            darwin_packages: metadata.template.darwin_packages,
+            shell_hook: metadata.template.shell_hook,
+            groups: metadata.groups,
+            version_command: metadata.template.version_command,
+        });
+    }
+
+    Ok(templates)
+}
+
+/// The groups compiled into the binary that any template can take, such as
+/// `k8s`, by name.
+pub fn groups() -> Result<BTreeMap<String, TemplateGroup>> {
+    let mut groups = BTreeMap::new();
+    for file_path in Templates::iter() {
+        let Some(name) = file_path
+            .strip_prefix(GROUPS_DIR)
+            .and_then(|file_name| file_name.strip_suffix(".toml"))
+        else {
+            continue;
+        };
+        let group: TemplateGroup = toml::from_str(&read(&file_path)?).map_err(|source| {
+            TemplateError::InvalidMetadata {
+                path: PathBuf::from(file_path.as_ref()),
+                source,
+            }
+        })?;
+        groups.insert(name.to_string(), group);
+    }
+    Ok(groups)
+}
+
+/// The metadata TOML of an embedded template, as written.
+pub fn metadata_source(template_name: &str) -> Option<String> {
+    read(&format!("{template_name}.toml")).ok()
+}
+
+fn read(file_path: &str) -> Result<String> {
+    let file = Templates::get(file_path)
+        .ok_or_else(|| TemplateError::MissingFile(PathBuf::from(file_path)))?;
+    String::from_utf8(file.data.into_owned())
+        .map_err(|_| TemplateError::NotUtf8(PathBuf::from(file_path)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> HashMap<String, (String, String)> {
+        load()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.name, (t.description, t.flake_content)))
+            .collect()
+    }
+
+    #[cfg(feature = "templates-systems")]
+    #[test]
+    fn test_template_structure() {
+        let templates = templates();
+        assert!(!templates.is_empty(), "Templates should not be empty");
+
+        // Test that rust template exists
+        assert!(templates.contains_key("rust"), "Rust template should exist");
+
+        if let Some((description, content)) = templates.get("rust") {
+            assert!(!description.is_empty(), "Description should not be empty");
+            assert!(!content.is_empty(), "Content should not be empty");
+            assert!(
+                content.contains("rust-overlay"),
+                "Rust template should contain rust-overlay"
+            );
+        }
+    }
+
+    #[cfg(feature = "templates-all")]
+    #[test]
+    fn test_all_templates_exist() {
+        let templates = templates();
+        let expected_templates = [
+            "bun",
+            "c-cpp",
+            "clojure",
+            "csharp",
+            "cue",
+            "dhall",
+            "elixir",
+            "elm",
+            "gleam",
+            "go",
+            "hashi",
+            "haskell",
+            "java",
+            "kotlin",
+            "latex",
+            "nickel",
+            "nim",
+            "nix",
+            "node",
+            "ocaml",
+            "opa",
+            "php",
+            "protobuf",
+            "pulumi",
+            "python",
+            "r",
+            "ruby",
+            "rust",
+            "rust-toolchain",
+            "scala",
+            "shell",
+            "swift",
+            "vlang",
+            "zig",
+        ];
+
+        for template in &expected_templates {
+            assert!(
+                templates.contains_key(*template),
+                "Template '{template}' should exist"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rust_template_has_overlay() {
+        let templates = templates();
+        if let Some((_, content)) = templates.get("rust") {
+            assert!(
+                content.contains("overlays.default"),
+                "Rust template should have overlay"
+            );
+            assert!(
+                content.contains("rustToolchain"),
+                "Rust template should define rustToolchain"
+            );
+        }
+    }
+
+    #[test]
+    fn test_go_template_version() {
+        let templates = templates();
+        if let Some((_, content)) = templates.get("go") {
+            assert!(
+                content.contains("go"),
+                "Go template should contain go package"
+            );
+        }
+    }
+
+    #[test]
+    fn test_java_templates_have_jdk() {
+        let templates = templates();
+        let java_templates = ["java", "kotlin", "scala"];
+
+        for template_name in &java_templates {
+            if let Some((_, content)) = templates.get(*template_name) {
+                // Java templates should reference JDK in some form
+                assert!(
+                    content.contains("jdk") || content.contains("openjdk"),
+                    "{template_name} template should reference JDK"
+                );
+            }
+        }
+    }
+}
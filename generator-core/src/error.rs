@@ -0,0 +1,143 @@
+use crate::suggest;
+use nix_parser::ParseError;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Every failure the library reports. Match on the category, then on the
+/// nested enum for specifics; the underlying cause is kept as the
+/// [`source`](std::error::Error::source).
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+
+    #[error(transparent)]
+    Merge(#[from] MergeError),
+
+    #[error("Failed to parse nix template")]
+    Parse(#[from] ParseError),
+
+    #[error(
+        "Internal error: generated {artifact} for [{}] failed validation: {message}\n\
+         This is a bug in nix-flake-generator; please report it with the command you ran.",
+        .templates.join(", ")
+    )]
+    InvalidOutput {
+        artifact: String,
+        templates: Vec<String>,
+        message: String,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Looking up or loading templates failed.
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("{}", unknown_template_message(.name, .suggestions))]
+    Unknown {
+        name: String,
+        /// Registered names close enough to be a likely typo
+        suggestions: Vec<String>,
+    },
+
+    #[error("Template directory {} does not exist", .0.display())]
+    MissingDir(PathBuf),
+
+    #[error("Invalid template metadata in {}", .path.display())]
+    InvalidMetadata {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid template file name {}", .0.display())]
+    InvalidName(PathBuf),
+
+    #[error("Template file {} is missing", .0.display())]
+    MissingFile(PathBuf),
+
+    #[error("Template file {} is not valid UTF-8", .0.display())]
+    NotUtf8(PathBuf),
+
+    #[error("Template provider '{provider}' failed: {message}")]
+    Provider { provider: String, message: String },
+
+    #[error("Template '{template}' extends unknown template '{base}'")]
+    UnknownBase { template: String, base: String },
+
+    #[error("Templates extend each other in a cycle: {}", .0.join(" -> "))]
+    InheritanceCycle(Vec<String>),
+
+    #[error("{}", needs_compiler_message(.0))]
+    NeedsCompiler(Vec<String>),
+
+    #[error("{}", unknown_group_message(.group, .available))]
+    UnknownGroup {
+        group: String,
+        /// Groups the selected templates do have
+        available: Vec<String>,
+    },
+
+    #[error("Cannot derive template '{template}' from '{base}': {message}")]
+    InvalidDelta {
+        template: String,
+        base: String,
+        message: String,
+    },
+}
+
+/// Combining templates into one flake failed.
+#[derive(Debug, thiserror::Error)]
+pub enum MergeError {
+    #[error("No templates provided for merging")]
+    NoTemplates,
+
+    #[error("Templates '{first}' and '{second}' both define {attribute} with different values")]
+    Conflict {
+        attribute: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("Failed to parse template '{template}'")]
+    Parse {
+        template: String,
+        #[source]
+        source: ParseError,
+    },
+}
+
+fn needs_compiler_message(templates: &[String]) -> String {
+    let verb = if templates.len() == 1 { "needs" } else { "need" };
+    format!(
+        "{} {verb} a C compiler and cannot use mkShellNoCC",
+        templates.join(", ")
+    )
+}
+
+fn unknown_group_message(group: &str, available: &[String]) -> String {
+    if available.is_empty() {
+        format!("None of the selected templates has optional groups, so '{group}' cannot be added")
+    } else {
+        format!(
+            "None of the selected templates has a '{group}' group (available: {})",
+            available.join(", ")
+        )
+    }
+}
+
+fn unknown_template_message(name: &str, suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        format!("Template '{name}' not found")
+    } else {
+        let choices: Vec<&str> = suggestions.iter().map(String::as_str).collect();
+        format!(
+            "Unknown template '{}', did you mean {}?",
+            name,
+            suggest::format_choices(&choices)
+        )
+    }
+}
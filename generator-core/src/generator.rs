@@ -0,0 +1,128 @@
+use crate::error::Result;
+use crate::language::{Language, RustOptions};
+use crate::options::GenerationOptions;
+use crate::registry::{Registry, RenderedFlake};
+
+/// Builder for rendering a flake from one or more language templates.
+///
+/// ```no_run
+/// use nix_flake_generator_core::{Generator, Language};
+///
+/// let rendered = Generator::new()
+///     .languages([Language::Rust, Language::Go])
+///     .render()?;
+/// println!("{}", rendered.flake_content);
+/// # Ok::<(), nix_flake_generator_core::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    registry: Registry,
+    languages: Vec<String>,
+    options: GenerationOptions,
+}
+
+impl Generator {
+    /// A generator using the embedded templates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a custom registry, e.g. one with template directories loaded.
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Templates to combine, in order, as [`Language`]s or template names.
+    /// Earlier templates win where the merge engine has to pick one side.
+    pub fn languages<I, S>(mut self, languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.languages = languages.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add one language after those already selected.
+    pub fn language(mut self, language: Language) -> Self {
+        self.languages.push(language.into());
+        self
+    }
+
+    /// Add Rust, configured by `options`.
+    pub fn rust(self, options: RustOptions) -> Self {
+        self.language(options.language())
+    }
+
+    pub fn options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Merge the selected templates and apply the options, without touching
+    /// the filesystem.
+    pub fn render(&self) -> Result<RenderedFlake> {
+        let names: Vec<&str> = self.languages.iter().map(String::as_str).collect();
+        self.registry.render(&names, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, TemplateError};
+
+    #[test]
+    fn test_render_multiple_languages() {
+        let rendered = Generator::new()
+            .languages([Language::Rust, Language::Go])
+            .render()
+            .unwrap();
+        assert!(rendered.flake_content.contains("rust-overlay"));
+        assert!(rendered.flake_content.contains("go"));
+    }
+
+    #[test]
+    fn test_rust_toolchain_file_option() {
+        let rendered = Generator::new()
+            .rust(RustOptions { toolchain_file: true })
+            .render()
+            .unwrap();
+        assert!(rendered.additional_files.contains_key("rust-toolchain.toml"));
+    }
+
+    #[test]
+    fn test_render_ignores_language_order() {
+        let forward = Generator::new().languages(["rust", "go", "python"]).render().unwrap();
+        let reverse = Generator::new().languages(["python", "go", "rust"]).render().unwrap();
+        assert_eq!(forward.flake_content, reverse.flake_content);
+
+        let first_input = forward
+            .flake_content
+            .lines()
+            .skip_while(|line| !line.contains("inputs = {"))
+            .nth(1)
+            .unwrap();
+        assert!(first_input.trim_start().starts_with("nixpkgs.url"));
+    }
+
+    #[test]
+    fn test_render_keeps_template_comments() {
+        let rendered = Generator::new().languages(["go", "rust"]).render().unwrap();
+        assert!(rendered
+            .flake_content
+            .contains("goVersion = 24; # Change this to update the whole stack"));
+        assert!(rendered.flake_content.contains("# Required by rust-analyzer\n"));
+    }
+
+    #[test]
+    fn test_unknown_language_suggests() {
+        match Generator::new().languages(["pyton"]).render() {
+            Err(Error::Template(TemplateError::Unknown { suggestions, .. })) => {
+                assert_eq!(suggestions, vec!["python".to_string()]);
+            }
+            other => panic!("expected UnknownTemplate, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A language template bundled with the generator. Templates loaded from
+/// template directories are addressed by name instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Language {
+    Bun,
+    CCpp,
+    Clojure,
+    CSharp,
+    Cue,
+    Dhall,
+    Elixir,
+    Elm,
+    Gleam,
+    Go,
+    Hashi,
+    Haskell,
+    Java,
+    Kotlin,
+    Latex,
+    Nickel,
+    Nim,
+    Nix,
+    Node,
+    OCaml,
+    Opa,
+    Php,
+    Protobuf,
+    Pulumi,
+    Python,
+    R,
+    Ruby,
+    Rust,
+    RustToolchain,
+    Scala,
+    Shell,
+    Swift,
+    Vlang,
+    Zig,
+}
+
+impl Language {
+    pub const ALL: [Language; 34] = [
+        Language::Bun,
+        Language::CCpp,
+        Language::Clojure,
+        Language::CSharp,
+        Language::Cue,
+        Language::Dhall,
+        Language::Elixir,
+        Language::Elm,
+        Language::Gleam,
+        Language::Go,
+        Language::Hashi,
+        Language::Haskell,
+        Language::Java,
+        Language::Kotlin,
+        Language::Latex,
+        Language::Nickel,
+        Language::Nim,
+        Language::Nix,
+        Language::Node,
+        Language::OCaml,
+        Language::Opa,
+        Language::Php,
+        Language::Protobuf,
+        Language::Pulumi,
+        Language::Python,
+        Language::R,
+        Language::Ruby,
+        Language::Rust,
+        Language::RustToolchain,
+        Language::Scala,
+        Language::Shell,
+        Language::Swift,
+        Language::Vlang,
+        Language::Zig,
+    ];
+
+    /// Name of the bundled template for this language.
+    pub fn template_name(self) -> &'static str {
+        match self {
+            Language::Bun => "bun",
+            Language::CCpp => "c-cpp",
+            Language::Clojure => "clojure",
+            Language::CSharp => "csharp",
+            Language::Cue => "cue",
+            Language::Dhall => "dhall",
+            Language::Elixir => "elixir",
+            Language::Elm => "elm",
+            Language::Gleam => "gleam",
+            Language::Go => "go",
+            Language::Hashi => "hashi",
+            Language::Haskell => "haskell",
+            Language::Java => "java",
+            Language::Kotlin => "kotlin",
+            Language::Latex => "latex",
+            Language::Nickel => "nickel",
+            Language::Nim => "nim",
+            Language::Nix => "nix",
+            Language::Node => "node",
+            Language::OCaml => "ocaml",
+            Language::Opa => "opa",
+            Language::Php => "php",
+            Language::Protobuf => "protobuf",
+            Language::Pulumi => "pulumi",
+            Language::Python => "python",
+            Language::R => "r",
+            Language::Ruby => "ruby",
+            Language::Rust => "rust",
+            Language::RustToolchain => "rust-toolchain",
+            Language::Scala => "scala",
+            Language::Shell => "shell",
+            Language::Swift => "swift",
+            Language::Vlang => "vlang",
+            Language::Zig => "zig",
+        }
+    }
+
+    /// Alternative names accepted by [`FromStr`], e.g. `py` for Python.
+    pub fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Language::Bun => &[],
+            Language::CCpp => &["c", "cpp", "c++"],
+            Language::Clojure => &["clj"],
+            Language::CSharp => &["c#", "cs", "dotnet"],
+            Language::Cue => &[],
+            Language::Dhall => &[],
+            Language::Elixir => &["ex", "erlang"],
+            Language::Elm => &[],
+            Language::Gleam => &[],
+            Language::Go => &["golang"],
+            Language::Hashi => &["terraform", "tf", "packer", "nomad"],
+            Language::Haskell => &["hs", "ghc"],
+            Language::Java => &["jdk"],
+            Language::Kotlin => &["kt"],
+            Language::Latex => &["tex"],
+            Language::Nickel => &[],
+            Language::Nim => &[],
+            Language::Nix => &[],
+            Language::Node => &["nodejs", "js", "javascript", "ts", "typescript"],
+            Language::OCaml => &["ml"],
+            Language::Opa => &["rego"],
+            Language::Php => &[],
+            Language::Protobuf => &["proto", "grpc"],
+            Language::Pulumi => &[],
+            Language::Python => &["py", "python3"],
+            Language::R => &[],
+            Language::Ruby => &["rb"],
+            Language::Rust => &["rs"],
+            Language::RustToolchain => &[],
+            Language::Scala => &[],
+            Language::Shell => &["sh", "bash"],
+            Language::Swift => &[],
+            Language::Vlang => &["v"],
+            Language::Zig => &[],
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.template_name())
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.template_name().to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown language '{0}'")]
+pub struct ParseLanguageError(pub String);
+
+impl FromStr for Language {
+    type Err = ParseLanguageError;
+
+    /// Accepts template names and aliases, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Language::ALL
+            .into_iter()
+            .find(|language| {
+                language.template_name() == name || language.aliases().contains(&name.as_str())
+            })
+            .ok_or_else(|| ParseLanguageError(s.to_string()))
+    }
+}
+
+/// Options for the Rust templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RustOptions {
+    /// Take the toolchain from a `rust-toolchain.toml` in the project (one is
+    /// generated if missing) instead of the latest stable release
+    pub toolchain_file: bool,
+}
+
+impl RustOptions {
+    pub fn language(self) -> Language {
+        if self.toolchain_file {
+            Language::RustToolchain
+        } else {
+            Language::Rust
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_accepts_names_and_aliases() {
+        assert_eq!("python".parse::<Language>(), Ok(Language::Python));
+        assert_eq!("PY".parse::<Language>(), Ok(Language::Python));
+        assert_eq!("ts".parse::<Language>(), Ok(Language::Node));
+        assert_eq!("c-cpp".parse::<Language>(), Ok(Language::CCpp));
+        assert!("cobol".parse::<Language>().is_err());
+    }
+
+    #[cfg(feature = "templates-all")]
+    #[test]
+    fn test_every_language_has_a_bundled_template() {
+        let registry = crate::Registry::new();
+        for language in Language::ALL {
+            assert!(registry.get(language.template_name()).is_ok(), "{language} has no template");
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Template registry, fragment merging and rendering behind
+//! `nix-flake-generator`, usable without shelling out to the CLI.
+//!
+//! [`Generator`] is the entry point for rendering; [`Registry`] holds the
+//! embedded templates and any loaded from template directories.
+//!
+//! Nothing here runs nix or other processes. Reading template directories
+//! is behind the default `fs` feature; without it the crate builds for
+//! `wasm32-unknown-unknown`, and the `wasm` feature adds JavaScript bindings.
+
+pub mod delta;
+mod embedded_templates;
+pub mod error;
+pub mod generator;
+pub mod language;
+pub mod merger;
+pub mod options;
+pub mod provider;
+pub mod registry;
+pub mod suggest;
+mod toolchains;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use delta::TemplateDelta;
+pub use error::{Error, MergeError, Result, TemplateError};
+pub use generator::Generator;
+pub use language::{Language, ParseLanguageError, RustOptions};
+pub use options::{
+    Beam, BuildTool, ConflictStrategy, Embedded, GenerationOptions, Java, JdkDist, Latex,
+    LatexScheme, Php, Stdenv, dotnet_sdk_attribute, elixir_attribute, ghc_attribute,
+    ocaml_attribute, php_attribute, php_extension, ruby_attribute, terraform_provider,
+    toolchain_ref,
+};
+#[cfg(feature = "fs")]
+pub use provider::DirectoryProvider;
+pub use provider::{EmbeddedProvider, TemplateProvider};
+pub use registry::{Registry, RenderedFlake, Template, TemplateGroup};
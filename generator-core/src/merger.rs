@@ -0,0 +1,717 @@
+//! Combining several templates into one flake.
+//!
+//! The merged flake does not depend on the order templates were given in:
+//! `nixpkgs` is always the first input and the remaining inputs, overlay
+//! attributes, packages, environment variables and let bindings are sorted
+//! by name. Exact duplicate packages and shell hooks are emitted once.
+//!
+//! Comments the templates attach to inputs, overlay attributes, packages,
+//! environment variables and let bindings are kept with the attribute.
+//! The template each attribute came from is recorded as its provenance and,
+//! with [`GenerationOptions::provenance_comments`], noted in a comment line
+//! above the attribute.
+//! With [`GenerationOptions::banner`] the shell hook ends in one banner
+//! naming every template and printing its toolchain version.
+
+use crate::delta;
+use crate::error::{MergeError, Result};
+use crate::options::{ConflictStrategy, GenerationOptions};
+use crate::registry::Template;
+use nix_parser::{
+    extract_flake_fragments, AttrPath, AttrPathPart, Binding, Comments, NixpkgsConfig, ParseError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::{debug, debug_span, trace, warn};
+
+#[derive(Default)]
+pub struct FlakeFragments {
+    pub header: String,
+    pub inputs: BTreeMap<String, String>,
+    pub overlays: BTreeMap<String, Vec<Binding>>, // Now using AST bindings
+    pub packages: BTreeSet<String>,
+    pub env_vars: BTreeMap<String, String>,
+    pub shell_hooks: BTreeSet<String>,
+    pub nixpkgs_config: NixpkgsConfig,
+    pub let_bindings: BTreeMap<String, String>,
+    /// Template comments keyed by merged attribute, e.g. `inputs.nixpkgs`,
+    /// `packages.go` or `let.goVersion`.
+    pub comments: BTreeMap<String, Comments>,
+}
+
+/// The template, and the version of it, that contributed a merged attribute.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    pub template: String,
+    pub version: String,
+}
+
+impl Origin {
+    fn of(template: &Template) -> Self {
+        Self {
+            template: template.name.clone(),
+            version: template.version(),
+        }
+    }
+}
+
+/// Origins keyed by merged attribute: `inputs.<name>`,
+/// `overlays.<overlay>.<path>`, `packages.<name>`, `env.<name>`,
+/// `let.<name>` and `shellHook.<template>`.
+pub type Provenance = BTreeMap<String, Origin>;
+
+/// A merged flake and where its attributes came from.
+#[derive(Debug, Clone)]
+pub struct MergedFlake {
+    pub flake_content: String,
+    pub provenance: Provenance,
+}
+
+/// Merge templates into one flake. Inputs, overlay attributes and
+/// environment variables that templates define differently are settled by
+/// `strategy`.
+pub fn merge_templates(templates: &[Template], strategy: ConflictStrategy) -> Result<String> {
+    let options = GenerationOptions {
+        on_conflict: strategy,
+        ..Default::default()
+    };
+    let merged = merge_templates_with(templates, &options, |template| {
+        extract_flake_fragments(&template.flake_content)
+    })?;
+    Ok(merged.flake_content)
+}
+
+/// Like [`merge_templates`], with parsing left to `parse` so callers can
+/// reuse fragments parsed for an earlier merge, and the provenance of the
+/// merged attributes returned alongside the flake. A single template is
+/// used as-is apart from its darwin packages and banner, so it never carries
+/// provenance comments.
+pub fn merge_templates_with<F>(
+    templates: &[Template],
+    options: &GenerationOptions,
+    mut parse: F,
+) -> Result<MergedFlake>
+where
+    F: FnMut(&Template) -> std::result::Result<nix_parser::FlakeFragments, ParseError>,
+{
+    if templates.is_empty() {
+        return Err(MergeError::NoTemplates.into());
+    }
+
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    let _span = debug_span!("merge", templates = ?names).entered();
+
+    let mut fragments = FlakeFragments::default();
+    let mut conflicts = Conflicts::new(options.on_conflict);
+
+    if let [template] = templates {
+        debug!("single template, using its flake as-is");
+        match parse(template) {
+            Ok(parsed) => {
+                let origin = Origin::of(template);
+                merge_parsed_fragments(&origin, parsed, &mut fragments, &mut conflicts)?;
+            }
+            Err(e) => debug!("no provenance for unparsable template: {e}"),
+        }
+        return Ok(MergedFlake {
+            flake_content: finish(template.flake_content.clone(), templates, options),
+            provenance: conflicts.origins,
+        });
+    }
+
+    let header_names: BTreeSet<&str> = names.iter().copied().collect();
+    fragments.header = format!(
+        "Multi-language development environment ({})",
+        header_names.into_iter().collect::<Vec<_>>().join(", ")
+    );
+
+    for template in templates {
+        let _span = debug_span!("extract", template = %template.name).entered();
+        let parsed = parse(template).map_err(|source| MergeError::Parse {
+            template: template.name.clone(),
+            source,
+        })?;
+        merge_parsed_fragments(&Origin::of(template), parsed, &mut fragments, &mut conflicts)?;
+    }
+
+    debug!(
+        inputs = fragments.inputs.len(),
+        overlays = fragments.overlays.len(),
+        packages = fragments.packages.len(),
+        env_vars = fragments.env_vars.len(),
+        shell_hooks = fragments.shell_hooks.len(),
+        allow_unfree = fragments.nixpkgs_config.allow_unfree,
+        "merged fragments"
+    );
+    let annotate = options.provenance_comments.then_some(&conflicts.origins);
+    let flake_content = generate_merged_flake(&fragments, annotate)?;
+    Ok(MergedFlake {
+        flake_content: finish(flake_content, templates, options),
+        provenance: conflicts.origins,
+    })
+}
+
+/// Additions that span all templates, made once to the merged flake.
+fn finish(content: String, templates: &[Template], options: &GenerationOptions) -> String {
+    let content = with_darwin_packages(content, templates);
+    let groups = options.selected_groups();
+    let content = with_hooks(content, templates, &groups);
+    let content = with_group_apps(content, templates, &groups);
+    if options.banner {
+        with_banner(content, templates)
+    } else {
+        content
+    }
+}
+
+/// Append a banner to the shell hook that names the templates and runs
+/// their version commands.
+fn with_banner(content: String, templates: &[Template]) -> String {
+    let by_name: BTreeMap<&str, &Template> =
+        templates.iter().map(|template| (template.name.as_str(), template)).collect();
+    let names: Vec<&str> = by_name.keys().copied().collect();
+    let mut lines = vec![format!("echo \"Development environment: {}\"", names.join(", "))];
+    for command in by_name.values().filter_map(|template| template.version_command.as_ref()) {
+        if !lines.contains(command) {
+            lines.push(command.clone());
+        }
+    }
+
+    match delta::add_shell_hook(&content, &lines) {
+        Some(added) => added,
+        None => {
+            warn!("No devShell packages list or shell hook to add the banner to");
+            content
+        }
+    }
+}
+
+/// Append the shell hook lines of the templates' metadata and of the
+/// requested groups. The merge keeps only known hooks of the templates'
+/// flakes, so these are added to its result.
+fn with_hooks(content: String, templates: &[Template], groups: &[String]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for template in templates {
+        let group_lines = groups
+            .iter()
+            .filter_map(|name| template.groups.get(name))
+            .flat_map(|group| &group.shell_hook);
+        for line in template.shell_hook.iter().chain(group_lines) {
+            if !lines.contains(line) {
+                lines.push(line.clone());
+            }
+        }
+    }
+    if lines.is_empty() {
+        return content;
+    }
+
+    match delta::add_shell_hook(&content, &lines) {
+        Some(added) => added,
+        None => {
+            warn!("No devShell packages list or shell hook to add the template hooks to");
+            content
+        }
+    }
+}
+
+/// Add the apps of the requested groups. Templates offering an app of the
+/// same name should run the same command; the first one is kept otherwise.
+fn with_group_apps(content: String, templates: &[Template], groups: &[String]) -> String {
+    let mut apps: BTreeMap<String, String> = BTreeMap::new();
+    for template in templates {
+        for group in groups.iter().filter_map(|name| template.groups.get(name)) {
+            for (name, command) in &group.apps {
+                match apps.get(name) {
+                    Some(existing) if existing != command => warn!(
+                        template = %template.name,
+                        "App {name} already runs '{existing}'; '{command}' not used"
+                    ),
+                    Some(_) => {}
+                    None => {
+                        apps.insert(name.clone(), command.clone());
+                    }
+                }
+            }
+        }
+    }
+    if apps.is_empty() {
+        return content;
+    }
+
+    match delta::add_apps(&content, &apps) {
+        Some(added) => added,
+        None => {
+            warn!("No devShells = forEachSupportedSystem output to add apps next to");
+            content
+        }
+    }
+}
+
+/// Add the templates' darwin-only packages behind `stdenv.isDarwin`. They are
+/// kept out of the templates' flakes, where the merge would lose them along
+/// with any other conditional packages.
+fn with_darwin_packages(content: String, templates: &[Template]) -> String {
+    let packages: BTreeSet<&String> = templates.iter().flat_map(|t| &t.darwin_packages).collect();
+    let packages: Vec<String> = packages.into_iter().cloned().collect();
+    if packages.is_empty() {
+        return content;
+    }
+
+    match delta::add_darwin_packages(&content, &packages) {
+        Some(added) => added,
+        None => {
+            warn!("No devShell packages list; darwin packages {} not added", packages.join(", "));
+            content
+        }
+    }
+}
+
+/// Which template each merged attribute came from, for settling
+/// disagreements between templates according to the conflict strategy.
+struct Conflicts {
+    strategy: ConflictStrategy,
+    origins: Provenance,
+}
+
+impl Conflicts {
+    fn new(strategy: ConflictStrategy) -> Self {
+        Self {
+            strategy,
+            origins: Provenance::new(),
+        }
+    }
+
+    /// Record `origin` as the source of `attribute`, which is not subject
+    /// to conflicts.
+    fn record(&mut self, attribute: String, origin: &Origin) {
+        self.origins.insert(attribute, origin.clone());
+    }
+
+    /// Whether `origin`'s value for `attribute` should be used. `existing`
+    /// is whether an earlier template set it, and `differs` whether that
+    /// earlier value is different.
+    fn take(&mut self, attribute: &str, origin: &Origin, existing: bool, differs: bool) -> Result<bool> {
+        let template = origin.template.as_str();
+        if !existing {
+            self.origins.insert(attribute.to_string(), origin.clone());
+            return Ok(true);
+        }
+        if !differs {
+            trace!(attribute, "already defined identically");
+            return Ok(false);
+        }
+
+        let first = self
+            .origins
+            .get(attribute)
+            .map(|origin| origin.template.clone())
+            .unwrap_or_default();
+        match self.strategy {
+            ConflictStrategy::PreferFirst => {
+                warn!("Templates '{first}' and '{template}' both define {attribute}; keeping '{first}'");
+                Ok(false)
+            }
+            ConflictStrategy::PreferLast => {
+                warn!("Templates '{first}' and '{template}' both define {attribute}; using '{template}'");
+                self.origins.insert(attribute.to_string(), origin.clone());
+                Ok(true)
+            }
+            ConflictStrategy::Error => Err(MergeError::Conflict {
+                attribute: attribute.to_string(),
+                first,
+                second: template.to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+fn merge_parsed_fragments(
+    origin: &Origin,
+    mut parsed_fragments: nix_parser::FlakeFragments,
+    fragments: &mut FlakeFragments,
+    conflicts: &mut Conflicts,
+) -> Result<()> {
+    debug!(
+        inputs = parsed_fragments.inputs.len(),
+        overlays = parsed_fragments.overlays.len(),
+        packages = parsed_fragments.packages.len(),
+        env_vars = parsed_fragments.env_vars.len(),
+        "extracted fragments"
+    );
+    let source_comments = std::mem::take(&mut parsed_fragments.comments);
+
+    // Merge inputs
+    for (key, value) in parsed_fragments.inputs {
+        trace!(input = %key, url = %value, "input");
+        let previous = fragments.inputs.get(&key);
+        let attribute = format!("inputs.{key}");
+        if conflicts.take(&attribute, origin, previous.is_some(), previous != Some(&value))? {
+            let written = [
+                format!("inputs.{key}.url"),
+                format!("{key}.url"),
+                format!("inputs.{key}"),
+                key.clone(),
+            ];
+            let comments = written.iter().find_map(|path| source_comments.get(path));
+            attach_comments(&mut fragments.comments, attribute, comments);
+            fragments.inputs.insert(key, value);
+        }
+    }
+
+    // Merge overlays - deduplicate bindings at AST level
+    for (key, bindings) in parsed_fragments.overlays {
+        let existing_bindings = fragments.overlays.entry(key.clone()).or_default();
+        merge_overlay_bindings(
+            origin,
+            &key,
+            existing_bindings,
+            bindings,
+            &source_comments,
+            &mut fragments.comments,
+            conflicts,
+        )?;
+    }
+
+    // Merge packages (convert Vec to BTreeSet)
+    // Note: We don't filter out overlay-defined packages anymore since they're actually 
+    // available for use once the overlay is applied
+    for package in parsed_fragments.packages {
+        if fragments.packages.insert(package.clone()) {
+            trace!(%package, "package");
+            let attribute = format!("packages.{package}");
+            conflicts.record(attribute.clone(), origin);
+            attach_comments(&mut fragments.comments, attribute, source_comments.get(&package));
+        } else {
+            trace!(%package, "package already present, deduplicated");
+        }
+    }
+
+    // Merge environment variables
+    for (key, value) in parsed_fragments.env_vars {
+        trace!(env_var = %key, "environment variable");
+        let previous = fragments.env_vars.get(&key);
+        let attribute = format!("env.{key}");
+        if conflicts.take(&attribute, origin, previous.is_some(), previous != Some(&value))? {
+            attach_comments(&mut fragments.comments, attribute, source_comments.get(&key));
+            fragments.env_vars.insert(key, value);
+        }
+    }
+
+    // Merge shell hooks (convert Vec to BTreeSet)
+    if !parsed_fragments.shell_hooks.is_empty() {
+        conflicts.record(format!("shellHook.{}", origin.template), origin);
+    }
+    for hook in parsed_fragments.shell_hooks {
+        fragments.shell_hooks.insert(hook);
+    }
+
+    // Merge the nixpkgs config; options other than the flags and insecure
+    // packages can conflict like any other attribute
+    let mut config = parsed_fragments.nixpkgs_config;
+    for (path, value) in std::mem::take(&mut config.extra) {
+        let previous = fragments.nixpkgs_config.extra.get(&path);
+        let attribute = format!("config.{path}");
+        if conflicts.take(&attribute, origin, previous.is_some(), previous != Some(&value))? {
+            config.extra.insert(path, value);
+        }
+    }
+    fragments.nixpkgs_config.merge(config);
+
+    // Merge let bindings
+    for (key, value) in parsed_fragments.let_bindings {
+        let attribute = format!("let.{key}");
+        conflicts.record(attribute.clone(), origin);
+        attach_comments(&mut fragments.comments, attribute, source_comments.get(&key));
+        fragments.let_bindings.insert(key, value);
+    }
+
+    Ok(())
+}
+
+
+
+/// Render merged fragments as a flake, noting each attribute's template in
+/// a comment when `provenance` is given.
+fn generate_merged_flake(
+    fragments: &FlakeFragments,
+    provenance: Option<&Provenance>,
+) -> Result<String> {
+    let comments_for = |attribute: String| annotated(fragments, provenance, &attribute);
+
+    let mut inputs_fragment = String::new();
+
+    // Generate inputs from extracted data, nixpkgs first
+    let (nixpkgs, others): (Vec<_>, Vec<_>) =
+        fragments.inputs.iter().partition(|(name, _)| *name == "nixpkgs");
+
+    for (key, url) in nixpkgs.into_iter().chain(others) {
+        let comments = comments_for(format!("inputs.{key}"));
+        if key.contains("overlay") {
+            // Handle overlay inputs with follows pattern
+            push_line(&mut inputs_fragment, "    ", &format!("{key} = {{"), comments);
+            inputs_fragment.push_str(&format!(
+                r#"      url = "{url}";
+      inputs.nixpkgs.follows = "nixpkgs";
+    }};
+"#
+            ));
+        } else {
+            // Simple URL inputs
+            push_line(&mut inputs_fragment, "    ", &format!(r#"{key}.url = "{url}";"#), comments);
+        }
+    }
+
+    let mut overlays_fragment = String::new();
+    if !fragments.overlays.is_empty() {
+        overlays_fragment.push_str("      overlays.default = final: prev: rec {\n");
+
+        // Generate overlay content from AST bindings
+        for (overlay, bindings) in &fragments.overlays {
+            let mut sorted_bindings: Vec<_> = bindings.iter().collect();
+            sorted_bindings.sort_by_cached_key(|binding| format_attr_path(&binding.path));
+            for binding in sorted_bindings {
+                let path = format_attr_path(&binding.path);
+                push_line(
+                    &mut overlays_fragment,
+                    "        ",
+                    &format!("{} = {};", path, binding.value.to_nix_string()),
+                    comments_for(format!("overlays.{overlay}.{path}")),
+                );
+            }
+        }
+
+        overlays_fragment.push_str("      };\n");
+    }
+
+    let mut packages_fragment = String::new();
+    for package in &fragments.packages {
+        let comments = comments_for(format!("packages.{package}"));
+        push_line(&mut packages_fragment, "              ", package, comments);
+    }
+
+    let env_fragment = if !fragments.env_vars.is_empty() {
+        let mut env_content = String::from("\n            env = {\n");
+        for (key, value) in &fragments.env_vars {
+            let comments = comments_for(format!("env.{key}"));
+            push_line(&mut env_content, "              ", &format!("{key} = {value};"), comments);
+        }
+        env_content.push_str("            };");
+        env_content
+    } else {
+        String::new()
+    };
+
+    let shell_hook_fragment = if !fragments.shell_hooks.is_empty() {
+        let mut hook_content = String::new();
+        for hook in &fragments.shell_hooks {
+            if hook.as_str() == "python-venv" {
+                hook_content.push_str(
+                    r#"
+            shellHook = ''
+              # Create virtual environment if it doesn't exist
+              if [ ! -d ".venv" ]; then
+                python -m venv .venv
+              fi
+              
+              # Activate virtual environment
+              source .venv/bin/activate
+              
+              # Upgrade pip in virtual environment
+              pip install --upgrade pip
+            '';"#,
+                );
+            }
+        }
+        hook_content
+    } else {
+        String::new()
+    };
+
+    let input_names = fragments
+        .inputs
+        .keys()
+        .filter(|k| *k != "nixpkgs")
+        .map(|k| format!("\n      {k},"))
+        .collect::<String>();
+
+    // Generate let bindings fragment
+    let let_bindings_fragment = if !fragments.let_bindings.is_empty() {
+        let mut bindings_content = String::new();
+        for (name, value) in &fragments.let_bindings {
+            let comments = comments_for(format!("let.{name}"));
+            push_line(&mut bindings_content, "      ", &format!("{name} = {value};"), comments);
+        }
+        bindings_content
+    } else {
+        String::new()
+    };
+
+    let flake = format!(
+        r#"{{
+  description = "{}";
+
+  inputs = {{
+{}  }};
+
+  outputs =
+    {{
+      self,
+      nixpkgs,{}
+    }}:
+    let
+{}      forEachSupportedSystem =
+        f:
+        nixpkgs.lib.genAttrs supportedSystems (
+          system:
+          f {{
+            pkgs = import nixpkgs {{
+              inherit system;{}
+            }};
+          }}
+        );
+    in
+    {{
+{}
+      devShells = forEachSupportedSystem (
+        {{ pkgs }}:
+        {{
+          default = pkgs.mkShell {{
+            packages = with pkgs; [
+{}            ];{}{}
+          }};
+        }}
+      );
+    }};
+}}
+"#,
+        fragments.header,
+        inputs_fragment,
+        input_names,
+        let_bindings_fragment,
+        generate_pkgs_config(fragments),
+        overlays_fragment,
+        packages_fragment,
+        env_fragment,
+        shell_hook_fragment
+    );
+
+    Ok(flake)
+}
+
+/// Template comments for `attribute`, with a leading `from <template>
+/// <version>` line directly above it when annotating. A leading line stays
+/// in place on attributes opening a block or spanning several lines.
+fn annotated(
+    fragments: &FlakeFragments,
+    provenance: Option<&Provenance>,
+    attribute: &str,
+) -> Option<Comments> {
+    let mut comments = fragments.comments.get(attribute).cloned();
+    if let Some(origin) = provenance.and_then(|provenance| provenance.get(attribute)) {
+        let comments = comments.get_or_insert_with(Comments::default);
+        comments.leading.push(format!("from {} {}", origin.template, origin.version));
+    }
+    comments
+}
+
+/// Append `line` at `indent`, preceded by its leading comments and followed
+/// by its trailing one.
+fn push_line(out: &mut String, indent: &str, line: &str, comments: Option<Comments>) {
+    let comment = |text: &str| if text.is_empty() { "#".to_string() } else { format!("# {text}") };
+
+    if let Some(comments) = &comments {
+        for leading in &comments.leading {
+            out.push_str(&format!("{indent}{}\n", comment(leading)));
+        }
+    }
+    out.push_str(indent);
+    out.push_str(line);
+    if let Some(trailing) = comments.and_then(|c| c.trailing) {
+        out.push(' ');
+        out.push_str(&comment(&trailing));
+    }
+    out.push('\n');
+}
+
+
+/// Replace the comments of `attribute` with those of the template that
+/// provided its value.
+fn attach_comments(
+    comments: &mut BTreeMap<String, Comments>,
+    attribute: String,
+    source: Option<&Comments>,
+) {
+    match source {
+        Some(source) => comments.insert(attribute, source.clone()),
+        None => comments.remove(&attribute),
+    };
+}
+
+fn merge_overlay_bindings(
+    origin: &Origin,
+    overlay: &str,
+    existing: &mut Vec<Binding>,
+    new_bindings: Vec<Binding>,
+    source_comments: &HashMap<String, Comments>,
+    comments: &mut BTreeMap<String, Comments>,
+    conflicts: &mut Conflicts,
+) -> Result<()> {
+    for binding in new_bindings {
+        let path_str = format_attr_path(&binding.path);
+        let position = existing
+            .iter()
+            .position(|b| format_attr_path(&b.path) == path_str);
+        let differs = position.is_some_and(|i| existing[i].value != binding.value);
+        let attribute = format!("overlays.{overlay}.{path_str}");
+
+        if conflicts.take(&attribute, origin, position.is_some(), differs)? {
+            trace!(binding = %path_str, "overlay binding");
+            attach_comments(comments, attribute, source_comments.get(&path_str));
+            match position {
+                Some(i) => existing[i] = binding,
+                None => existing.push(binding),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_attr_path(path: &AttrPath) -> String {
+    path.parts.iter()
+        .map(|part| match part {
+            AttrPathPart::Identifier(id) => id.clone(),
+            AttrPathPart::String(s) => format!("\"{s}\""),
+            AttrPathPart::Interpolation(expr) => format!("${{{}}}", expr.to_nix_string()),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+
+fn generate_pkgs_config(fragments: &FlakeFragments) -> String {
+    // Generate overlay references from inputs dynamically
+    let overlay_refs: Vec<String> = fragments.inputs.keys()
+        .filter(|key| key.contains("overlay"))
+        .map(|key| format!("\n                {key}.overlays.default"))
+        .collect();
+    
+    let overlay_refs_str = overlay_refs.join("");
+
+    let config: String = fragments
+        .nixpkgs_config
+        .to_nix_bindings()
+        .iter()
+        .map(|binding| format!("\n              {binding}"))
+        .collect();
+
+    if !fragments.overlays.is_empty() {
+        format!(
+            "{config}
+              overlays = [{overlay_refs_str}
+                self.overlays.default
+              ];")
+    } else {
+        config
+    }}
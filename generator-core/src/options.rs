@@ -0,0 +1,1191 @@
+use crate::{delta, toolchains};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+pub const DEFAULT_NIXPKGS_URL: &str = "github:NixOS/nixpkgs/nixos-unstable";
+
+/// Settings that adjust rendered flakes independently of the templates
+/// selected. They come from config files and are overridden by CLI flags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GenerationOptions {
+    pub nixpkgs_ref: Option<String>,
+    pub systems: Option<Vec<String>>,
+    pub substituters: Vec<String>,
+    pub trusted_public_keys: Vec<String>,
+    #[serde(skip_serializing_if = "ConflictStrategy::is_default")]
+    pub on_conflict: ConflictStrategy,
+    /// Mark each merged attribute with a comment naming its template
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub provenance_comments: bool,
+    /// Text injected at the `# @<name>` anchors of the flake, keyed by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub snippets: BTreeMap<String, String>,
+    /// Leave out the templates' extra packages, keeping the core toolchain
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub minimal: bool,
+    /// Build shells with `mkShellNoCC`, leaving out the C compiler
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_cc: bool,
+    /// Print the languages and their toolchain versions on shell entry
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub banner: bool,
+    /// Enable flakes in the flake's nixConfig and have the shell hook explain
+    /// how to enable them for good when nix.conf doesn't
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub flakes_hint: bool,
+    /// Let bindings given a new value, such as `goVersion = "22"`, written
+    /// as Nix expressions
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Environment variables set in the devShell
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Flake outputs such as `.#packages.default` whose build inputs the
+    /// devShell inherits through `inputsFrom`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs_from: Vec<String>,
+    /// Optional package groups of the templates to add, such as `tools`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Build shells with this stdenv and add its debugging tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdenv: Option<Stdenv>,
+    /// The TeX Live set put in place of `texlive.combined.scheme-full`
+    #[serde(skip_serializing_if = "Latex::is_default")]
+    pub latex: Latex,
+    /// Providers built into terraform with `withPlugins`, by registry
+    /// address such as `hashicorp/aws`, so `terraform init` needs no network
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub terraform_providers: Vec<String>,
+    /// Erlang/OTP and Elixir releases in place of those the BEAM templates pin
+    #[serde(skip_serializing_if = "Beam::is_default")]
+    pub beam: Beam,
+    /// PHP release and extensions of the php template's interpreter
+    #[serde(skip_serializing_if = "Php::is_default")]
+    pub php: Php,
+    /// Ruby release, such as `3.3`, in place of the one the ruby template pins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ruby: Option<String>,
+    /// Major releases of the .NET SDKs the csharp template combines, such as 8
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dotnet_sdks: Vec<u32>,
+    /// JDK and build tool of the java, kotlin and scala templates
+    #[serde(skip_serializing_if = "Java::is_default")]
+    pub java: Java,
+    /// OCaml release, such as `5.2`, whose `ocaml-ng` package set replaces
+    /// `ocamlPackages`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ocaml: Option<String>,
+    /// Zig from the zig-overlay flake, `master` or a release such as `0.13.0`,
+    /// in place of nixpkgs' zig
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zig: Option<String>,
+    /// V built from this git ref of its repository, such as `master`, in
+    /// place of nixpkgs' release
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vlang: Option<String>,
+    /// GHC release, such as `9.8`, in place of nixpkgs' default one, with the
+    /// haskell-language-server and cabal-install of its `haskell.packages` set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghc: Option<String>,
+    /// Bare-metal target to add a cross toolchain and probe tools for,
+    /// through the templates' `embedded-<target>` groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<Embedded>,
+    /// Add a `dev-image` package with the default devShell as a container
+    /// image, for contributors without nix
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub container: bool,
+}
+
+impl GenerationOptions {
+    /// The groups to add: those asked for by name, followed by the group of
+    /// the embedded target.
+    pub fn selected_groups(&self) -> Vec<String> {
+        let mut groups = self.groups.clone();
+        if let Some(embedded) = self.embedded {
+            let group = embedded.group();
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+}
+
+/// What the merge engine does when two templates give the same input,
+/// overlay attribute or environment variable different values.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Keep the value from the template listed first, with a warning
+    #[default]
+    PreferFirst,
+    /// Use the value from the template listed last, with a warning
+    PreferLast,
+    /// Fail, naming both templates and the attribute
+    Error,
+}
+
+impl ConflictStrategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConflictStrategy::PreferFirst => "prefer-first",
+            ConflictStrategy::PreferLast => "prefer-last",
+            ConflictStrategy::Error => "error",
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The compiler toolchain devShells are built with.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stdenv {
+    /// `clangStdenv`, with clang-tools and lldb
+    Clang,
+    /// `gccStdenv`, with gdb
+    Gcc,
+}
+
+impl Stdenv {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stdenv::Clang => "clang",
+            Stdenv::Gcc => "gcc",
+        }
+    }
+
+    /// The nixpkgs attribute of the stdenv.
+    pub fn attribute(self) -> &'static str {
+        match self {
+            Stdenv::Clang => "clangStdenv",
+            Stdenv::Gcc => "gccStdenv",
+        }
+    }
+
+    /// Tools that go with the compiler.
+    pub fn tools(self) -> &'static [&'static str] {
+        match self {
+            Stdenv::Clang => &["clang-tools", "lldb"],
+            Stdenv::Gcc => &["gdb"],
+        }
+    }
+}
+
+impl fmt::Display for Stdenv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The TeX Live set of flakes with LaTeX, combined from a scheme and the
+/// packages optional features need.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Latex {
+    pub scheme: LatexScheme,
+    /// Add biber and biblatex for bibliographies
+    pub biber: bool,
+    /// Add minted, with pygments to run it, for highlighted code listings
+    pub pygments: bool,
+}
+
+impl Latex {
+    pub fn is_default(&self) -> bool {
+        *self == Latex::default()
+    }
+
+    /// The packages list entry of the set.
+    pub(crate) fn package(&self) -> String {
+        let mut packages = vec![self.scheme.attribute()];
+        if self.biber {
+            packages.extend(["biber", "biblatex"]);
+        }
+        if self.pygments {
+            packages.push("minted");
+        }
+        format!("(texlive.combine {{ inherit (texlive) {}; }})", packages.join(" "))
+    }
+}
+
+/// A TeX Live scheme, from the bare TeX engine to every package.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LatexScheme {
+    Minimal,
+    Small,
+    #[default]
+    Full,
+}
+
+impl LatexScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            LatexScheme::Minimal => "minimal",
+            LatexScheme::Small => "small",
+            LatexScheme::Full => "full",
+        }
+    }
+
+    /// The texlive attribute of the scheme.
+    fn attribute(self) -> &'static str {
+        match self {
+            LatexScheme::Minimal => "scheme-minimal",
+            LatexScheme::Small => "scheme-small",
+            LatexScheme::Full => "scheme-full",
+        }
+    }
+}
+
+impl fmt::Display for LatexScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A bare-metal target of the c-cpp and rust templates.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Embedded {
+    /// ARM Cortex-M: arm-none-eabi GCC with newlib, or Rust's
+    /// `thumbv7em-none-eabihf`
+    ArmNoneEabi,
+    /// RISC-V microcontrollers: riscv32-none-elf GCC with newlib, or Rust's
+    /// `riscv32imac-unknown-none-elf`
+    Riscv32,
+}
+
+impl Embedded {
+    pub fn name(self) -> &'static str {
+        match self {
+            Embedded::ArmNoneEabi => "arm-none-eabi",
+            Embedded::Riscv32 => "riscv32",
+        }
+    }
+
+    /// The template group with the target's toolchain and probe tools.
+    pub fn group(self) -> String {
+        format!("embedded-{}", self.name())
+    }
+
+    /// The Rust target added to the rust template's toolchain.
+    fn rust_target(self) -> &'static str {
+        match self {
+            Embedded::ArmNoneEabi => "thumbv7em-none-eabihf",
+            Embedded::Riscv32 => "riscv32imac-unknown-none-elf",
+        }
+    }
+}
+
+impl fmt::Display for Embedded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The JDK the JVM templates' overlays put in place as `jdk`, which their
+/// build tools run on, and the build tool they list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Java {
+    /// JDK feature release, such as 21; the templates' `javaVersion` when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    pub dist: JdkDist,
+    /// The one build tool to list, in place of those the templates list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_tool: Option<BuildTool>,
+}
+
+impl Java {
+    pub fn is_default(&self) -> bool {
+        *self == Java::default()
+    }
+}
+
+/// A distribution of the JDK in nixpkgs.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JdkDist {
+    /// nixpkgs' own OpenJDK builds, `jdk<version>`
+    #[default]
+    Openjdk,
+    /// Eclipse Temurin binaries, `temurin-bin-<version>`
+    Temurin,
+    /// Azul Zulu, `zulu<version>`
+    Zulu,
+    /// GraalVM Community Edition; nixpkgs only has its latest release
+    Graalvm,
+}
+
+impl JdkDist {
+    pub fn name(self) -> &'static str {
+        match self {
+            JdkDist::Openjdk => "openjdk",
+            JdkDist::Temurin => "temurin",
+            JdkDist::Zulu => "zulu",
+            JdkDist::Graalvm => "graalvm",
+        }
+    }
+
+    /// The overlay's expression for the JDK of release `javaVersion`.
+    pub(crate) fn expression(self) -> &'static str {
+        match self {
+            JdkDist::Openjdk => "prev.\"jdk${toString javaVersion}\"",
+            JdkDist::Temurin => "prev.\"temurin-bin-${toString javaVersion}\"",
+            JdkDist::Zulu => "prev.\"zulu${toString javaVersion}\"",
+            JdkDist::Graalvm => "prev.graalvmPackages.graalvm-ce",
+        }
+    }
+}
+
+impl fmt::Display for JdkDist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A JVM build tool, listed as the nixpkgs package of the same name.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildTool {
+    Maven,
+    Gradle,
+    Sbt,
+    Mill,
+}
+
+impl BuildTool {
+    pub const ALL: [BuildTool; 4] =
+        [BuildTool::Maven, BuildTool::Gradle, BuildTool::Sbt, BuildTool::Mill];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildTool::Maven => "maven",
+            BuildTool::Gradle => "gradle",
+            BuildTool::Sbt => "sbt",
+            BuildTool::Mill => "mill",
+        }
+    }
+
+    /// The overlay's binding of the tool, built against the overlay's `jdk`.
+    pub(crate) fn overlay_value(self) -> &'static str {
+        match self {
+            BuildTool::Maven => "prev.maven.override { jdk_headless = jdk; }",
+            BuildTool::Gradle => "prev.gradle.override { java = jdk; }",
+            BuildTool::Sbt => "prev.sbt.override { jre = jdk; }",
+            BuildTool::Mill => "prev.mill.override { jre = jdk; }",
+        }
+    }
+}
+
+impl fmt::Display for BuildTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Releases of the BEAM toolchain, taken from nixpkgs' `beam.packages` sets.
+/// Unset ones keep what the templates pin.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Beam {
+    /// Erlang/OTP major release, such as 27
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otp: Option<u32>,
+    /// Elixir release, such as `1.17`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elixir: Option<String>,
+}
+
+impl Beam {
+    pub fn is_default(&self) -> bool {
+        *self == Beam::default()
+    }
+}
+
+/// The attribute of an Elixir release in a `beam.packages` set: `1.17` is
+/// `elixir_1_17`.
+pub fn elixir_attribute(version: &str) -> Result<String, String> {
+    minor_release(version)
+        .map(|release| format!("elixir_{release}"))
+        .ok_or_else(|| format!("invalid Elixir version '{version}' (expected one such as 1.17)"))
+}
+
+/// The nixpkgs attribute of a Ruby release: `3.3` is `ruby_3_3`.
+pub fn ruby_attribute(version: &str) -> Result<String, String> {
+    minor_release(version)
+        .map(|release| format!("ruby_{release}"))
+        .ok_or_else(|| format!("invalid Ruby version '{version}' (expected one such as 3.3)"))
+}
+
+/// The `ocaml-ng` package set of an OCaml release: `5.2` is
+/// `ocamlPackages_5_2`.
+pub fn ocaml_attribute(version: &str) -> Result<String, String> {
+    minor_release(version)
+        .map(|release| format!("ocamlPackages_{release}"))
+        .ok_or_else(|| format!("invalid OCaml version '{version}' (expected one such as 5.2)"))
+}
+
+/// Check a toolchain version or git ref given on the command line, such as
+/// `master`, `0.13.0` or `weekly.2024.40`, before it goes into a flake URL
+/// or attribute name.
+pub fn toolchain_ref(reference: &str) -> Result<(), String> {
+    let valid = reference.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && reference.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid version '{reference}' (expected master or one such as 0.13.0)"))
+    }
+}
+
+/// The nixpkgs name of a GHC release: `9.8` is `ghc98`.
+pub fn ghc_attribute(version: &str) -> Result<String, String> {
+    minor_release(version)
+        .map(|release| format!("ghc{}", release.replace('_', "")))
+        .ok_or_else(|| format!("invalid GHC version '{version}' (expected one such as 9.8)"))
+}
+
+/// A `major.minor` release as attribute names spell it, `1_17`.
+fn minor_release(version: &str) -> Option<String> {
+    let parts: Vec<&str> = version.split('.').collect();
+    let numeric = |part: &&str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    (parts.len() == 2 && parts.iter().all(numeric)).then(|| parts.join("_"))
+}
+
+/// The PHP interpreter, built with `withExtensions`; composer runs on it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Php {
+    /// Release such as `8.3`; nixpkgs' default PHP when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Extensions enabled on top of the default ones, such as `intl`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+}
+
+impl Php {
+    pub fn is_default(&self) -> bool {
+        *self == Php::default()
+    }
+}
+
+/// The nixpkgs attribute of a PHP release: `8.3` is `php83`.
+pub fn php_attribute(version: &str) -> Result<String, String> {
+    match version.split_once('.') {
+        Some((major, minor))
+            if [major, minor]
+                .iter()
+                .all(|part| part.len() == 1 && part.chars().all(|c| c.is_ascii_digit())) =>
+        {
+            Ok(format!("php{major}{minor}"))
+        }
+        _ => Err(format!("invalid PHP version '{version}' (expected one such as 8.3)")),
+    }
+}
+
+/// Check the name of a PHP extension, which is its attribute in
+/// `php.extensions`, such as `pdo_pgsql`.
+pub fn php_extension(name: &str) -> Result<(), String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid PHP extension '{name}' (expected one such as pdo_pgsql)"))
+    }
+}
+
+/// Major releases of the .NET SDK in nixpkgs' `dotnetCorePackages`.
+const DOTNET_SDKS: [u32; 5] = [6, 7, 8, 9, 10];
+
+/// The `dotnetCorePackages` attribute of a .NET SDK major release: `8` is
+/// `sdk_8_0`.
+pub fn dotnet_sdk_attribute(major: u32) -> Result<String, String> {
+    if DOTNET_SDKS.contains(&major) {
+        Ok(format!("sdk_{major}_0"))
+    } else {
+        let majors: Vec<String> = DOTNET_SDKS.iter().map(u32::to_string).collect();
+        Err(format!("unsupported .NET SDK '{major}' (expected one of {})", majors.join(", ")))
+    }
+}
+
+/// The attribute of a provider in nixpkgs' terraform-providers, from its
+/// registry address: `hashicorp/aws`, or just `aws`, is `hashicorp_aws`.
+pub fn terraform_provider(address: &str) -> Result<String, String> {
+    let (namespace, name) = address.split_once('/').unwrap_or(("hashicorp", address));
+    let valid = |part: &str| {
+        part.starts_with(|c: char| c.is_ascii_lowercase())
+            && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    };
+    if !valid(namespace) || !valid(name) {
+        return Err(format!(
+            "invalid provider '{address}' (expected a registry address such as hashicorp/aws)"
+        ));
+    }
+    Ok(format!("{namespace}_{name}"))
+}
+
+/// Flake URL for a nixpkgs reference. Bare branch names and revisions are
+/// resolved against the NixOS/nixpkgs GitHub repository.
+pub fn nixpkgs_url(reference: &str) -> String {
+    if reference.contains(':') {
+        reference.to_string()
+    } else {
+        format!("github:NixOS/nixpkgs/{reference}")
+    }
+}
+
+pub fn apply(content: &str, options: &GenerationOptions) -> String {
+    let mut content = insert_snippets(content, &options.snippets);
+
+    for (name, value) in &options.variables {
+        match delta::replace_assignment(&content, name, value) {
+            Some(replaced) => content = replaced,
+            None => tracing::warn!("The flake does not bind {name}; it was not set to {value}"),
+        }
+    }
+
+    if !options.env.is_empty() {
+        match delta::add_env(&content, &options.env) {
+            Some(added) => content = added,
+            None => tracing::warn!("No devShell packages list; environment variables not set"),
+        }
+    }
+
+    if !options.inputs_from.is_empty() {
+        content = add_inputs_from(&content, &options.inputs_from);
+    }
+
+    if let Some(reference) = &options.nixpkgs_ref {
+        content = content.replace(
+            &format!("\"{DEFAULT_NIXPKGS_URL}\""),
+            &format!("\"{}\"", nixpkgs_url(reference)),
+        );
+    }
+
+    if let Some(systems) = &options.systems {
+        content = replace_supported_systems(&content, systems);
+    }
+
+    if !options.substituters.is_empty()
+        || !options.trusted_public_keys.is_empty()
+        || options.flakes_hint
+    {
+        content = insert_nix_config(&content, options);
+    }
+
+    if options.flakes_hint {
+        match delta::add_shell_hook(&content, &flakes_check()) {
+            Some(added) => content = added,
+            None => tracing::warn!("No devShell packages list; the flakes check was not added"),
+        }
+    }
+
+    if !options.latex.is_default() {
+        content = toolchains::use_latex(&content, &options.latex);
+    }
+
+    if !options.terraform_providers.is_empty() {
+        content = toolchains::with_terraform_providers(&content, &options.terraform_providers);
+    }
+
+    if !options.beam.is_default() {
+        content = toolchains::use_beam(&content, &options.beam);
+    }
+
+    if !options.php.is_default() {
+        content = toolchains::use_php(&content, &options.php);
+    }
+
+    if !options.java.is_default() {
+        content = toolchains::use_java(&content, &options.java);
+    }
+
+    if let Some(version) = &options.ocaml {
+        content = toolchains::use_ocaml(&content, version);
+    }
+
+    if let Some(version) = &options.ghc {
+        content = toolchains::use_ghc(&content, version);
+    }
+
+    if let Some(embedded) = options.embedded {
+        content = toolchains::add_rust_target(&content, embedded.rust_target());
+    }
+
+    if let Some(version) = &options.zig {
+        content = toolchains::use_zig(&content, version);
+    }
+
+    if let Some(reference) = &options.vlang {
+        content = toolchains::use_vlang(&content, reference);
+    }
+
+    if !options.dotnet_sdks.is_empty() {
+        content = toolchains::combine_dotnet_sdks(&content, &options.dotnet_sdks);
+    }
+
+    if let Some(version) = &options.ruby {
+        content = toolchains::use_ruby(&content, version);
+    }
+
+    if options.container {
+        match delta::add_dev_image(&content) {
+            Some(added) => content = added,
+            None => tracing::warn!(
+                "No devShells output, or packages outputs already; the dev-image was not added"
+            ),
+        }
+    }
+
+    if options.no_cc {
+        content = content.replace("pkgs.mkShell {", "pkgs.mkShellNoCC {");
+    } else if let Some(stdenv) = options.stdenv {
+        content = use_stdenv(&content, stdenv);
+    }
+
+    content
+}
+
+/// Replace each `# @<name>` anchor line with the snippet of that name,
+/// indented like the anchor, and drop anchors without one. `packages`
+/// snippets (one package per line) and `shellHook` snippets (shell commands)
+/// go to the devShell's packages list and shell hook when the flake has no
+/// anchor for them.
+fn insert_snippets(content: &str, snippets: &BTreeMap<String, String>) -> String {
+    let mut placed = BTreeSet::new();
+    let mut result = String::with_capacity(content.len());
+
+    for line in content.split_inclusive('\n') {
+        let Some(name) = anchor_name(line) else {
+            result.push_str(line);
+            continue;
+        };
+        if let Some(snippet) = snippets.get(name) {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for snippet_line in snippet.lines() {
+                if !snippet_line.is_empty() {
+                    result.push_str(indent);
+                }
+                result.push_str(snippet_line);
+                result.push('\n');
+            }
+            placed.insert(name);
+        }
+    }
+
+    for (name, snippet) in snippets {
+        if placed.contains(name.as_str()) {
+            continue;
+        }
+        let lines: Vec<String> = snippet.lines().map(String::from).collect();
+        let inserted = match name.as_str() {
+            "packages" => delta::add_packages(&result, &lines),
+            "shellHook" => delta::add_shell_hook(&result, &lines),
+            _ => None,
+        };
+        match inserted {
+            Some(inserted) => result = inserted,
+            None => tracing::warn!("No '# @{name}' anchor in the flake; snippet '{name}' was not inserted"),
+        }
+    }
+
+    result
+}
+
+/// The name of an anchor line such as `    # @packages`.
+fn anchor_name(line: &str) -> Option<&str> {
+    let name = line.trim().strip_prefix("# @")?;
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    valid.then_some(name)
+}
+
+/// Build the devShell with `stdenv`, either by filling in the `stdenv`
+/// override the template suggests in a comment or by overriding `mkShell`,
+/// and add the stdenv's tools the flake does not list yet.
+fn use_stdenv(content: &str, stdenv: Stdenv) -> String {
+    let binding = format!("stdenv = pkgs.{};", stdenv.attribute());
+    let suggested = content
+        .split_inclusive('\n')
+        .find(|line| line.trim().starts_with("# stdenv = "));
+
+    let mut result = match suggested {
+        Some(line) => {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            content.replacen(line, &format!("{indent}{binding}\n"), 1)
+        }
+        None => content.replace(
+            "pkgs.mkShell {",
+            &format!("pkgs.mkShell.override {{ {binding} }} {{"),
+        ),
+    };
+
+    let missing: Vec<String> = stdenv
+        .tools()
+        .iter()
+        .filter(|tool| {
+            !result
+                .split(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_')))
+                .any(|word| word == **tool)
+        })
+        .map(|tool| tool.to_string())
+        .collect();
+    if !missing.is_empty() {
+        match delta::add_packages(&result, &missing) {
+            Some(added) => result = added,
+            None => tracing::warn!("No devShell packages list; {} not added", missing.join(", ")),
+        }
+    }
+
+    result
+}
+
+fn nix_string_list(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|item| format!("\"{item}\"")).collect();
+    format!("[ {} ]", quoted.join(" "))
+}
+
+fn replace_supported_systems(content: &str, systems: &[String]) -> String {
+    let Some(binding) = content.find("supportedSystems = [") else {
+        return content.to_string();
+    };
+    let start = binding + "supportedSystems = ".len();
+    let Some(len) = content[start..].find(']') else {
+        return content.to_string();
+    };
+
+    format!(
+        "{}{}{}",
+        &content[..start],
+        nix_string_list(systems),
+        &content[start + len + 1..]
+    )
+}
+
+/// Compose the devShell with `inputsFrom` out of flake output references
+/// such as `.#packages.default` or `github:org/app#packages.default`. Other
+/// flakes become inputs, reached through `self.inputs`.
+fn add_inputs_from(content: &str, references: &[String]) -> String {
+    let mut content = content.to_string();
+    let mut derivations = Vec::new();
+    for reference in references {
+        let (flake, attribute) = reference
+            .split_once('#')
+            .unwrap_or((reference.as_str(), "packages.default"));
+        let base = match flake {
+            "" | "." | "self" => "self".to_string(),
+            url => {
+                let name = input_name(url);
+                if !content.contains(&format!("{name}.url = ")) {
+                    match delta::add_input(&content, &name, url) {
+                        Some(added) => content = added,
+                        None => {
+                            tracing::warn!(
+                                "No nixpkgs input to add {url} next to; {reference} was not added"
+                            );
+                            continue;
+                        }
+                    }
+                }
+                format!("self.inputs.{name}")
+            }
+        };
+        derivations.push(format!("{base}.{}", per_system(attribute)));
+    }
+
+    if derivations.is_empty() {
+        return content;
+    }
+    match delta::add_inputs_from(&content, &derivations) {
+        Some(added) => added,
+        None => {
+            tracing::warn!("No devShell packages list; inputsFrom was not added");
+            content
+        }
+    }
+}
+
+/// `packages.default` as it is reached from the devShell:
+/// `packages.${pkgs.system}.default`. Longer paths are used as given.
+fn per_system(attribute: &str) -> String {
+    const PER_SYSTEM: [&str; 4] = ["packages", "devShells", "checks", "legacyPackages"];
+    match attribute.split_once('.') {
+        Some((output, name)) if PER_SYSTEM.contains(&output) && !name.contains('.') => {
+            format!("{output}.${{pkgs.system}}.{name}")
+        }
+        _ => attribute.to_string(),
+    }
+}
+
+/// The input name for a flake URL: the repository of `github:org/app/ref`
+/// style shorthands, else the last path segment without `.git`.
+fn input_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url).trim_end_matches('/');
+    let segment = match ["github:", "gitlab:", "sourcehut:"]
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix))
+    {
+        Some(shorthand) => shorthand.split('/').nth(1).unwrap_or(shorthand),
+        None => path.rsplit(['/', ':']).next().unwrap_or(path),
+    };
+    let name: String = segment
+        .trim_end_matches(".git")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("input-{name}")
+    }
+}
+
+/// Shell hook lines telling newcomers who entered the shell with a one-off
+/// `--extra-experimental-features` how to enable flakes permanently.
+fn flakes_check() -> Vec<String> {
+    [
+        "if ! nix --extra-experimental-features nix-command config show experimental-features \\",
+        "  2>/dev/null | grep -qw flakes; then",
+        "  echo \"Flakes are not enabled in your nix configuration. To enable them, add\"",
+        "  echo \"  experimental-features = nix-command flakes\"",
+        "  echo \"to ~/.config/nix/nix.conf (or /etc/nix/nix.conf) and restart the nix daemon.\"",
+        "fi",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Set the `extra-*` settings in the flake's `nixConfig`: merged into the
+/// template's own when it has one, otherwise in a top-level attribute
+/// inserted directly after the description.
+fn insert_nix_config(content: &str, options: &GenerationOptions) -> String {
+    let mut settings = Vec::new();
+    if !options.substituters.is_empty() {
+        settings.push(("extra-substituters", options.substituters.clone()));
+    }
+    if !options.trusted_public_keys.is_empty() {
+        settings.push(("extra-trusted-public-keys", options.trusted_public_keys.clone()));
+    }
+    if options.flakes_hint {
+        settings.push((
+            "extra-experimental-features",
+            vec!["nix-command".to_string(), "flakes".to_string()],
+        ));
+    }
+
+    if delta::has_nix_config(content) {
+        let mut content = content.to_string();
+        for (name, values) in &settings {
+            match delta::add_nix_config(&content, name, values) {
+                Some(added) => content = added,
+                None => {
+                    tracing::warn!("The template's nixConfig {name} is not a list; left as is")
+                }
+            }
+        }
+        return content;
+    }
+
+    let Some(description) = content.find("description = ") else {
+        return content.to_string();
+    };
+    let Some(line_end) = content[description..].find('\n') else {
+        return content.to_string();
+    };
+    let insert_at = description + line_end + 1;
+
+    let mut block = String::from("\n  nixConfig = {\n");
+    for (name, values) in &settings {
+        block.push_str(&format!("    {name} = {};\n", nix_string_list(values)));
+    }
+    block.push_str("  };\n");
+
+    format!("{}{}{}", &content[..insert_at], block, &content[insert_at..])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const FLAKE: &str = r#"{
+  description = "Test";
+
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+
+  outputs =
+    { self, nixpkgs }:
+    let
+      supportedSystems = [
+        "x86_64-linux"
+        "aarch64-darwin"
+      ];
+    in
+    { };
+}
+"#;
+
+    #[test]
+    fn test_apply_nixpkgs_ref() {
+        let options = GenerationOptions {
+            nixpkgs_ref: Some("nixos-24.05".to_string()),
+            ..Default::default()
+        };
+        let result = apply(FLAKE, &options);
+        assert!(result.contains("\"github:NixOS/nixpkgs/nixos-24.05\""));
+        assert!(!result.contains("nixos-unstable"));
+    }
+
+    #[test]
+    fn test_apply_systems() {
+        let options = GenerationOptions {
+            systems: Some(vec!["x86_64-linux".to_string()]),
+            ..Default::default()
+        };
+        let result = apply(FLAKE, &options);
+        assert!(result.contains("supportedSystems = [ \"x86_64-linux\" ];"));
+        assert!(!result.contains("aarch64-darwin"));
+    }
+
+    #[test]
+    fn test_apply_substituters() {
+        let options = GenerationOptions {
+            substituters: vec!["https://cache.example.com".to_string()],
+            ..Default::default()
+        };
+        let result = apply(FLAKE, &options);
+        assert!(result.contains("extra-substituters = [ \"https://cache.example.com\" ];"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_apply_substituters_merges_nix_config() {
+        let flake = FLAKE.replace(
+            "  inputs.nixpkgs",
+            "  nixConfig = {\n    extra-substituters = [\n      \"https://cache.example.com\"\n    ];\n  };\n\n  inputs.nixpkgs",
+        );
+        let options = GenerationOptions {
+            substituters: vec![
+                "https://cache.example.com".to_string(),
+                "https://corp.cachix.org".to_string(),
+            ],
+            trusted_public_keys: vec!["corp.cachix.org-1:abc=".to_string()],
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert_eq!(result.matches("nixConfig").count(), 1);
+        assert!(result.contains(
+            "    extra-substituters = [\n      \"https://cache.example.com\"\n      \"https://corp.cachix.org\"\n    ];\n"
+        ));
+        assert!(result.contains("    extra-trusted-public-keys = [ \"corp.cachix.org-1:abc=\" ];\n  };"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_apply_snippets() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = [\n          hello\n        ];\n        # @corp\n      };\n    };",
+        );
+        let options = GenerationOptions {
+            snippets: BTreeMap::from([
+                ("corp".to_string(), "env.HTTPS_PROXY = \"http://proxy:3128\";".to_string()),
+                ("packages".to_string(), "corp-vpn".to_string()),
+                ("shellHook".to_string(), "corp-login".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("          hello\n          corp-vpn\n        ];"));
+        assert!(result.contains("        shellHook = ''\n          corp-login\n        '';"));
+        assert!(result.contains("        env.HTTPS_PROXY = \"http://proxy:3128\";\n"));
+        assert!(!result.contains("# @corp"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_apply_variables() {
+        let flake = FLAKE.replace("    let\n", "    let\n      goVersion = 24; # Change this\n");
+        let options = GenerationOptions {
+            variables: BTreeMap::from([("goVersion".to_string(), "22".to_string())]),
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("      goVersion = 22; # Change this\n"));
+    }
+
+    #[test]
+    fn test_apply_stdenv() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = [\n          gdb\n        ];\n      };\n    };",
+        );
+
+        let options = GenerationOptions {
+            stdenv: Some(Stdenv::Gcc),
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("pkgs.mkShell.override { stdenv = pkgs.gccStdenv; } {"));
+        assert_eq!(result.matches("gdb").count(), 1);
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+
+        let options = GenerationOptions {
+            stdenv: Some(Stdenv::Clang),
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("stdenv = pkgs.clangStdenv;"));
+        assert!(result.contains("          gdb\n          clang-tools\n          lldb\n        ];"));
+    }
+
+    #[test]
+    fn test_dotnet_sdk_attribute() {
+        assert!(dotnet_sdk_attribute(8).is_ok_and(|attribute| attribute == "sdk_8_0"));
+        assert!(dotnet_sdk_attribute(42).is_err());
+        assert!(dotnet_sdk_attribute(5).is_err());
+    }
+
+    #[test]
+    fn test_toolchain_attributes() {
+        assert!(elixir_attribute("1.17.2").is_err());
+        assert!(php_attribute("8").is_err());
+        assert!(php_extension("pdo-pgsql").is_err());
+        assert!(ruby_attribute("3").is_err());
+        assert!(ghc_attribute("9.10").is_ok_and(|attribute| attribute == "ghc910"));
+        assert!(toolchain_ref("../evil").is_err());
+    }
+
+    #[test]
+    fn test_embedded_selects_group() {
+        let options = GenerationOptions {
+            groups: vec!["tools".to_string()],
+            embedded: Some(Embedded::Riscv32),
+            ..Default::default()
+        };
+        assert_eq!(options.selected_groups(), vec!["tools", "embedded-riscv32"]);
+    }
+
+    #[test]
+    fn test_apply_container() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells = forEachSupportedSystem (\n        { pkgs }:\n        {\n          default = pkgs.mkShell { };\n        }\n      );\n    };",
+        );
+
+        let options = GenerationOptions {
+            container: true,
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("      );\n\n      packages = forEachSupportedSystem (\n"));
+        assert!(result.contains("drv = self.devShells.${pkgs.system}.default;"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+        assert_eq!(apply(&result, &options), result);
+    }
+
+    #[test]
+    fn test_terraform_provider() {
+        assert_eq!(terraform_provider("google-beta"), Ok("hashicorp_google-beta".to_string()));
+        assert!(terraform_provider("Hashicorp/aws").is_err());
+        assert!(terraform_provider("a/b/c").is_err());
+    }
+
+    #[test]
+    fn test_apply_flakes_hint() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = [\n          go\n        ];\n      };\n    };",
+        );
+        let options = GenerationOptions {
+            flakes_hint: true,
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("extra-experimental-features = [ \"nix-command\" \"flakes\" ];"));
+        assert!(result.contains("grep -qw flakes"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_apply_flakes_hint_merges_nix_config() {
+        let flake = FLAKE.replace(
+            "  inputs.nixpkgs",
+            "  nixConfig.extra-experimental-features = \"nix-command\";\n\n  inputs.nixpkgs",
+        );
+        let options = GenerationOptions {
+            flakes_hint: true,
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert_eq!(result.matches("nixConfig").count(), 1);
+        assert!(result.contains("  nixConfig.extra-experimental-features = \"nix-command flakes\";\n"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+
+        let options = GenerationOptions {
+            substituters: vec!["https://corp.cachix.org".to_string()],
+            ..options
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains(
+            "  nixConfig.extra-experimental-features = \"nix-command flakes\";\n  nixConfig.extra-substituters = [ \"https://corp.cachix.org\" ];\n"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_apply_inputs_from() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = [\n          go\n        ];\n      };\n    };",
+        );
+        let options = GenerationOptions {
+            inputs_from: vec![
+                ".#packages.default".to_string(),
+                "github:org/backend#packages.server".to_string(),
+            ],
+            ..Default::default()
+        };
+        let result = apply(&flake, &options);
+        assert!(result.contains("  inputs.backend.url = \"github:org/backend\";\n"));
+        assert!(result.contains(
+            "inputsFrom = [ self.packages.${pkgs.system}.default self.inputs.backend.packages.${pkgs.system}.server ];"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_input_name() {
+        assert_eq!(input_name("github:org/app/main"), "app");
+        assert_eq!(input_name("git+https://example.com/team/my.app.git?ref=main"), "my-app");
+        assert_eq!(input_name("path:../2d"), "input-2d");
+    }
+
+    #[test]
+    fn test_apply_default_is_identity() {
+        assert_eq!(apply(FLAKE, &GenerationOptions::default()), FLAKE);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::embedded_templates;
+#[cfg(feature = "fs")]
+use crate::embedded_templates::TemplateMetadata;
+use crate::error::Result;
+#[cfg(feature = "fs")]
+use crate::error::TemplateError;
+use crate::registry::Template;
+#[cfg(feature = "fs")]
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+
+/// A source of templates beyond the embedded ones. Implement this to serve
+/// templates from somewhere other than a directory, e.g. a company-internal
+/// index, and add it with [`Registry::add_provider`](crate::Registry::add_provider).
+pub trait TemplateProvider {
+    /// Short name used in diagnostics.
+    fn name(&self) -> &str;
+
+    /// Every template this provider offers. Called once per registration.
+    fn templates(&self) -> Result<Vec<Template>>;
+}
+
+/// The templates compiled into the crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbeddedProvider;
+
+impl EmbeddedProvider {
+    /// The metadata TOML of an embedded template, for copying it next to a
+    /// replacement `.nix` file in a template directory.
+    pub fn metadata(&self, template_name: &str) -> Option<String> {
+        embedded_templates::metadata_source(template_name)
+    }
+}
+
+impl TemplateProvider for EmbeddedProvider {
+    fn name(&self) -> &str {
+        "embedded"
+    }
+
+    fn templates(&self) -> Result<Vec<Template>> {
+        embedded_templates::load()
+    }
+}
+
+/// `<name>.toml` + `<name>.nix` template pairs in a directory.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone)]
+pub struct DirectoryProvider {
+    dir: PathBuf,
+    name: String,
+}
+
+#[cfg(feature = "fs")]
+impl DirectoryProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let name = dir.display().to_string();
+        Self { dir, name }
+    }
+}
+
+#[cfg(feature = "fs")]
+impl TemplateProvider for DirectoryProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn templates(&self) -> Result<Vec<Template>> {
+        if !self.dir.is_dir() {
+            return Err(TemplateError::MissingDir(self.dir.clone()).into());
+        }
+
+        let mut templates = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let toml_path = entry?.path();
+            if toml_path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            let nix_path = toml_path.with_extension("nix");
+            let metadata: TemplateMetadata = toml::from_str(&fs::read_to_string(&toml_path)?)
+                .map_err(|source| TemplateError::InvalidMetadata {
+                    path: toml_path.clone(),
+                    source,
+                })?;
+            // Templates extending another take their flake from the base
+            let flake_content = match metadata.template.extends {
+                Some(_) => String::new(),
+                None if nix_path.exists() => fs::read_to_string(&nix_path)?,
+                None => continue,
+            };
+            let name = toml_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .ok_or_else(|| TemplateError::InvalidName(toml_path.clone()))?;
+
+            templates.push(Template {
+                name,
+                description: metadata.template.description,
+                flake_content,
+                additional_files: HashMap::new(),
+                extends: metadata.template.extends,
+                delta: metadata.delta,
+                extra_packages: metadata.template.extra_packages,
+                no_cc: metadata.template.no_cc,
+                darwin_packages: metadata.template.darwin_packages,
+                shell_hook: metadata.template.shell_hook,
+                groups: metadata.groups,
+                version_command: metadata.template.version_command,
+            });
+        }
+
+        Ok(templates)
+    }
+}
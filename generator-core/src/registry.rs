@@ -0,0 +1,865 @@
+use crate::delta::{self, TemplateDelta};
+use crate::error::{Error, Result, TemplateError};
+use crate::language::Language;
+use crate::options::{self, GenerationOptions};
+#[cfg(feature = "fs")]
+use crate::provider::DirectoryProvider;
+use crate::provider::{EmbeddedProvider, TemplateProvider};
+use crate::merger::{self, Provenance};
+use crate::suggest;
+use serde::{Deserialize, Serialize};
+use nix_parser::{FlakeFragments, ParseError};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub description: String,
+    /// Left empty by providers for templates that extend another; filled in
+    /// from the base template when the template is registered.
+    #[serde(default)]
+    pub flake_content: String,
+    #[serde(default)]
+    pub additional_files: HashMap<String, String>,
+    /// Template this one is derived from by applying `delta`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(default, skip_serializing_if = "TemplateDelta::is_empty")]
+    pub delta: TemplateDelta,
+    /// Optional tooling such as linters and formatters, left out by
+    /// [`GenerationOptions::minimal`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_packages: Vec<String>,
+    /// Works without a C compiler, so it can use `mkShellNoCC`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_cc: bool,
+    /// Packages such as apple_sdk frameworks that the devShell only gets on
+    /// darwin, behind `stdenv.isDarwin`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub darwin_packages: Vec<String>,
+    /// Lines added to the devShell's shell hook, kept when the template is
+    /// combined with others
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shell_hook: Vec<String>,
+    /// Optional package groups, added with [`GenerationOptions::groups`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, TemplateGroup>,
+    /// Shell command printing the toolchain version, run by the banner of
+    /// [`GenerationOptions::banner`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_command: Option<String>,
+}
+
+/// A named set of optional packages a template offers, declared as
+/// `[groups.<name>]` in its metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct TemplateGroup {
+    pub packages: Vec<String>,
+    /// Lines added to the devShell's shell hook, once however many of the
+    /// combined templates' groups have them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shell_hook: Vec<String>,
+    /// Commands run inside the devShell by `nix run .#<name>`, added to the
+    /// flake's `apps`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub apps: BTreeMap<String, String>,
+}
+
+impl Template {
+    /// Short content hash identifying a revision of the template.
+    pub fn version(&self) -> String {
+        let digest = Sha256::digest(self.flake_content.as_bytes());
+        digest.iter().take(6).map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// The template without its extra packages. Packages are matched as
+    /// whole lines of the flake, the way templates list them.
+    pub fn minimal(&self) -> Template {
+        let flake_content = self
+            .flake_content
+            .split_inclusive('\n')
+            .filter(|line| !self.extra_packages.iter().any(|package| line.trim() == package))
+            .collect();
+        Template {
+            flake_content,
+            extra_packages: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// The template with the packages of the named groups it has added to
+    /// its devShell. Groups it does not have are ignored, as are packages
+    /// the flake already lists.
+    pub fn with_groups(&self, groups: &[String]) -> Template {
+        let mut packages: Vec<String> = Vec::new();
+        for group in groups.iter().filter_map(|name| self.groups.get(name)) {
+            for package in &group.packages {
+                let listed = self.flake_content.lines().any(|line| line.trim() == package);
+                if !listed && !packages.contains(package) {
+                    packages.push(package.clone());
+                }
+            }
+        }
+        if packages.is_empty() {
+            return self.clone();
+        }
+
+        match delta::add_packages(&self.flake_content, &packages) {
+            Some(flake_content) => Template {
+                flake_content,
+                ..self.clone()
+            },
+            None => {
+                tracing::warn!(
+                    template = %self.name,
+                    "No devShell packages list; {} not added",
+                    packages.join(", ")
+                );
+                self.clone()
+            }
+        }
+    }
+}
+
+/// A generated flake.nix and the auxiliary files that go next to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedFlake {
+    pub flake_content: String,
+    pub additional_files: HashMap<String, String>,
+    /// Which template contributed each merged attribute.
+    pub provenance: Provenance,
+}
+
+/// The set of templates available for rendering: the embedded ones plus any
+/// loaded from template directories.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    templates: HashMap<String, Template>,
+    /// Fragments parsed from each template's flake, shared between clones.
+    /// Entries keep the source they were parsed from, so a template replaced
+    /// in one clone is never served stale fragments by another.
+    parsed: Arc<Mutex<HashMap<String, (String, FlakeFragments)>>>,
+    /// Groups any template can take, such as `k8s`. A template's own group
+    /// of the same name takes precedence.
+    groups: BTreeMap<String, TemplateGroup>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// A registry containing the embedded templates.
+    ///
+    /// # Panics
+    ///
+    /// If an embedded template fails to load, which the build script rules
+    /// out; use [`Registry::try_new`] to handle it anyway.
+    pub fn new() -> Self {
+        Self::try_new().expect("embedded templates are valid")
+    }
+
+    /// A registry containing the embedded templates, reporting templates
+    /// that fail to load instead of skipping them.
+    pub fn try_new() -> Result<Self> {
+        let mut registry = Self::empty();
+        registry.add_provider(&EmbeddedProvider)?;
+        for (name, group) in crate::embedded_templates::groups()? {
+            registry.insert_group(name, group);
+        }
+        Ok(registry)
+    }
+
+    /// A registry without any templates, to be filled from providers.
+    pub fn empty() -> Self {
+        Self {
+            templates: HashMap::new(),
+            parsed: Arc::default(),
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Add a template, replacing any existing one of the same name.
+    pub fn insert(&mut self, template: Template) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    /// Offer a group to every template, replacing any existing shared group
+    /// of the same name.
+    pub fn insert_group(&mut self, name: impl Into<String>, group: TemplateGroup) {
+        self.groups.insert(name.into(), group);
+    }
+
+    /// The groups offered to every template, by name.
+    pub fn groups(&self) -> &BTreeMap<String, TemplateGroup> {
+        &self.groups
+    }
+
+    /// Add every template from `provider`, replacing existing templates of
+    /// the same name. Templates that extend another are derived once their
+    /// base is registered; a template may extend the one it replaces.
+    pub fn add_provider(&mut self, provider: &dyn TemplateProvider) -> Result<()> {
+        let templates = provider.templates()?;
+        tracing::debug!(provider = provider.name(), count = templates.len(), "loaded templates");
+
+        let mut derived = BTreeMap::new();
+        for template in templates {
+            if template.extends.is_some() {
+                derived.insert(template.name.clone(), template);
+            } else {
+                self.insert(template);
+            }
+        }
+        self.insert_derived(derived)
+    }
+
+    /// Register templates that extend another, bases first.
+    fn insert_derived(&mut self, mut pending: BTreeMap<String, Template>) -> Result<()> {
+        while let Some(first) = pending.keys().next().cloned() {
+            // Follow the chain of pending bases down to one already registered
+            let mut chain = vec![first];
+            loop {
+                let current = &pending[chain.last().expect("chain is never empty")];
+                let base = current.extends.clone().unwrap_or_default();
+                if base == current.name || !pending.contains_key(&base) {
+                    break;
+                }
+                if chain.contains(&base) {
+                    chain.push(base);
+                    return Err(TemplateError::InheritanceCycle(chain).into());
+                }
+                chain.push(base);
+            }
+
+            let name = chain.pop().expect("chain is never empty");
+            let template = pending.remove(&name).expect("chain holds pending templates");
+            let derived = self.derive(template)?;
+            self.insert(derived);
+        }
+        Ok(())
+    }
+
+    /// Fill in the flake of a template that extends another by applying its
+    /// delta to the base. Additional files of the base are inherited.
+    fn derive(&self, mut template: Template) -> Result<Template> {
+        let base_name = template.extends.clone().unwrap_or_default();
+        let base = self.get(&base_name).map_err(|_| TemplateError::UnknownBase {
+            template: template.name.clone(),
+            base: base_name.clone(),
+        })?;
+
+        template.flake_content = delta::apply(&base.flake_content, &template.delta).map_err(
+            |message| TemplateError::InvalidDelta {
+                template: template.name.clone(),
+                base: base_name.clone(),
+                message,
+            },
+        )?;
+        let mut extra_packages = base.extra_packages.clone();
+        extra_packages.append(&mut template.extra_packages);
+        template.extra_packages = extra_packages;
+        template.no_cc |= base.no_cc;
+        let mut darwin_packages = base.darwin_packages.clone();
+        darwin_packages.append(&mut template.darwin_packages);
+        template.darwin_packages = darwin_packages;
+        let mut shell_hook = base.shell_hook.clone();
+        shell_hook.append(&mut template.shell_hook);
+        template.shell_hook = shell_hook;
+        if template.version_command.is_none() {
+            template.version_command = base.version_command.clone();
+        }
+        for (name, group) in &base.groups {
+            template.groups.entry(name.clone()).or_insert_with(|| group.clone());
+        }
+        for (filename, content) in &base.additional_files {
+            template
+                .additional_files
+                .entry(filename.clone())
+                .or_insert_with(|| content.clone());
+        }
+        tracing::debug!(template = %template.name, base = %base_name, "derived template");
+        Ok(template)
+    }
+
+    /// Load `<name>.toml` + `<name>.nix` template pairs from a directory.
+    /// Templates found here replace embedded templates of the same name.
+    #[cfg(feature = "fs")]
+    pub fn load_template_dir(&mut self, dir: &Path) -> Result<()> {
+        self.add_provider(&DirectoryProvider::new(dir))
+    }
+
+    /// Look up a template by name, falling back to language aliases such as
+    /// `py` or `ts` for the bundled templates.
+    pub fn get(&self, template_name: &str) -> Result<&Template> {
+        if let Some(template) = self.templates.get(template_name) {
+            return Ok(template);
+        }
+        if let Ok(language) = template_name.parse::<Language>() {
+            if let Some(template) = self.templates.get(language.template_name()) {
+                return Ok(template);
+            }
+        }
+
+        Err(TemplateError::Unknown {
+            name: template_name.to_string(),
+            suggestions: suggest::close_matches(
+                template_name,
+                self.templates.keys().map(String::as_str),
+            )
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        }
+        .into())
+    }
+
+    /// Names of all registered templates, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// All registered templates, sorted by name.
+    pub fn templates(&self) -> Vec<&Template> {
+        let mut sorted: Vec<&Template> = self.templates.values().collect();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+        sorted
+    }
+
+    /// Render the flake and auxiliary files for the given templates without
+    /// touching the filesystem.
+    pub fn render(
+        &self,
+        template_names: &[&str],
+        options: &GenerationOptions,
+    ) -> Result<RenderedFlake> {
+        let _span = tracing::debug_span!("render", templates = ?template_names).entered();
+        let templates = self.select(template_names, options)?;
+
+        let merged = merger::merge_templates_with(&templates, options, |template| {
+            self.fragments(template)
+        })?;
+        let flake_content = options::apply(&merged.flake_content, options);
+        tracing::debug!(?options, "applied generation options");
+
+        let rendered = RenderedFlake {
+            flake_content,
+            additional_files: collect_additional_files(&templates),
+            provenance: merged.provenance,
+        };
+        validate_rendered(&rendered, template_names)?;
+        tracing::debug!(additional_files = rendered.additional_files.len(), "rendered and validated");
+        Ok(rendered)
+    }
+
+    /// Render one flake with a devShell per named set of templates, such as
+    /// a `frontend` and a `backend` shell in a monorepo. Inputs, overlays
+    /// and bindings are those of all the templates merged; each shell gets
+    /// the packages, environment and hooks of its own templates.
+    pub fn render_shells(
+        &self,
+        shells: &[(String, Vec<String>)],
+        options: &GenerationOptions,
+    ) -> Result<RenderedFlake> {
+        let mut all: Vec<&str> = Vec::new();
+        for name in shells.iter().flat_map(|(_, templates)| templates) {
+            if !all.contains(&name.as_str()) {
+                all.push(name);
+            }
+        }
+        let merged = self.render(&all, options)?;
+        let missing = |templates: &[&str]| Error::InvalidOutput {
+            artifact: "flake.nix".to_string(),
+            templates: templates.iter().map(|t| t.to_string()).collect(),
+            message: "no devShells.default binding to take the shell from".to_string(),
+        };
+
+        let mut flakes = Vec::new();
+        for (name, templates) in shells {
+            let templates: Vec<&str> = templates.iter().map(String::as_str).collect();
+            let flake = self.render(&templates, options)?.flake_content;
+            flakes.push((name, templates, flake));
+        }
+        let named = flakes
+            .iter()
+            .map(|(name, templates, flake)| {
+                let shell = delta::default_shell(flake).ok_or_else(|| missing(templates))?;
+                Ok(((*name).clone(), shell))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let flake_content = delta::replace_default_shell(&merged.flake_content, &named)
+            .ok_or_else(|| missing(&all))?;
+        let rendered = RenderedFlake {
+            flake_content,
+            ..merged
+        };
+        validate_rendered(&rendered, &all)?;
+        Ok(rendered)
+    }
+
+    /// The named templates as they go into a merge: reduced to their core
+    /// packages when `options` asks for minimal flakes, then given the
+    /// packages of the groups `options` asks for, their own or shared ones.
+    /// Templates that need a C compiler are refused when `options` asks for
+    /// `mkShellNoCC`.
+    pub fn select(
+        &self,
+        template_names: &[&str],
+        options: &GenerationOptions,
+    ) -> Result<Vec<Template>> {
+        let groups = options.selected_groups();
+        let templates = template_names
+            .iter()
+            .map(|name| {
+                let template = self.get(name)?;
+                let mut template = if options.minimal {
+                    template.minimal()
+                } else {
+                    template.clone()
+                };
+                for (name, group) in &self.groups {
+                    template.groups.entry(name.clone()).or_insert_with(|| group.clone());
+                }
+                Ok(template.with_groups(&groups))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let available: BTreeSet<&String> =
+            templates.iter().flat_map(|template| template.groups.keys()).collect();
+        if let Some(group) = groups.iter().find(|group| !available.contains(group)) {
+            return Err(TemplateError::UnknownGroup {
+                group: group.clone(),
+                available: available.into_iter().cloned().collect(),
+            }
+            .into());
+        }
+
+        if options.no_cc {
+            let needing: Vec<String> = templates
+                .iter()
+                .filter(|template| !template.no_cc)
+                .map(|template| template.name.clone())
+                .collect();
+            if !needing.is_empty() {
+                return Err(TemplateError::NeedsCompiler(needing).into());
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Parsed fragments of a template, parsing it only the first time.
+    fn fragments(&self, template: &Template) -> std::result::Result<FlakeFragments, ParseError> {
+        let mut parsed = self.parsed.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((source, fragments)) = parsed.get(&template.name) {
+            if *source == template.flake_content {
+                tracing::trace!(template = %template.name, "reusing parsed fragments");
+                return Ok(fragments.clone());
+            }
+        }
+
+        let fragments = nix_parser::extract_flake_fragments(&template.flake_content)?;
+        parsed.insert(
+            template.name.clone(),
+            (template.flake_content.clone(), fragments.clone()),
+        );
+        Ok(fragments)
+    }
+}
+
+/// Additional files of all templates; the first template to define a file wins.
+pub fn collect_additional_files(templates: &[Template]) -> HashMap<String, String> {
+    let mut additional_files = HashMap::new();
+    for template in templates {
+        for (filename, content) in &template.additional_files {
+            additional_files
+                .entry(filename.clone())
+                .or_insert_with(|| content.clone());
+        }
+    }
+    additional_files
+}
+
+/// Self-check every generated artifact before anything is written: the flake
+/// must re-parse and match the flake schema, and TOML files must parse. A
+/// failure here is a bug in a template or in the merge engine.
+pub fn validate_rendered(rendered: &RenderedFlake, template_names: &[&str]) -> Result<()> {
+    let report = |artifact: &str, message: String| Error::InvalidOutput {
+        artifact: artifact.to_string(),
+        templates: template_names.iter().map(|t| t.to_string()).collect(),
+        message,
+    };
+
+    nix_parser::validate_flake(&rendered.flake_content)
+        .map_err(|e| report("flake.nix", e.to_string()))?;
+
+    for (filename, content) in &rendered.additional_files {
+        if filename.ends_with(".toml") {
+            toml::from_str::<toml::Table>(content)
+                .map_err(|e| report(filename.as_str(), e.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Embedded;
+
+    #[test]
+    fn test_render_parses_each_template_once() {
+        let registry = Registry::new();
+        let options = GenerationOptions::default();
+        let first = registry.render(&["rust", "go"], &options).unwrap();
+        assert_eq!(registry.parsed.lock().unwrap().len(), 2);
+
+        let second = registry.clone().render(&["go", "rust", "python"], &options).unwrap();
+        assert_eq!(registry.parsed.lock().unwrap().len(), 3);
+        assert_ne!(first.flake_content, second.flake_content);
+    }
+
+    #[test]
+    fn test_render_keeps_nixpkgs_config() {
+        let registry = Registry::new();
+        let rendered = registry.render(&["hashi", "go"], &GenerationOptions::default()).unwrap();
+        assert_eq!(rendered.flake_content.matches("config.allowUnfree = true;").count(), 1);
+    }
+
+    #[test]
+    fn test_render_shells() {
+        let registry = Registry::new();
+        let shells = vec![
+            ("frontend".to_string(), vec!["node".to_string(), "elm".to_string()]),
+            ("backend".to_string(), vec!["go".to_string()]),
+        ];
+        let rendered = registry.render_shells(&shells, &GenerationOptions::default()).unwrap();
+        let flake = &rendered.flake_content;
+
+        assert!(flake.contains("          frontend = pkgs.mkShell {"));
+        assert!(flake.contains("          backend = pkgs.mkShell {"));
+        assert!(!flake.contains("default = pkgs.mkShell"));
+        // Overlays of every shell's templates are kept, packages aren't shared
+        assert!(flake.contains("go = final."));
+        assert_eq!(flake.matches("gotools").count(), 1);
+    }
+
+    #[test]
+    fn test_replaced_template_is_reparsed() {
+        let mut registry = Registry::new();
+        let options = GenerationOptions::default();
+        registry.render(&["rust", "go"], &options).unwrap();
+
+        let mut go = registry.get("go").unwrap().clone();
+        go.flake_content = go.flake_content.replace("golangci-lint", "delve");
+        registry.insert(go);
+
+        let rendered = registry.render(&["rust", "go"], &options).unwrap();
+        assert!(rendered.flake_content.contains("delve"));
+        assert!(!rendered.flake_content.contains("golangci-lint"));
+    }
+
+    #[test]
+    fn test_render_records_provenance() {
+        let registry = Registry::new();
+        let go = registry.get("go").unwrap();
+        let rendered = registry
+            .render(&["rust", "go"], &GenerationOptions::default())
+            .unwrap();
+
+        let origin = &rendered.provenance["packages.golangci-lint"];
+        assert_eq!(origin.template, "go");
+        assert_eq!(origin.version, go.version());
+        assert_eq!(rendered.provenance["env.RUST_SRC_PATH"].template, "rust");
+        assert!(!rendered.flake_content.contains("# from"));
+
+        let options = GenerationOptions {
+            provenance_comments: true,
+            ..Default::default()
+        };
+        let annotated = registry.render(&["rust", "go"], &options).unwrap();
+        let content = &annotated.flake_content;
+        let rust = registry.get("rust").unwrap().version();
+        let go = go.version();
+        assert!(content.contains(&format!(
+            "              # from go {go}\n              golangci-lint\n"
+        )));
+        assert!(content.contains(&format!(
+            "      # from go {go}\n      goVersion = 24; # Change this to update the whole stack\n"
+        )));
+        // Above an input opening a block and an overlay spanning several lines
+        assert!(content.contains(&format!("    # from rust {rust}\n    rust-overlay = {{\n")));
+        assert!(content.contains(&format!("        # from rust {rust}\n        rustToolchain =")));
+        assert!(!content.contains("{ # from") && !content.contains("}; # from"));
+        assert!(nix_parser::validate_flake(content).is_ok());
+    }
+
+    struct Fixed(Vec<Template>);
+
+    impl TemplateProvider for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn templates(&self) -> Result<Vec<Template>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn derived(name: &str, base: &str, delta: TemplateDelta) -> Template {
+        Template {
+            name: name.to_string(),
+            description: format!("{base} variant"),
+            extends: Some(base.to_string()),
+            delta,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_extends_applies_delta() {
+        let mut registry = Registry::new();
+        let grpc = TemplateDelta {
+            packages: vec!["protobuf".to_string()],
+            variables: BTreeMap::from([("goVersion".to_string(), "23".to_string())]),
+            ..Default::default()
+        };
+        let company = TemplateDelta {
+            packages: vec!["buf".to_string()],
+            ..Default::default()
+        };
+        registry
+            .add_provider(&Fixed(vec![
+                derived("go-grpc", "go", grpc),
+                derived("go", "go", company),
+            ]))
+            .unwrap();
+
+        let go = &registry.get("go").unwrap().flake_content;
+        assert!(go.contains("buf\n") && !go.contains("protobuf"));
+        // Derived from the replaced go template
+        let grpc = &registry.get("go-grpc").unwrap().flake_content;
+        assert!(grpc.contains("goVersion = 23;"));
+        assert!(grpc.contains("protobuf") && grpc.contains("buf\n"));
+    }
+
+    #[test]
+    fn test_extends_cycle_is_reported() {
+        let mut registry = Registry::new();
+        let cycle = Fixed(vec![
+            derived("a", "b", TemplateDelta::default()),
+            derived("b", "a", TemplateDelta::default()),
+        ]);
+        assert!(matches!(
+            registry.add_provider(&cycle),
+            Err(Error::Template(TemplateError::InheritanceCycle(_)))
+        ));
+    }
+
+    #[test]
+    fn test_minimal_render_drops_extra_packages() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            minimal: true,
+            ..Default::default()
+        };
+
+        for name in registry.names() {
+            let rendered = registry.render(&[name], &options).unwrap();
+            for package in &registry.get(name).unwrap().extra_packages {
+                assert!(
+                    !rendered.flake_content.lines().any(|line| line.trim() == package),
+                    "{name} still lists {package}"
+                );
+            }
+        }
+
+        let rendered = registry.render(&["go", "rust"], &options).unwrap();
+        assert!(rendered.flake_content.contains("rustToolchain"));
+        assert!(!rendered.flake_content.contains("golangci-lint"));
+    }
+
+    #[test]
+    fn test_no_cc_refuses_compiled_languages() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            no_cc: true,
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["python", "node"], &options).unwrap();
+        assert!(rendered.flake_content.contains("pkgs.mkShellNoCC {"));
+        assert!(!rendered.flake_content.contains("pkgs.mkShell {"));
+
+        match registry.render(&["python", "rust"], &options) {
+            Err(err @ Error::Template(TemplateError::NeedsCompiler(_))) => {
+                assert_eq!(
+                    err.to_string(),
+                    "rust needs a C compiler and cannot use mkShellNoCC"
+                );
+            }
+            other => panic!("expected NeedsCompiler, got {other:?}"),
+        }
+
+        match registry.render(&["rust", "go"], &options) {
+            Err(err @ Error::Template(TemplateError::NeedsCompiler(_))) => {
+                assert_eq!(
+                    err.to_string(),
+                    "rust, go need a C compiler and cannot use mkShellNoCC"
+                );
+            }
+            other => panic!("expected NeedsCompiler, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_darwin_packages_are_conditional() {
+        let registry = Registry::new();
+        for names in [&["rust"][..], &["rust", "rust-toolchain", "go"][..]] {
+            let rendered = registry.render(names, &GenerationOptions::default()).unwrap();
+            let darwin = rendered
+                .flake_content
+                .split("pkgs.lib.optionals pkgs.stdenv.isDarwin")
+                .nth(1)
+                .unwrap_or_else(|| panic!("{names:?} has no darwin packages"));
+            assert!(darwin.contains("libiconv"));
+            assert_eq!(rendered.flake_content.matches("libiconv").count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_with_groups_adds_packages() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            groups: vec!["tools".to_string(), "grpc".to_string()],
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["go"], &options).unwrap();
+        for package in ["gopls", "delve", "protoc-gen-go-grpc"] {
+            assert!(rendered.flake_content.lines().any(|line| line.trim() == package));
+        }
+        let rendered = registry.render(&["go", "python"], &options).unwrap();
+        assert!(rendered.flake_content.contains("pyright"));
+
+        let options = GenerationOptions {
+            groups: vec!["docs".to_string()],
+            ..Default::default()
+        };
+        match registry.render(&["go"], &options) {
+            Err(Error::Template(TemplateError::UnknownGroup { group, available })) => {
+                assert_eq!(group, "docs");
+                assert_eq!(available, ["grpc", "k8s", "tools"].map(String::from).to_vec());
+            }
+            other => panic!("expected UnknownGroup, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_k8s_group_goes_with_any_template() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            groups: vec!["k8s".to_string()],
+            ..Default::default()
+        };
+
+        for names in [&["go"][..], &["python", "node"]] {
+            let rendered = registry.render(names, &options).unwrap();
+            for package in ["kubectl", "kubernetes-helm", "k9s", "kind", "tilt"] {
+                let listed = rendered.flake_content.lines().filter(|line| line.trim() == package);
+                assert_eq!(listed.count(), 1, "{package} in {names:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_embedded_adds_cross_toolchains() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            embedded: Some(Embedded::ArmNoneEabi),
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["c-cpp", "rust"], &options).unwrap();
+        let content = &rendered.flake_content;
+        for package in ["gcc-arm-embedded", "openocd", "probe-rs-tools", "flip-link"] {
+            assert!(content.lines().any(|line| line.trim() == package), "{package} missing");
+        }
+        assert!(content.contains("targets = [ \"thumbv7em-none-eabihf\" ];"));
+        assert!(content.contains("60-openocd.rules"));
+
+        match registry.render(&["go"], &options) {
+            Err(Error::Template(TemplateError::UnknownGroup { group, .. })) => {
+                assert_eq!(group, "embedded-arm-none-eabi");
+            }
+            other => panic!("expected UnknownGroup, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_grpc_group_exports_protoc_once() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            groups: vec!["grpc".to_string()],
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["go", "rust", "python"], &options).unwrap();
+        let plugins = ["protoc-gen-go-grpc", "protoc-gen-tonic", "python311Packages.mypy-protobuf"];
+        for package in plugins {
+            assert!(rendered.flake_content.contains(package), "{package} missing");
+        }
+        assert_eq!(rendered.flake_content.matches("export PROTOC=").count(), 1);
+        // Appended to python's venv hook rather than replacing it
+        assert!(rendered.flake_content.contains("source .venv/bin/activate"));
+
+        let rendered = registry.render(&["rust"], &options).unwrap();
+        assert!(rendered.flake_content.contains("export PROTOC=${pkgs.protobuf}/bin/protoc"));
+    }
+
+    #[test]
+    fn test_jupyter_group_adds_notebook_app() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            groups: vec!["jupyter".to_string()],
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["python", "r"], &options).unwrap();
+        let content = &rendered.flake_content;
+        assert!(content.contains("python311Packages.jupyterlab"));
+        assert!(content.contains("rPackages.IRkernel"));
+        assert_eq!(content.matches("apps = forEachSupportedSystem").count(), 1);
+        assert_eq!(content.matches("notebook = {").count(), 1);
+        assert!(content.contains("develop ${self} --command jupyter lab \"$@\""));
+        // After the devShells, inside the outputs
+        assert!(content.find("devShells =").unwrap() < content.find("apps =").unwrap());
+    }
+
+    #[test]
+    fn test_banner_is_combined() {
+        let registry = Registry::new();
+        let options = GenerationOptions {
+            banner: true,
+            ..Default::default()
+        };
+
+        let rendered = registry.render(&["rust", "go"], &options).unwrap();
+        let content = &rendered.flake_content;
+        assert_eq!(content.matches("echo \"Development environment: go, rust\"").count(), 1);
+        assert!(content.contains("go version"));
+        assert!(content.contains("rustc --version"));
+
+        let rendered = registry.render(&["python"], &options).unwrap();
+        assert!(rendered.flake_content.contains("python --version"));
+    }
+}
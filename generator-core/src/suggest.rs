@@ -0,0 +1,59 @@
+/// Levenshtein distance between two strings, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Candidates close enough to `name` to be a likely typo, best first.
+pub fn close_matches<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+    let mut matches: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    matches.sort();
+    matches.into_iter().take(3).map(|(_, candidate)| candidate).collect()
+}
+
+/// Render suggestions as `'a'`, `'a' or 'b'`, `'a', 'b' or 'c'`.
+pub fn format_choices(choices: &[&str]) -> String {
+    let quoted: Vec<String> = choices.iter().map(|c| format!("'{c}'")).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("pyton", "python"), 1);
+        assert_eq!(edit_distance("rust", "rust"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_close_matches() {
+        let names = ["python", "rust", "ruby", "go"];
+        assert_eq!(close_matches("pyton", names), vec!["python"]);
+        assert_eq!(close_matches("rubt", names), vec!["ruby", "rust"]);
+        assert!(close_matches("haskell", names).is_empty());
+        assert_eq!(format_choices(&["ruby", "rust"]), "'ruby' or 'rust'");
+    }
+}
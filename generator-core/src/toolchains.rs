@@ -0,0 +1,575 @@
+//! Per-language rewrites of a rendered flake for the toolchain releases and
+//! variants [`GenerationOptions`](crate::GenerationOptions) asks for.
+//!
+//! Each rewrite leaves the flake as it is, with a warning, when it lacks what
+//! the rewrite expects, such as a template's package or overlay binding.
+
+use crate::delta;
+use crate::options::{
+    Beam, BuildTool, Java, JdkDist, Latex, Php, dotnet_sdk_attribute, elixir_attribute,
+    ghc_attribute, ocaml_attribute, php_attribute, php_extension, ruby_attribute,
+    terraform_provider, toolchain_ref,
+};
+
+/// Replace the full TeX Live scheme the templates list with the set `latex`
+/// describes, adding pygments for minted.
+pub(crate) fn use_latex(content: &str, latex: &Latex) -> String {
+    let full = "texlive.combined.scheme-full";
+    let Some(mut result) = delta::replace_package(content, full, &latex.package()) else {
+        tracing::warn!("No {full} in the flake; the LaTeX options were not applied");
+        return content.to_string();
+    };
+
+    if latex.pygments && !result.lines().any(|line| line.trim() == "python3Packages.pygments") {
+        match delta::add_packages(&result, &["python3Packages.pygments".to_string()]) {
+            Some(added) => result = added,
+            None => tracing::warn!("No devShell packages list; pygments not added"),
+        }
+    }
+    result
+}
+
+/// Replace the plain `terraform` package with one that has the providers
+/// built in. Providers that aren't valid addresses are left out.
+pub(crate) fn with_terraform_providers(content: &str, providers: &[String]) -> String {
+    let attributes: Vec<String> = providers
+        .iter()
+        .filter_map(|address| match terraform_provider(address) {
+            Ok(attribute) => Some(format!("p.{attribute}")),
+            Err(message) => {
+                tracing::warn!("{message}; it was not added");
+                None
+            }
+        })
+        .collect();
+    let package = format!("(terraform.withPlugins (p: [ {} ]))", attributes.join(" "));
+
+    delta::replace_package(content, "terraform", &package).unwrap_or_else(|| {
+        tracing::warn!("No terraform package in the flake; the providers were not added");
+        content.to_string()
+    })
+}
+
+/// Point the BEAM overlays at the releases `beam` asks for: the interpreter
+/// at `beam.interpreters.erlang_<otp>`, and Elixir at its attribute in the
+/// set built with it.
+pub(crate) fn use_beam(content: &str, beam: &Beam) -> String {
+    let mut result = content.to_string();
+    if let Some(otp) = beam.otp {
+        match replace_versioned(&result, "beam.interpreters.erlang_", &otp.to_string()) {
+            Some(replaced) => result = replaced,
+            None => tracing::warn!("No Erlang interpreter in the flake; OTP {otp} was not used"),
+        }
+    }
+    if let Some(version) = &beam.elixir {
+        let replaced = elixir_attribute(version).ok().and_then(|attribute| {
+            let version = attribute.trim_start_matches("elixir_");
+            replace_versioned(&result, "pkgs-beam.elixir_", version)
+        });
+        match replaced {
+            Some(replaced) => result = replaced,
+            None => tracing::warn!("No Elixir package in the flake; Elixir {version} was not used"),
+        }
+    }
+    result
+}
+
+/// Rewrite the overlay's `php = prev.php.withExtensions (...)` for the release
+/// and extensions `php` asks for. Invalid ones are left out.
+pub(crate) fn use_php(content: &str, php: &Php) -> String {
+    let attribute = match php.version.as_deref().map(php_attribute) {
+        Some(Ok(attribute)) => attribute,
+        Some(Err(message)) => {
+            tracing::warn!("{message}; the default PHP is used");
+            "php".to_string()
+        }
+        None => "php".to_string(),
+    };
+    let extensions: Vec<String> = php
+        .extensions
+        .iter()
+        .filter(|name| match php_extension(name) {
+            Ok(()) => true,
+            Err(message) => {
+                tracing::warn!("{message}; it was not added");
+                false
+            }
+        })
+        .map(|name| format!("all.{name}"))
+        .collect();
+    let enabled = if extensions.is_empty() {
+        "enabled".to_string()
+    } else {
+        format!("enabled ++ [ {} ]", extensions.join(" "))
+    };
+
+    let value = format!("prev.{attribute}.withExtensions ({{ enabled, all }}: {enabled})");
+    delta::replace_assignment(content, "php", &value).unwrap_or_else(|| {
+        tracing::warn!("No PHP overlay in the flake; the PHP options were not applied");
+        content.to_string()
+    })
+}
+
+/// Set the JVM templates' `javaVersion`, take `jdk` from the distribution
+/// `java` asks for, and list its build tool in place of the others.
+pub(crate) fn use_java(content: &str, java: &Java) -> String {
+    let mut result = content.to_string();
+    if let Some(version) = java.version {
+        match delta::replace_assignment(&result, "javaVersion", &version.to_string()) {
+            Some(replaced) => result = replaced,
+            None => {
+                tracing::warn!("The flake does not bind javaVersion; JDK {version} was not used")
+            }
+        }
+    }
+
+    if java.dist != JdkDist::Openjdk {
+        let openjdk = JdkDist::Openjdk.expression();
+        if result.contains(openjdk) {
+            result = result.replace(openjdk, java.dist.expression());
+        } else {
+            tracing::warn!("No {openjdk} in the flake; the {} JDK was not used", java.dist);
+        }
+    }
+
+    if let Some(tool) = java.build_tool {
+        result = use_build_tool(&result, tool);
+    }
+    result
+}
+
+/// List `tool` in the devShell in place of the other build tools, and build
+/// it against the overlay's `jdk`. The others were what brought a JDK into
+/// the shell, so `jdk` is listed too.
+fn use_build_tool(content: &str, tool: BuildTool) -> String {
+    let mut result = content.to_string();
+    for other in BuildTool::ALL.iter().filter(|other| **other != tool) {
+        if let Some(removed) = delta::remove_package(&result, other.name()) {
+            result = removed;
+        }
+        if let Some(removed) = delta::remove_assignment(&result, other.name()) {
+            result = removed;
+        }
+    }
+
+    if delta::replace_assignment(&result, tool.name(), tool.overlay_value()).is_none() {
+        match delta::insert_assignment(&result, "jdk", tool.name(), tool.overlay_value()) {
+            Some(inserted) => result = inserted,
+            None => tracing::warn!("No jdk in the flake's overlay; {tool} uses its default JDK"),
+        }
+    }
+
+    let missing: Vec<String> = ["jdk", tool.name()]
+        .into_iter()
+        .filter(|name| !delta::has_package(&result, name))
+        .map(str::to_string)
+        .collect();
+    if !missing.is_empty() {
+        match delta::add_packages(&result, &missing) {
+            Some(added) => result = added,
+            None => tracing::warn!("No devShell packages list; {} not added", missing.join(", ")),
+        }
+    }
+    result
+}
+
+/// Take the packages listed from `ocamlPackages` from the package set of the
+/// OCaml release `version` instead.
+pub(crate) fn use_ocaml(content: &str, version: &str) -> String {
+    let set = match ocaml_attribute(version) {
+        Ok(set) => format!("ocaml-ng.{set}."),
+        Err(message) => {
+            tracing::warn!("{message}; the default OCaml is used");
+            return content.to_string();
+        }
+    };
+    let listed: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("ocamlPackages."))
+        .collect();
+    let mut result = content.to_string();
+    for package in &listed {
+        let from_set = package.replacen("ocamlPackages.", &set, 1);
+        if let Some(replaced) = delta::replace_package(&result, package, &from_set) {
+            result = replaced;
+        }
+    }
+    if result == content {
+        tracing::warn!("No ocamlPackages in the flake; OCaml {version} was not used");
+    }
+    result
+}
+
+/// Add a target to the rust template's stable toolchain, after its
+/// extensions. Flakes without that toolchain are left as they are; a
+/// rust-toolchain.toml lists its targets itself.
+pub(crate) fn add_rust_target(content: &str, target: &str) -> String {
+    let Some(toolchain) = content.find("rust.stable.latest.default.override {") else {
+        return content.to_string();
+    };
+    let Some(extensions) = content[toolchain..].find("extensions = [").map(|i| toolchain + i) else {
+        return content.to_string();
+    };
+    let Some(end) = content[extensions..].find("];").map(|i| extensions + i + "];".len()) else {
+        return content.to_string();
+    };
+    let line_start = content[..extensions].rfind('\n').map_or(0, |i| i + 1);
+    let indent = &content[line_start..extensions];
+    format!(
+        "{}\n{indent}targets = [ \"{target}\" ];{}",
+        &content[..end],
+        &content[end..]
+    )
+}
+
+/// Take GHC from `haskell.compiler`, and haskell-language-server and
+/// cabal-install from the `haskell.packages` set built with that release.
+pub(crate) fn use_ghc(content: &str, version: &str) -> String {
+    let attribute = match ghc_attribute(version) {
+        Ok(attribute) => attribute,
+        Err(message) => {
+            tracing::warn!("{message}; the default GHC is used");
+            return content.to_string();
+        }
+    };
+    let compiler = format!("haskell.compiler.{attribute}");
+    let mut result = match delta::replace_package(content, "ghc", &compiler) {
+        Some(replaced) => replaced,
+        None => {
+            tracing::warn!("No ghc package in the flake; GHC {version} was not used");
+            return content.to_string();
+        }
+    };
+    for tool in ["haskell-language-server", "cabal-install"] {
+        let from_set = format!("haskell.packages.{attribute}.{tool}");
+        if let Some(replaced) = delta::replace_package(&result, tool, &from_set) {
+            result = replaced;
+        }
+    }
+    result
+}
+
+/// Take zig from the zig-overlay flake, which has nightly builds as `master`
+/// besides the releases.
+pub(crate) fn use_zig(content: &str, version: &str) -> String {
+    if let Err(message) = toolchain_ref(version) {
+        tracing::warn!("{message}; nixpkgs' zig is used");
+        return content.to_string();
+    }
+    let attribute = match version {
+        "master" => version.to_string(),
+        release => format!("\"{release}\""),
+    };
+    let package = format!("self.inputs.zig-overlay.packages.${{pkgs.system}}.{attribute}");
+    let Some(replaced) = delta::replace_package(content, "zig", &package) else {
+        tracing::warn!("No zig package in the flake; zig {version} was not used");
+        return content.to_string();
+    };
+    if replaced.contains("zig-overlay.url = ") {
+        return replaced;
+    }
+    delta::add_input(&replaced, "zig-overlay", "github:mitchellh/zig-overlay").unwrap_or_else(|| {
+        tracing::warn!("No nixpkgs input to add zig-overlay next to; zig {version} was not used");
+        content.to_string()
+    })
+}
+
+/// Build nixpkgs' vlang from the V repository at `reference`, fetched as a
+/// flake input so the lock file pins it.
+pub(crate) fn use_vlang(content: &str, reference: &str) -> String {
+    if let Err(message) = toolchain_ref(reference) {
+        tracing::warn!("{message}; nixpkgs' vlang is used");
+        return content.to_string();
+    }
+    let package = format!(
+        "(vlang.overrideAttrs {{ version = \"{reference}\"; src = self.inputs.vlang-src; }})"
+    );
+    let Some(replaced) = delta::replace_package(content, "vlang", &package) else {
+        tracing::warn!("No vlang package in the flake; V {reference} was not used");
+        return content.to_string();
+    };
+    let url = match reference {
+        "master" => "github:vlang/v".to_string(),
+        reference => format!("github:vlang/v/{reference}"),
+    };
+    delta::add_source_input(&replaced, "vlang-src", &url).unwrap_or_else(|| {
+        tracing::warn!("No nixpkgs input to add vlang-src next to; V {reference} was not used");
+        content.to_string()
+    })
+}
+
+/// Rewrite the overlay's `dotnet-combined` to combine the SDKs of the major
+/// releases in `sdks`.
+pub(crate) fn combine_dotnet_sdks(content: &str, sdks: &[u32]) -> String {
+    let mut unique: Vec<u32> = Vec::new();
+    for sdk in sdks {
+        if !unique.contains(sdk) {
+            unique.push(*sdk);
+        }
+    }
+    let packages: Vec<String> = unique
+        .iter()
+        .filter_map(|sdk| match dotnet_sdk_attribute(*sdk) {
+            Ok(attribute) => Some(format!("final.dotnetCorePackages.{attribute}")),
+            Err(message) => {
+                tracing::warn!("{message}");
+                None
+            }
+        })
+        .collect();
+    if packages.is_empty() {
+        return content.to_string();
+    }
+
+    let value = format!("final.dotnetCorePackages.combinePackages [ {} ]", packages.join(" "));
+    delta::replace_assignment(content, "dotnet-combined", &value).unwrap_or_else(|| {
+        tracing::warn!("No dotnet-combined in the flake; the .NET SDKs were not combined");
+        content.to_string()
+    })
+}
+
+/// Take Ruby from the release `version` in place of the one the template
+/// pins.
+pub(crate) fn use_ruby(content: &str, version: &str) -> String {
+    let replaced = ruby_attribute(version).ok().and_then(|attribute| {
+        replace_versioned(content, "ruby_", attribute.trim_start_matches("ruby_"))
+    });
+    replaced.unwrap_or_else(|| {
+        tracing::warn!("No versioned Ruby in the flake; Ruby {version} was not used");
+        content.to_string()
+    })
+}
+
+/// Replace the version suffix, digits and underscores, after each occurrence
+/// of `prefix`. None when there is no versioned occurrence.
+fn replace_versioned(content: &str, prefix: &str, version: &str) -> Option<String> {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    let mut found = false;
+    while let Some(start) = rest.find(prefix) {
+        let after = start + prefix.len();
+        let suffix = rest[after..]
+            .find(|c: char| !c.is_ascii_digit() && c != '_')
+            .unwrap_or(rest.len() - after);
+        result.push_str(&rest[..after]);
+        // Not a versioned attribute, such as `elixir_ls`
+        if suffix > 0 {
+            result.push_str(version);
+            found = true;
+        }
+        rest = &rest[after + suffix..];
+    }
+    result.push_str(rest);
+    found.then_some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{LatexScheme, tests::FLAKE};
+
+    #[test]
+    fn test_use_latex() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [\n          texlive.combined.scheme-full\n          tectonic\n        ];\n      };\n    };",
+        );
+
+        let latex = Latex {
+            scheme: LatexScheme::Small,
+            biber: true,
+            pygments: true,
+        };
+        let result = use_latex(&flake, &latex);
+        assert!(result.contains(
+            "(texlive.combine { inherit (texlive) scheme-small biber biblatex minted; })\n"
+        ));
+        assert!(result.contains("          python3Packages.pygments\n"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+
+        let latex = Latex {
+            scheme: LatexScheme::Minimal,
+            ..Default::default()
+        };
+        let result = use_latex(&flake, &latex);
+        assert!(result.contains("(texlive.combine { inherit (texlive) scheme-minimal; })"));
+        assert!(!result.contains("pygments"));
+    }
+
+    #[test]
+    fn test_with_terraform_providers() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [\n          terraform\n          tflint\n        ];\n      };\n    };",
+        );
+
+        let providers = vec!["aws".to_string(), "cloudflare/cloudflare".to_string()];
+        let result = with_terraform_providers(&flake, &providers);
+        assert!(result.contains(
+            "          (terraform.withPlugins (p: [ p.hashicorp_aws p.cloudflare_cloudflare ]))\n"
+        ));
+        assert!(result.contains("          tflint\n"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_use_beam() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      overlays.default = final: prev: rec {\n        erlang = final.beam.interpreters.erlang_27;\n        pkgs-beam = final.beam.packagesWith erlang;\n        elixir = pkgs-beam.elixir_1_17;\n      };\n    };",
+        );
+
+        let beam = Beam {
+            otp: Some(26),
+            elixir: Some("1.16".to_string()),
+        };
+        let result = use_beam(&flake, &beam);
+        assert!(result.contains("erlang = final.beam.interpreters.erlang_26;\n"));
+        assert!(result.contains("elixir = pkgs-beam.elixir_1_16;\n"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_use_php() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      overlays.default = final: prev: {\n        php = prev.php.withExtensions ({ enabled, all }: enabled);\n        composer = final.php.packages.composer;\n      };\n    };",
+        );
+
+        let php = Php {
+            version: Some("8.3".to_string()),
+            extensions: vec!["intl".to_string(), "pdo_pgsql".to_string()],
+        };
+        let result = use_php(&flake, &php);
+        assert!(result.contains(
+            "        php = prev.php83.withExtensions ({ enabled, all }: enabled ++ [ all.intl all.pdo_pgsql ]);\n"
+        ));
+        assert!(result.contains("composer = final.php.packages.composer;"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_use_ruby() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [ ruby_3_3 ];\n      };\n    };",
+        );
+
+        let result = use_ruby(&flake, "3.2");
+        assert!(result.contains("packages = with pkgs; [ ruby_3_2 ];"));
+    }
+
+    #[test]
+    fn test_combine_dotnet_sdks() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      overlays.default = final: prev: {\n        dotnet-combined = final.dotnetCorePackages.combinePackages [ final.dotnetCorePackages.sdk_8_0 ];\n      };\n    };",
+        );
+
+        let result = combine_dotnet_sdks(&flake, &[9, 8, 9]);
+        assert!(result.contains(
+            "combinePackages [ final.dotnetCorePackages.sdk_9_0 final.dotnetCorePackages.sdk_8_0 ];\n"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_use_java() {
+        let flake = FLAKE
+            .replace(
+                "    let\n",
+                "    let\n      javaVersion = 21; # Change this value to update the whole stack\n",
+            )
+            .replace(
+                "    { };",
+                "    {\n      overlays.default = final: prev: rec {\n        jdk = prev.\"jdk${toString javaVersion}\";\n        maven = prev.maven;\n        gradle = prev.gradle.override { java = jdk; };\n      };\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [\n          maven\n          gradle\n          zlib\n        ];\n\n        env.JAVA_HOME = \"${pkgs.jdk.home}\";\n      };\n    };",
+            );
+
+        let java = Java {
+            version: Some(17),
+            dist: JdkDist::Temurin,
+            build_tool: Some(BuildTool::Mill),
+        };
+        let result = use_java(&flake, &java);
+        assert!(result.contains("javaVersion = 17; # Change this value"));
+        assert!(result.contains(
+            "        jdk = prev.\"temurin-bin-${toString javaVersion}\";\n        mill = prev.mill.override { jre = jdk; };\n      };"
+        ));
+        assert!(result.contains("packages = with pkgs; [\n          zlib\n          jdk\n          mill\n        ];"));
+        assert!(!result.contains("maven") && !result.contains("gradle"));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+
+        // A tool the overlay already builds keeps its binding
+        let java = Java {
+            build_tool: Some(BuildTool::Gradle),
+            ..Default::default()
+        };
+        let result = use_java(&flake, &java);
+        assert_eq!(result.matches("gradle = prev.gradle.override { java = jdk; };").count(), 1);
+        assert!(result.contains("[\n          gradle\n          zlib\n          jdk\n        ];"));
+        assert!(!result.contains("maven"));
+    }
+
+    #[test]
+    fn test_use_ocaml() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [\n          ocamlPackages.ocaml\n          ocamlformat\n          ocamlPackages.dune_3\n        ];\n      };\n    };",
+        );
+
+        let result = use_ocaml(&flake, "5.2");
+        assert!(result.contains("          ocaml-ng.ocamlPackages_5_2.ocaml\n"));
+        assert!(result.contains("          ocaml-ng.ocamlPackages_5_2.dune_3\n"));
+        assert!(result.contains("          ocamlformat\n"));
+    }
+
+    #[test]
+    fn test_use_zig_and_vlang() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [ zig vlang ];\n      };\n    };",
+        );
+
+        let result = use_vlang(&use_zig(&flake, "master"), "weekly.2024.40");
+        assert!(result.contains("inputs.zig-overlay.url = \"github:mitchellh/zig-overlay\";"));
+        assert!(result.contains(
+            "inputs.vlang-src = { url = \"github:vlang/v/weekly.2024.40\"; flake = false; };"
+        ));
+        assert!(result.contains(
+            "[ self.inputs.zig-overlay.packages.${pkgs.system}.master (vlang.overrideAttrs { version = \"weekly.2024.40\"; src = self.inputs.vlang-src; }) ]"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+
+        assert!(use_zig(&flake, "0.13.0").contains(".${pkgs.system}.\"0.13.0\" vlang ]"));
+        assert_eq!(use_zig(&flake, "../evil"), flake);
+    }
+
+    #[test]
+    fn test_use_ghc() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      devShells.default = pkgs.mkShell {\n        packages = with pkgs; [\n          cabal-install\n          ghc\n          haskell-language-server\n        ];\n      };\n    };",
+        );
+
+        let result = use_ghc(&flake, "9.8");
+        assert!(result.contains(
+            "          haskell.packages.ghc98.cabal-install\n          haskell.compiler.ghc98\n          haskell.packages.ghc98.haskell-language-server\n"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+
+    #[test]
+    fn test_add_rust_target() {
+        let flake = FLAKE.replace(
+            "    { };",
+            "    {\n      overlays.default = final: prev: {\n        rustToolchain = prev.rust-bin.rust.stable.latest.default.override {\n          extensions = [\n            \"rust-src\"\n          ];\n        };\n      };\n    };",
+        );
+
+        let result = add_rust_target(&flake, "riscv32imac-unknown-none-elf");
+        assert!(result.contains(
+            "          ];\n          targets = [ \"riscv32imac-unknown-none-elf\" ];\n        };"
+        ));
+        assert!(nix_parser::parse_nix_expr(&result).is_ok());
+    }
+}
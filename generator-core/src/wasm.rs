@@ -0,0 +1,51 @@
+//! JavaScript bindings for the web playground.
+//!
+//! ```js
+//! import init, { templates, render } from "nix-flake-generator-core";
+//! await init();
+//! const { flake_content, additional_files } = render(["rust", "go"], {
+//!   "nixpkgs-ref": "nixos-24.05",
+//! });
+//! ```
+
+use crate::generator::Generator;
+use crate::options::GenerationOptions;
+use crate::registry::Registry;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize)]
+struct TemplateSummary<'a> {
+    name: &'a str,
+    description: &'a str,
+}
+
+/// All bundled templates as `{ name, description }` objects, sorted by name.
+#[wasm_bindgen]
+pub fn templates() -> Result<JsValue, JsError> {
+    let registry = Registry::new();
+    let summaries: Vec<_> = registry
+        .templates()
+        .into_iter()
+        .map(|t| TemplateSummary {
+            name: &t.name,
+            description: &t.description,
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&summaries)?)
+}
+
+/// Render a flake for the given template names or language aliases.
+/// `options` takes the same kebab-case keys as the config file and may be
+/// `undefined`. Returns `{ flake_content, additional_files }`.
+#[wasm_bindgen]
+pub fn render(languages: Vec<String>, options: JsValue) -> Result<JsValue, JsError> {
+    let options: GenerationOptions = if options.is_undefined() || options.is_null() {
+        GenerationOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+
+    let rendered = Generator::new().languages(languages).options(options).render()?;
+    Ok(serde_wasm_bindgen::to_value(&rendered)?)
+}
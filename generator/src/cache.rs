@@ -0,0 +1,252 @@
+use crate::dirs;
+use crate::output::{self, OutputFormat};
+use crate::template::TemplateManager;
+use crate::timestamp;
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use nix_flake_generator_core::options::GenerationOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub languages: Vec<String>,
+    pub created: String,
+    /// Directories `shell` and `run` used the entry from, for `clean`
+    #[serde(default)]
+    pub projects: Vec<PathBuf>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl CacheEntry {
+    pub fn is_locked(&self) -> bool {
+        self.path.join("flake.lock").exists()
+    }
+}
+
+/// Root of the tool's cache, following the XDG base directory spec or the
+/// platform's convention.
+pub fn cache_root() -> Result<PathBuf> {
+    dirs::dir(dirs::Kind::Cache)
+}
+
+fn shells_dir() -> Result<PathBuf> {
+    Ok(cache_root()?.join("shells"))
+}
+
+/// Cache key for a generation request. It covers the tool version, the
+/// generation options, the shared groups, and every template involved in
+/// full, in the requested order, so editing a template's flake, files or
+/// metadata invalidates the entry without any rendering.
+pub fn entry_key(
+    manager: &TemplateManager,
+    languages: &[&str],
+    options: &GenerationOptions,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(serde_json::to_string(options)?.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(serde_json::to_string(manager.groups())?.as_bytes());
+
+    for language in languages {
+        let template = manager.get(language)?;
+        hasher.update([0u8]);
+        hasher.update(language.as_bytes());
+        hasher.update([0u8]);
+        // Through a Value, whose maps are ordered by key, so the additional
+        // files hash the same whatever order they were loaded in
+        hasher.update(serde_json::to_value(template)?.to_string().as_bytes());
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Directory holding the generated flake for `languages`, rendering it only
+/// on a cache miss. nix writes flake.lock next to the flake, so later runs
+/// reuse the lock as well.
+pub fn materialize(
+    manager: &TemplateManager,
+    languages: &[&str],
+    options: &GenerationOptions,
+) -> Result<PathBuf> {
+    let key = entry_key(manager, languages, options)?;
+    let dir = shells_dir()?.join(&key);
+
+    let project = env::current_dir()?;
+
+    // The policy may have changed since the entry was cached
+    if let Ok(cached) = fs::read_to_string(dir.join("flake.nix")) {
+        manager.check_policy(languages, &cached)?;
+        record_project(&key, &project)?;
+        return Ok(dir);
+    }
+
+    let rendered = manager.render(languages, options)?;
+    let mut transaction = FileTransaction::new();
+    for (filename, content) in &rendered.additional_files {
+        transaction.write(dir.join(filename), content.as_str());
+    }
+
+    let entry = CacheEntry {
+        key: key.clone(),
+        languages: languages.iter().map(|l| l.to_string()).collect(),
+        created: timestamp::rfc3339_now(),
+        projects: vec![project],
+        path: dir.clone(),
+    };
+    // Metadata lives beside the flake directory so it never ends up in the
+    // store path nix copies.
+    transaction.write(
+        shells_dir()?.join(format!("{key}.json")),
+        serde_json::to_string_pretty(&entry)?,
+    );
+    // flake.nix is written last: its presence marks the entry as complete.
+    transaction.write(dir.join("flake.nix"), rendered.flake_content.as_str());
+    transaction.commit()?;
+
+    Ok(dir)
+}
+
+/// Add `project` to the projects of a cached entry that lacks it.
+fn record_project(key: &str, project: &Path) -> Result<()> {
+    let metadata = shells_dir()?.join(format!("{key}.json"));
+    let Ok(mut entry) = serde_json::from_str::<CacheEntry>(&fs::read_to_string(&metadata)?)
+    else {
+        return Ok(());
+    };
+    if !entry.projects.iter().any(|p| p == project) {
+        entry.projects.push(project.to_path_buf());
+        let mut transaction = FileTransaction::new();
+        transaction.write(metadata, serde_json::to_string_pretty(&entry)?);
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+pub fn list_entries() -> Result<Vec<CacheEntry>> {
+    let dir = shells_dir()?;
+    let mut entries = Vec::new();
+    if !dir.exists() {
+        return Ok(entries);
+    }
+
+    for item in fs::read_dir(&dir)? {
+        let path = item?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let content = fs::read_to_string(&path)?;
+            if let Ok(mut entry) = serde_json::from_str::<CacheEntry>(&content) {
+                entry.path = dir.join(&entry.key);
+                if entry.path.join("flake.nix").exists() {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| a.created.cmp(&b.created));
+    Ok(entries)
+}
+
+/// Remove every cached environment, returning how many were removed.
+pub fn clean() -> Result<usize> {
+    let count = list_entries()?.len();
+    let dir = shells_dir()?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(count)
+}
+
+/// Remove one cached environment and its metadata.
+pub fn remove(entry: &CacheEntry) -> Result<()> {
+    fs::remove_dir_all(&entry.path)?;
+    let metadata = shells_dir()?.join(format!("{}.json", entry.key));
+    if metadata.exists() {
+        fs::remove_file(metadata)?;
+    }
+    Ok(())
+}
+
+pub fn print_entries(entries: &[CacheEntry], format: OutputFormat) -> Result<()> {
+    if format.is_json() {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "key": entry.key,
+                    "languages": entry.languages,
+                    "created": entry.created,
+                    "locked": entry.is_locked(),
+                    "projects": entry.projects,
+                    "path": entry.path,
+                })
+            })
+            .collect();
+        return output::print_json("cache list", &entries);
+    }
+
+    if entries.is_empty() {
+        println!("No cached environments");
+        return Ok(());
+    }
+
+    println!("Cached environments:");
+    for entry in entries {
+        let lock = if entry.is_locked() { ", locked" } else { "" };
+        println!(
+            "  {} {} ({}{})",
+            &entry.key[..12.min(entry.key.len())],
+            entry.languages.join(","),
+            entry.created,
+            lock
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn key_with_tools(dir: &Path, tools: &str) -> String {
+        let mut manager = TemplateManager::new().await.unwrap();
+        let flake = manager.get("go").unwrap().flake_content.clone();
+        fs::write(dir.join("go.nix"), flake).unwrap();
+        fs::write(
+            dir.join("go.toml"),
+            format!(
+                r#"[template]
+name = "go"
+description = "Go"
+
+[groups.tools]
+packages = {tools}
+"#
+            ),
+        )
+        .unwrap();
+        manager.load_template_dir(dir).unwrap();
+        entry_key(&manager, &["go"], &GenerationOptions::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_entry_key_covers_groups() {
+        let dir = TempDir::new().unwrap();
+        let before = key_with_tools(dir.path(), r#"["gopls"]"#).await;
+        assert_eq!(before, key_with_tools(dir.path(), r#"["gopls"]"#).await);
+        let after = key_with_tools(dir.path(), r#"["gopls", "delve"]"#).await;
+        assert_ne!(before, after);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::timestamp;
+
+/// NixOS releases and the last day they receive updates. A release newer
+/// than the last entry is assumed to be supported.
+const RELEASES: &[(&str, (i64, u32, u32))] = &[
+    ("22.11", (2023, 6, 30)),
+    ("23.05", (2023, 12, 31)),
+    ("23.11", (2024, 6, 30)),
+    ("24.05", (2024, 12, 31)),
+    ("24.11", (2025, 6, 30)),
+];
+
+/// How to move off an unsupported release.
+const UPGRADE: &str = "point the nixpkgs input at a newer release (`init --pin-nixpkgs latest-stable` \
+                       picks the newest) and run `nix flake update nixpkgs`";
+
+/// Months before the end of support to start warning.
+const WARN_MONTHS: i64 = 2;
+
+/// The release (e.g. `24.05`) of the first nixpkgs input in a flake pinned
+/// to a release branch (`nixos-24.05`, `nixpkgs-24.05-darwin`,
+/// `release-24.05`). Unstable branches and commits have none.
+pub fn nixpkgs_release(content: &str) -> Option<&str> {
+    content.split('"').skip(1).step_by(2).find_map(|literal| {
+        let (_, reference) = literal.split_once("NixOS/nixpkgs/")?;
+        let branch = reference.split(['?', '/']).next()?;
+        let release = ["nixos-", "nixpkgs-", "release-"]
+            .iter()
+            .find_map(|prefix| branch.strip_prefix(prefix))?;
+        let release = release.split('-').next()?;
+        let (year, month) = release.split_once('.')?;
+        (year.parse::<u32>().is_ok() && month.parse::<u32>().is_ok()).then_some(release)
+    })
+}
+
+/// A warning when the flake's nixpkgs release is past or near its end of
+/// support, with the command to move to the newest release.
+pub fn support_warning(content: &str) -> Option<String> {
+    support_warning_on(content, timestamp::today())
+}
+
+fn support_warning_on(content: &str, today: (i64, u32, u32)) -> Option<String> {
+    let release = nixpkgs_release(content)?;
+    let end = match RELEASES.iter().find(|(name, _)| *name == release) {
+        Some((_, end)) => *end,
+        None if RELEASES.iter().all(|(name, _)| newer(name, release)) => RELEASES[0].1,
+        None => return None,
+    };
+    let (year, month, day) = end;
+    if today > end {
+        Some(format!(
+            "nixpkgs {release} reached end of life on {year:04}-{month:02}-{day:02}; {UPGRADE}"
+        ))
+    } else if months(today) + WARN_MONTHS >= months(end) {
+        Some(format!(
+            "nixpkgs {release} reaches end of life on {year:04}-{month:02}-{day:02}; {UPGRADE}"
+        ))
+    } else {
+        None
+    }
+}
+
+fn months((year, month, _): (i64, u32, u32)) -> i64 {
+    year * 12 + i64::from(month)
+}
+
+/// Whether release `a` (`YY.MM`) is newer than `b`.
+fn newer(a: &str, b: &str) -> bool {
+    let parse = |release: &str| {
+        let (year, month) = release.split_once('.')?;
+        Some((year.parse::<u32>().ok()?, month.parse::<u32>().ok()?))
+    };
+    parse(a) > parse(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nixpkgs_release() {
+        let flake = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.05";"#;
+        assert_eq!(nixpkgs_release(flake), Some("24.05"));
+        let darwin = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixpkgs-23.11-darwin";"#;
+        assert_eq!(nixpkgs_release(darwin), Some("23.11"));
+        let unstable = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";"#;
+        assert_eq!(nixpkgs_release(unstable), None);
+    }
+
+    #[test]
+    fn test_support_warning() {
+        let flake = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.05";"#;
+        assert_eq!(support_warning_on(flake, (2024, 8, 1)), None);
+        assert!(
+            support_warning_on(flake, (2024, 11, 15))
+                .unwrap()
+                .starts_with("nixpkgs 24.05 reaches end of life on 2024-12-31")
+        );
+        assert!(
+            support_warning_on(flake, (2025, 1, 1))
+                .unwrap()
+                .starts_with("nixpkgs 24.05 reached end of life")
+        );
+
+        let old = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-21.11";"#;
+        assert!(support_warning_on(old, (2025, 1, 1)).is_some());
+        let new = r#"inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-26.05";"#;
+        assert_eq!(support_warning_on(new, (2026, 6, 1)), None);
+    }
+}
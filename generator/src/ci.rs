@@ -0,0 +1,152 @@
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A CI recipe entering the devShell, shared by every project generated
+/// with the tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Artifact {
+    /// GitHub composite action in .github/actions/nix-develop
+    Action,
+    /// `ci/nix-develop.sh` for other CI systems
+    Script,
+}
+
+impl Artifact {
+    pub fn path(self) -> &'static str {
+        match self {
+            Artifact::Action => ".github/actions/nix-develop/action.yml",
+            Artifact::Script => "ci/nix-develop.sh",
+        }
+    }
+
+    fn content(self) -> &'static str {
+        match self {
+            Artifact::Action => ACTION,
+            Artifact::Script => SCRIPT,
+        }
+    }
+}
+
+const ACTION: &str = r#"# Install nix, restore the nix store cached for this flake.lock and run
+# commands in the flake's devShell:
+#
+#   steps:
+#     - uses: actions/checkout@v4
+#     - uses: ./.github/actions/nix-develop
+#       with:
+#         run: cargo test
+#
+# Kept in a shared repository, other repositories use it as
+# `<owner>/<repo>/.github/actions/nix-develop@main`.
+name: nix develop
+description: Run commands in the devShell of the flake, with the nix store cached on flake.lock
+inputs:
+  run:
+    description: Commands to run in the devShell; without them the shell is only built
+    required: false
+    default: ""
+  shell:
+    description: Name of the devShell
+    required: false
+    default: default
+runs:
+  using: composite
+  steps:
+    - uses: cachix/install-nix-action@v30
+      with:
+        extra_nix_config: |
+          experimental-features = nix-command flakes
+    - uses: nix-community/cache-nix-action@v5
+      with:
+        primary-key: nix-${{ runner.os }}-${{ hashFiles('**/flake.lock') }}
+        restore-prefixes-first-match: nix-${{ runner.os }}-
+    - name: Build the devShell
+      shell: bash
+      run: nix develop ".#${{ inputs.shell }}" --command true
+    - name: Run in the devShell
+      if: inputs.run != ''
+      shell: bash
+      env:
+        NIX_DEVELOP_RUN: ${{ inputs.run }}
+      run: nix develop ".#${{ inputs.shell }}" --command bash -euo pipefail -c "$NIX_DEVELOP_RUN"
+"#;
+
+const SCRIPT: &str = r#"#!/usr/bin/env sh
+# Run a command in the devShell of this flake on any CI system, installing
+# nix first when the runner has none:
+#
+#   ci/nix-develop.sh cargo test
+#
+# Cache /nix between jobs under the key `ci/nix-develop.sh --cache-key`
+# prints, which changes with flake.lock.
+set -eu
+cd "$(dirname "$0")/.."
+
+if [ "${1:-}" = "--cache-key" ]; then
+  if command -v sha256sum >/dev/null 2>&1; then
+    hash=$(sha256sum flake.lock | cut -d ' ' -f 1)
+  else
+    hash=$(shasum -a 256 flake.lock | cut -d ' ' -f 1)
+  fi
+  echo "nix-$(uname -s)-$hash"
+  exit 0
+fi
+
+if ! command -v nix >/dev/null 2>&1; then
+  curl --proto '=https' --tlsv1.2 -sSfL https://nixos.org/nix/install | sh -s -- --no-daemon
+  . "$HOME/.nix-profile/etc/profile.d/nix.sh"
+fi
+
+features="nix-command flakes"
+if [ "$#" -eq 0 ]; then
+  exec nix --extra-experimental-features "$features" develop --command true
+fi
+exec nix --extra-experimental-features "$features" develop --command "$@"
+"#;
+
+/// Write the artifacts into `target_path`, making the script executable.
+/// Returns the files that changed.
+pub fn write(target_path: &Path, artifacts: &[Artifact]) -> Result<Vec<PathBuf>> {
+    let mut transaction = FileTransaction::new();
+    let mut written = Vec::new();
+    for artifact in artifacts {
+        let path = target_path.join(artifact.path());
+        if fs::read_to_string(&path).ok().as_deref() != Some(artifact.content()) {
+            transaction.write(&path, artifact.content());
+            written.push(path);
+        }
+    }
+    transaction.commit()?;
+
+    #[cfg(unix)]
+    if artifacts.contains(&Artifact::Script) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(
+            target_path.join(Artifact::Script.path()),
+            fs::Permissions::from_mode(0o755),
+        )?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_artifacts() {
+        let dir = TempDir::new().unwrap();
+
+        let written = write(dir.path(), &[Artifact::Action, Artifact::Script]).unwrap();
+        let unchanged = write(dir.path(), &[Artifact::Action]).unwrap();
+        let action = fs::read_to_string(dir.path().join(Artifact::Action.path())).unwrap();
+
+        assert_eq!(written.len(), 2);
+        assert!(unchanged.is_empty());
+        assert!(action.contains("hashFiles('**/flake.lock')"));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cache::{self, CacheEntry};
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Something the tool or nix left in or for a project.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Artifact {
+    /// `.direnv/`, nix-direnv's cached environment and GC roots
+    Direnv { path: PathBuf },
+    /// A `result` or `result-*` symlink from `nix build`
+    Result { path: PathBuf },
+    /// A flake `shell` or `run` cached for the project
+    CachedShell {
+        path: PathBuf,
+        #[serde(skip)]
+        entry: CacheEntry,
+    },
+}
+
+impl Artifact {
+    pub fn describe(&self) -> String {
+        match self {
+            Artifact::Direnv { path } => path.display().to_string(),
+            Artifact::Result { path } => match fs::read_link(path) {
+                Ok(target) => format!("{} -> {}", path.display(), target.display()),
+                Err(_) => path.display().to_string(),
+            },
+            Artifact::CachedShell { path, entry } => {
+                format!("{} ({})", path.display(), entry.languages.join(","))
+            }
+        }
+    }
+}
+
+/// Artifacts in `project`, which must be normalized, and cached shells used
+/// from it. Only symlinks count as build results; regular files named
+/// `result` are the project's own.
+pub fn find(project: &Path) -> Result<Vec<Artifact>> {
+    let mut artifacts = Vec::new();
+
+    let direnv = project.join(".direnv");
+    if direnv.is_dir() {
+        artifacts.push(Artifact::Direnv { path: direnv });
+    }
+
+    if project.is_dir() {
+        let mut results: Vec<PathBuf> = fs::read_dir(project)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_symlink()))
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(is_result_link)
+            })
+            .collect();
+        results.sort();
+        artifacts.extend(results.into_iter().map(|path| Artifact::Result { path }));
+    }
+
+    for entry in cache::list_entries()? {
+        if entry.projects.iter().any(|used_from| used_from == project) {
+            artifacts.push(Artifact::CachedShell {
+                path: entry.path.clone(),
+                entry,
+            });
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Delete the artifacts. Cached shells go even when other projects used
+/// them too; they are regenerated on the next `shell` or `run`.
+pub fn remove(artifacts: &[Artifact]) -> Result<()> {
+    for artifact in artifacts {
+        match artifact {
+            Artifact::Direnv { path } => fs::remove_dir_all(path)?,
+            Artifact::Result { path } => fs::remove_file(path)?,
+            Artifact::CachedShell { entry, .. } => cache::remove(entry)?,
+        }
+    }
+    Ok(())
+}
+
+fn is_result_link(name: &str) -> bool {
+    name == "result" || name.starts_with("result-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_result_link() {
+        assert!(is_result_link("result"));
+        assert!(is_result_link("result-dev"));
+        assert!(!is_result_link("results"));
+        assert!(!is_result_link(".result"));
+    }
+}
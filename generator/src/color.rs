@@ -0,0 +1,80 @@
+use std::env;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD_RED: &str = "\x1b[1;31m";
+
+/// Decide once whether each stream gets ANSI colors: only terminals, and
+/// never with `--no-color` or a non-empty `NO_COLOR` (https://no-color.org).
+pub fn init(no_color: bool) {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let allowed = !no_color && !no_color_env;
+    STDOUT.store(allowed && std::io::stdout().is_terminal(), Ordering::Relaxed);
+    STDERR.store(allowed && std::io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+pub fn stderr_enabled() -> bool {
+    STDERR.load(Ordering::Relaxed)
+}
+
+fn paint(enabled: bool, code: &str, text: &str) -> String {
+    if enabled {
+        format!("{code}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// A confirmation message printed to stdout.
+pub fn success(text: &str) -> String {
+    paint(STDOUT.load(Ordering::Relaxed), GREEN, text)
+}
+
+/// The `Error:` prefix printed to stderr.
+pub fn error_label() -> String {
+    paint(stderr_enabled(), BOLD_RED, "Error:")
+}
+
+/// Color a unified diff for stderr: headers bold, hunks cyan, removals red
+/// and additions green.
+pub fn diff(text: &str) -> String {
+    if !stderr_enabled() {
+        return text.to_string();
+    }
+    text.lines()
+        .map(|line| {
+            let code = if line.starts_with("---") || line.starts_with("+++") {
+                BOLD
+            } else if line.starts_with("@@") {
+                CYAN
+            } else if line.starts_with('-') {
+                RED
+            } else if line.starts_with('+') {
+                GREEN
+            } else {
+                return format!("{line}\n");
+            };
+            format!("{code}{line}{RESET}\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_when_disabled() {
+        init(true);
+        assert_eq!(success("done"), "done");
+        assert_eq!(diff("-a\n+b\n"), "-a\n+b\n");
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::Config;
+use crate::template::TemplateManager;
+use clap::Command;
+use clap::builder::PossibleValuesParser;
+use clap_complete::Shell;
+use std::io;
+
+/// Subcommands whose first positional takes template names.
+const TEMPLATE_ARGS: [&str; 3] = ["init", "shell", "run"];
+
+/// Print a completion script for `shell`. Template, alias and profile names
+/// known right now (including user template directories and config) are
+/// baked into the script as candidates, so re-run this after adding any.
+pub fn print(command: Command, shell: Shell, manager: &TemplateManager, config: &Config) {
+    let mut templates: Vec<String> = manager.names().into_iter().map(String::from).collect();
+    templates.extend(config.aliases.keys().cloned());
+    let profiles: Vec<String> = config.profiles.keys().cloned().collect();
+
+    let mut command = command;
+    for name in TEMPLATE_ARGS {
+        let candidates = templates.clone();
+        command = command.mut_subcommand(name, |sub| {
+            sub.mut_arg("templates", |arg| {
+                arg.value_parser(PossibleValuesParser::new(candidates))
+            })
+        });
+    }
+    if !profiles.is_empty() {
+        command = command.mut_subcommand("init", |sub| {
+            sub.mut_arg("profile", |arg| {
+                arg.value_parser(PossibleValuesParser::new(profiles))
+            })
+        });
+    }
+
+    let bin_name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, bin_name, &mut io::stdout());
+}
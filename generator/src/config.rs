@@ -0,0 +1,639 @@
+use crate::dirs;
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::options::{ConflictStrategy, GenerationOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_CONFIG_FILE: &str = ".nix-flake-generator.toml";
+
+/// Every key accepted in a config file, for reference documentation.
+/// `<name>` stands for a user-chosen profile, alias or snippet name.
+pub const KEYS: &[(&str, &str)] = &[
+    ("nixpkgs-ref", "nixpkgs branch, revision or full flake URL used for the nixpkgs input"),
+    ("systems", "Systems the generated devShells are defined for"),
+    ("substituters", "Extra binary caches added to the generated flake's nixConfig"),
+    ("trusted-public-keys", "Public keys for the extra binary caches"),
+    ("on-conflict", "How merges settle templates that disagree: prefer-first, prefer-last or error"),
+    ("provenance-comments", "Note the template each merged attribute came from in a comment"),
+    ("banner", "Print the languages and toolchain versions on entering the shell"),
+    ("flakes-hint", "Enable flakes in nixConfig and explain how to turn them on when nix.conf doesn't"),
+    ("snippets.<name>", "Text injected at the # @<name> anchor of every generated flake"),
+    ("template-dirs", "Directories containing additional <name>.toml + <name>.nix templates"),
+    ("post-generate", "Commands run in the target directory after init writes files (global config only)"),
+    ("template-providers", "Commands printing a JSON array of extra templates (global config only)"),
+    ("template-signing-keys", "minisign or ssh keys provider templates must be signed by (global config only)"),
+    ("offline", "Never access the network, as with --offline"),
+    ("proxy", "Proxy for remote fetches when HTTPS_PROXY is unset"),
+    ("tokens.<host>", "Token for fetches from <host>, or env:<NAME> to read it from a variable (global config only)"),
+    ("policy", "Organisation policy file every generated flake must satisfy (global config only)"),
+    ("header", "Notice put as leading comments into generated files; {version} is the tool version"),
+    ("defaults.path", "Target directory used when --path is not given"),
+    ("profiles.<name>.languages", "Templates used by init --profile <name>"),
+    ("profiles.<name>.options.nixpkgs-ref", "nixpkgs-ref override for the profile"),
+    ("profiles.<name>.options.systems", "systems override for the profile"),
+    ("profiles.<name>.options.substituters", "substituters override for the profile"),
+    ("profiles.<name>.options.trusted-public-keys", "trusted-public-keys override for the profile"),
+    ("shells.<name>.languages", "Templates of the <name> devShell written by generate"),
+    ("aliases.<name>", "Comma-separated templates (or aliases) that <name> expands to"),
+];
+
+/// Names the global config file to read instead of the default one.
+pub const CONFIG_ENV: &str = "NFG_CONFIG";
+
+/// Environment variables overriding the config files, for CI systems and
+/// wrappers that can't write them. Command-line flags still win.
+pub const ENV_VARS: &[(&str, &str)] = &[
+    (CONFIG_ENV, "Config file read instead of the global one"),
+    ("NFG_TEMPLATE_DIR", "Template directories, separated like PATH, searched before template-dirs"),
+    ("NFG_NIXPKGS_REF", "nixpkgs-ref"),
+    ("NFG_SYSTEMS", "systems, comma-separated"),
+    ("NFG_SUBSTITUTERS", "substituters, comma-separated"),
+    ("NFG_TRUSTED_PUBLIC_KEYS", "trusted-public-keys, comma-separated"),
+    ("NFG_ON_CONFLICT", "on-conflict"),
+    ("NFG_OFFLINE", "offline: true or false (1 or 0)"),
+    ("NFG_PROXY", "proxy"),
+    ("NFG_PROVENANCE_COMMENTS", "provenance-comments: true or false"),
+    ("NFG_BANNER", "banner: true or false"),
+    ("NFG_FLAKES_HINT", "flakes-hint: true or false"),
+    ("NFG_HEADER", "header"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// nixpkgs branch, revision or full flake URL used for the nixpkgs input
+    pub nixpkgs_ref: Option<String>,
+    /// Systems the generated devShells are defined for
+    pub systems: Option<Vec<String>>,
+    /// Extra binary caches added to the generated flake's nixConfig
+    pub substituters: Option<Vec<String>>,
+    /// Public keys for the extra binary caches
+    pub trusted_public_keys: Option<Vec<String>>,
+    /// How merges settle templates that define the same attribute differently
+    pub on_conflict: Option<ConflictStrategy>,
+    /// Note the template each merged attribute came from in a comment
+    pub provenance_comments: Option<bool>,
+    /// Print the languages and toolchain versions on entering the shell
+    pub banner: Option<bool>,
+    /// Enable flakes in the generated nixConfig and check for them on
+    /// entering the shell
+    pub flakes_hint: Option<bool>,
+    /// Text injected at the `# @<anchor>` lines of every generated flake
+    pub snippets: BTreeMap<String, String>,
+    /// Directories containing additional `<name>.toml` + `<name>.nix` templates
+    pub template_dirs: Option<Vec<PathBuf>>,
+    /// Shell commands run in the target directory after `init` writes files.
+    /// Only honoured from the global config, never from a project's.
+    pub post_generate: Option<Vec<String>>,
+    /// Shell commands printing a JSON array of templates. Like hooks, only
+    /// honoured from the global config.
+    pub template_providers: Option<Vec<String>>,
+    /// minisign or ssh public keys that template provider manifests must be
+    /// signed with. Only honoured from the global config.
+    pub template_signing_keys: Option<Vec<String>>,
+    /// Never access the network, as with `--offline`
+    pub offline: Option<bool>,
+    /// Proxy for remote fetches, used when `HTTPS_PROXY` is unset
+    pub proxy: Option<String>,
+    /// Tokens for remote fetches by host, or `env:NAME` to read one from the
+    /// environment. Only honoured from the global config.
+    pub tokens: BTreeMap<String, String>,
+    /// Organisation policy file restricting inputs, substituters and
+    /// templates. Only honoured from the global config.
+    pub policy: Option<PathBuf>,
+    /// Days after which `explain --lock` flags a locked input as stale
+    pub lock_max_age: Option<u32>,
+    /// File of nixpkgs attribute names, one per line, that
+    /// `init --check-packages` uses when nix is not available
+    pub package_index: Option<PathBuf>,
+    /// Largest devShell closure `init` should produce, checked after writing
+    /// when nix is available
+    pub closure_budget: Option<ClosureBudget>,
+    /// Notice, such as a license or ownership line, written as leading
+    /// comments into every generated flake.nix and auxiliary file.
+    /// `{version}` stands for the tool version.
+    pub header: Option<String>,
+    /// Default values for command-line flags
+    pub defaults: Defaults,
+    /// Named language stacks usable with `init --profile <name>`
+    pub profiles: BTreeMap<String, Profile>,
+    /// Named devShells written together into one flake by `generate`
+    pub shells: BTreeMap<String, Shell>,
+    /// Alternative template names, expanding to one or more templates
+    pub aliases: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub languages: Vec<String>,
+    pub options: ProfileOptions,
+}
+
+/// A devShell of a project with several, such as one per team of a monorepo.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Shell {
+    pub languages: Vec<String>,
+}
+
+/// Generation settings a profile overrides on top of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileOptions {
+    pub nixpkgs_ref: Option<String>,
+    pub systems: Option<Vec<String>>,
+    pub substituters: Option<Vec<String>>,
+    pub trusted_public_keys: Option<Vec<String>>,
+}
+
+impl ProfileOptions {
+    pub fn apply_to(&self, options: &mut GenerationOptions) {
+        if let Some(nixpkgs_ref) = &self.nixpkgs_ref {
+            options.nixpkgs_ref = Some(nixpkgs_ref.clone());
+        }
+        if let Some(systems) = &self.systems {
+            options.systems = Some(systems.clone());
+        }
+        if let Some(substituters) = &self.substituters {
+            options.substituters = substituters.clone();
+        }
+        if let Some(keys) = &self.trusted_public_keys {
+            options.trusted_public_keys = keys.clone();
+        }
+    }
+}
+
+/// A limit on the size of a generated devShell's closure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClosureBudget {
+    /// Such as `2 GiB`
+    pub max: String,
+    /// Fail `init` when over budget, rather than warn
+    #[serde(default)]
+    pub fail: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Defaults {
+    /// Target directory used when `--path` is not given
+    pub path: Option<PathBuf>,
+}
+
+pub fn global_config_path() -> Result<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(dirs::dir(dirs::Kind::Config)?.join("config.toml"))
+}
+
+pub fn project_config_path() -> PathBuf {
+    PathBuf::from(PROJECT_CONFIG_FILE)
+}
+
+impl Config {
+    /// Load the global config overlaid with the project config, either of
+    /// which may be missing, and then with the `NFG_*` environment variables.
+    pub fn load() -> Result<Self> {
+        let global = Self::load_file(&global_config_path()?)?;
+        let mut project = Self::load_file(&project_config_path())?;
+        // A checked-out repository must not be able to run commands on init
+        if project.post_generate.take().is_some() {
+            tracing::warn!(
+                "Ignoring post-generate hooks in {}; set them in the global config or pass --hook",
+                PROJECT_CONFIG_FILE
+            );
+        }
+        if project.template_providers.take().is_some() {
+            tracing::warn!(
+                "Ignoring template-providers in {}; set them in the global config",
+                PROJECT_CONFIG_FILE
+            );
+        }
+        if project.template_signing_keys.take().is_some() {
+            tracing::warn!(
+                "Ignoring template-signing-keys in {}; set them in the global config",
+                PROJECT_CONFIG_FILE
+            );
+        }
+        if !std::mem::take(&mut project.tokens).is_empty() {
+            tracing::warn!(
+                "Ignoring tokens in {}; set them in the global config",
+                PROJECT_CONFIG_FILE
+            );
+        }
+        if project.policy.take().is_some() {
+            tracing::warn!(
+                "Ignoring policy in {}; set it in the global config or {}",
+                PROJECT_CONFIG_FILE,
+                crate::policy::POLICY_ENV
+            );
+        }
+        Ok(global.overlay(project).overlay(Self::from_env()?))
+    }
+
+    /// The settings of the `NFG_*` variables in [`ENV_VARS`].
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(name).filter(|value| !value.is_empty());
+        let list = |name: &str| {
+            var(name).map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+        };
+        let flag = |name: &str| -> Result<Option<bool>> {
+            let Some(value) = var(name) else {
+                return Ok(None);
+            };
+            match value.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => Ok(Some(true)),
+                "0" | "false" | "no" => Ok(Some(false)),
+                _ => Err(anyhow!("{} must be true or false, not '{}'", name, value)),
+            }
+        };
+        let on_conflict = match var("NFG_ON_CONFLICT") {
+            Some(value) => Some(toml::Value::String(value.clone()).try_into().map_err(|_| {
+                anyhow!(
+                    "NFG_ON_CONFLICT must be prefer-first, prefer-last or error, not '{}'",
+                    value
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(Self {
+            nixpkgs_ref: var("NFG_NIXPKGS_REF"),
+            systems: list("NFG_SYSTEMS"),
+            substituters: list("NFG_SUBSTITUTERS"),
+            trusted_public_keys: list("NFG_TRUSTED_PUBLIC_KEYS"),
+            on_conflict,
+            provenance_comments: flag("NFG_PROVENANCE_COMMENTS")?,
+            banner: flag("NFG_BANNER")?,
+            flakes_hint: flag("NFG_FLAKES_HINT")?,
+            template_dirs: var("NFG_TEMPLATE_DIR").map(|paths| env::split_paths(&paths).collect()),
+            offline: flag("NFG_OFFLINE")?,
+            proxy: var("NFG_PROXY"),
+            header: var("NFG_HEADER"),
+            ..Self::default()
+        })
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Combine two configs, with values set in `other` taking precedence.
+    /// Template directories accumulate, with `other`'s searched first.
+    pub fn overlay(self, other: Self) -> Self {
+        let template_dirs = match (other.template_dirs, self.template_dirs) {
+            (Some(mut first), Some(second)) => {
+                first.extend(second);
+                Some(first)
+            }
+            (first, second) => first.or(second),
+        };
+
+        Self {
+            nixpkgs_ref: other.nixpkgs_ref.or(self.nixpkgs_ref),
+            systems: other.systems.or(self.systems),
+            substituters: other.substituters.or(self.substituters),
+            trusted_public_keys: other.trusted_public_keys.or(self.trusted_public_keys),
+            on_conflict: other.on_conflict.or(self.on_conflict),
+            provenance_comments: other.provenance_comments.or(self.provenance_comments),
+            banner: other.banner.or(self.banner),
+            flakes_hint: other.flakes_hint.or(self.flakes_hint),
+            snippets: {
+                let mut snippets = self.snippets;
+                snippets.extend(other.snippets);
+                snippets
+            },
+            template_dirs,
+            post_generate: other.post_generate.or(self.post_generate),
+            template_providers: other.template_providers.or(self.template_providers),
+            template_signing_keys: other.template_signing_keys.or(self.template_signing_keys),
+            offline: other.offline.or(self.offline),
+            proxy: other.proxy.or(self.proxy),
+            tokens: {
+                let mut tokens = self.tokens;
+                tokens.extend(other.tokens);
+                tokens
+            },
+            policy: other.policy.or(self.policy),
+            lock_max_age: other.lock_max_age.or(self.lock_max_age),
+            package_index: other.package_index.or(self.package_index),
+            closure_budget: other.closure_budget.or(self.closure_budget),
+            header: other.header.or(self.header),
+            defaults: Defaults {
+                path: other.defaults.path.or(self.defaults.path),
+            },
+            profiles: {
+                let mut profiles = self.profiles;
+                profiles.extend(other.profiles);
+                profiles
+            },
+            shells: {
+                let mut shells = self.shells;
+                shells.extend(other.shells);
+                shells
+            },
+            aliases: {
+                let mut aliases = self.aliases;
+                aliases.extend(other.aliases);
+                aliases
+            },
+        }
+    }
+
+    /// Expand aliases in a list of template names. Aliases may refer to other
+    /// aliases; duplicates are dropped, keeping the first occurrence.
+    pub fn expand_aliases(&self, names: &[&str]) -> Result<Vec<String>> {
+        let mut expanded = Vec::new();
+        for name in names {
+            self.expand_alias(name, &mut Vec::new(), &mut expanded)?;
+        }
+        Ok(expanded)
+    }
+
+    fn expand_alias(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        expanded: &mut Vec<String>,
+    ) -> Result<()> {
+        let Some(target) = self.aliases.get(name) else {
+            if !expanded.iter().any(|n| n == name) {
+                expanded.push(name.to_string());
+            }
+            return Ok(());
+        };
+
+        if stack.iter().any(|n| n == name) {
+            stack.push(name.to_string());
+            return Err(anyhow!("Alias cycle detected: {}", stack.join(" -> ")));
+        }
+
+        stack.push(name.to_string());
+        for part in target.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            self.expand_alias(part, stack, expanded)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow!("Profile '{}' not found (no profiles are configured)", name)
+            } else {
+                anyhow!("Profile '{}' not found (available: {})", name, known.join(", "))
+            }
+        })
+    }
+
+    pub fn generation_options(&self) -> GenerationOptions {
+        GenerationOptions {
+            nixpkgs_ref: self.nixpkgs_ref.clone(),
+            systems: self.systems.clone(),
+            substituters: self.substituters.clone().unwrap_or_default(),
+            trusted_public_keys: self.trusted_public_keys.clone().unwrap_or_default(),
+            on_conflict: self.on_conflict.unwrap_or_default(),
+            provenance_comments: self.provenance_comments.unwrap_or_default(),
+            banner: self.banner.unwrap_or_default(),
+            flakes_hint: self.flakes_hint.unwrap_or_default(),
+            snippets: self.snippets.clone(),
+            // The rest are command-line only
+            ..Default::default()
+        }
+    }
+
+    /// Look up a dotted key such as `defaults.path` in the effective config.
+    pub fn get(&self, key: &str) -> Result<Option<toml::Value>> {
+        let value = toml::Value::try_from(self)?;
+        let mut current = &value;
+        for part in key.split('.') {
+            match current.get(part) {
+                Some(next) => current = next,
+                None => {
+                    validate_key(key)?;
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(Some(current.clone()))
+    }
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let mut probe = toml::Table::new();
+    insert_dotted(&mut probe, key, toml::Value::Array(Vec::new()));
+    // Wrong types are fine here; only unknown fields matter.
+    match toml::Value::Table(probe).try_into::<Config>() {
+        Err(e) if e.to_string().contains("unknown field") => {
+            Err(anyhow!("Unknown config key '{}'", key))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn insert_dotted(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(inner) = entry {
+                insert_dotted(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Interpret a command-line value as TOML (so lists and booleans work), and
+/// fall back to a plain string.
+fn parse_value(raw: &str) -> toml::Value {
+    let wrapped = format!("value = {raw}");
+    toml::from_str::<toml::Table>(&wrapped)
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Set a dotted key in the config file at `path`, keeping everything else in
+/// the file intact. The result must still be a valid config.
+pub fn set_value(path: &Path, key: &str, raw: &str) -> Result<()> {
+    let mut table = if path.exists() {
+        toml::from_str::<toml::Table>(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))?
+    } else {
+        toml::Table::new()
+    };
+
+    insert_dotted(&mut table, key, parse_value(raw));
+
+    toml::Value::Table(table.clone())
+        .try_into::<Config>()
+        .map_err(|e| anyhow!("Invalid value for '{}': {}", key, e))?;
+
+    crate::writer::write_atomic(path, toml::to_string_pretty(&table)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_prefers_project_values() {
+        let global = Config {
+            nixpkgs_ref: Some("nixos-24.05".to_string()),
+            systems: Some(vec!["x86_64-linux".to_string()]),
+            template_dirs: Some(vec![PathBuf::from("/global")]),
+            ..Default::default()
+        };
+        let project = Config {
+            nixpkgs_ref: Some("nixos-unstable".to_string()),
+            template_dirs: Some(vec![PathBuf::from("/project")]),
+            ..Default::default()
+        };
+
+        let merged = global.overlay(project);
+        assert_eq!(merged.nixpkgs_ref.as_deref(), Some("nixos-unstable"));
+        assert_eq!(merged.systems, Some(vec!["x86_64-linux".to_string()]));
+        assert_eq!(
+            merged.template_dirs,
+            Some(vec![PathBuf::from("/project"), PathBuf::from("/global")])
+        );
+    }
+
+    #[test]
+    fn test_profiles_parse_and_apply() {
+        let config: Config = toml::from_str(
+            r#"
+[profiles.webapp]
+languages = ["node", "elm"]
+options = { nixpkgs-ref = "nixos-24.05" }
+"#,
+        )
+        .unwrap();
+
+        let profile = config.profile("webapp").unwrap();
+        assert_eq!(profile.languages, vec!["node", "elm"]);
+
+        let mut options = GenerationOptions::default();
+        profile.options.apply_to(&mut options);
+        assert_eq!(options.nixpkgs_ref.as_deref(), Some("nixos-24.05"));
+
+        assert!(config.profile("missing").is_err());
+    }
+
+    #[test]
+    fn test_expand_aliases() {
+        let config: Config = toml::from_str(
+            r#"
+[aliases]
+js = "node"
+fullstack = "rust,js"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.expand_aliases(&["fullstack", "go", "node"]).unwrap(),
+            vec!["rust", "node", "go"]
+        );
+    }
+
+    #[test]
+    fn test_alias_cycle() {
+        let config: Config = toml::from_str(
+            r#"
+[aliases]
+a = "b"
+b = "rust,a"
+"#,
+        )
+        .unwrap();
+
+        let err = config.expand_aliases(&["a"]).unwrap_err();
+        assert!(err.to_string().contains("a -> b -> a"));
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("nixos-24.05"), toml::Value::String("nixos-24.05".to_string()));
+        assert_eq!(
+            parse_value(r#"["x86_64-linux"]"#),
+            toml::Value::Array(vec![toml::Value::String("x86_64-linux".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_get_unknown_key() {
+        let config = Config::default();
+        assert!(config.get("nixpkgs-ref").unwrap().is_none());
+        assert!(config.get("no-such-key").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = BTreeMap::from([
+            ("NFG_NIXPKGS_REF", "nixos-24.11"),
+            ("NFG_SYSTEMS", "x86_64-linux, aarch64-darwin"),
+            ("NFG_OFFLINE", "1"),
+            ("NFG_ON_CONFLICT", "error"),
+            ("NFG_PROXY", ""),
+        ]);
+        let env = Config::from_vars(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        let project = Config {
+            nixpkgs_ref: Some("nixos-unstable".to_string()),
+            proxy: Some("http://proxy:3128".to_string()),
+            ..Default::default()
+        };
+
+        let merged = project.overlay(env);
+        assert_eq!(merged.nixpkgs_ref.as_deref(), Some("nixos-24.11"));
+        assert_eq!(
+            merged.systems,
+            Some(vec!["x86_64-linux".to_string(), "aarch64-darwin".to_string()])
+        );
+        assert_eq!(merged.offline, Some(true));
+        assert_eq!(merged.on_conflict, Some(ConflictStrategy::Error));
+        // Empty variables are unset
+        assert_eq!(merged.proxy.as_deref(), Some("http://proxy:3128"));
+
+        let invalid = Config::from_vars(|name| (name == "NFG_BANNER").then(|| "maybe".to_string()));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_documented_keys_are_valid() {
+        for (key, _) in KEYS {
+            let key = key.replace("<name>", "example");
+            assert!(validate_key(&key).is_ok(), "{key} is documented but rejected");
+        }
+    }
+}
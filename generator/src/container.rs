@@ -0,0 +1,78 @@
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Both build the devShell inside the official nix image, so docker is all a
+// contributor needs; `dev-image` in the flake is the same shell built by nix
+const DOCKERFILE: &str = r#"# The development shell of this project, for contributors without nix.
+#
+#   docker build -t dev-shell .
+#   docker run --rm -it -v "$PWD":/workspace dev-shell
+#   docker run --rm -it -v "$PWD":/workspace dev-shell cargo test
+#
+# With nix, the same shell is the flake's dev-image package:
+#
+#   nix build .#dev-image && docker load < result
+FROM nixos/nix:latest
+
+RUN echo "experimental-features = nix-command flakes" >> /etc/nix/nix.conf
+
+WORKDIR /workspace
+COPY flake.nix flake.lock* ./
+
+# Fetch and build the shell once, so containers start without downloading it
+RUN nix develop path:/workspace --command true
+
+ENTRYPOINT ["nix", "develop", "path:/workspace", "--command"]
+CMD ["bash"]
+"#;
+
+const COMPOSE: &str = r#"# The development shell of this project, for contributors without nix.
+#
+#   docker compose run --rm dev
+#   docker compose run --rm dev cargo test
+services:
+  dev:
+    build: .
+    image: dev-shell
+    volumes:
+      - .:/workspace
+    stdin_open: true
+    tty: true
+"#;
+
+/// Write `Dockerfile` and `compose.yaml` into `target_path`.
+/// Returns the files that changed.
+pub fn write(target_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut transaction = FileTransaction::new();
+    let mut written = Vec::new();
+    for (name, content) in [("Dockerfile", DOCKERFILE), ("compose.yaml", COMPOSE)] {
+        let path = target_path.join(name);
+        if fs::read_to_string(&path).ok().as_deref() != Some(content) {
+            transaction.write(&path, content);
+            written.push(path);
+        }
+    }
+    transaction.commit()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_container_files() {
+        let dir = TempDir::new().unwrap();
+
+        let written = write(dir.path()).unwrap();
+        let unchanged = write(dir.path()).unwrap();
+        let dockerfile = fs::read_to_string(dir.path().join("Dockerfile")).unwrap();
+
+        assert_eq!(written.len(), 2);
+        assert!(unchanged.is_empty());
+        assert!(dockerfile.contains("RUN nix develop path:/workspace --command true"));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::nix;
+use crate::output::{self, OutputFormat};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageVersion {
+    pub name: String,
+    pub pname: Option<String>,
+    pub version: Option<String>,
+}
+
+// mkShell moves `packages` into nativeBuildInputs, so both lists are needed
+// to see everything the shell provides.
+const PACKAGES_EXPR: &str = r#"shell: map (p: {
+  name = p.name or "unknown";
+  pname = p.pname or null;
+  version = p.version or null;
+}) ((shell.nativeBuildInputs or [ ]) ++ (shell.buildInputs or [ ]))"#;
+
+pub fn resolve_packages(target_path: &Path) -> Result<Vec<PackageVersion>> {
+    let flake = nix::flake_ref(target_path)?;
+    nix::require_nix()?;
+    let installable = nix::dev_shell_installable(&flake)?;
+    let value = nix::eval_json(&installable, PACKAGES_EXPR)?;
+
+    let mut packages: Vec<PackageVersion> = serde_json::from_value(value)?;
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages.dedup_by(|a, b| a.name == b.name);
+
+    Ok(packages)
+}
+
+pub fn print_packages(packages: &[PackageVersion], format: OutputFormat) -> Result<()> {
+    if format.is_json() {
+        return output::print_json("deps", &packages);
+    }
+
+    println!("Resolved packages:");
+    for package in packages {
+        let name = package.pname.as_deref().unwrap_or(&package.name);
+        match &package.version {
+            Some(version) => println!("  {name} {version}"),
+            None => println!("  {name}"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,413 @@
+use crate::migrate::Imported;
+use crate::remote::Remote;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Stage names taken to be the development stage of a multi-stage build.
+const DEV_STAGES: &[&str] = &["dev", "develop", "development", "devcontainer"];
+
+/// Base images that provide no toolchain of their own.
+const OS_IMAGES: &[&str] = &[
+    "alpine",
+    "almalinux",
+    "amazonlinux",
+    "archlinux",
+    "busybox",
+    "centos",
+    "debian",
+    "distroless",
+    "fedora",
+    "rockylinux",
+    "scratch",
+    "ubuntu",
+];
+
+/// Directories holding third-party code rather than the project's own.
+const VENDORED_DIRS: &[&str] = &["node_modules", "vendor", "third_party", ".git"];
+
+/// One `FROM` of a Dockerfile with the commands it runs.
+#[derive(Debug, Default)]
+struct Stage {
+    image: String,
+    name: Option<String>,
+    runs: Vec<String>,
+}
+
+/// Read the toolchains a Dockerfile installs: those of its base images and
+/// of packages installed with apt, apk, dnf or yum. Only the development
+/// stage (`AS dev` and similar) and the stages it builds on are scanned
+/// when there is one.
+pub fn from_dockerfile(path: &Path) -> Result<Imported> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let stages = parse_stages(&content);
+    let aliases: Vec<&str> = stages.iter().filter_map(|s| s.name.as_deref()).collect();
+
+    let mut imported = Imported {
+        file: path.to_path_buf(),
+        ..Default::default()
+    };
+    for stage in dev_stages(&stages) {
+        if !aliases.contains(&stage.image.as_str()) {
+            add_image(&stage.image, &mut imported);
+        }
+        for run in &stage.runs {
+            add_run(run, &mut imported);
+        }
+    }
+    Ok(imported)
+}
+
+fn parse_stages(content: &str) -> Vec<Stage> {
+    let mut stages: Vec<Stage> = Vec::new();
+    let mut instruction = String::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || (line.is_empty() && instruction.is_empty()) {
+            continue;
+        }
+        if let Some(continued) = line.strip_suffix('\\') {
+            instruction.push_str(continued);
+            instruction.push(' ');
+            continue;
+        }
+        instruction.push_str(line);
+
+        let mut words = instruction.split_whitespace();
+        match words.next().map(str::to_ascii_uppercase).as_deref() {
+            Some("FROM") => {
+                let args: Vec<&str> = words.filter(|word| !word.starts_with("--")).collect();
+                stages.push(Stage {
+                    image: args.first().unwrap_or(&"").to_string(),
+                    name: match args[..] {
+                        [_, keyword, name] if keyword.eq_ignore_ascii_case("as") => {
+                            Some(name.to_string())
+                        }
+                        _ => None,
+                    },
+                    runs: Vec::new(),
+                });
+            }
+            Some("RUN") => {
+                if let Some(stage) = stages.last_mut() {
+                    stage.runs.push(words.collect::<Vec<_>>().join(" "));
+                }
+            }
+            _ => {}
+        }
+        instruction.clear();
+    }
+
+    stages
+}
+
+/// The development stage and the stages it is built `FROM`, or every stage
+/// when none is named like a development stage.
+fn dev_stages(stages: &[Stage]) -> Vec<&Stage> {
+    let Some(mut current) = stages.iter().find(|stage| {
+        stage
+            .name
+            .as_deref()
+            .is_some_and(|name| DEV_STAGES.contains(&name.to_ascii_lowercase().as_str()))
+    }) else {
+        return stages.iter().collect();
+    };
+
+    let mut chain = vec![current];
+    while let Some(base) = stages
+        .iter()
+        .find(|stage| stage.name.as_deref() == Some(current.image.as_str()))
+    {
+        if chain.iter().any(|stage| std::ptr::eq(*stage, base)) {
+            break;
+        }
+        chain.push(base);
+        current = base;
+    }
+    chain.reverse();
+    chain
+}
+
+fn add_image(image: &str, imported: &mut Imported) {
+    let reference = image.split('@').next().unwrap_or(image);
+    let (repository, tag) = match reference.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+        _ => (reference, "latest"),
+    };
+    let name = repository.rsplit('/').next().unwrap_or(repository);
+
+    let tool = match name {
+        "golang" => Some(("go", tag)),
+        "python" | "node" | "rust" | "ruby" | "php" | "elixir" | "erlang" | "haskell"
+        | "swift" | "clojure" | "bun" | "zig" => Some((name, tag)),
+        "openjdk" | "eclipse-temurin" | "amazoncorretto" | "ibm-semeru-runtimes" => {
+            Some(("java", tag))
+        }
+        "maven" | "gradle" => Some(("java", "latest")),
+        "gcc" => Some(("c", tag)),
+        "sdk" if repository.ends_with("dotnet/sdk") => Some(("dotnet", tag)),
+        _ => None,
+    };
+    match tool {
+        Some((tool, version)) => push_tool(imported, tool, version),
+        None if OS_IMAGES.iter().any(|os| name.starts_with(os)) => {}
+        None => imported.skipped.push(format!("FROM {image}: no matching template")),
+    }
+}
+
+/// Packages installed by a `RUN` command.
+fn add_run(command: &str, imported: &mut Imported) {
+    if command.contains("rustup") {
+        push_tool(imported, "rust", "latest");
+    }
+
+    for part in command.split(['&', ';', '|']) {
+        let words: Vec<&str> = part.split_whitespace().collect();
+        let packages = match words[..] {
+            ["apt-get" | "apt" | "dnf" | "yum" | "microdnf", ref rest @ ..] => {
+                match rest.iter().position(|word| *word == "install") {
+                    Some(i) => &rest[i + 1..],
+                    None => continue,
+                }
+            }
+            ["apk", "add", ref rest @ ..] => rest,
+            _ => continue,
+        };
+
+        for package in packages.iter().filter(|word| !word.starts_with('-')) {
+            // apt pins `name=version`, apk `name=version` or `name~version`
+            let name = package.split(['=', '~']).next().unwrap_or(package);
+            add_system_package(name, imported);
+        }
+    }
+}
+
+fn add_system_package(name: &str, imported: &mut Imported) {
+    let digits: String = name.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect();
+    let version = if digits.trim_matches('.').is_empty() {
+        "latest"
+    } else {
+        digits.trim_matches('.')
+    };
+
+    let tool = match name {
+        "golang" | "golang-go" | "go" => Some("go"),
+        "nodejs" | "npm" => Some("node"),
+        "rustc" | "cargo" => Some("rust"),
+        "ruby" | "ruby-dev" | "ruby-full" => Some("ruby"),
+        "php" | "php-cli" => Some("php"),
+        "elixir" => Some("elixir"),
+        "ghc" | "cabal-install" => Some("haskell"),
+        "zig" => Some("zig"),
+        "build-essential" | "gcc" | "g++" | "clang" | "cmake" => Some("c"),
+        _ if name.starts_with("python3") || name == "python" => Some("python"),
+        _ if name.starts_with("openjdk") || name.starts_with("temurin") => Some("java"),
+        _ => None,
+    };
+    if let Some(tool) = tool {
+        let version = if matches!(tool, "python" | "java") { version } else { "latest" };
+        push_tool(imported, tool, version);
+        return;
+    }
+
+    let package = match name {
+        "git" | "curl" | "wget" | "jq" | "unzip" | "zip" | "ripgrep" | "rsync" | "gnupg" => name,
+        "make" => "gnumake",
+        "openssh-client" => "openssh",
+        "postgresql-client" => "postgresql",
+        "pkg-config" | "pkgconf" => "pkg-config",
+        "ca-certificates" | "locales" | "tzdata" | "sudo" | "software-properties-common" => {
+            return;
+        }
+        _ => {
+            imported
+                .skipped
+                .push(format!("system package {name}: add its nixpkgs equivalent by hand"));
+            return;
+        }
+    };
+    if !imported.packages.iter().any(|p| p == package) {
+        imported.packages.push(package.to_string());
+    }
+}
+
+fn push_tool(imported: &mut Imported, tool: &str, version: &str) {
+    match imported.tools.iter_mut().find(|(name, _)| name == tool) {
+        // A pinned version beats `latest` from another line
+        Some((_, existing)) if existing == "latest" => *existing = version.to_string(),
+        Some(_) => {}
+        None => imported.tools.push((tool.to_string(), version.to_string())),
+    }
+}
+
+/// The paths of every file in a remote repository's default branch. Only
+/// the trees are fetched: a shallow, blob-less clone into a scratch
+/// directory that is removed again.
+pub fn remote_files(url: &str, remote: &Remote) -> Result<Vec<String>> {
+    let scratch = tempfile::Builder::new()
+        .prefix("nix-flake-generator-repo-")
+        .tempdir()?;
+    remote.shallow_clone(url, scratch.path(), true)?;
+
+    let tree = Command::new("git")
+        .arg("-C")
+        .arg(scratch.path())
+        .args(["ls-tree", "-r", "--name-only", "HEAD"])
+        .output()
+        .context("Failed to run git")?;
+    if !tree.status.success() {
+        return Err(anyhow::anyhow!(
+            "Listing the files of {url} failed: {}",
+            String::from_utf8_lossy(&tree.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&tree.stdout).lines().map(String::from).collect())
+}
+
+/// The templates whose marker files (`Cargo.toml`, `go.mod`, `*.csproj`...)
+/// appear among `files`, ignoring vendored directories.
+pub fn templates_for_files<S: AsRef<str>>(files: &[S]) -> BTreeSet<&'static str> {
+    let mut templates = BTreeSet::new();
+    for path in files.iter().map(AsRef::as_ref) {
+        let mut components: Vec<&str> = path.split('/').collect();
+        let name = components.pop().unwrap_or_default();
+        if components.iter().any(|dir| VENDORED_DIRS.contains(dir)) {
+            continue;
+        }
+        if components.last() == Some(&"cue.mod") {
+            templates.insert("cue");
+        }
+        if let Some(template) = template_for_file(name) {
+            templates.insert(template);
+        }
+    }
+
+    // A pinned toolchain or bun lockfile refines what the manifest says
+    if templates.contains("rust-toolchain") {
+        templates.remove("rust");
+    }
+    if templates.contains("bun") {
+        templates.remove("node");
+    }
+    templates
+}
+
+fn template_for_file(name: &str) -> Option<&'static str> {
+    let template = match name {
+        "Cargo.toml" => "rust",
+        "rust-toolchain" | "rust-toolchain.toml" => "rust-toolchain",
+        "go.mod" => "go",
+        "package.json" => "node",
+        "bun.lock" | "bun.lockb" => "bun",
+        "pyproject.toml" | "requirements.txt" | "setup.py" | "Pipfile" => "python",
+        "Gemfile" => "ruby",
+        "mix.exs" => "elixir",
+        "build.zig" => "zig",
+        "CMakeLists.txt" | "meson.build" => "c-cpp",
+        "pom.xml" | "build.gradle" => "java",
+        "build.gradle.kts" => "kotlin",
+        "build.sbt" => "scala",
+        "project.clj" | "deps.edn" => "clojure",
+        "stack.yaml" | "cabal.project" => "haskell",
+        "dune-project" => "ocaml",
+        "composer.json" => "php",
+        "Package.swift" => "swift",
+        "gleam.toml" => "gleam",
+        "elm.json" => "elm",
+        "Pulumi.yaml" => "pulumi",
+        "buf.yaml" => "protobuf",
+        "renv.lock" | "DESCRIPTION" => "r",
+        "v.mod" => "vlang",
+        _ => {
+            let (_, extension) = name.rsplit_once('.')?;
+            match extension {
+                "cabal" => "haskell",
+                "csproj" | "fsproj" | "sln" => "csharp",
+                "nimble" => "nim",
+                "tf" => "hashi",
+                "proto" => "protobuf",
+                "rego" => "opa",
+                "dhall" => "dhall",
+                "ncl" => "nickel",
+                "tex" => "latex",
+                _ => return None,
+            }
+        }
+    };
+    Some(template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCKERFILE: &str = r#"
+FROM golang:1.22-alpine AS dev
+RUN apk add --no-cache git make \
+    python3 postgresql-client libxml2
+# Build and ship
+FROM dev AS build
+RUN go build ./...
+
+FROM gcr.io/distroless/static
+"#;
+
+    #[test]
+    fn test_parse_stages() {
+        let stages = parse_stages(DOCKERFILE);
+        assert_eq!(stages.len(), 3);
+        assert_eq!(stages[0].name.as_deref(), Some("dev"));
+        assert_eq!(
+            stages[0].runs,
+            vec!["apk add --no-cache git make python3 postgresql-client libxml2"]
+        );
+        assert_eq!(stages[1].image, "dev");
+    }
+
+    #[test]
+    fn test_dev_stage_toolchains() {
+        let stages = parse_stages(DOCKERFILE);
+        let mut imported = Imported::default();
+        for stage in dev_stages(&stages) {
+            add_image(&stage.image, &mut imported);
+            for run in &stage.runs {
+                add_run(run, &mut imported);
+            }
+        }
+
+        assert_eq!(
+            imported.tools,
+            vec![
+                ("go".to_string(), "1.22-alpine".to_string()),
+                ("python".to_string(), "3".to_string()),
+            ]
+        );
+        assert_eq!(imported.packages, vec!["git", "gnumake", "postgresql"]);
+        assert_eq!(
+            imported.skipped,
+            vec!["system package libxml2: add its nixpkgs equivalent by hand"]
+        );
+    }
+
+    #[test]
+    fn test_templates_for_files() {
+        let files = [
+            "Cargo.toml",
+            "rust-toolchain.toml",
+            "web/package.json",
+            "web/node_modules/left-pad/package.json",
+            "api/proto/service.proto",
+            "infra/main.tf",
+            "vendor/github.com/x/go.mod",
+            "README.md",
+        ];
+        assert_eq!(
+            templates_for_files(&files).into_iter().collect::<Vec<_>>(),
+            vec!["hashi", "node", "protobuf", "rust-toolchain"]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use crate::output;
+use crate::pipe;
+use crate::writer::FileTransaction;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use nix_parser::ParseError;
+use nix_parser::lint::{self, Rule, Severity};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories never searched for nix files.
+const SKIPPED_DIRS: &[&str] = &[".git", ".direnv", "node_modules", "result"];
+
+/// Rule id of files that don't parse.
+const SYNTAX_RULE: &str = "syntax";
+
+/// The least severe finding `lint` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum MinSeverity {
+    #[default]
+    Warning,
+    Error,
+}
+
+impl MinSeverity {
+    fn includes(self, severity: Severity) -> bool {
+        match self {
+            MinSeverity::Warning => true,
+            MinSeverity::Error => severity == Severity::Error,
+        }
+    }
+}
+
+/// A problem found in one nix file.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub file: PathBuf,
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub line: Option<usize>,
+    /// `--fix` ran but left it in place, such as an entry sharing its line
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unfixed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub files: usize,
+    /// Problems fixed by `--fix`
+    pub fixed: usize,
+    pub findings: Vec<Finding>,
+}
+
+/// Lint the nix files in `paths`, searching directories recursively.
+/// With `fix`, unused let bindings and duplicate list entries are removed
+/// first and only what's left is reported, marked when it could have been
+/// fixed but was left in place, such as an entry sharing its line.
+pub fn run(paths: &[PathBuf], min: MinSeverity, fix: bool) -> Result<Report> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            return Err(anyhow!("{} does not exist", path.display()));
+        }
+    }
+
+    let mut transaction = FileTransaction::new();
+    let mut report = Report {
+        files: files.len(),
+        fixed: 0,
+        findings: Vec::new(),
+    };
+    for file in files {
+        let original = fs::read_to_string(&file)
+            .map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e))?;
+        let text = lint_source(&file, &original, min, fix, &mut report);
+        if text != original {
+            transaction.write(&file, text);
+        }
+    }
+    transaction.commit()?;
+    Ok(report)
+}
+
+/// Lint nix source read from stdin, returning the report and the source with
+/// the fixes of `fix` applied.
+pub fn run_stdin(min: MinSeverity, fix: bool) -> Result<(Report, String)> {
+    let (name, original) = pipe::read_source(None)?;
+    let mut report = Report {
+        files: 1,
+        fixed: 0,
+        findings: Vec::new(),
+    };
+    let text = lint_source(Path::new(&name), &original, min, fix, &mut report);
+    Ok((report, text))
+}
+
+/// Add the findings of one file to `report`, returning its text with the
+/// fixes of `fix` applied.
+fn lint_source(
+    file: &Path,
+    original: &str,
+    min: MinSeverity,
+    fix: bool,
+    report: &mut Report,
+) -> String {
+    let mut text = original.to_string();
+    let diagnostics = loop {
+        let diagnostics = match lint::lint(&text) {
+            Ok(diagnostics) => diagnostics,
+            Err(error) => {
+                report.findings.push(syntax_finding(file, &error));
+                break Vec::new();
+            }
+        };
+        // Fixing one binding can leave another unused, so go again
+        let fixable: Vec<_> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.fix.is_some() && min.includes(diagnostic.severity))
+            .cloned()
+            .collect();
+        if !fix || fixable.is_empty() {
+            break diagnostics;
+        }
+        report.fixed += fixable.len();
+        text = lint::apply_fixes(&text, &fixable);
+    };
+
+    report.findings.extend(
+        diagnostics
+            .into_iter()
+            .filter(|diagnostic| min.includes(diagnostic.severity))
+            .map(|diagnostic| Finding {
+                file: file.to_path_buf(),
+                rule: diagnostic.rule.id(),
+                severity: diagnostic.severity,
+                message: diagnostic.message,
+                line: diagnostic.line,
+                unfixed: fix && diagnostic.rule.fixable(),
+            }),
+    );
+    text
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name) {
+                collect(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "nix") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn syntax_finding(file: &Path, error: &ParseError) -> Finding {
+    let line = match error {
+        ParseError::Syntax { line, .. } | ParseError::TrailingInput { line, .. } => Some(*line),
+        ParseError::Analysis(_) => None,
+    };
+    Finding {
+        file: file.to_path_buf(),
+        rule: SYNTAX_RULE,
+        severity: Severity::Error,
+        message: error.to_string(),
+        line,
+        unfixed: false,
+    }
+}
+
+/// Print the findings: to stderr, apart from the status messages, unless
+/// `stdout`, as when stdin was linted without `--fix`.
+pub fn print_report(report: &Report, format: output::OutputFormat, stdout: bool) -> Result<()> {
+    if format.is_json() {
+        return output::print_json("lint", report);
+    }
+    for finding in &report.findings {
+        let location = match finding.line {
+            Some(line) => format!("{}:{line}", finding.file.display()),
+            None => finding.file.display().to_string(),
+        };
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let unfixed = if finding.unfixed { " (left in place by --fix)" } else { "" };
+        let line = format!("{location}: {severity}: {} [{}]{unfixed}", finding.message, finding.rule);
+        if stdout {
+            println!("{line}");
+        } else {
+            eprintln!("{line}");
+        }
+    }
+    Ok(())
+}
+
+/// The report as a SARIF 2.1.0 log, which CI systems turn into annotations.
+pub fn sarif(report: &Report) -> Value {
+    let level = |severity: Severity| match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut rules: Vec<Value> = Rule::ALL
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id(),
+                "shortDescription": { "text": rule.description() },
+                "defaultConfiguration": { "level": level(rule.severity()) },
+            })
+        })
+        .collect();
+    rules.push(json!({
+        "id": SYNTAX_RULE,
+        "shortDescription": { "text": "A file that doesn't parse as nix" },
+        "defaultConfiguration": { "level": "error" },
+    }));
+
+    let results: Vec<Value> = report
+        .findings
+        .iter()
+        .map(|finding| {
+            // SARIF URIs use forward slashes on every platform
+            let file = finding.file.strip_prefix(".").unwrap_or(&finding.file);
+            let uri = file.to_string_lossy().replace('\\', "/");
+            let mut location = json!({ "artifactLocation": { "uri": uri } });
+            if let Some(line) = finding.line {
+                location["region"] = json!({ "startLine": line });
+            }
+            json!({
+                "ruleId": finding.rule,
+                "level": level(finding.severity),
+                "message": { "text": finding.message },
+                "locations": [{ "physicalLocation": location }],
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "nix-flake-generator",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_fixes_and_reports() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".direnv")).unwrap();
+        fs::write(
+            dir.path().join("shell.nix"),
+            "{ pkgs }:\nlet\n  unused = 1;\n  used = 2;\nin\n[\n  used\n  used\n  missing\n]\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join(".direnv").join("ignored.nix"), "{").unwrap();
+
+        let report = run(&[dir.path().to_path_buf()], MinSeverity::Warning, true).unwrap();
+        let fixed = fs::read_to_string(dir.path().join("shell.nix")).unwrap();
+
+        assert_eq!(report.files, 1);
+        assert_eq!(report.fixed, 2);
+        assert_eq!(fixed, "{ pkgs }:\nlet\n  used = 2;\nin\n[\n  used\n  missing\n]\n");
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].rule, "undefined-variable");
+        assert_eq!(report.findings[0].line, Some(7));
+
+        let result = &sarif(&report)["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "undefined-variable");
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 7);
+    }
+
+    #[test]
+    fn test_run_leaves_inline_duplicates() {
+        let dir = TempDir::new().unwrap();
+        let input = "{ x }:\n[ x x \"s\" \"s\" ]\n";
+        fs::write(dir.path().join("a.nix"), input).unwrap();
+
+        let report = run(&[dir.path().to_path_buf()], MinSeverity::Warning, true).unwrap();
+        let after = fs::read_to_string(dir.path().join("a.nix")).unwrap();
+
+        assert_eq!(report.fixed, 0);
+        assert_eq!(after, input);
+        let placed: Vec<_> = report.findings.iter().map(|f| (f.line, f.unfixed)).collect();
+        assert_eq!(placed, vec![(Some(2), true), (Some(2), true)]);
+
+        let result = &sarif(&report)["runs"][0]["results"][0];
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 2);
+    }
+}
@@ -0,0 +1,139 @@
+/// A single line of a line-based diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line diff of two texts based on their longest common subsequence.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let (n, m) = (old_lines.len(), new_lines.len());
+
+    // lcs[i][j] = LCS length of old_lines[i..] and new_lines[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_lines[i] == new_lines[j] {
+            result.push(DiffLine::Same(old_lines[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(DiffLine::Removed(old_lines[i]));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(new_lines[j]));
+            j += 1;
+        }
+    }
+    result.extend(old_lines[i..].iter().copied().map(DiffLine::Removed));
+    result.extend(new_lines[j..].iter().copied().map(DiffLine::Added));
+    result
+}
+
+/// Unified diff with three lines of context, or an empty string when the
+/// texts are identical.
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    const CONTEXT: usize = 3;
+    let lines = diff_lines(old, new);
+    if lines.iter().all(|line| matches!(line, DiffLine::Same(_))) {
+        return String::new();
+    }
+
+    let mut output = format!("--- {old_label}\n+++ {new_label}\n");
+
+    // Group changes into hunks, merging those separated by little context
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changed {
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let old_start = 1 + lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Added(_)))
+            .count();
+        let new_start = 1 + lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Removed(_)))
+            .count();
+        let old_count = lines[start..end]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Added(_)))
+            .count();
+        let new_count = lines[start..end]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Removed(_)))
+            .count();
+
+        output.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+        for line in &lines[start..end] {
+            match line {
+                DiffLine::Same(text) => output.push_str(&format!(" {text}\n")),
+                DiffLine::Removed(text) => output.push_str(&format!("-{text}\n")),
+                DiffLine::Added(text) => output.push_str(&format!("+{text}\n")),
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = diff_lines("a\nb\nc\n", "a\nx\nc\n");
+        assert_eq!(
+            lines,
+            vec![
+                DiffLine::Same("a"),
+                DiffLine::Removed("b"),
+                DiffLine::Added("x"),
+                DiffLine::Same("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unified_identical_is_empty() {
+        assert_eq!(unified("a\nb\n", "a\nb\n", "old", "new"), "");
+    }
+
+    #[test]
+    fn test_unified_hunk_header() {
+        let diff = unified("a\nb\nc\n", "a\nb\nc\nd\n", "old", "new");
+        assert!(diff.starts_with("--- old\n+++ new\n"));
+        assert!(diff.contains("@@ -1,3 +1,4 @@"));
+        assert!(diff.contains("+d\n"));
+    }
+}
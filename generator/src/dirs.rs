@@ -0,0 +1,136 @@
+use anyhow::{Result, anyhow};
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+const APP: &str = "nix-flake-generator";
+
+/// A kind of state the tool keeps outside of projects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The global config file
+    Config,
+    /// Templates installed by `template sync`
+    Data,
+    /// Cached shells, safe to delete
+    Cache,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    Unix,
+    MacOs,
+    Windows,
+}
+
+impl Kind {
+    fn xdg_var(self) -> &'static str {
+        match self {
+            Kind::Config => "XDG_CONFIG_HOME",
+            Kind::Data => "XDG_DATA_HOME",
+            Kind::Cache => "XDG_CACHE_HOME",
+        }
+    }
+
+    /// Where the XDG spec puts the directory when its variable is unset.
+    fn xdg_default(self) -> PathBuf {
+        match self {
+            Kind::Config => PathBuf::from(".config"),
+            Kind::Data => PathBuf::from(".local").join("share"),
+            Kind::Cache => PathBuf::from(".cache"),
+        }
+    }
+}
+
+/// The tool's directory for `kind`. An XDG variable is honoured on every
+/// platform; otherwise Linux and other unixes use the XDG defaults, macOS
+/// `~/Library/Application Support` (and `~/Library/Caches`) and Windows
+/// `%APPDATA%` (and `%LOCALAPPDATA%`). Directories an earlier version
+/// created under the XDG defaults keep being used on macOS and Windows.
+pub fn dir(kind: Kind) -> Result<PathBuf> {
+    let platform = if cfg!(target_os = "macos") {
+        Platform::MacOs
+    } else if cfg!(windows) {
+        Platform::Windows
+    } else {
+        Platform::Unix
+    };
+    resolve(kind, platform, |name| env::var_os(name), |path| path.exists())
+}
+
+fn resolve(
+    kind: Kind,
+    platform: Platform,
+    lookup: impl Fn(&str) -> Option<OsString>,
+    exists: impl Fn(&Path) -> bool,
+) -> Result<PathBuf> {
+    let var = |name: &str| lookup(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    if let Some(base) = var(kind.xdg_var()) {
+        return Ok(base.join(APP));
+    }
+
+    let home = var("HOME").or_else(|| var("USERPROFILE"));
+    let legacy = home.as_ref().map(|home| home.join(kind.xdg_default()).join(APP));
+    let native = match platform {
+        Platform::Unix => None,
+        Platform::MacOs => home.as_ref().map(|home| {
+            let library = home.join("Library");
+            match kind {
+                Kind::Cache => library.join("Caches").join(APP),
+                Kind::Config | Kind::Data => library.join("Application Support").join(APP),
+            }
+        }),
+        Platform::Windows => match kind {
+            Kind::Config => var("APPDATA").map(|dir| dir.join(APP).join("config")),
+            Kind::Data => var("LOCALAPPDATA").map(|dir| dir.join(APP).join("data")),
+            Kind::Cache => var("LOCALAPPDATA").map(|dir| dir.join(APP).join("cache")),
+        },
+    };
+
+    match (native, legacy) {
+        (Some(_), Some(legacy)) if exists(legacy.as_path()) => Ok(legacy),
+        (Some(native), _) => Ok(native),
+        (None, Some(legacy)) => Ok(legacy),
+        (None, None) => Err(anyhow!("HOME is not set")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn resolve_with(kind: Kind, platform: Platform, vars: &[(&str, &str)]) -> PathBuf {
+        let vars: BTreeMap<&str, &str> = vars.iter().copied().collect();
+        resolve(kind, platform, |name| vars.get(name).map(OsString::from), |_| false).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let home = [("HOME", "/home/me")];
+        assert_eq!(
+            resolve_with(Kind::Cache, Platform::Unix, &home),
+            PathBuf::from("/home/me/.cache/nix-flake-generator")
+        );
+        assert_eq!(
+            resolve_with(Kind::Config, Platform::MacOs, &home),
+            PathBuf::from("/home/me/Library/Application Support/nix-flake-generator")
+        );
+        let xdg = [("HOME", "/home/me"), ("XDG_DATA_HOME", "/data")];
+        assert_eq!(
+            resolve_with(Kind::Data, Platform::MacOs, &xdg),
+            PathBuf::from("/data/nix-flake-generator")
+        );
+        assert_eq!(
+            resolve_with(Kind::Cache, Platform::Windows, &[("LOCALAPPDATA", "/local")]),
+            PathBuf::from("/local/nix-flake-generator/cache")
+        );
+    }
+
+    #[test]
+    fn test_resolve_keeps_existing_xdg_dir() {
+        let vars = |name: &str| (name == "HOME").then(|| OsString::from("/home/me"));
+        let dir = resolve(Kind::Config, Platform::MacOs, vars, |_| true).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/me/.config/nix-flake-generator"));
+    }
+}
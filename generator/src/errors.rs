@@ -0,0 +1,176 @@
+use crate::color;
+use crate::nix::NixError;
+use crate::output::{OutputFormat, SCHEMA_VERSION};
+use nix_flake_generator_core::{Error as CoreError, MergeError, TemplateError};
+use serde_json::json;
+use std::fmt;
+
+/// Exit code clap uses for invalid command-line usage.
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+/// Failure categories with stable exit codes. Exit code 2 is left to clap
+/// for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other,
+    UnknownTemplate,
+    ParseFailure,
+    MergeConflict,
+    Io,
+    NixValidation,
+    PolicyViolation,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 7] = [
+        ErrorKind::Other,
+        ErrorKind::UnknownTemplate,
+        ErrorKind::ParseFailure,
+        ErrorKind::MergeConflict,
+        ErrorKind::Io,
+        ErrorKind::NixValidation,
+        ErrorKind::PolicyViolation,
+    ];
+
+    pub fn code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::UnknownTemplate => 3,
+            ErrorKind::ParseFailure => 4,
+            ErrorKind::MergeConflict => 5,
+            ErrorKind::Io => 6,
+            ErrorKind::NixValidation => 7,
+            ErrorKind::PolicyViolation => 8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::UnknownTemplate => "unknown_template",
+            ErrorKind::ParseFailure => "parse_failure",
+            ErrorKind::MergeConflict => "merge_conflict",
+            ErrorKind::Io => "io",
+            ErrorKind::NixValidation => "nix_validation",
+            ErrorKind::PolicyViolation => "policy_violation",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "Any other failure",
+            ErrorKind::UnknownTemplate => "A template name is not in the registry",
+            ErrorKind::ParseFailure => "A template or generated flake failed to parse",
+            ErrorKind::MergeConflict => {
+                "An existing flake.nix would be overwritten, or templates conflict under --on-conflict error"
+            }
+            ErrorKind::Io => "Reading or writing files failed",
+            ErrorKind::NixValidation => "--verify reported a problem",
+            ErrorKind::PolicyViolation => {
+                "The organisation policy file forbids a template, input or missing substituter"
+            }
+        }
+    }
+}
+
+/// An error tagged with its category, carried inside `anyhow::Error`.
+#[derive(Debug)]
+pub struct CodedError {
+    pub kind: ErrorKind,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+pub fn coded(kind: ErrorKind, message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(CodedError {
+        kind,
+        message: message.into(),
+    })
+}
+
+/// The first explicit tag in the error chain wins; untagged parser and IO
+/// errors are recognised by type.
+pub fn classify(error: &anyhow::Error) -> ErrorKind {
+    for cause in error.chain() {
+        if let Some(coded) = cause.downcast_ref::<CodedError>() {
+            return coded.kind;
+        }
+        if let Some(error) = cause.downcast_ref::<CoreError>() {
+            return match error {
+                CoreError::Template(TemplateError::Unknown { .. }) => ErrorKind::UnknownTemplate,
+                CoreError::Template(_) | CoreError::Merge(MergeError::NoTemplates) => {
+                    ErrorKind::Other
+                }
+                CoreError::Parse(_)
+                | CoreError::Merge(MergeError::Parse { .. })
+                | CoreError::InvalidOutput { .. } => ErrorKind::ParseFailure,
+                CoreError::Merge(MergeError::Conflict { .. }) => ErrorKind::MergeConflict,
+                CoreError::Io(_) => ErrorKind::Io,
+            };
+        }
+        if let Some(error) = cause.downcast_ref::<NixError>() {
+            return match error {
+                NixError::VerificationFailed(_) => ErrorKind::NixValidation,
+                _ => ErrorKind::Other,
+            };
+        }
+        if cause.is::<nix_parser::ParseError>() {
+            return ErrorKind::ParseFailure;
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorKind::Io;
+        }
+    }
+    ErrorKind::Other
+}
+
+/// Print an error to stderr in the requested format and return the exit code.
+pub fn report(error: &anyhow::Error, format: OutputFormat) -> i32 {
+    let kind = classify(error);
+    if format.is_json() {
+        let document = json!({
+            "schema_version": SCHEMA_VERSION,
+            "error": {
+                "code": kind.code(),
+                "kind": kind.name(),
+                "message": format!("{error:#}"),
+            },
+        });
+        eprintln!("{document}");
+    } else {
+        eprintln!("{} {error:?}", color::error_label());
+    }
+    kind.code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let error = coded(ErrorKind::UnknownTemplate, "Template 'x' not found");
+        assert_eq!(classify(&error), ErrorKind::UnknownTemplate);
+
+        let error = anyhow::Error::new(std::io::Error::other("disk full")).context("Failed to write");
+        assert_eq!(classify(&error), ErrorKind::Io);
+
+        let error = anyhow::Error::new(CoreError::from(TemplateError::Unknown {
+            name: "x".to_string(),
+            suggestions: Vec::new(),
+        }));
+        assert_eq!(classify(&error), ErrorKind::UnknownTemplate);
+
+        let error = anyhow::Error::new(NixError::Spawn(std::io::Error::other("not found")));
+        assert_eq!(classify(&error), ErrorKind::Other);
+
+        assert_eq!(classify(&anyhow::anyhow!("something else")), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::lock::{LockFile, LockInput};
+use crate::metadata::GenerationRecord;
+use crate::nix::{self, NixError};
+use crate::output::{self, OutputFormat};
+use crate::timestamp;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Lock age in days past which an input is flagged, unless `lock-max-age`
+/// in config or `--max-age` says otherwise.
+pub const DEFAULT_MAX_LOCK_AGE: u32 = 90;
+
+/// What a flake was generated from and what its inputs are locked to.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    /// The generation record at the top of flake.nix, when it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<GenerationRecord>,
+    pub inputs: Vec<InputLock>,
+    /// Whether `nix flake metadata` supplied the lock data, rather than
+    /// flake.lock as it is on disk
+    pub from_nix: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InputLock {
+    pub name: String,
+    /// The reference as written in flake.nix, such as `github:NixOS/nixpkgs/nixos-unstable`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    /// The input this one follows, as a `/`-separated path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follows: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Commit time of the locked revision, in seconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_days: Option<u64>,
+    /// Older than the configured maximum lock age
+    pub stale: bool,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    locks: LockFile,
+}
+
+/// Explain the flake in `target_path`. With `lock`, the lock data comes from
+/// `nix flake metadata` when nix is available, and inputs locked longer ago
+/// than `max_age_days` are flagged.
+pub fn explain(target_path: &Path, lock: bool, max_age_days: u32) -> Result<Explanation> {
+    let flake_path = target_path.join("flake.nix");
+    let content = fs::read_to_string(&flake_path)
+        .map_err(|_| NixError::NoFlake(target_path.to_path_buf()))?;
+    let generation = GenerationRecord::read(&content)?;
+
+    let mut from_nix = false;
+    let lock_file = if lock && nix::is_available() {
+        from_nix = true;
+        Some(flake_metadata(target_path)?.locks)
+    } else {
+        if lock {
+            tracing::warn!("nix is not available; lock data is read from flake.lock as it is");
+        }
+        LockFile::read(target_path)?
+    };
+
+    let mut inputs = lock_file.as_ref().map(root_inputs).unwrap_or_default();
+    if lock {
+        mark_ages(&mut inputs, timestamp::unix_now(), max_age_days);
+    } else {
+        for input in &mut inputs {
+            input.revision = None;
+            input.last_modified = None;
+        }
+    }
+
+    Ok(Explanation {
+        generation,
+        inputs,
+        from_nix,
+    })
+}
+
+/// Explain flake source without a directory, such as piped to stdin: the
+/// inputs are the ones it declares, with no lock data.
+pub fn explain_source(content: &str) -> Result<Explanation> {
+    let generation = GenerationRecord::read(content)?;
+    let fragments = nix_parser::extract_flake_fragments(content)?;
+    let mut inputs: Vec<InputLock> = fragments
+        .inputs
+        .into_iter()
+        .map(|(name, url)| InputLock {
+            name,
+            original: Some(url),
+            follows: None,
+            revision: None,
+            last_modified: None,
+            age_days: None,
+            stale: false,
+        })
+        .collect();
+    inputs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Explanation {
+        generation,
+        inputs,
+        from_nix: false,
+    })
+}
+
+/// `nix flake metadata --json`, which locks inputs missing from flake.lock
+/// without writing it.
+fn flake_metadata(target_path: &Path) -> Result<Metadata> {
+    let flake = nix::flake_ref(target_path)?;
+    let output = nix::nix_command()
+        .args(["flake", "metadata", "--json", "--no-write-lock-file", &flake])
+        .output()
+        .map_err(NixError::Spawn)?;
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: "nix flake metadata".to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// The inputs of the flake itself, in the order of their names.
+fn root_inputs(lock: &LockFile) -> Vec<InputLock> {
+    let Some(root) = lock.root_node() else {
+        return Vec::new();
+    };
+    root.inputs
+        .iter()
+        .map(|(name, input)| match input {
+            LockInput::Follows(path) => InputLock {
+                name: name.clone(),
+                original: None,
+                follows: Some(path.join("/")),
+                revision: None,
+                last_modified: None,
+                age_days: None,
+                stale: false,
+            },
+            LockInput::Node(node) => {
+                let node = lock.nodes.get(node);
+                let locked = node.and_then(|node| node.locked.as_ref());
+                InputLock {
+                    name: name.clone(),
+                    original: node.and_then(|node| node.original_ref()),
+                    follows: None,
+                    revision: locked
+                        .and_then(|locked| locked.get("rev"))
+                        .and_then(|rev| rev.as_str())
+                        .map(str::to_string),
+                    last_modified: locked
+                        .and_then(|locked| locked.get("lastModified"))
+                        .and_then(|time| time.as_i64()),
+                    age_days: None,
+                    stale: false,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Set the age of each locked input at `now` and flag those older than
+/// `max_age_days`.
+fn mark_ages(inputs: &mut [InputLock], now: i64, max_age_days: u32) {
+    for input in inputs {
+        let Some(last_modified) = input.last_modified else {
+            continue;
+        };
+        let age_days = (now - last_modified).max(0) as u64 / 86_400;
+        input.age_days = Some(age_days);
+        input.stale = age_days > u64::from(max_age_days);
+    }
+}
+
+pub fn print_explanation(
+    explanation: &Explanation,
+    max_age_days: u32,
+    format: OutputFormat,
+) -> Result<()> {
+    if format.is_json() {
+        return output::print_json("explain", explanation);
+    }
+
+    match &explanation.generation {
+        Some(record) => {
+            println!(
+                "Generated by nix-flake-generator {} from: {}",
+                record.version,
+                record.templates.join(", ")
+            );
+            for (name, version) in &record.template_versions {
+                println!("  {name} {version}");
+            }
+        }
+        None => println!("Not generated by nix-flake-generator (no generation record)"),
+    }
+
+    if explanation.inputs.is_empty() {
+        println!("Inputs: none locked (run `nix flake lock`)");
+        return Ok(());
+    }
+    println!("Inputs:");
+    for input in &explanation.inputs {
+        let mut line = format!("  {}", input.name);
+        if let Some(follows) = &input.follows {
+            line.push_str(&format!(" follows {follows}"));
+        }
+        if let Some(original) = &input.original {
+            line.push_str(&format!(" {original}"));
+        }
+        if let Some(revision) = &input.revision {
+            line.push_str(&format!(" @ {}", &revision[..revision.len().min(12)]));
+        }
+        if let Some(last_modified) = input.last_modified {
+            line.push_str(&format!(" ({}", timestamp::date(last_modified)));
+            if let Some(age) = input.age_days {
+                line.push_str(&format!(", {age} days old"));
+            }
+            line.push(')');
+        }
+        println!("{line}");
+    }
+
+    let stale: Vec<&str> = explanation
+        .inputs
+        .iter()
+        .filter(|input| input.stale)
+        .map(|input| input.name.as_str())
+        .collect();
+    if !stale.is_empty() {
+        tracing::warn!(
+            "Locked more than {max_age_days} days ago: {} (update with `nix flake update`)",
+            stale.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use crate::lock::tests::LOCK;
+
+    #[test]
+    fn test_root_inputs() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let inputs = root_inputs(&lock);
+
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(
+            inputs[1].original.as_deref(),
+            Some("github:NixOS/nixpkgs/nixos-unstable")
+        );
+        assert_eq!(inputs[1].last_modified, Some(1_714_521_600));
+        assert_eq!(inputs[2].follows.as_deref(), Some("devenv/nixpkgs"));
+    }
+
+    #[test]
+    fn test_mark_ages() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let mut inputs = root_inputs(&lock);
+        let day = 86_400;
+
+        mark_ages(&mut inputs, 1_714_521_600 + 30 * day, DEFAULT_MAX_LOCK_AGE);
+        assert_eq!(inputs[1].age_days, Some(30));
+        assert!(!inputs[1].stale);
+        assert!(!inputs[0].stale);
+        assert!(inputs[2].age_days.is_none());
+
+        mark_ages(&mut inputs, 1_714_521_600 + 91 * day, DEFAULT_MAX_LOCK_AGE);
+        assert!(inputs[0].stale && inputs[1].stale);
+    }
+
+    #[test]
+    fn test_explain_without_lock() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("flake.nix"), "{ outputs = _: { }; }\n").unwrap();
+        fs::write(dir.path().join("flake.lock"), LOCK).unwrap();
+
+        let plain = explain(dir.path(), false, DEFAULT_MAX_LOCK_AGE);
+
+        let plain = plain.unwrap();
+        assert!(plain.generation.is_none());
+        assert!(plain.inputs[1].revision.is_none());
+        assert!(!plain.inputs[1].stale);
+    }
+
+    #[test]
+    fn test_explain_source() {
+        let explanation = explain_source(
+            "{\n  inputs.nixpkgs.url = \"github:NixOS/nixpkgs/nixos-unstable\";\n  outputs = _: { };\n}\n",
+        )
+        .unwrap();
+
+        assert!(explanation.generation.is_none());
+        assert_eq!(explanation.inputs.len(), 1);
+        assert_eq!(explanation.inputs[0].name, "nixpkgs");
+        assert_eq!(
+            explanation.inputs[0].original.as_deref(),
+            Some("github:NixOS/nixpkgs/nixos-unstable")
+        );
+        assert!(explanation.inputs[0].revision.is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::nix;
+use crate::template::TemplateManager;
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::GenerationOptions;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(rename = "case")]
+    cases: Vec<Case>,
+}
+
+/// One template combination and what its flake must (not) contain.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Case {
+    /// Defaults to the template names joined with `+`
+    name: Option<String>,
+    templates: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(default)]
+    minimal: bool,
+    #[serde(default)]
+    contains: Vec<String>,
+    #[serde(default)]
+    excludes: Vec<String>,
+}
+
+impl Case {
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.templates.join("+"))
+    }
+}
+
+/// The outcome of one case; it passed when nothing failed.
+#[derive(Debug, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Generate every case of the spec from the templates in `dir` (on top of
+/// the embedded ones) into a scratch directory and compare the flakes with
+/// the expectations. With `nix_check` each flake must also pass
+/// `nix flake check --no-build`.
+pub async fn run(dir: &Path, spec_path: &Path, nix_check: bool) -> Result<Vec<CaseResult>> {
+    let content = fs::read_to_string(spec_path)
+        .map_err(|e| anyhow!("Failed to read test spec {}: {}", spec_path.display(), e))?;
+    let spec: Spec = toml::from_str(&content)
+        .map_err(|e| anyhow!("Invalid test spec {}: {}", spec_path.display(), e))?;
+    if nix_check {
+        nix::require_nix()?;
+    }
+
+    let mut manager = TemplateManager::new().await?;
+    manager.load_template_dir(dir)?;
+
+    let mut results = Vec::new();
+    for case in &spec.cases {
+        let scratch = tempfile::Builder::new()
+            .prefix("nix-flake-generator-template-test-")
+            .tempdir()?;
+        let failures = match generate(&manager, case, scratch.path()) {
+            Ok(flake) => {
+                let mut failures = expectations(case, &flake);
+                if nix_check && failures.is_empty() {
+                    failures.extend(flake_check(scratch.path()).err());
+                }
+                failures
+            }
+            Err(e) => vec![format!("generation failed: {e:#}")],
+        };
+        results.push(CaseResult {
+            name: case.name(),
+            failures,
+        });
+    }
+    Ok(results)
+}
+
+/// Render the case and write its files into `target`, returning the flake.
+fn generate(manager: &TemplateManager, case: &Case, target: &Path) -> Result<String> {
+    let options = GenerationOptions {
+        groups: case.groups.clone(),
+        minimal: case.minimal,
+        ..GenerationOptions::default()
+    };
+    let templates: Vec<&str> = case.templates.iter().map(String::as_str).collect();
+    let rendered = manager.render(&templates, &options)?;
+
+    fs::create_dir_all(target)?;
+    fs::write(target.join("flake.nix"), &rendered.flake_content)?;
+    for (filename, content) in &rendered.additional_files {
+        let path = target.join(filename);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    Ok(rendered.flake_content)
+}
+
+fn expectations(case: &Case, flake: &str) -> Vec<String> {
+    let missing = case
+        .contains
+        .iter()
+        .filter(|expected| !flake.contains(expected.as_str()))
+        .map(|expected| format!("flake.nix does not contain {expected:?}"));
+    let unexpected = case
+        .excludes
+        .iter()
+        .filter(|excluded| flake.contains(excluded.as_str()))
+        .map(|excluded| format!("flake.nix contains {excluded:?}"));
+    missing.chain(unexpected).collect()
+}
+
+fn flake_check(target: &Path) -> Result<(), String> {
+    let flake = nix::flake_ref(target).map_err(|e| e.to_string())?;
+    let output = nix::nix_command()
+        .args(["flake", "check", "--no-build", &flake])
+        .output()
+        .map_err(|e| format!("failed to run nix: {e}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "nix flake check failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expectations() {
+        let spec: Spec = toml::from_str(
+            r#"
+[[case]]
+templates = ["go", "rust"]
+contains = ["gopls", "cargo"]
+excludes = ["python3"]
+"#,
+        )
+        .unwrap();
+        let case = &spec.cases[0];
+        assert_eq!(case.name(), "go+rust");
+        assert!(expectations(case, "gopls cargo").is_empty());
+        assert_eq!(
+            expectations(case, "gopls python3"),
+            vec![
+                "flake.nix does not contain \"cargo\"".to_string(),
+                "flake.nix contains \"python3\"".to_string(),
+            ]
+        );
+    }
+}
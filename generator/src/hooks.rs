@@ -0,0 +1,59 @@
+use crate::output::status;
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::options::{self, GenerationOptions};
+use std::path::Path;
+use std::process::Command;
+
+/// What a post-generation hook can learn about the generation through its
+/// environment.
+pub struct HookContext<'a> {
+    pub templates: &'a [&'a str],
+    pub target_path: &'a Path,
+    pub options: &'a GenerationOptions,
+}
+
+/// Run each hook through the platform shell in the target directory, in
+/// order, stopping at the first one that fails.
+pub fn run(hooks: &[&str], context: &HookContext) -> Result<()> {
+    for hook in hooks {
+        status!("Running hook: {hook}");
+        let status = shell_command(hook)
+            .current_dir(context.target_path)
+            .env("NFG_TEMPLATES", context.templates.join(","))
+            .env("NFG_TARGET_DIR", context.target_path)
+            .env("NFG_FLAKE", context.target_path.join("flake.nix"))
+            .env("NFG_NIXPKGS_URL", nixpkgs_url(context.options))
+            .env(
+                "NFG_SYSTEMS",
+                context.options.systems.as_deref().unwrap_or_default().join(","),
+            )
+            .status()
+            .map_err(|e| anyhow!("Failed to run hook '{}': {}", hook, e))?;
+
+        if !status.success() {
+            return Err(anyhow!("Hook '{}' failed with {}", hook, status));
+        }
+    }
+    Ok(())
+}
+
+/// The nixpkgs flake URL generated flakes follow with `options`.
+pub(crate) fn nixpkgs_url(options: &GenerationOptions) -> String {
+    options
+        .nixpkgs_ref
+        .as_deref()
+        .map(options::nixpkgs_url)
+        .unwrap_or_else(|| options::DEFAULT_NIXPKGS_URL.to_string())
+}
+
+pub(crate) fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
@@ -0,0 +1,197 @@
+use crate::lock::{LockFile, LockInput};
+use crate::nix::{self, NixError};
+use crate::output::{self, OutputFormat, status};
+use crate::writer::FileTransaction;
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::delta;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// A copy of an input that another path in the lock already provides.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Duplicate {
+    /// Input names from the flake down to the copy, such as `devenv/nixpkgs`
+    pub path: String,
+    /// What the copies have in common, such as `github:nixos/nixpkgs`
+    pub source: String,
+    /// The input to follow instead: a top-level input, or the path of the
+    /// first copy when the flake has none
+    pub follows: String,
+}
+
+/// Find inputs pulled in more than once through other flakes. Each copy
+/// follows the flake's own input of the same source, or else the first copy
+/// found; inputs below a copy are left alone, as following it drops them.
+pub fn duplicates(lock: &LockFile) -> Vec<Duplicate> {
+    let Some(root) = lock.root_node() else {
+        return Vec::new();
+    };
+
+    // The flake's own inputs come first, so copies collapse onto them
+    let mut first: BTreeMap<String, (&str, Vec<String>)> = BTreeMap::new();
+    for (name, input) in &root.inputs {
+        if let LockInput::Node(node) = input {
+            if let Some(source) = lock.nodes.get(node).and_then(|node| node.source()) {
+                first.entry(source).or_insert((node, vec![name.clone()]));
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut pending: Vec<(&str, Vec<String>)> = root
+        .inputs
+        .iter()
+        .rev()
+        .filter_map(|(name, input)| match input {
+            LockInput::Node(node) => Some((node.as_str(), vec![name.clone()])),
+            LockInput::Follows(_) => None,
+        })
+        .collect();
+    while let Some((node_name, path)) = pending.pop() {
+        let Some(node) = lock.nodes.get(node_name) else {
+            continue;
+        };
+        for (name, input) in node.inputs.iter().rev() {
+            let LockInput::Node(child) = input else {
+                continue;
+            };
+            let mut child_path = path.clone();
+            child_path.push(name.clone());
+            if let Some(source) = lock.nodes.get(child).and_then(|node| node.source()) {
+                match first.get(&source) {
+                    Some((kept, kept_path)) if *kept != child.as_str() => {
+                        found.push(Duplicate {
+                            path: child_path.join("/"),
+                            source,
+                            follows: kept_path.join("/"),
+                        });
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => {
+                        first.insert(source, (child, child_path.clone()));
+                    }
+                }
+            }
+            pending.push((child, child_path));
+        }
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+/// Add a `follows` to flake.nix in `target_path` for every duplicate in its
+/// flake.lock, then relock with nix when it is available. With `dry_run`
+/// nothing is written.
+pub fn dedupe(target_path: &Path, dry_run: bool) -> Result<Vec<Duplicate>> {
+    let flake_path = target_path.join("flake.nix");
+    let content = fs::read_to_string(&flake_path)
+        .map_err(|_| NixError::NoFlake(target_path.to_path_buf()))?;
+    let lock = LockFile::read(target_path)?.ok_or_else(|| {
+        anyhow!(
+            "No flake.lock in {}; run `nix flake lock` first",
+            target_path.display()
+        )
+    })?;
+
+    let found = duplicates(&lock);
+    if found.is_empty() || dry_run {
+        return Ok(found);
+    }
+
+    let follows: Vec<(Vec<String>, String)> = found
+        .iter()
+        .map(|duplicate| {
+            let path = duplicate.path.split('/').map(str::to_string).collect();
+            (path, duplicate.follows.clone())
+        })
+        .collect();
+    let updated = delta::add_follows(&content, &follows).ok_or_else(|| {
+        anyhow!("{} has no nixpkgs input to add follows next to", flake_path.display())
+    })?;
+    nix_parser::validate_flake(&updated)?;
+
+    let mut transaction = FileTransaction::new();
+    transaction.write(&flake_path, updated);
+    transaction.commit()?;
+
+    if nix::is_available() {
+        status!("Updating flake.lock");
+        let flake = nix::flake_ref(target_path)?;
+        let output = nix::nix_command()
+            .args(["flake", "lock", &flake])
+            .output()
+            .map_err(NixError::Spawn)?;
+        if !output.status.success() {
+            return Err(NixError::Failed {
+                command: "nix flake lock".to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }
+            .into());
+        }
+    } else {
+        tracing::warn!("nix is not available; run `nix flake lock` to drop the copies");
+    }
+    Ok(found)
+}
+
+pub fn print_duplicates(found: &[Duplicate], format: OutputFormat) -> Result<()> {
+    if format.is_json() {
+        return output::print_json("input dedupe", &found);
+    }
+
+    if found.is_empty() {
+        println!("No input is locked more than once");
+        return Ok(());
+    }
+    println!("Duplicate inputs:");
+    for duplicate in found {
+        println!("  {} follows {} ({})", duplicate.path, duplicate.follows, duplicate.source);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use crate::lock::tests::LOCK;
+
+    #[test]
+    fn test_duplicates() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let found = duplicates(&lock);
+
+        let paths: Vec<(&str, &str)> = found
+            .iter()
+            .map(|duplicate| (duplicate.path.as_str(), duplicate.follows.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("devenv/cachix/nixpkgs", "nixpkgs"),
+                ("devenv/nixpkgs", "nixpkgs"),
+            ]
+        );
+        assert_eq!(found[0].source, "github:nixos/nixpkgs");
+    }
+
+    #[test]
+    fn test_dedupe_dry_run_leaves_flake_untouched() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("flake.nix"),
+            "{\n  inputs.nixpkgs.url = \"github:NixOS/nixpkgs/nixos-unstable\";\n  inputs.devenv.url = \"github:cachix/devenv\";\n\n  outputs = { self, nixpkgs, devenv }: { };\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("flake.lock"), LOCK).unwrap();
+
+        let planned = dedupe(dir.path(), true).unwrap();
+        let untouched = fs::read_to_string(dir.path().join("flake.nix")).unwrap();
+
+        assert_eq!(planned.len(), 2);
+        assert!(!untouched.contains("follows"));
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::{EmbeddedProvider, TemplateDelta, TemplateGroup, TemplateProvider};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The metadata schema, stricter than the registry's: unknown keys are
+/// reported instead of ignored, since they are usually typos.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Metadata {
+    template: Info,
+    #[serde(default)]
+    delta: TemplateDelta,
+    #[serde(default)]
+    groups: BTreeMap<String, TemplateGroup>,
+}
+
+// Fields only the registry uses are still declared so their types are checked
+#[allow(dead_code)]
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Info {
+    name: Option<String>,
+    description: String,
+    extends: Option<String>,
+    version_command: Option<String>,
+    #[serde(default)]
+    extra_packages: Vec<String>,
+    #[serde(default)]
+    no_cc: bool,
+    #[serde(default)]
+    darwin_packages: Vec<String>,
+    #[serde(default)]
+    shell_hook: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The template won't load or won't produce a working flake
+    Error,
+    /// The template works but breaks a convention
+    Warning,
+}
+
+/// A problem found in one file of a template directory.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub file: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Check every template in `dir` the way the registry would load it, and
+/// against the conventions the embedded templates follow.
+pub fn lint(dir: &Path) -> Result<Vec<Finding>> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| anyhow!("Failed to read template directory {}: {}", dir.display(), e))?;
+    let mut metadata = BTreeSet::new();
+    let mut flakes = BTreeSet::new();
+    for entry in entries {
+        let path = entry?.path();
+        let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let stem = stem.to_string_lossy().into_owned();
+        match extension.to_str() {
+            Some("toml") => metadata.insert(stem),
+            Some("nix") => flakes.insert(stem),
+            _ => false,
+        };
+    }
+
+    let mut findings = Vec::new();
+    for name in flakes.difference(&metadata) {
+        findings.push(Finding {
+            file: format!("{name}.nix"),
+            severity: Severity::Warning,
+            message: format!("no {name}.toml next to it, so it is not loaded as a template"),
+        });
+    }
+    for name in &metadata {
+        lint_template(dir, name, &mut findings);
+    }
+    Ok(findings)
+}
+
+fn lint_template(dir: &Path, name: &str, findings: &mut Vec<Finding>) {
+    let toml_file = format!("{name}.toml");
+    let mut report = |file: &str, severity: Severity, message: String| {
+        findings.push(Finding {
+            file: file.to_string(),
+            severity,
+            message,
+        })
+    };
+
+    if !is_kebab_case(name) {
+        report(
+            &toml_file,
+            Severity::Warning,
+            "template names should be lowercase words joined by hyphens (e.g. c-cpp)".to_string(),
+        );
+    }
+
+    let metadata = match fs::read_to_string(dir.join(&toml_file)) {
+        Ok(content) => content,
+        Err(e) => return report(&toml_file, Severity::Error, e.to_string()),
+    };
+    let metadata: Metadata = match toml::from_str(&metadata) {
+        Ok(metadata) => metadata,
+        Err(e) => return report(&toml_file, Severity::Error, e.to_string().trim_end().to_string()),
+    };
+    if metadata.template.description.trim().is_empty() {
+        report(&toml_file, Severity::Error, "template.description is empty".to_string());
+    }
+    if let Some(declared) = metadata.template.name.as_deref().filter(|n| *n != name) {
+        report(
+            &toml_file,
+            Severity::Error,
+            format!("template.name is '{declared}' but the file is named {toml_file}"),
+        );
+    }
+    if metadata.template.version_command.is_none() {
+        report(
+            &toml_file,
+            Severity::Warning,
+            "no template.version-command, so verify and deps can't report the toolchain"
+                .to_string(),
+        );
+    }
+    for (group, packages) in &metadata.groups {
+        if packages.packages.is_empty() {
+            report(&toml_file, Severity::Error, format!("group '{group}' has no packages"));
+        }
+    }
+
+    let nix_file = format!("{name}.nix");
+    let flake = match &metadata.template.extends {
+        Some(base) => {
+            let Some(base_flake) = base_flake(dir, base) else {
+                return report(
+                    &toml_file,
+                    Severity::Error,
+                    format!("extends unknown template '{base}'"),
+                );
+            };
+            match nix_flake_generator_core::delta::apply(&base_flake, &metadata.delta) {
+                Ok(flake) => flake,
+                Err(e) => return report(&toml_file, Severity::Error, format!("delta: {e}")),
+            }
+        }
+        None => match fs::read_to_string(dir.join(&nix_file)) {
+            Ok(flake) => flake,
+            Err(e) => return report(&nix_file, Severity::Error, e.to_string()),
+        },
+    };
+    // Problems in a derived flake are the delta's
+    let flake_file = if metadata.template.extends.is_some() {
+        toml_file.as_str()
+    } else {
+        nix_file.as_str()
+    };
+
+    if let Err(e) = nix_parser::validate_flake(&flake) {
+        let message = match e {
+            nix_parser::ParseError::Syntax { line, column, .. }
+            | nix_parser::ParseError::TrailingInput { line, column, .. } => {
+                format!("{line}:{column}: {e}")
+            }
+            nix_parser::ParseError::Analysis(_) => e.to_string(),
+        };
+        return report(flake_file, Severity::Error, message);
+    }
+    let fragments = match nix_parser::extract_flake_fragments(&flake) {
+        Ok(fragments) => fragments,
+        Err(e) => return report(flake_file, Severity::Error, e.to_string()),
+    };
+    if fragments.packages.is_empty() {
+        report(
+            flake_file,
+            Severity::Error,
+            "no devShell packages could be extracted, so merging adds nothing".to_string(),
+        );
+    }
+    if !fragments.inputs.contains_key("nixpkgs") {
+        report(flake_file, Severity::Error, "no nixpkgs input".to_string());
+    }
+    let mut inputs: Vec<_> = fragments.inputs.iter().collect();
+    inputs.sort();
+    for (input, url) in inputs {
+        if url.trim().is_empty() {
+            report(flake_file, Severity::Error, format!("input '{input}' has no URL"));
+        }
+    }
+
+    for package in &metadata.template.extra_packages {
+        if !flake.lines().any(|line| line.trim() == package) {
+            report(
+                flake_file,
+                Severity::Error,
+                format!("extra package '{package}' is not listed on its own line"),
+            );
+        }
+    }
+
+    match fragments.let_bindings.get("supportedSystems") {
+        None => report(
+            flake_file,
+            Severity::Warning,
+            "no supportedSystems binding, so the systems the flake covers can't be checked"
+                .to_string(),
+        ),
+        Some(systems) => {
+            for platform in ["linux", "darwin"] {
+                if !systems.contains(&format!("-{platform}\"")) {
+                    report(
+                        flake_file,
+                        Severity::Warning,
+                        format!("supportedSystems has no {platform} system"),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The flake a derived template extends: from the same directory, else
+/// an embedded one.
+fn base_flake(dir: &Path, base: &str) -> Option<String> {
+    if dir.join(format!("{base}.toml")).exists() {
+        return fs::read_to_string(dir.join(format!("{base}.nix"))).ok();
+    }
+    EmbeddedProvider
+        .templates()
+        .ok()?
+        .into_iter()
+        .find(|template| template.name == base)
+        .map(|template| template.flake_content)
+}
+
+fn is_kebab_case(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('-').all(|word| {
+            !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_kebab_case() {
+        assert!(is_kebab_case("c-cpp"));
+        assert!(is_kebab_case("python3"));
+        assert!(!is_kebab_case("CPlusPlus"));
+        assert!(!is_kebab_case("my_template"));
+        assert!(!is_kebab_case("trailing-"));
+    }
+
+    #[test]
+    fn test_lint() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("tool.toml"),
+            "[template]\ndescription = \"A tool\"\nextra-pakages = []\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("orphan.nix"), "{ }").unwrap();
+        fs::write(
+            dir.path().join("linux-only.toml"),
+            "[template]\ndescription = \"Linux\"\nversion-command = \"true\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("linux-only.nix"),
+            r#"{
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+  outputs = { self, nixpkgs }:
+    let
+      supportedSystems = [ "x86_64-linux" ];
+      forEachSupportedSystem = f: nixpkgs.lib.genAttrs supportedSystems (system: f {
+        pkgs = import nixpkgs { inherit system; };
+      });
+    in
+    {
+      devShells = forEachSupportedSystem ({ pkgs }: {
+        default = pkgs.mkShell {
+          packages = with pkgs; [
+            hello
+          ];
+        };
+      });
+    };
+}"#,
+        )
+        .unwrap();
+
+        let findings = lint(dir.path()).unwrap();
+
+        let messages: Vec<(&str, Severity, &str)> = findings
+            .iter()
+            .map(|f| (f.file.as_str(), f.severity, f.message.as_str()))
+            .collect();
+        assert_eq!(messages.len(), 3, "{messages:?}");
+        assert_eq!(messages[0].0, "orphan.nix");
+        assert_eq!(
+            messages[1],
+            ("linux-only.nix", Severity::Warning, "supportedSystems has no darwin system")
+        );
+        assert_eq!(messages[2].0, "tool.toml");
+        assert_eq!(messages[2].1, Severity::Error);
+        assert!(messages[2].2.contains("extra-pakages"));
+    }
+}
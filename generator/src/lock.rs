@@ -0,0 +1,151 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The parts of flake.lock (or the `locks` of `nix flake metadata --json`)
+/// read here.
+#[derive(Debug, Deserialize)]
+pub struct LockFile {
+    pub nodes: BTreeMap<String, LockNode>,
+    pub root: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LockNode {
+    pub inputs: BTreeMap<String, LockInput>,
+    pub locked: Option<serde_json::Map<String, serde_json::Value>>,
+    pub original: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// A node name, or the path of the input it follows.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LockInput {
+    Node(String),
+    Follows(Vec<String>),
+}
+
+impl LockFile {
+    /// Read `flake.lock` in `target_path`, if there is one.
+    pub fn read(target_path: &Path) -> Result<Option<Self>> {
+        let path = target_path.join("flake.lock");
+        if !path.exists() {
+            return Ok(None);
+        }
+        serde_json::from_str(&fs::read_to_string(&path)?)
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn root_node(&self) -> Option<&LockNode> {
+        self.nodes.get(&self.root)
+    }
+}
+
+impl LockNode {
+    /// The reference as written in the flake that declared it.
+    pub fn original_ref(&self) -> Option<String> {
+        self.original.as_ref().map(flake_ref)
+    }
+
+    /// What identifies the input regardless of the branch or revision asked
+    /// for: `github:nixos/nixpkgs` for any nixpkgs branch on GitHub.
+    pub fn source(&self) -> Option<String> {
+        let attrs = self.original.as_ref().or(self.locked.as_ref())?;
+        let field = |key: &str| attrs.get(key).and_then(|value| value.as_str());
+        let kind = field("type")?;
+        match kind {
+            "github" | "gitlab" | "sourcehut" => Some(format!(
+                "{kind}:{}/{}",
+                field("owner")?.to_lowercase(),
+                field("repo")?.to_lowercase()
+            )),
+            "indirect" => Some(format!("flake:{}", field("id")?)),
+            _ => {
+                let url = field("url").or(field("path"))?;
+                Some(url.split('?').next().unwrap_or(url).trim_end_matches('/').to_string())
+            }
+        }
+    }
+}
+
+/// A lock file's attribute set of a flake reference in URL form.
+fn flake_ref(attrs: &serde_json::Map<String, serde_json::Value>) -> String {
+    let field = |key: &str| attrs.get(key).and_then(|value| value.as_str());
+    let kind = field("type").unwrap_or("indirect");
+    match kind {
+        "github" | "gitlab" | "sourcehut" => {
+            let mut reference = format!(
+                "{kind}:{}/{}",
+                field("owner").unwrap_or_default(),
+                field("repo").unwrap_or_default()
+            );
+            if let Some(branch) = field("ref").or(field("rev")) {
+                reference.push('/');
+                reference.push_str(branch);
+            }
+            reference
+        }
+        "indirect" => format!("flake:{}", field("id").unwrap_or_default()),
+        "path" => format!("path:{}", field("path").unwrap_or_default()),
+        _ => match field("url") {
+            Some(url) if url.contains(':') => format!("{kind}+{url}"),
+            Some(url) => url.to_string(),
+            None => kind.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A project with nixpkgs, and devenv bringing its own nixpkgs and a
+    /// cachix input bringing a third.
+    pub(crate) const LOCK: &str = r#"{
+  "nodes": {
+    "cachix": {
+      "inputs": { "nixpkgs": "nixpkgs_3" },
+      "locked": { "lastModified": 1700000000, "owner": "cachix", "repo": "cachix", "rev": "c0", "type": "github" },
+      "original": { "owner": "cachix", "repo": "cachix", "type": "github" }
+    },
+    "devenv": {
+      "inputs": { "cachix": "cachix", "nixpkgs": "nixpkgs_2" },
+      "locked": { "lastModified": 1710000000, "owner": "cachix", "repo": "devenv", "rev": "d0", "type": "github" },
+      "original": { "owner": "cachix", "repo": "devenv", "type": "github" }
+    },
+    "nixpkgs": {
+      "locked": { "lastModified": 1714521600, "owner": "NixOS", "repo": "nixpkgs", "rev": "0123456789abcdef0123456789abcdef01234567", "type": "github" },
+      "original": { "owner": "NixOS", "ref": "nixos-unstable", "repo": "nixpkgs", "type": "github" }
+    },
+    "nixpkgs_2": {
+      "locked": { "lastModified": 1700000000, "owner": "NixOS", "repo": "nixpkgs", "rev": "a1", "type": "github" },
+      "original": { "owner": "NixOS", "ref": "nixpkgs-unstable", "repo": "nixpkgs", "type": "github" }
+    },
+    "nixpkgs_3": {
+      "locked": { "lastModified": 1690000000, "owner": "nixos", "repo": "nixpkgs", "rev": "b2", "type": "github" },
+      "original": { "owner": "nixos", "ref": "nixos-23.05", "repo": "nixpkgs", "type": "github" }
+    },
+    "root": {
+      "inputs": { "devenv": "devenv", "nixpkgs": "nixpkgs", "systems": ["devenv", "nixpkgs"] }
+    }
+  },
+  "root": "root",
+  "version": 7
+}"#;
+
+    #[test]
+    fn test_lock_node_refs() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let nixpkgs = &lock.nodes["nixpkgs"];
+        assert_eq!(
+            nixpkgs.original_ref().as_deref(),
+            Some("github:NixOS/nixpkgs/nixos-unstable")
+        );
+        assert_eq!(nixpkgs.source(), lock.nodes["nixpkgs_3"].source());
+        assert!(matches!(lock.root_node().unwrap().inputs["systems"], LockInput::Follows(_)));
+    }
+}
@@ -0,0 +1,27 @@
+use crate::color;
+use crate::output;
+use tracing::level_filters::LevelFilter;
+
+/// Set up diagnostics on stderr. Warnings are shown by default, `-v` adds the
+/// pipeline stages and merge decisions, `-vv` every fragment, and `--quiet`
+/// leaves only errors.
+pub fn init(verbose: u8, quiet: bool) {
+    let level = if quiet {
+        LevelFilter::ERROR
+    } else {
+        match verbose {
+            0 => LevelFilter::WARN,
+            1 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        }
+    };
+    output::set_quiet(quiet);
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_target(false)
+        .with_ansi(color::stderr_enabled())
+        .without_time()
+        .init();
+}
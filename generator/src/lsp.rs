@@ -0,0 +1,283 @@
+use crate::template::TemplateManager;
+use anyhow::{Context, Result, anyhow};
+use nix_flake_generator_core::{ConflictStrategy, Template, merger};
+use nix_parser::ParseError;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, BufReader, Write};
+
+/// nixpkgs branches offered alongside the input URLs the templates use.
+const NIXPKGS_BRANCHES: &[&str] = &[
+    "nixos-unstable",
+    "nixpkgs-unstable",
+    "nixos-24.11",
+    "nixos-24.05",
+];
+
+/// Serve the language server protocol on stdin/stdout until the client
+/// sends `exit`. Documents are synced in full on every change.
+pub fn run(manager: &TemplateManager) -> Result<()> {
+    let catalog = Catalog::new(manager);
+    let mut documents: HashMap<String, String> = HashMap::new();
+    let mut reader = BufReader::new(std::io::stdin().lock());
+    let mut writer = std::io::stdout().lock();
+    let mut shutting_down = false;
+
+    while let Some(message) = read_message(&mut reader)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        tracing::debug!(method, "lsp message");
+
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": ["\"", "/"] },
+                    "codeActionProvider": true,
+                },
+                "serverInfo": { "name": "nix-flake-generator" },
+            })),
+            "shutdown" => {
+                shutting_down = true;
+                Some(Value::Null)
+            }
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                documents.insert(uri.to_string(), text.to_string());
+                publish_diagnostics(&mut writer, uri, text)?;
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    documents.insert(uri.to_string(), text.to_string());
+                    publish_diagnostics(&mut writer, uri, text)?;
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                documents.remove(uri);
+                None
+            }
+            "textDocument/completion" => {
+                let text = document(&documents, params);
+                let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+                let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+                Some(catalog.complete(&line_prefix(text, line, character)))
+            }
+            "textDocument/codeAction" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                Some(catalog.code_actions(manager, uri, document(&documents, params)))
+            }
+            _ => id.as_ref().map(|_| Value::Null),
+        };
+
+        if let (Some(id), Some(result)) = (id, result) {
+            write_message(&mut writer, &json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+        }
+    }
+
+    if !shutting_down {
+        tracing::warn!("language client exited without shutting down");
+    }
+    Ok(())
+}
+
+/// Completion candidates gathered once from the registry.
+struct Catalog {
+    input_urls: BTreeSet<String>,
+    packages: BTreeSet<String>,
+    templates: Vec<String>,
+}
+
+impl Catalog {
+    fn new(manager: &TemplateManager) -> Self {
+        let mut input_urls: BTreeSet<String> = NIXPKGS_BRANCHES
+            .iter()
+            .map(|branch| format!("github:NixOS/nixpkgs/{branch}"))
+            .collect();
+        let mut packages = BTreeSet::new();
+        let templates: Vec<String> = manager.names().into_iter().map(String::from).collect();
+
+        for name in &templates {
+            let Ok(template) = manager.get(name) else { continue };
+            match nix_parser::extract_flake_fragments(&template.flake_content) {
+                Ok(fragments) => {
+                    input_urls.extend(fragments.inputs.into_values());
+                    packages.extend(fragments.packages);
+                }
+                Err(e) => tracing::debug!(template = %name, "skipping unparsable template: {e}"),
+            }
+        }
+
+        Self {
+            input_urls,
+            packages,
+            templates,
+        }
+    }
+
+    /// Input URLs after `url =`, package names everywhere else.
+    fn complete(&self, prefix: &str) -> Value {
+        let (candidates, kind) = if prefix.contains("url =") {
+            (&self.input_urls, 12) // Value
+        } else {
+            (&self.packages, 6) // Variable
+        };
+
+        let items: Vec<Value> = candidates
+            .iter()
+            .map(|label| json!({ "label": label, "kind": kind }))
+            .collect();
+        json!({ "isIncomplete": false, "items": items })
+    }
+
+    /// One "Add <template>" action per template, replacing the document with
+    /// the result of merging that template into it.
+    fn code_actions(&self, manager: &TemplateManager, uri: &str, text: &str) -> Value {
+        let local = Template {
+            name: "local".to_string(),
+            description: "Open document".to_string(),
+            flake_content: text.to_string(),
+            ..Default::default()
+        };
+
+        let actions: Vec<Value> = self
+            .templates
+            .iter()
+            .filter_map(|name| {
+                let template = manager.get(name).ok()?.clone();
+                let merged = merger::merge_templates(
+                    &[local.clone(), template],
+                    ConflictStrategy::PreferFirst,
+                )
+                .ok()?;
+                let edit = json!({ "range": whole_document(text), "newText": merged });
+                Some(json!({
+                    "title": format!("Add {name} to this flake"),
+                    "kind": "refactor",
+                    "edit": { "changes": { (uri): [edit] } },
+                }))
+            })
+            .collect();
+        Value::Array(actions)
+    }
+}
+
+fn publish_diagnostics(writer: &mut impl Write, uri: &str, text: &str) -> Result<()> {
+    let diagnostics: Vec<Value> = match nix_parser::validate_flake(text) {
+        Ok(_) => Vec::new(),
+        Err(error) => {
+            let start = match &error {
+                ParseError::Syntax { line, column, .. }
+                | ParseError::TrailingInput { line, column, .. } => {
+                    json!({ "line": line - 1, "character": column - 1 })
+                }
+                ParseError::Analysis(_) => json!({ "line": 0, "character": 0 }),
+            };
+            vec![json!({
+                "range": { "start": start, "end": start },
+                "severity": 1,
+                "source": "nix-flake-generator",
+                "message": error.to_string(),
+            })]
+        }
+    };
+
+    write_message(
+        writer,
+        &json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }),
+    )
+}
+
+fn document<'a>(documents: &'a HashMap<String, String>, params: &Value) -> &'a str {
+    params["textDocument"]["uri"]
+        .as_str()
+        .and_then(|uri| documents.get(uri))
+        .map_or("", String::as_str)
+}
+
+fn line_prefix(text: &str, line: usize, character: usize) -> String {
+    text.lines()
+        .nth(line)
+        .map(|l| l.chars().take(character).collect())
+        .unwrap_or_default()
+}
+
+fn whole_document(text: &str) -> Value {
+    let lines = text.split('\n').count();
+    let last = text.rsplit('\n').next().unwrap_or_default();
+    json!({
+        "start": { "line": 0, "character": 0 },
+        "end": { "line": lines - 1, "character": last.encode_utf16().count() },
+    })
+}
+
+/// Read one `Content-Length` framed JSON-RPC message, or `None` at EOF.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().context("Invalid Content-Length")?);
+        }
+    }
+
+    let length = length.ok_or_else(|| anyhow!("Message without Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+
+        let mut reader = BufReader::new(buffer.as_slice());
+        let message = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(message["method"], "exit");
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_whole_document_range() {
+        assert_eq!(
+            whole_document("{\n  a = 1;\n}"),
+            json!({
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 2, "character": 1 },
+            })
+        );
+    }
+}
@@ -1,17 +1,89 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
-mod embedded_templates;
-mod merger;
+mod cache;
+mod ci;
+mod channels;
+mod clean;
+mod color;
+mod completions;
+mod config;
+mod container;
+mod deps;
+mod detect;
+mod diagnostics;
+mod diff;
+mod dirs;
+mod errors;
+mod explain;
+mod golden;
+mod hooks;
+mod inputs;
+mod lint;
+mod lock;
+mod logging;
+mod lsp;
+mod metadata;
+mod migrate;
+mod nix;
+mod output;
+mod packages;
+mod paths;
+mod pin;
+mod pipe;
+mod platform;
+mod policy;
+mod plugins;
+mod reference;
+mod remote;
+mod sbom;
+mod shell;
+mod signing;
+mod stats;
+mod sync;
 mod template;
+mod timestamp;
+mod vscode;
+mod watch;
+mod wrapper;
+mod writer;
 
-use template::TemplateManager;
+use config::Config;
+use nix_flake_generator_core::{
+    BuildTool, ConflictStrategy, Embedded, GenerationOptions, JdkDist, LatexScheme, Stdenv,
+    dotnet_sdk_attribute, elixir_attribute, ghc_attribute, ocaml_attribute, php_attribute,
+    php_extension, ruby_attribute, terraform_provider, toolchain_ref,
+};
+use output::{OutputFormat, status, success};
+use template::{OverwritePolicy, TemplateManager, WriteOutcome};
 
 #[derive(Parser)]
 #[command(name = "nix-flake-generator")]
 #[command(about = "Generate development environments from nix templates")]
+#[command(disable_help_subcommand = true)]
+#[command(after_help = "Other commands run `nix-flake-generator-<command>` from PATH as plugins.")]
 struct Cli {
+    /// Output format for list, deps, explain, input dedupe, cache list, alias list and config get
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    output: OutputFormat,
+    /// Show pipeline diagnostics on stderr (-v for merge decisions, -vv for every fragment)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Only print results and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Disable colored output (also honoured via the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Never access the network: skip template providers, pin nixpkgs branches
+    /// rather than commits and run nix with --offline (also a config key)
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Use templates from providers even when their signature is missing or invalid
+    #[arg(long, global = true)]
+    allow_unsigned: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -19,48 +91,1415 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a template (single or multi-language)
-    Init {
-        /// Template name(s) - single template (e.g., 'rust') or comma-separated list for multi-language (e.g., 'rust,go,node')
-        templates: String,
-        /// Target directory (defaults to current directory)
+    Init(Box<InitArgs>),
+    /// Write one flake with a devShell per [shells.<name>] table of the config
+    Generate {
+        /// Target directory, created if missing (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Replace an existing flake.nix, keeping a timestamped backup
+        #[arg(long)]
+        force: bool,
+    },
+    /// Re-run the generation recorded in an existing flake.nix
+    Regenerate {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+    },
+    /// Regenerate whenever the project config or a language manifest changes
+    Watch {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+    },
+    /// Format a nix file in place with nixfmt, or stdin to stdout
+    Fmt {
+        /// File to format ('-' or omitted reads stdin)
+        file: Option<PathBuf>,
+    },
+    /// Check nix files for unused bindings, undefined variables and duplicates
+    Lint {
+        /// Files or directories to check ('-' or omitted reads stdin and prints the findings,
+        /// or the fixed source with --fix)
+        paths: Vec<PathBuf>,
+        /// Only report findings at least this severe
+        #[arg(long, value_enum, default_value = "warning")]
+        severity: diagnostics::MinSeverity,
+        /// Remove unused let bindings and duplicate list entries in place, where they have their
+        /// lines to themselves; the others are reported as left in place
+        #[arg(long)]
+        fix: bool,
+        /// Print a SARIF log for CI annotations instead of the findings
+        #[arg(long)]
+        sarif: bool,
+    },
+    /// Merge other flakes' inputs, overlays and devShell contents into this project's flake.nix
+    Merge {
+        /// Flakes to merge in ('-' or omitted reads stdin and prints the result)
+        files: Vec<PathBuf>,
+        /// Directory containing the flake.nix to merge into (defaults to current directory)
         #[arg(short, long)]
         path: Option<PathBuf>,
+        /// Print the merged flake instead of writing it
+        #[arg(long)]
+        stdout: bool,
+        /// How to settle flakes that define the same attribute differently (overrides config)
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictStrategy>,
+    },
+    /// Generate a flake from asdf, mise, devbox or devenv settings
+    Migrate {
+        /// Version manager to migrate from
+        #[arg(value_enum)]
+        from: migrate::Source,
+        /// Directory containing the file to migrate (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Replace an existing flake.nix, keeping a timestamped backup
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print a flake replacing the development environment of a Dockerfile
+    Detect {
+        /// Dockerfile whose base images and installed packages to read
+        #[arg(long, value_name = "DOCKERFILE")]
+        from_dockerfile: PathBuf,
     },
     /// List available templates
     List,
+    /// Enter a throwaway development shell without writing any files (requires nix)
+    Shell {
+        /// Template name(s) - single template or comma-separated list
+        templates: String,
+        #[command(flatten)]
+        shell_options: ShellOptions,
+    },
+    /// Run a command inside a generated environment and exit with its status (requires nix)
+    Run {
+        /// Template name(s) - single template or comma-separated list
+        templates: String,
+        #[command(flatten)]
+        shell_options: ShellOptions,
+        /// Command to run, given after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Inspect or clear cached environments used by `shell` and `run`
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Remove .direnv/, result symlinks and the cached shells used from a project
+    Clean {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Maintain templates: update them from upstream, lint and test custom ones
+    #[command(alias = "templates")]
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
+    /// Show template aliases defined in config
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommands,
+    },
+    /// Read or change configuration values
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// List the package versions resolved by a flake's devShell (requires nix)
+    Deps {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Emit JSON instead of plain text (same as --output json)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show what a flake was generated from and what its inputs are locked to
+    Explain {
+        /// Directory containing flake.nix ('-' or omitted reads the flake from stdin, without
+        /// its lock)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Add locked revisions and dates, from `nix flake metadata` when nix is available,
+        /// and flag stale locks
+        #[arg(long)]
+        lock: bool,
+        /// Days after which a lock is stale (overrides config; default 90)
+        #[arg(long, requires = "lock")]
+        max_age: Option<u32>,
+    },
+    /// Write CI recipes that install nix, cache the store on flake.lock and run commands in
+    /// the devShell
+    Ci {
+        #[command(subcommand)]
+        command: CiCommands,
+    },
+    /// Inspect and tidy a flake's inputs
+    Input {
+        #[command(subcommand)]
+        command: InputCommands,
+    },
+    /// Report the package count and closure size of a flake's devShell (requires nix)
+    Stats {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// How many of the largest packages to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Print a shell completion script, including current template and profile names
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print a man page, or write one page per subcommand with --dir
+    Man {
+        /// Directory to write nix-flake-generator*.1 pages into
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Print help for a command, or the full reference with --all
+    Help {
+        /// Subcommand to show help for
+        command: Option<String>,
+        /// Document every subcommand, flag, config key and exit code
+        #[arg(long, conflicts_with = "command")]
+        all: bool,
+    },
+    /// Serve a language server for flake.nix files over stdio
+    Lsp,
+    /// Generate an SBOM for a flake's devShell closure (requires nix)
+    Sbom {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Document format
+        #[arg(long, value_enum, default_value = "cyclonedx")]
+        format: sbom::SbomFormat,
+        /// Write the document to a file instead of stdout
+        #[arg(short = 'o', long)]
+        output_file: Option<PathBuf>,
+    },
+    // Any other command runs `nix-flake-generator-<command>` from PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+/// Arguments of `init`, boxed in `Commands` as they far outnumber those of the
+/// other commands
+#[derive(Args)]
+struct InitArgs {
+    /// Template name(s) - single template (e.g., 'rust') or comma-separated list for multi-language (e.g., 'rust,go,node')
+    #[arg(required_unless_present_any = ["profile", "from_repo", "from_flake"])]
+    templates: Option<String>,
+    /// Target directory, created if missing (defaults to current directory)
+    #[arg(short, long)]
+    path: Option<PathBuf>,
+    /// Use the languages and options of a profile defined in config
+    #[arg(long)]
+    profile: Option<String>,
+    /// Add the languages detected in a remote git repository, without cloning its files
+    #[arg(long, value_name = "URL")]
+    from_repo: Option<String>,
+    /// Start from an upstream flake template with `nix flake init -t`, then merge the listed
+    /// templates into it (requires nix)
+    #[arg(long, value_name = "FLAKE", conflicts_with_all = ["stdout", "merge", "force"])]
+    from_flake: Option<String>,
+    /// Print the flake instead of writing it
+    #[arg(long, conflicts_with_all = ["merge", "force", "verify", "hooks"])]
+    stdout: bool,
+    /// nixpkgs branch, revision or flake URL (overrides config)
+    #[arg(long)]
+    nixpkgs_ref: Option<String>,
+    /// Pin nixpkgs to the current commit of the newest stable or unstable branch
+    /// (the branch itself with --offline)
+    #[arg(long, value_enum, conflicts_with = "nixpkgs_ref")]
+    pin_nixpkgs: Option<pin::Channel>,
+    /// Comma-separated systems to generate devShells for (overrides config)
+    #[arg(long, value_delimiter = ',')]
+    systems: Option<Vec<String>>,
+    /// Replace an existing flake.nix, keeping a timestamped backup
+    #[arg(long, conflicts_with = "merge")]
+    force: bool,
+    /// Merge the templates into an existing flake.nix instead of replacing it
+    #[arg(long)]
+    merge: bool,
+    /// Check that every package the flake takes from nixpkgs exists in the nixpkgs it
+    /// pins, before writing (through nix, or the package-index config key without it)
+    #[arg(long)]
+    check_packages: bool,
+    /// Check the result with nix after writing ('full' also enters the devShell)
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "check")]
+    verify: Option<nix::VerifyLevel>,
+    /// Command to run in the target directory after writing (repeatable)
+    #[arg(long = "hook", value_name = "COMMAND")]
+    hooks: Vec<String>,
+    /// How to settle templates that define the same attribute differently (overrides config)
+    #[arg(long, value_enum)]
+    on_conflict: Option<ConflictStrategy>,
+    /// Note the template each merged attribute came from in a comment
+    #[arg(long)]
+    provenance: bool,
+    #[command(flatten)]
+    shell_options: ShellOptions,
+    /// Inherit the build inputs of flake outputs such as '.#packages.default' or
+    /// 'github:org/app#packages.default' through inputsFrom (comma-separated)
+    #[arg(long, value_name = "REF", value_delimiter = ',')]
+    inputs_from: Vec<String>,
+    /// TeX Live scheme replacing the full one of the latex and r templates
+    #[arg(long, value_enum)]
+    latex_scheme: Option<LatexScheme>,
+    /// Add biber and biblatex to the TeX Live set
+    #[arg(long)]
+    with_biber: bool,
+    /// Add minted to the TeX Live set, and pygments to run it
+    #[arg(long)]
+    with_pygments: bool,
+    /// Terraform providers to build into the hashi template's terraform, by registry
+    /// address such as hashicorp/aws or just aws (comma-separated)
+    #[arg(long, value_name = "PROVIDER", value_delimiter = ',', value_parser = hashi_provider)]
+    hashi_providers: Vec<String>,
+    /// Erlang/OTP major release for the elixir and gleam templates, such as 27
+    #[arg(long, value_name = "RELEASE")]
+    erlang_otp: Option<u32>,
+    /// Elixir release for the elixir template, such as 1.17
+    #[arg(long, value_name = "VERSION", value_parser = elixir_version)]
+    elixir: Option<String>,
+    /// PHP release for the php template, such as 8.3
+    #[arg(long, value_name = "VERSION", value_parser = php_version)]
+    php_version: Option<String>,
+    /// PHP extensions to enable, such as intl,pdo_pgsql (comma-separated)
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',', value_parser = php_ext)]
+    php_ext: Vec<String>,
+    /// Ruby release for the ruby template, such as 3.3
+    #[arg(long, value_name = "VERSION", value_parser = ruby_version)]
+    ruby: Option<String>,
+    /// Major releases of the .NET SDKs for the csharp template to combine, such as 8,9
+    /// (comma-separated)
+    #[arg(long, value_name = "RELEASES", value_delimiter = ',', value_parser = dotnet_sdk)]
+    dotnet_sdks: Vec<u32>,
+    /// JDK feature release for the java, kotlin and scala templates, such as 21
+    #[arg(long, value_name = "RELEASE")]
+    jdk: Option<u32>,
+    /// JDK distribution for the java, kotlin and scala templates
+    #[arg(long, value_enum)]
+    jdk_dist: Option<JdkDist>,
+    /// The one build tool the java, kotlin and scala templates list
+    #[arg(long, value_enum)]
+    build_tool: Option<BuildTool>,
+    /// OCaml release for the ocaml template, such as 5.2
+    #[arg(long, value_name = "VERSION", value_parser = ocaml_version)]
+    ocaml: Option<String>,
+    /// Zig from zig-overlay for the zig template: master (nightly) or a release such as
+    /// 0.13.0
+    #[arg(long, value_name = "VERSION", value_parser = toolchain_version)]
+    zig: Option<String>,
+    /// Build V for the vlang template from a git ref of its repository, such as master
+    #[arg(long, value_name = "REF", value_parser = toolchain_version)]
+    vlang: Option<String>,
+    /// GHC release for the haskell template, such as 9.8, with haskell-language-server and
+    /// cabal-install from its haskell.packages set; checked against nixpkgs when nix is available
+    #[arg(long, value_name = "VERSION", value_parser = ghc_version)]
+    ghc: Option<String>,
+    /// Bare-metal target to add a cross toolchain and probe tools for, to the c-cpp and
+    /// rust templates
+    #[arg(long, value_enum)]
+    embedded: Option<Embedded>,
+    /// Enable flakes in nixConfig and explain on shell entry how to turn them on for good
+    /// (overrides config)
+    #[arg(long)]
+    flakes_hint: bool,
+    /// Also write .vscode/settings.json and extensions.json for the templates
+    #[arg(long, conflicts_with = "stdout")]
+    vscode: bool,
+    /// Also write scripts entering the devShell for people new to nix: dev.sh, dev.ps1
+    /// (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "stdout")]
+    wrapper: Vec<wrapper::Script>,
+    /// Also write a Dockerfile and compose.yaml running the devShell in a container, and
+    /// add a dev-image package building it as an image, for contributors without nix
+    #[arg(long, conflicts_with = "stdout")]
+    container: bool,
+}
+
+/// What goes into the devShell, shared by `init`, `shell` and `run`
+#[derive(Args)]
+struct ShellOptions {
+    /// Leave out optional tooling such as linters and formatters
+    #[arg(long)]
+    minimal: bool,
+    /// Optional package groups of the templates to add (comma-separated)
+    #[arg(long = "with", value_name = "GROUPS", value_delimiter = ',')]
+    groups: Vec<String>,
+    /// Print the languages and toolchain versions on entering the shell (overrides config)
+    #[arg(long)]
+    banner: bool,
+    /// Build the shell with mkShellNoCC, without a C compiler
+    #[arg(long)]
+    no_cc: bool,
+    /// Build the shell with clangStdenv or gccStdenv and the matching debugger
+    #[arg(long, value_enum, conflicts_with = "no_cc")]
+    stdenv: Option<Stdenv>,
+}
+
+impl ShellOptions {
+    /// Set the flags on `options`; `--banner` only turns the config's banner on.
+    fn apply(&self, options: &mut GenerationOptions) {
+        options.minimal = self.minimal;
+        options.groups = self.groups.clone();
+        if self.banner {
+            options.banner = true;
+        }
+        options.no_cc = self.no_cc;
+        options.stdenv = self.stdenv;
+    }
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// List cached environments
+    List,
+    /// Remove all cached environments
+    Clean,
+    /// Print the cache directory
+    Dir,
+}
+
+#[derive(Subcommand)]
+enum CiCommands {
+    /// Write a reusable GitHub composite action and/or a script for other CI systems
+    Artifacts {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// What to write: action, script (comma-separated)
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "action")]
+        kind: Vec<ci::Artifact>,
+    },
+}
+
+#[derive(Subcommand)]
+enum InputCommands {
+    /// Make inputs that flake.lock holds several copies of, such as nixpkgs pulled in by
+    /// other flakes, follow one copy, and relock when nix is available
+    Dedupe {
+        /// Directory containing flake.nix (defaults to current directory)
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+        /// Only list the duplicates and the follows that would be added
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// Install templates that changed in the upstream dev-templates repository
+    /// into the user template directory, where they replace the bundled ones
+    Sync {
+        /// Only show what changed upstream
+        #[arg(long)]
+        dry_run: bool,
+        /// Repository to compare with
+        #[arg(long, default_value = sync::UPSTREAM)]
+        upstream: String,
+    },
+    /// Check a directory of custom templates: metadata schema, flakes that
+    /// parse and merge, naming and linux/darwin coverage
+    Lint {
+        /// Directory holding <name>.toml and <name>.nix files
+        dir: PathBuf,
+    },
+    /// Generate the template combinations of a test spec and check the
+    /// flakes contain (or leave out) the expected text
+    Test {
+        /// Directory holding <name>.toml and <name>.nix files
+        dir: PathBuf,
+        /// TOML file of [[case]] tables; keep it out of the template directory
+        spec: PathBuf,
+        /// Also run `nix flake check --no-build` on each flake (requires nix)
+        #[arg(long)]
+        nix_check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AliasCommands {
+    /// List configured aliases and what they expand to
+    List,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the effective value of a key (e.g. 'nixpkgs-ref', 'defaults.path')
+    Get { key: String },
+    /// Set a key in the global config (or the project config with --project)
+    Set {
+        key: String,
+        /// Value, parsed as TOML when possible (e.g. '["x86_64-linux"]')
+        value: String,
+        /// Write to ./.nix-flake-generator.toml instead of the global config
+        #[arg(long)]
+        project: bool,
+    },
+    /// Print the path of the global config file (NFG_CONFIG, or under the XDG config directory)
+    Path {
+        /// Print the project config path instead
+        #[arg(long)]
+        project: bool,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let cli = Cli::parse();
-    let manager = TemplateManager::new().await?;
+    color::init(cli.no_color);
+    logging::init(cli.verbose, cli.quiet);
+    let format = cli.output;
+    if let Err(error) = run(cli).await {
+        std::process::exit(errors::report(&error, format));
+    }
+}
+
+fn hashi_provider(address: &str) -> Result<String, String> {
+    terraform_provider(address).map(|_| address.to_string())
+}
+
+fn elixir_version(version: &str) -> Result<String, String> {
+    elixir_attribute(version).map(|_| version.to_string())
+}
+
+fn php_version(version: &str) -> Result<String, String> {
+    php_attribute(version).map(|_| version.to_string())
+}
+
+fn php_ext(name: &str) -> Result<String, String> {
+    php_extension(name).map(|()| name.to_string())
+}
+
+fn ocaml_version(version: &str) -> Result<String, String> {
+    ocaml_attribute(version).map(|_| version.to_string())
+}
+
+fn ghc_version(version: &str) -> Result<String, String> {
+    ghc_attribute(version).map(|_| version.to_string())
+}
+
+fn toolchain_version(reference: &str) -> Result<String, String> {
+    toolchain_ref(reference).map(|()| reference.to_string())
+}
+
+fn ruby_version(version: &str) -> Result<String, String> {
+    ruby_attribute(version).map(|_| version.to_string())
+}
+
+fn dotnet_sdk(release: &str) -> Result<u32, String> {
+    let major = release
+        .parse()
+        .map_err(|_| format!("invalid .NET SDK '{release}' (expected a major release such as 8)"))?;
+    dotnet_sdk_attribute(major).map(|_| major)
+}
+
+async fn load_manager(config: &Config, allow_unsigned: bool) -> Result<TemplateManager> {
+    let mut manager = TemplateManager::new().await?;
+    // Synced templates replace the embedded ones; template-dirs replace both
+    let synced = sync::user_templates_dir()?;
+    if synced.is_dir() {
+        manager.load_template_dir(&synced)?;
+    }
+    for dir in config.template_dirs.iter().flatten() {
+        manager.load_template_dir(dir)?;
+    }
+    for command in config.template_providers.iter().flatten() {
+        if remote::is_offline() {
+            tracing::warn!("Skipping template provider '{command}' in offline mode");
+            continue;
+        }
+        let keys = config.template_signing_keys.clone().unwrap_or_default();
+        let provider = plugins::CommandProvider::new(command)
+            .verify_with(keys, allow_unsigned)
+            .fetch_with(remote::Remote::from_config(config));
+        manager.add_provider(&provider)?;
+    }
+    if let Some(policy) = policy::load(config)? {
+        manager.set_policy(policy);
+    }
+    if let Some(header) = &config.header {
+        manager.set_header(metadata::Header::new(header));
+    }
+    Ok(manager)
+}
+
+/// How to treat an existing flake.nix that differs: replace it with
+/// `--force`, else ask when there is a terminal to ask on.
+fn overwrite_policy(force: bool) -> OverwritePolicy {
+    if force {
+        OverwritePolicy::Backup
+    } else if std::io::stdin().is_terminal() {
+        OverwritePolicy::Prompt
+    } else {
+        OverwritePolicy::Refuse
+    }
+}
+
+/// Print a path for scripts, or with `--output json` also whether it exists.
+fn print_path(command: &str, path: &Path, format: OutputFormat) -> Result<()> {
+    if format.is_json() {
+        return output::print_json(
+            command,
+            &serde_json::json!({ "path": path, "exists": path.exists() }),
+        );
+    }
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Re-run the generation recorded in `target_path/flake.nix`, with the
+/// recorded options unless `options` replaces them.
+async fn regenerate(
+    manager: &TemplateManager,
+    target_path: &Path,
+    options: Option<&GenerationOptions>,
+) -> Result<()> {
+    let flake_path = target_path.join("flake.nix");
+    let content = std::fs::read_to_string(&flake_path)
+        .with_context(|| format!("Failed to read {}", flake_path.display()))?;
+    let record = metadata::GenerationRecord::read(&content)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} has no generation record; it was not created by nix-flake-generator",
+            flake_path.display()
+        )
+    })?;
+
+    for name in record.changed_templates(manager) {
+        status!("Template '{name}' changed since the last generation");
+    }
+
+    let options = options.unwrap_or(&record.options);
+    let template_list: Vec<&str> = record.templates.iter().map(String::as_str).collect();
+    let outcome = if !record.shells.is_empty() {
+        let shells: Vec<(String, Vec<String>)> = record.shells.clone().into_iter().collect();
+        manager
+            .init_shells(&shells, target_path, options, OverwritePolicy::Backup)
+            .await?
+    } else if template_list.len() > 1 {
+        manager
+            .init_multi(&template_list, target_path, options, OverwritePolicy::Backup)
+            .await?
+    } else if let Some(template) = template_list.first() {
+        manager
+            .init_single(template, target_path, options, OverwritePolicy::Backup)
+            .await?
+    } else {
+        return Err(anyhow::anyhow!("Generation record lists no templates"));
+    };
+    if outcome == WriteOutcome::UpToDate {
+        status!("{} is already up to date", target_path.display());
+    } else {
+        success!(
+            "Regenerated {} in {}",
+            template_list.join(","),
+            target_path.display()
+        );
+    }
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = Config::load()?;
+    if cli.offline || config.offline.unwrap_or_default() {
+        remote::set_offline(true);
+    }
+    let manager = load_manager(&config, cli.allow_unsigned).await?;
+    let remote = remote::Remote::from_config(&config);
+    let mut options = config.generation_options();
 
     match cli.command {
-        Commands::Init { templates, path } => {
-            let target_path = path.unwrap_or_else(|| PathBuf::from("."));
+        Commands::Init(args) => {
+            let InitArgs {
+                templates,
+                path,
+                profile,
+                from_repo,
+                from_flake,
+                stdout,
+                nixpkgs_ref,
+                pin_nixpkgs,
+                systems,
+                force,
+                merge,
+                check_packages,
+                verify,
+                hooks,
+                on_conflict,
+                provenance,
+                shell_options,
+                inputs_from,
+                latex_scheme,
+                with_biber,
+                with_pygments,
+                hashi_providers,
+                erlang_otp,
+                elixir,
+                php_version,
+                php_ext,
+                ruby,
+                dotnet_sdks,
+                jdk,
+                jdk_dist,
+                build_tool,
+                ocaml,
+                zig,
+                vlang,
+                ghc,
+                embedded,
+                flakes_hint,
+                vscode,
+                wrapper,
+                container,
+            } = *args;
+            // Profile languages come first, followed by any listed explicitly
+            let mut names: Vec<&str> = Vec::new();
+            if let Some(profile_name) = &profile {
+                let profile = config.profile(profile_name)?;
+                names.extend(profile.languages.iter().map(String::as_str));
+                profile.options.apply_to(&mut options);
+            }
+            if let Some(templates) = &templates {
+                names.extend(templates.split(',').map(|s| s.trim()));
+            }
+            let mut template_list = config.expand_aliases(&names)?;
+            if let Some(url) = &from_repo {
+                remote::require_network("--from-repo")?;
+                let available = manager.names();
+                let files = detect::remote_files(url, &remote)?;
+                let detected: Vec<&str> = detect::templates_for_files(&files)
+                    .into_iter()
+                    .filter(|template| available.contains(template))
+                    .collect();
+                if detected.is_empty() && template_list.is_empty() {
+                    return Err(anyhow::anyhow!("No known languages detected in {url}"));
+                }
+                if !stdout {
+                    status!("Detected {} in {url}", detected.join(", "));
+                }
+                for template in detected {
+                    if !template_list.iter().any(|name| name == template) {
+                        template_list.push(template.to_string());
+                    }
+                }
+            }
+
+            if nixpkgs_ref.is_some() {
+                options.nixpkgs_ref = nixpkgs_ref;
+            }
+            if let Some(channel) = pin_nixpkgs {
+                let pin = pin::resolve(channel, remote::is_offline(), &remote)?;
+                if let (Some(rev), false) = (&pin.rev, stdout) {
+                    status!("Pinned nixpkgs to {} at {rev}", pin.branch);
+                }
+                options.nixpkgs_ref = Some(pin.reference().to_string());
+            }
+            if systems.is_some() {
+                options.systems = systems;
+            }
+            if let Some(strategy) = on_conflict {
+                options.on_conflict = strategy;
+            }
+            if provenance {
+                options.provenance_comments = true;
+            }
+            shell_options.apply(&mut options);
+            if !inputs_from.is_empty() {
+                options.inputs_from = inputs_from;
+            }
+            if let Some(scheme) = latex_scheme {
+                options.latex.scheme = scheme;
+            }
+            if with_biber {
+                options.latex.biber = true;
+            }
+            if with_pygments {
+                options.latex.pygments = true;
+            }
+            if !hashi_providers.is_empty() {
+                options.terraform_providers = hashi_providers;
+            }
+            if erlang_otp.is_some() {
+                options.beam.otp = erlang_otp;
+            }
+            if elixir.is_some() {
+                options.beam.elixir = elixir;
+            }
+            if php_version.is_some() {
+                options.php.version = php_version;
+            }
+            if !php_ext.is_empty() {
+                options.php.extensions = php_ext;
+            }
+            if ruby.is_some() {
+                options.ruby = ruby;
+            }
+            if !dotnet_sdks.is_empty() {
+                options.dotnet_sdks = dotnet_sdks;
+            }
+            if jdk.is_some() {
+                options.java.version = jdk;
+            }
+            if let Some(dist) = jdk_dist {
+                options.java.dist = dist;
+            }
+            if build_tool.is_some() {
+                options.java.build_tool = build_tool;
+            }
+            if ocaml.is_some() {
+                options.ocaml = ocaml;
+            }
+            if zig.is_some() {
+                options.zig = zig;
+            }
+            if vlang.is_some() {
+                options.vlang = vlang;
+            }
+            if let Some(version) = ghc {
+                let attribute = ghc_attribute(&version).map_err(|e| anyhow::anyhow!(e))?;
+                if nix::is_available() {
+                    nix::check_ghc(&hooks::nixpkgs_url(&options), &attribute)?;
+                } else {
+                    tracing::warn!("nix is not available; GHC {version} was not looked up");
+                }
+                options.ghc = Some(version);
+            }
+            if embedded.is_some() {
+                options.embedded = embedded;
+            }
+            if flakes_hint {
+                options.flakes_hint = true;
+            }
+            if container {
+                options.container = true;
+            }
+
+            let template_list: Vec<&str> = template_list.iter().map(String::as_str).collect();
+            if check_packages {
+                let rendered = manager.render(&template_list, &options)?;
+                packages::check(
+                    &rendered.flake_content,
+                    &hooks::nixpkgs_url(&options),
+                    config.package_index.as_deref(),
+                )?;
+            }
+            if stdout {
+                print!("{}", manager.render(&template_list, &options)?.flake_content);
+                return Ok(());
+            }
+
+            let target_path = paths::prepare_target_dir(
+                &path
+                    .or_else(|| config.defaults.path.clone())
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            )?;
+            let policy = overwrite_policy(force);
+
+            if let Some(template) = &from_flake {
+                nix::init_from_template(&target_path, template)?;
+                let content = std::fs::read_to_string(target_path.join("flake.nix"))?;
+                manager.check_policy(&[], &content)?;
+                success!("Initialized {} in {}", template, target_path.display());
+            }
+            // Templates listed with --from-flake go on top of the upstream flake
+            let merge = merge || from_flake.is_some();
 
             // Check if it's a single template or multiple templates
-            if templates.contains(',') {
+            let outcome = if from_flake.is_some() && template_list.is_empty() {
+                WriteOutcome::Written
+            } else if merge {
+                let outcome = manager.init_merge(&template_list, &target_path, &options).await?;
+                if outcome == WriteOutcome::UpToDate {
+                    status!("{} is already up to date", target_path.display());
+                } else {
+                    success!(
+                        "Merged {} into {}",
+                        template_list.join(","),
+                        target_path.join("flake.nix").display()
+                    );
+                }
+                outcome
+            } else if template_list.len() > 1 {
                 // Multi-language template
-                let template_list: Vec<&str> = templates.split(',').map(|s| s.trim()).collect();
-                manager.init_multi(&template_list, &target_path).await?;
-                println!(
-                    "Initialized multi-language template ({}) in {}",
-                    templates,
-                    target_path.display()
+                let outcome = manager
+                    .init_multi(&template_list, &target_path, &options, policy)
+                    .await?;
+                if outcome == WriteOutcome::UpToDate {
+                    status!("{} is already up to date", target_path.display());
+                } else {
+                    success!(
+                        "Initialized multi-language template ({}) in {}",
+                        template_list.join(","),
+                        target_path.display()
+                    );
+                }
+                outcome
+            } else if let Some(template) = template_list.first() {
+                // Single template
+                let outcome = manager
+                    .init_single(template, &target_path, &options, policy)
+                    .await?;
+                if outcome == WriteOutcome::UpToDate {
+                    status!("{} is already up to date", target_path.display());
+                } else {
+                    success!(
+                        "Initialized {} template in {}",
+                        template,
+                        target_path.display()
+                    );
+                }
+                outcome
+            } else {
+                return Err(anyhow::anyhow!("No templates selected"));
+            };
+
+            if vscode {
+                for file in vscode::write(&target_path, &template_list)? {
+                    status!("Wrote {}", file.display());
+                }
+            }
+            for file in wrapper::write(&target_path, &wrapper)? {
+                status!("Wrote {}", file.display());
+            }
+            if container {
+                for file in container::write(&target_path)? {
+                    status!("Wrote {}", file.display());
+                }
+            }
+
+            if let Some(advice) = platform::nix_advice(&target_path) {
+                tracing::warn!("{advice}");
+            }
+
+            if let Some(level) = verify {
+                nix::verify(&target_path, level)?;
+            }
+            if let Some(budget) = &config.closure_budget {
+                stats::check_budget(&target_path, budget)?;
+            }
+
+            if outcome == WriteOutcome::Written {
+                let hook_list: Vec<&str> = config
+                    .post_generate
+                    .iter()
+                    .flatten()
+                    .chain(&hooks)
+                    .map(String::as_str)
+                    .collect();
+                hooks::run(
+                    &hook_list,
+                    &hooks::HookContext {
+                        templates: &template_list,
+                        target_path: &target_path,
+                        options: &options,
+                    },
+                )?;
+            }
+        }
+        Commands::Generate { path, force } => {
+            if config.shells.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No shells are configured; add [shells.<name>] tables with languages to {}",
+                    config::PROJECT_CONFIG_FILE
+                ));
+            }
+            let mut shells = Vec::new();
+            for (name, shell) in &config.shells {
+                let names: Vec<&str> = shell.languages.iter().map(String::as_str).collect();
+                let languages = config.expand_aliases(&names)?;
+                if languages.is_empty() {
+                    return Err(anyhow::anyhow!("Shell '{name}' has no languages"));
+                }
+                shells.push((name.clone(), languages));
+            }
+
+            let target_path = paths::prepare_target_dir(
+                &path
+                    .or_else(|| config.defaults.path.clone())
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            )?;
+            let outcome = manager
+                .init_shells(&shells, &target_path, &options, overwrite_policy(force))
+                .await?;
+            if outcome == WriteOutcome::UpToDate {
+                status!("{} is already up to date", target_path.display());
+            } else {
+                let names: Vec<&str> = shells.iter().map(|(name, _)| name.as_str()).collect();
+                success!(
+                    "Generated devShells {} in {}",
+                    names.join(", "),
+                    target_path.join("flake.nix").display()
+                );
+            }
+        }
+        Commands::Regenerate { path } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            regenerate(&manager, &target_path, None).await?;
+        }
+        Commands::Watch { path } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let watched = watch::watched_files(&target_path);
+            status!(
+                "Watching {} for changes (Ctrl-C to stop)",
+                watched
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            loop {
+                let changed = watch::wait_for_change(&watched).await;
+                status!("{} changed, regenerating", changed.display());
+                // Reload so edits to templates dirs, profiles and options apply
+                let result = async {
+                    let config = Config::load()?;
+                    let manager = load_manager(&config, cli.allow_unsigned).await?;
+                    regenerate(&manager, &target_path, Some(&config.generation_options())).await
+                }
+                .await;
+                if let Err(error) = result {
+                    errors::report(&error, cli.output);
+                }
+            }
+        }
+        Commands::Fmt { file } => {
+            pipe::fmt(file.as_deref())?;
+        }
+        Commands::Lint {
+            paths,
+            severity,
+            fix,
+            sarif,
+        } => {
+            let stdin = paths.is_empty() || paths == [PathBuf::from("-")];
+            if stdin && fix && (sarif || cli.output.is_json()) {
+                return Err(anyhow::anyhow!(
+                    "lint --fix prints the fixed source of stdin; leave out --sarif and --output json"
+                ));
+            }
+            let report = if stdin {
+                let (report, fixed) = diagnostics::run_stdin(severity, fix)?;
+                if fix {
+                    print!("{fixed}");
+                }
+                report
+            } else {
+                diagnostics::run(&paths, severity, fix)?
+            };
+            if sarif {
+                println!("{}", serde_json::to_string_pretty(&diagnostics::sarif(&report))?);
+            } else {
+                diagnostics::print_report(&report, cli.output, stdin && !fix)?;
+                if report.fixed > 0 && !stdin && !cli.output.is_json() {
+                    success!("Fixed {} problem(s)", report.fixed);
+                }
+            }
+            if !report.findings.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} problem(s) in {} nix file(s)",
+                    report.findings.len(),
+                    report.files
+                ));
+            }
+        }
+        Commands::Merge {
+            files,
+            path,
+            stdout,
+            on_conflict,
+        } => {
+            let strategy = on_conflict.unwrap_or(options.on_conflict);
+            if files.is_empty() {
+                let sources = vec![pipe::read_source(None)?];
+                let merged = pipe::merge_sources(&sources, strategy)?;
+                manager.check_policy(&[], &merged)?;
+                print!("{merged}");
+                return Ok(());
+            }
+
+            let sources = files
+                .iter()
+                .map(|file| pipe::read_source(Some(file)))
+                .collect::<Result<Vec<_>>>()?;
+            if stdout {
+                let merged = pipe::merge_sources(&sources, strategy)?;
+                manager.check_policy(&[], &merged)?;
+                print!("{merged}");
+                return Ok(());
+            }
+
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let names: Vec<&str> = sources.iter().map(|(name, _)| name.as_str()).collect();
+            if manager.merge_flakes(&sources, &target_path, strategy)? == WriteOutcome::UpToDate {
+                status!("{} is already up to date", target_path.display());
+            } else {
+                success!(
+                    "Merged {} into {}",
+                    names.join(", "),
+                    target_path.join("flake.nix").display()
                 );
+            }
+        }
+        Commands::Migrate { from, path, force } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let imported = migrate::read(&target_path, from)?;
+            let migration = migrate::plan(&imported, &manager.names());
+            for problem in &migration.unmapped {
+                tracing::warn!("{problem}");
+            }
+            if migration.templates.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Nothing in {} maps to a template",
+                    imported.file.display()
+                ));
+            }
+
+            migration.apply_to(&mut options);
+            let template_list: Vec<&str> = migration.templates.iter().map(String::as_str).collect();
+            let policy = overwrite_policy(force);
+            let outcome = if let [template] = template_list[..] {
+                manager.init_single(template, &target_path, &options, policy).await?
             } else {
-                // Single template
-                manager.init_single(&templates, &target_path).await?;
-                println!(
-                    "Initialized {} template in {}",
-                    templates,
+                manager.init_multi(&template_list, &target_path, &options, policy).await?
+            };
+            if outcome == WriteOutcome::UpToDate {
+                status!("{} is already up to date", target_path.display());
+            } else {
+                success!(
+                    "Migrated {} to {} in {}",
+                    imported.file.display(),
+                    template_list.join(","),
                     target_path.display()
                 );
             }
         }
+        Commands::Detect { from_dockerfile } => {
+            let imported = detect::from_dockerfile(&from_dockerfile)?;
+            let migration = migrate::plan(&imported, &manager.names());
+            for problem in &migration.unmapped {
+                tracing::warn!("{problem}");
+            }
+            if migration.templates.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Nothing in {} maps to a template",
+                    from_dockerfile.display()
+                ));
+            }
+
+            migration.apply_to(&mut options);
+            let template_list: Vec<&str> = migration.templates.iter().map(String::as_str).collect();
+            print!("{}", manager.render(&template_list, &options)?.flake_content);
+        }
         Commands::List => {
-            manager.list_templates();
+            manager.list_templates(cli.output)?;
+        }
+        Commands::Shell {
+            templates,
+            shell_options,
+        } => {
+            shell_options.apply(&mut options);
+            let names: Vec<&str> = templates.split(',').map(|s| s.trim()).collect();
+            let expanded = config.expand_aliases(&names)?;
+            let template_list: Vec<&str> = expanded.iter().map(String::as_str).collect();
+            let flake_dir = cache::materialize(&manager, &template_list, &options)?;
+            shell::develop(&flake_dir)?;
+        }
+        Commands::Run {
+            templates,
+            shell_options,
+            command,
+        } => {
+            shell_options.apply(&mut options);
+            let names: Vec<&str> = templates.split(',').map(|s| s.trim()).collect();
+            let expanded = config.expand_aliases(&names)?;
+            let template_list: Vec<&str> = expanded.iter().map(String::as_str).collect();
+            let flake_dir = cache::materialize(&manager, &template_list, &options)?;
+            let code = shell::run(&flake_dir, &command)?;
+            std::process::exit(code);
+        }
+        Commands::Cache { command } => match command {
+            CacheCommands::List => {
+                cache::print_entries(&cache::list_entries()?, cli.output)?;
+            }
+            CacheCommands::Clean => {
+                let removed = cache::clean()?;
+                status!("Removed {removed} cached environment(s)");
+            }
+            CacheCommands::Dir => print_path("cache dir", &cache::cache_root()?, cli.output)?,
+        },
+        Commands::Clean { path, dry_run } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let artifacts = clean::find(&target_path)?;
+            if cli.output.is_json() {
+                output::print_json("clean", &artifacts)?;
+            } else if artifacts.is_empty() {
+                status!("Nothing to clean in {}", target_path.display());
+            } else {
+                let verb = if dry_run { "Would remove" } else { "Removing" };
+                for artifact in &artifacts {
+                    status!("{verb} {}", artifact.describe());
+                }
+            }
+
+            if !dry_run && !artifacts.is_empty() {
+                clean::remove(&artifacts)?;
+                success!("Removed {} artifact(s)", artifacts.len());
+            }
+        }
+        Commands::Template { command } => match command {
+            TemplateCommands::Sync { dry_run, upstream } => {
+                let dir = sync::user_templates_dir()?;
+                let report = sync::sync(&remote, &upstream, &dir, dry_run)?;
+                for (name, status) in &report.templates {
+                    match status {
+                        sync::Status::Changed { diff } => eprint!("{}", color::diff(diff)),
+                        sync::Status::NotUpstream => {
+                            tracing::debug!("{name} has no upstream counterpart")
+                        }
+                        sync::Status::UpToDate => {}
+                    }
+                }
+
+                let changed: Vec<&str> = report.changed().collect();
+                if changed.is_empty() {
+                    status!("All templates match {upstream}");
+                } else if dry_run {
+                    status!("Changed upstream: {}", changed.join(", "));
+                } else {
+                    success!("Installed {} into {}", changed.join(", "), report.dir.display());
+                }
+            }
+            TemplateCommands::Lint { dir } => {
+                let findings = lint::lint(&dir)?;
+                if cli.output.is_json() {
+                    output::print_json("template lint", &findings)?;
+                } else {
+                    for finding in &findings {
+                        let severity = match finding.severity {
+                            lint::Severity::Error => "error",
+                            lint::Severity::Warning => "warning",
+                        };
+                        eprintln!(
+                            "{severity}: {}: {}",
+                            dir.join(&finding.file).display(),
+                            finding.message
+                        );
+                    }
+                }
+
+                let errors = findings
+                    .iter()
+                    .filter(|finding| finding.severity == lint::Severity::Error)
+                    .count();
+                if errors > 0 {
+                    return Err(anyhow::anyhow!(
+                        "{errors} problem(s) in the templates in {}",
+                        dir.display()
+                    ));
+                }
+                status!("Templates in {} passed lint", dir.display());
+            }
+            TemplateCommands::Test {
+                dir,
+                spec,
+                nix_check,
+            } => {
+                let results = golden::run(&dir, &spec, nix_check).await?;
+                if cli.output.is_json() {
+                    output::print_json("template test", &results)?;
+                } else {
+                    for result in &results {
+                        if result.passed() {
+                            println!("PASS {}", result.name);
+                        } else {
+                            println!("FAIL {}", result.name);
+                            for failure in &result.failures {
+                                println!("  - {failure}");
+                            }
+                        }
+                    }
+                }
+
+                let failed = results.iter().filter(|result| !result.passed()).count();
+                if failed > 0 {
+                    return Err(anyhow::anyhow!(
+                        "{failed} of {} template test(s) failed",
+                        results.len()
+                    ));
+                }
+                status!("All {} template test(s) passed", results.len());
+            }
+        },
+        Commands::Alias { command } => match command {
+            AliasCommands::List => {
+                if cli.output.is_json() {
+                    output::print_json("alias list", &config.aliases)?;
+                } else if config.aliases.is_empty() {
+                    println!("No aliases configured");
+                } else {
+                    println!("Aliases:");
+                    for (alias, target) in &config.aliases {
+                        println!("  {alias} = {target}");
+                    }
+                }
+            }
+        },
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key } => {
+                let value = config.get(&key)?;
+                if cli.output.is_json() {
+                    output::print_json(
+                        "config get",
+                        &serde_json::json!({ "key": key, "value": value }),
+                    )?;
+                } else {
+                    match value {
+                        Some(toml::Value::String(value)) => println!("{value}"),
+                        Some(value) => println!("{value}"),
+                        None => println!("{key} is not set"),
+                    }
+                }
+            }
+            ConfigCommands::Set {
+                key,
+                value,
+                project,
+            } => {
+                let path = if project {
+                    config::project_config_path()
+                } else {
+                    config::global_config_path()?
+                };
+                config::set_value(&path, &key, &value)?;
+                status!("Set {} in {}", key, path.display());
+            }
+            ConfigCommands::Path { project } => {
+                let path = if project {
+                    paths::normalize(&config::project_config_path())?
+                } else {
+                    config::global_config_path()?
+                };
+                print_path("config path", &path, cli.output)?;
+            }
+        },
+        Commands::Deps { path, json } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let packages = deps::resolve_packages(&target_path)?;
+            let format = if json { OutputFormat::Json } else { cli.output };
+            deps::print_packages(&packages, format)?;
+        }
+        Commands::Explain {
+            path,
+            lock,
+            max_age,
+        } => {
+            let max_age = max_age
+                .or(config.lock_max_age)
+                .unwrap_or(explain::DEFAULT_MAX_LOCK_AGE);
+            let explanation = match path.filter(|path| path.as_os_str() != "-") {
+                Some(path) => explain::explain(&paths::normalize(&path)?, lock, max_age)?,
+                None if lock => {
+                    return Err(anyhow::anyhow!(
+                        "--lock reads the flake's lock, so it needs --path <directory>"
+                    ));
+                }
+                None => explain::explain_source(&pipe::read_source(None)?.1)?,
+            };
+            explain::print_explanation(&explanation, max_age, cli.output)?;
+        }
+        Commands::Ci { command } => match command {
+            CiCommands::Artifacts { path, kind } => {
+                let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+                let written = ci::write(&target_path, &kind)?;
+                if written.is_empty() {
+                    status!("{} is already up to date", target_path.display());
+                }
+                for file in written {
+                    status!("Wrote {}", file.display());
+                }
+            }
+        },
+        Commands::Input { command } => match command {
+            InputCommands::Dedupe { path, dry_run } => {
+                let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+                let found = inputs::dedupe(&target_path, dry_run)?;
+                inputs::print_duplicates(&found, cli.output)?;
+                if !found.is_empty() && !dry_run {
+                    success!(
+                        "Added {} follows to {}",
+                        found.len(),
+                        target_path.join("flake.nix").display()
+                    );
+                }
+            }
+        },
+        Commands::Stats { path, top } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let stats = stats::measure(&target_path, top)?;
+            stats::print_stats(&stats, cli.output)?;
+        }
+        Commands::Completions { shell } => {
+            completions::print(Cli::command(), shell, &manager, &config);
+        }
+        Commands::Man { dir } => {
+            reference::man(Cli::command(), dir.as_deref())?;
+        }
+        Commands::Help { command, all } => {
+            let mut cli_command = Cli::command();
+            if all {
+                print!("{}", reference::help_all(cli_command));
+            } else if let Some(name) = command {
+                cli_command.build();
+                let sub = cli_command
+                    .find_subcommand_mut(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown command '{}'", name))?;
+                sub.print_long_help()?;
+            } else {
+                cli_command.print_long_help()?;
+            }
+        }
+        Commands::Lsp => {
+            lsp::run(&manager)?;
+        }
+        Commands::Sbom {
+            path,
+            format,
+            output_file,
+        } => {
+            let target_path = paths::normalize(&path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let document = sbom::generate(&target_path, format)?;
+            match output_file {
+                Some(output) => {
+                    std::fs::write(&output, document)?;
+                    status!("Wrote SBOM to {}", output.display());
+                }
+                None => println!("{document}"),
+            }
+        }
+        Commands::External(args) => {
+            let builtin: Vec<String> = Cli::command()
+                .get_subcommands()
+                .map(|sub| sub.get_name().to_string())
+                .collect();
+            std::process::exit(plugins::run_external(&args, &builtin)?);
         }
     }
 
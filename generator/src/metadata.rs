@@ -0,0 +1,244 @@
+use crate::template::TemplateManager;
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::options::GenerationOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const BEGIN_MARKER: &str = "# @nix-flake-generator";
+const HEADER_MARKER: &str = "# @header";
+const END_MARKER: &str = "# @end";
+
+/// What a flake was generated from, stored as a comment block at the top of
+/// flake.nix so the generation can be replayed later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GenerationRecord {
+    pub version: String,
+    pub templates: Vec<String>,
+    pub template_versions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub options: GenerationOptions,
+    /// Templates of each named devShell, for flakes written by `generate`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shells: BTreeMap<String, Vec<String>>,
+}
+
+impl GenerationRecord {
+    pub fn new(
+        manager: &TemplateManager,
+        templates: &[&str],
+        options: &GenerationOptions,
+    ) -> Result<Self> {
+        let mut template_versions = BTreeMap::new();
+        for name in templates {
+            template_versions.insert(name.to_string(), manager.get(name)?.version());
+        }
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            templates: templates.iter().map(|t| t.to_string()).collect(),
+            template_versions,
+            options: options.clone(),
+            shells: BTreeMap::new(),
+        })
+    }
+
+    pub fn to_comment(&self) -> Result<String> {
+        let body = toml::to_string(self)?;
+        let mut comment = format!("{BEGIN_MARKER}\n");
+        for line in body.lines() {
+            if line.is_empty() {
+                comment.push_str("#\n");
+            } else {
+                comment.push_str(&format!("# {line}\n"));
+            }
+        }
+        comment.push_str(END_MARKER);
+        comment.push('\n');
+        Ok(comment)
+    }
+
+    /// Prefix flake content with the record, replacing any existing record.
+    pub fn prepend_to(&self, content: &str) -> Result<String> {
+        Ok(format!("{}{}", self.to_comment()?, strip(content)))
+    }
+
+    /// Read the record from flake content, if it has one.
+    pub fn read(content: &str) -> Result<Option<Self>> {
+        let Some(start) = content.find(BEGIN_MARKER) else {
+            return Ok(None);
+        };
+        let after_marker = &content[start + BEGIN_MARKER.len()..];
+        let end = after_marker
+            .find(END_MARKER)
+            .ok_or_else(|| anyhow!("Unterminated generation record in flake.nix"))?;
+
+        let body: String = after_marker[..end]
+            .lines()
+            .map(|line| line.trim_start().trim_start_matches('#'))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        toml::from_str(&body)
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid generation record in flake.nix: {}", e))
+    }
+
+    /// Templates whose content changed since this record was written.
+    pub fn changed_templates(&self, manager: &TemplateManager) -> Vec<String> {
+        self.template_versions
+            .iter()
+            .filter(|(name, version)| {
+                manager
+                    .get(name)
+                    .map(|template| &template.version() != *version)
+                    .unwrap_or(true)
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// The notice from the `header` config key, written as a comment block at
+/// the very top of generated files. It is recognized on later runs, so
+/// regenerating keeps it even where the config doesn't set one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    lines: Vec<String>,
+}
+
+impl Header {
+    /// `{version}` in `text` stands for the tool version.
+    pub fn new(text: &str) -> Self {
+        let text = text.replace("{version}", env!("CARGO_PKG_VERSION"));
+        Self {
+            lines: text.trim_end().lines().map(str::to_string).collect(),
+        }
+    }
+
+    pub fn to_comment(&self) -> String {
+        let mut comment = format!("{HEADER_MARKER}\n");
+        for line in &self.lines {
+            if line.is_empty() {
+                comment.push_str("#\n");
+            } else {
+                comment.push_str(&format!("# {line}\n"));
+            }
+        }
+        comment.push_str(END_MARKER);
+        comment.push('\n');
+        comment
+    }
+
+    /// Prefix content with the header, replacing any existing one.
+    pub fn prepend_to(&self, content: &str) -> String {
+        format!("{}{}", self.to_comment(), strip_header(content))
+    }
+
+    /// Read the header at the top of a file, if it has one.
+    pub fn read(content: &str) -> Option<Self> {
+        let body = content.strip_prefix(HEADER_MARKER)?;
+        let end = body.find(END_MARKER)?;
+        let lines = body[..end]
+            .lines()
+            .skip(1)
+            .map(|line| line.trim_start().trim_start_matches('#'))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line).to_string())
+            .collect();
+        Some(Self { lines })
+    }
+
+    /// Whether a generated file of this name takes `#` comments, and so
+    /// gets the header.
+    pub fn fits(filename: &str) -> bool {
+        filename == ".envrc"
+            || [".nix", ".toml", ".sh", ".yaml", ".yml"]
+                .iter()
+                .any(|extension| filename.ends_with(extension))
+    }
+}
+
+/// Remove a header from the top of file content.
+pub fn strip_header(content: &str) -> &str {
+    if !content.starts_with(HEADER_MARKER) {
+        return content;
+    }
+    match content.find(END_MARKER) {
+        Some(end) => content[end + END_MARKER.len()..].trim_start_matches('\n'),
+        None => content,
+    }
+}
+
+/// Remove a header and generation record from flake content.
+pub fn strip(content: &str) -> &str {
+    let content = strip_header(content);
+    if !content.starts_with(BEGIN_MARKER) {
+        return content;
+    }
+    match content.find(END_MARKER) {
+        Some(end) => content[end + END_MARKER.len()..].trim_start_matches('\n'),
+        None => content,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> GenerationRecord {
+        GenerationRecord {
+            version: "0.1.0".to_string(),
+            templates: vec!["rust".to_string(), "go".to_string()],
+            template_versions: BTreeMap::from([
+                ("go".to_string(), "aaaaaaaaaaaa".to_string()),
+                ("rust".to_string(), "bbbbbbbbbbbb".to_string()),
+            ]),
+            options: GenerationOptions {
+                nixpkgs_ref: Some("nixos-24.05".to_string()),
+                ..Default::default()
+            },
+            shells: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let content = record().prepend_to("{ }\n").unwrap();
+        assert!(content.starts_with(BEGIN_MARKER));
+        assert!(content.ends_with("{ }\n"));
+        assert_eq!(GenerationRecord::read(&content).unwrap(), Some(record()));
+    }
+
+    #[test]
+    fn test_prepend_replaces_existing_record() {
+        let once = record().prepend_to("{ }\n").unwrap();
+        let twice = record().prepend_to(&once).unwrap();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn test_record_is_valid_nix_comment() {
+        let content = record().prepend_to("{ description = \"x\"; }\n").unwrap();
+        assert!(nix_parser::parse_nix_expr(&content).is_ok());
+    }
+
+    #[test]
+    fn test_header_above_record() {
+        let header =
+            Header::new("Copyright Example Corp\n\nGenerated by nix-flake-generator {version}");
+        let content = header.prepend_to(&record().prepend_to("{ }\n").unwrap());
+        assert!(content.starts_with("# @header\n# Copyright Example Corp\n#\n# Generated by"));
+        assert!(!content.contains("{version}"));
+        assert_eq!(Header::read(&content), Some(header.clone()));
+        assert_eq!(GenerationRecord::read(&content).unwrap(), Some(record()));
+        assert_eq!(strip(&content), "{ }\n");
+        assert_eq!(header.prepend_to(&content), content);
+        assert!(nix_parser::parse_nix_expr(&content).is_ok());
+    }
+
+    #[test]
+    fn test_read_without_record() {
+        assert_eq!(GenerationRecord::read("{ }").unwrap(), None);
+    }
+}
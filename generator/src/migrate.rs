@@ -0,0 +1,598 @@
+use anyhow::{Context, Result, anyhow};
+use nix_flake_generator_core::GenerationOptions;
+use clap::ValueEnum;
+use nix_parser::{AttrPathPart, Binding, BinaryOperator, NixExpr};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version managers and environments whose tool lists can be migrated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Source {
+    Asdf,
+    Mise,
+    Devbox,
+    Devenv,
+}
+
+impl Source {
+    /// Files the tool versions are read from, in order of preference.
+    fn files(self) -> &'static [&'static str] {
+        match self {
+            Source::Asdf => &[".tool-versions"],
+            Source::Mise => &[".mise.toml", "mise.toml", ".tool-versions"],
+            Source::Devbox => &["devbox.json"],
+            Source::Devenv => &["devenv.nix"],
+        }
+    }
+}
+
+/// Which part of a tool version a template's version variable holds.
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    /// `21` of `21.0.2`
+    Major,
+    /// `22` of `1.22.3`
+    Minor,
+}
+
+/// The template for a tool, with the version variable it pins if any.
+fn template_for(tool: &str) -> Option<(&'static str, Option<(&'static str, Part)>)> {
+    let template = match tool {
+        "go" | "golang" => return Some(("go", Some(("goVersion", Part::Minor)))),
+        "java" => return Some(("java", Some(("javaVersion", Part::Major)))),
+        "nodejs" | "node" | "javascript" | "typescript" => "node",
+        "python" => "python",
+        "rust" => "rust",
+        "ruby" => "ruby",
+        "elixir" | "erlang" => "elixir",
+        "zig" => "zig",
+        "c" | "cplusplus" => "c-cpp",
+        "nix" => "nix",
+        "shell" => "shell",
+        "texlive" => "latex",
+        "bun" => "bun",
+        "terraform" | "packer" | "vault" | "nomad" => "hashi",
+        "kotlin" => "kotlin",
+        "scala" | "sbt" => "scala",
+        "clojure" | "leiningen" => "clojure",
+        "haskell" | "ghc" => "haskell",
+        "ocaml" => "ocaml",
+        "php" => "php",
+        "dotnet" | "dotnet-core" => "csharp",
+        "swift" => "swift",
+        "nim" => "nim",
+        "gleam" => "gleam",
+        "elm" => "elm",
+        "protoc" | "buf" => "protobuf",
+        "pulumi" => "pulumi",
+        "opa" | "conftest" => "opa",
+        "cue" => "cue",
+        "dhall" => "dhall",
+        "nickel" => "nickel",
+        "r" | "R" => "r",
+        "v" | "vlang" => "vlang",
+        _ => return None,
+    };
+    Some((template, None))
+}
+
+/// What a tool versions file or devbox.json describes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Imported {
+    pub file: PathBuf,
+    /// Tools or nixpkgs packages with their version (`latest` if unpinned)
+    pub tools: Vec<(String, String)>,
+    /// Whether tools without a template are nixpkgs packages to add as-is
+    pub packages_are_nixpkgs: bool,
+    /// Package expressions added as-is
+    pub packages: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub init_hook: Vec<String>,
+    /// Settings that have no equivalent in a flake
+    pub skipped: Vec<String>,
+}
+
+/// Templates, version variables and shell settings equivalent to an
+/// imported environment.
+#[derive(Debug, Default, PartialEq)]
+pub struct Migration {
+    pub templates: Vec<String>,
+    pub variables: BTreeMap<String, String>,
+    /// nixpkgs packages without a template of their own
+    pub packages: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub init_hook: Vec<String>,
+    /// What could not be carried over, one line per tool
+    pub unmapped: Vec<String>,
+}
+
+impl Migration {
+    /// Carry the version variables, environment, packages and shell hook
+    /// over to `options`. Packages and the hook go in as snippets, after any
+    /// configured ones.
+    pub fn apply_to(&self, options: &mut GenerationOptions) {
+        options.variables.extend(self.variables.clone());
+        options.env.extend(self.env.clone());
+        for (name, lines) in [("packages", &self.packages), ("shellHook", &self.init_hook)] {
+            if lines.is_empty() {
+                continue;
+            }
+            let snippet = options.snippets.entry(name.to_string()).or_default();
+            for line in lines {
+                if !snippet.is_empty() {
+                    snippet.push('\n');
+                }
+                snippet.push_str(line);
+            }
+        }
+    }
+}
+
+/// Read the tools, versions and, for devbox and devenv, environment pinned
+/// in `dir` by `source`.
+pub fn read(dir: &Path, source: Source) -> Result<Imported> {
+    let path = source
+        .files()
+        .iter()
+        .map(|file| dir.join(file))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            anyhow!("No {} found in {}", source.files().join(" or "), dir.display())
+        })?;
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut imported = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => Imported {
+            tools: parse_mise_toml(&content)
+                .with_context(|| format!("Invalid {}", path.display()))?,
+            ..Default::default()
+        },
+        Some("json") => {
+            parse_devbox_json(&content).with_context(|| format!("Invalid {}", path.display()))?
+        }
+        Some("nix") => {
+            parse_devenv_nix(&content).with_context(|| format!("Invalid {}", path.display()))?
+        }
+        _ => Imported {
+            tools: parse_tool_versions(&content),
+            ..Default::default()
+        },
+    };
+    imported.file = path;
+    Ok(imported)
+}
+
+/// `tool version [fallback versions]` lines; the first version wins.
+fn parse_tool_versions(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let tool = words.next()?;
+            let version = words.next().unwrap_or("latest");
+            Some((tool.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/// The `[tools]` table, whose values are a version, a list of versions or
+/// a table with a `version` key.
+fn parse_mise_toml(content: &str) -> Result<Vec<(String, String)>> {
+    let document: toml::Table = toml::from_str(content)?;
+    let Some(tools) = document.get("tools").and_then(toml::Value::as_table) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(tools
+        .iter()
+        .map(|(tool, value)| {
+            let version = match value {
+                toml::Value::Array(versions) => versions.first(),
+                toml::Value::Table(options) => options.get("version"),
+                other => Some(other),
+            };
+            let version = version
+                .and_then(toml::Value::as_str)
+                .unwrap_or("latest")
+                .to_string();
+            (tool.clone(), version)
+        })
+        .collect())
+}
+
+/// `packages` (a list of `name@version` or a map of names to versions),
+/// `env` and `shell.init_hook`.
+fn parse_devbox_json(content: &str) -> Result<Imported> {
+    let document: Value = serde_json::from_str(content)?;
+    let mut imported = Imported {
+        packages_are_nixpkgs: true,
+        ..Default::default()
+    };
+
+    match &document["packages"] {
+        Value::Array(packages) => {
+            for package in packages.iter().filter_map(Value::as_str) {
+                match package.rsplit_once('@') {
+                    Some((name, version)) if !name.is_empty() => {
+                        imported.tools.push((name.to_string(), version.to_string()))
+                    }
+                    _ => imported.tools.push((package.to_string(), "latest".to_string())),
+                }
+            }
+        }
+        Value::Object(packages) => {
+            for (name, value) in packages {
+                let version = value.as_str().or_else(|| value["version"].as_str());
+                imported
+                    .tools
+                    .push((name.clone(), version.unwrap_or("latest").to_string()));
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(env) = document["env"].as_object() {
+        for (key, value) in env {
+            match value.as_str() {
+                Some(value) => {
+                    imported.env.insert(key.clone(), value.to_string());
+                }
+                None => imported.skipped.push(format!("env.{key}: not a string")),
+            }
+        }
+    }
+
+    match &document["shell"]["init_hook"] {
+        Value::String(hook) => imported.init_hook = hook.lines().map(String::from).collect(),
+        Value::Array(lines) => {
+            imported.init_hook = lines.iter().filter_map(Value::as_str).map(String::from).collect()
+        }
+        _ => {}
+    }
+    if let Some(scripts) = document["shell"]["scripts"].as_object() {
+        for name in scripts.keys() {
+            imported.skipped.push(format!("shell.scripts.{name}: scripts are not migrated"));
+        }
+    }
+
+    Ok(imported)
+}
+
+/// `languages.<name>.enable` and `.version`, `packages`, `env`,
+/// `scripts.<name>.exec` and `enterShell` of a devenv module. Scripts become
+/// `writeShellScriptBin` packages.
+fn parse_devenv_nix(content: &str) -> Result<Imported> {
+    let mut expr = &nix_parser::parse_nix_expr(content)?;
+    // The module is usually a function of `{ pkgs, ... }`
+    loop {
+        expr = match expr {
+            NixExpr::Lambda { body, .. }
+            | NixExpr::LetIn { body, .. }
+            | NixExpr::With { body, .. } => body,
+            _ => break,
+        };
+    }
+    let NixExpr::AttrSet { bindings, .. } = expr else {
+        return Err(anyhow!("Expected the module to be an attribute set"));
+    };
+
+    let mut settings = Vec::new();
+    flatten(bindings, &[], &mut settings);
+
+    let mut imported = Imported::default();
+    let mut languages: BTreeMap<String, (bool, String)> = BTreeMap::new();
+    for (path, value) in settings {
+        let parts: Vec<&str> = path.iter().map(String::as_str).collect();
+        match (&parts[..], value) {
+            (["languages", name, "enable"], NixExpr::Bool(enabled)) => {
+                language(&mut languages, name).0 = *enabled;
+            }
+            (["languages", name, "version"], NixExpr::String(version)) => {
+                language(&mut languages, name).1 = version.clone();
+            }
+            (["packages"], packages) => imported.packages.extend(package_expressions(packages)),
+            (["env", name], NixExpr::String(value)) => {
+                imported.env.insert(name.to_string(), value.clone());
+            }
+            (["scripts", name, "exec"], NixExpr::String(exec)) => imported.packages.push(format!(
+                "(writeShellScriptBin \"{name}\" {})",
+                nix_string(&script_lines(exec).join("\n"))
+            )),
+            (["enterShell"], NixExpr::String(hook)) => imported.init_hook = script_lines(hook),
+            _ => imported.skipped.push(format!("{}: not migrated", path.join("."))),
+        }
+    }
+
+    imported.tools = languages
+        .into_iter()
+        .filter(|(_, (enabled, _))| *enabled)
+        .map(|(name, (_, version))| (name, version))
+        .collect();
+    Ok(imported)
+}
+
+/// Whether a devenv language is enabled, and its version.
+fn language<'a>(
+    languages: &'a mut BTreeMap<String, (bool, String)>,
+    name: &str,
+) -> &'a mut (bool, String) {
+    languages
+        .entry(name.to_string())
+        .or_insert_with(|| (false, "latest".to_string()))
+}
+
+/// Every binding with its full attribute path, descending into nested
+/// attribute sets such as `languages = { go.enable = true; }`.
+fn flatten<'a>(
+    bindings: &'a [Binding],
+    prefix: &[String],
+    out: &mut Vec<(Vec<String>, &'a NixExpr)>,
+) {
+    for binding in bindings {
+        let mut path = prefix.to_vec();
+        for part in &binding.path.parts {
+            match part {
+                AttrPathPart::Identifier(name) | AttrPathPart::String(name) => {
+                    path.push(name.clone())
+                }
+                AttrPathPart::Interpolation(_) => path.push("${...}".to_string()),
+            }
+        }
+        match &binding.value {
+            NixExpr::AttrSet { recursive: false, bindings } => flatten(bindings, &path, out),
+            value => out.push((path, value)),
+        }
+    }
+}
+
+/// Package names of a `packages` list, written as they are in a
+/// `with pkgs;` list.
+fn package_expressions(expr: &NixExpr) -> Vec<String> {
+    match expr {
+        NixExpr::With { body, .. } => package_expressions(body),
+        NixExpr::BinaryOp { left, op: BinaryOperator::Concat, right } => {
+            let mut packages = package_expressions(left);
+            packages.extend(package_expressions(right));
+            packages
+        }
+        NixExpr::List(items) => items
+            .iter()
+            .map(|item| match item {
+                NixExpr::Identifier(name) => name.clone(),
+                NixExpr::Select { .. } if selects_from_pkgs(item) => {
+                    item.to_nix_string().trim_start_matches("pkgs.").to_string()
+                }
+                other => format!("({})", other.to_nix_string()),
+            })
+            .collect(),
+        other => vec![format!("({})", other.to_nix_string())],
+    }
+}
+
+/// Whether `expr` is an attribute of `pkgs`, such as `pkgs.git` or
+/// `pkgs.nodePackages.prettier`, with no `or` default.
+fn selects_from_pkgs(expr: &NixExpr) -> bool {
+    match expr {
+        NixExpr::Select { expr, default: None, .. } => match &**expr {
+            NixExpr::Identifier(name) => name == "pkgs",
+            inner => selects_from_pkgs(inner),
+        },
+        _ => false,
+    }
+}
+
+/// The lines of an indented string, without its indentation and the blank
+/// lines around it.
+fn script_lines(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(start, |i| i + 1);
+    lines[start..end].iter().map(|line| line.to_string()).collect()
+}
+
+fn nix_string(value: &str) -> String {
+    format!("\"{}\"", nix_parser::escape_string(value))
+}
+
+/// Map tools to the `available` templates, pinning versions where the
+/// template has a version variable. Tools without a template are added as
+/// packages when they are nixpkgs packages, and reported otherwise. Packages
+/// without any template go into the `shell` template's devShell.
+pub fn plan(imported: &Imported, available: &[&str]) -> Migration {
+    let mut migration = Migration {
+        packages: imported.packages.clone(),
+        env: imported.env.clone(),
+        init_hook: imported.init_hook.clone(),
+        unmapped: imported.skipped.clone(),
+        ..Default::default()
+    };
+
+    for (tool, version) in &imported.tools {
+        let Some((template, variable)) = template_for(tool) else {
+            if !imported.packages_are_nixpkgs || tool.contains([':', '#', '/']) {
+                migration.unmapped.push(format!("{tool} {version}: no matching template"));
+                continue;
+            }
+            migration.packages.push(tool.clone());
+            if version != "latest" {
+                migration
+                    .unmapped
+                    .push(format!("{tool} {version}: added with the nixpkgs version"));
+            }
+            continue;
+        };
+        if !available.contains(&template) {
+            migration
+                .unmapped
+                .push(format!("{tool} {version}: template '{template}' is not available"));
+            continue;
+        }
+        if !migration.templates.iter().any(|t| t == template) {
+            migration.templates.push(template.to_string());
+        }
+
+        match variable {
+            Some((name, part)) => match version_part(version, part) {
+                Some(value) => {
+                    migration.variables.insert(name.to_string(), value);
+                }
+                None => migration.unmapped.push(format!(
+                    "{tool} {version}: version not understood, the {template} template's default is used"
+                )),
+            },
+            None if version != "latest" => migration.unmapped.push(format!(
+                "{tool} {version}: the {template} template uses the nixpkgs version"
+            )),
+            None => {}
+        }
+    }
+
+    // Plain packages still need a devShell to go into
+    let needs_shell = migration.templates.is_empty() && !migration.packages.is_empty();
+    if needs_shell && available.contains(&"shell") {
+        migration.templates.push("shell".to_string());
+    }
+    migration
+}
+
+/// The number a version variable takes from a version such as `1.22.3` or
+/// `temurin-21.0.2+13`.
+fn version_part(version: &str, part: Part) -> Option<String> {
+    let start = version.find(|c: char| c.is_ascii_digit())?;
+    let mut numbers = version[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty());
+    match part {
+        Part::Major => numbers.next(),
+        Part::Minor => numbers.nth(1),
+    }
+    .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_versions() {
+        let tools = parse_tool_versions("golang 1.22.3 1.21.0\n# pinned by CI\nnodejs 20.11.0 # LTS\n\n");
+        assert_eq!(
+            tools,
+            vec![
+                ("golang".to_string(), "1.22.3".to_string()),
+                ("nodejs".to_string(), "20.11.0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mise_toml() {
+        let tools = parse_mise_toml(
+            "[tools]\ngo = \"1.22\"\njava = [\"temurin-21.0.2\", \"17\"]\npython = { version = \"3.11\" }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tools,
+            vec![
+                ("go".to_string(), "1.22".to_string()),
+                ("java".to_string(), "temurin-21.0.2".to_string()),
+                ("python".to_string(), "3.11".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_devbox_json() {
+        let imported = parse_devbox_json(
+            r#"{
+  "packages": ["go@1.22", "jq@latest", "ripgrep"],
+  "env": { "GOFLAGS": "-mod=mod" },
+  "shell": {
+    "init_hook": ["echo ready"],
+    "scripts": { "test": "go test ./..." }
+  }
+}"#,
+        )
+        .unwrap();
+
+        let migration = plan(&imported, &["go"]);
+        assert_eq!(migration.templates, vec!["go"]);
+        assert_eq!(migration.variables["goVersion"], "22");
+        assert_eq!(migration.packages, vec!["jq", "ripgrep"]);
+        assert_eq!(imported.env["GOFLAGS"], "-mod=mod");
+        assert_eq!(imported.init_hook, vec!["echo ready"]);
+        assert_eq!(migration.unmapped, vec!["shell.scripts.test: scripts are not migrated"]);
+    }
+
+    #[test]
+    fn test_parse_devenv_nix() {
+        let imported = parse_devenv_nix(
+            r#"{ pkgs, ... }:
+{
+  packages = [ pkgs.git pkgs.nodePackages.prettier ];
+  env.GREETING = "hello";
+  languages.go = {
+    enable = true;
+    version = "1.22";
+  };
+  languages.python.enable = false;
+  scripts.hello.exec = ''
+    echo $GREETING
+  '';
+  enterShell = ''
+    hello
+  '';
+  services.postgres.enable = true;
+}"#,
+        )
+        .unwrap();
+
+        assert_eq!(imported.tools, vec![("go".to_string(), "1.22".to_string())]);
+        assert_eq!(
+            imported.packages,
+            vec![
+                "git",
+                "nodePackages.prettier",
+                "(writeShellScriptBin \"hello\" \"echo $GREETING\")",
+            ]
+        );
+        assert_eq!(imported.env["GREETING"], "hello");
+        assert_eq!(imported.init_hook, vec!["hello"]);
+        assert_eq!(imported.skipped, vec!["services.postgres.enable: not migrated"]);
+    }
+
+    #[test]
+    fn test_plan() {
+        let tools = vec![
+            ("golang".to_string(), "1.22.3".to_string()),
+            ("java".to_string(), "temurin-21.0.2+13".to_string()),
+            ("nodejs".to_string(), "20.11.0".to_string()),
+            ("awscli".to_string(), "2.15.0".to_string()),
+        ];
+        let imported = Imported {
+            tools,
+            ..Default::default()
+        };
+        let migration = plan(&imported, &["go", "java", "node"]);
+
+        assert_eq!(migration.templates, vec!["go", "java", "node"]);
+        assert_eq!(
+            migration.variables,
+            BTreeMap::from([
+                ("goVersion".to_string(), "22".to_string()),
+                ("javaVersion".to_string(), "21".to_string()),
+            ])
+        );
+        assert_eq!(
+            migration.unmapped,
+            vec![
+                "nodejs 20.11.0: the node template uses the nixpkgs version",
+                "awscli 2.15.0: no matching template",
+            ]
+        );
+    }
+}
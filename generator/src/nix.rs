@@ -0,0 +1,236 @@
+use crate::channels;
+use crate::output::{status, success};
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+/// Failures invoking nix itself, as opposed to problems in the flake.
+#[derive(Debug, thiserror::Error)]
+pub enum NixError {
+    #[error("nix is required for this command but was not found on PATH")]
+    NotInstalled,
+
+    #[error("Failed to run nix")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("{command} failed: {stderr}")]
+    Failed { command: String, stderr: String },
+
+    #[error("Verification failed: nix exited with {0}")]
+    VerificationFailed(ExitStatus),
+
+    #[error("No flake.nix found in {}", .0.display())]
+    NoFlake(PathBuf),
+}
+
+/// Build a `nix` command with the flake features enabled, so invocations work
+/// on installations that haven't turned them on globally.
+pub fn nix_command() -> Command {
+    let mut command = Command::new("nix");
+    command.args(["--extra-experimental-features", "nix-command flakes"]);
+    if crate::remote::is_offline() {
+        command.arg("--offline");
+    }
+    command
+}
+
+pub fn is_available() -> bool {
+    Command::new("nix").arg("--version").output().is_ok()
+}
+
+pub fn require_nix() -> Result<()> {
+    if is_available() {
+        Ok(())
+    } else {
+        Err(NixError::NotInstalled.into())
+    }
+}
+
+pub fn current_system() -> Result<String> {
+    let output = nix_command()
+        .args(["eval", "--impure", "--raw", "--expr", "builtins.currentSystem"])
+        .output()
+        .map_err(NixError::Spawn)?;
+
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: "Determining the current system".to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Flake reference for a local directory. The `path:` scheme avoids nix
+/// ignoring files that are not yet tracked by git.
+pub fn flake_ref(target_path: &Path) -> Result<String> {
+    if !target_path.join("flake.nix").exists() {
+        return Err(NixError::NoFlake(target_path.to_path_buf()).into());
+    }
+    let absolute = fs::canonicalize(target_path)?;
+    Ok(format!("path:{}", absolute.display()))
+}
+
+/// Installable for the default devShell of a flake on the current system.
+pub fn dev_shell_installable(flake: &str) -> Result<String> {
+    let system = current_system()?;
+    Ok(format!("{flake}#devShells.{system}.default"))
+}
+
+pub fn eval_json(installable: &str, apply: &str) -> Result<serde_json::Value> {
+    let output = nix_command()
+        .args(["eval", "--json", installable, "--apply", apply])
+        .output()
+        .map_err(NixError::Spawn)?;
+
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: format!("nix eval for {installable}"),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Check that `nixpkgs` has GHC `attribute`, such as `ghc98`, and the
+/// haskell-language-server and cabal-install of its `haskell.packages` set,
+/// the packages `--ghc` puts in the flake, before a flake asks for them.
+pub fn check_ghc(nixpkgs: &str, attribute: &str) -> Result<()> {
+    let expression = format!(
+        r#"let
+  pkgs = (builtins.getFlake "{nixpkgs}").legacyPackages.${{builtins.currentSystem}};
+  evaluates = drv: (builtins.tryEval drv.drvPath).success;
+  set = pkgs.haskell.packages.{attribute};
+in
+{{
+  ghc = pkgs.haskell.compiler ? {attribute} && evaluates pkgs.haskell.compiler.{attribute};
+  set = pkgs.haskell.packages ? {attribute};
+  hls = pkgs.haskell.packages ? {attribute} && evaluates set.haskell-language-server;
+  cabal = pkgs.haskell.packages ? {attribute} && evaluates set.cabal-install;
+}}"#
+    );
+    let output = nix_command()
+        .args(["eval", "--impure", "--json", "--expr", &expression])
+        .output()
+        .map_err(NixError::Spawn)?;
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: format!("Looking up {attribute} in {nixpkgs}"),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    let found: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    if found["ghc"] != true {
+        return Err(anyhow!("{nixpkgs} has no haskell.compiler.{attribute}"));
+    }
+    if found["set"] != true {
+        return Err(anyhow!("{nixpkgs} has no haskell.packages.{attribute}"));
+    }
+    for (key, tool) in [("hls", "haskell-language-server"), ("cabal", "cabal-install")] {
+        if found[key] != true {
+            return Err(anyhow!(
+                "{nixpkgs} has no haskell.packages.{attribute}.{tool} that builds"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The top-level attribute names of `nixpkgs`'s packages for the current
+/// system. Only names are listed, so nothing is evaluated.
+pub fn attribute_names(nixpkgs: &str) -> Result<Vec<String>> {
+    let expression = format!(
+        r#"builtins.attrNames (builtins.getFlake "{nixpkgs}").legacyPackages.${{builtins.currentSystem}}"#
+    );
+    let output = nix_command()
+        .args(["eval", "--impure", "--json", "--expr", &expression])
+        .output()
+        .map_err(NixError::Spawn)?;
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: format!("Listing the packages of {nixpkgs}"),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Start a project in `target_path` from an upstream flake template with
+/// `nix flake init -t`, such as `github:nix-community/templates#rust`.
+pub fn init_from_template(target_path: &Path, template: &str) -> Result<()> {
+    require_nix()?;
+    if target_path.join("flake.nix").exists() {
+        return Err(anyhow!(
+            "{} already has a flake.nix; --from-flake only starts new projects",
+            target_path.display()
+        ));
+    }
+
+    status!("Initializing {} from {template}", target_path.display());
+    let output = nix_command()
+        .current_dir(target_path)
+        .args(["flake", "init", "--template", template])
+        .output()
+        .map_err(NixError::Spawn)?;
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: format!("nix flake init --template {template}"),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    if !target_path.join("flake.nix").exists() {
+        return Err(anyhow!("{template} did not provide a flake.nix"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VerifyLevel {
+    /// Run `nix flake check --no-build`
+    Check,
+    /// Additionally build and enter the devShell with `nix develop -c true`
+    Full,
+}
+
+/// Check a generated flake with nix itself, streaming nix's output.
+pub fn verify(target_path: &Path, level: VerifyLevel) -> Result<()> {
+    let flake = flake_ref(target_path)?;
+    require_nix()?;
+
+    if let Some(warning) = fs::read_to_string(target_path.join("flake.nix"))
+        .ok()
+        .and_then(|content| channels::support_warning(&content))
+    {
+        tracing::warn!("{warning}");
+    }
+
+    status!("Verifying {} with nix flake check", target_path.display());
+    run_streaming(nix_command().args(["flake", "check", "--no-build", &flake]))?;
+
+    if level == VerifyLevel::Full {
+        status!("Verifying {} with nix develop", target_path.display());
+        run_streaming(nix_command().args(["develop", &flake, "--command", "true"]))?;
+    }
+
+    success!("Verification passed");
+    Ok(())
+}
+
+fn run_streaming(command: &mut Command) -> Result<()> {
+    let status = command.status().map_err(NixError::Spawn)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(NixError::VerificationFailed(status).into())
+    }
+}
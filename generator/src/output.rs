@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Version of the JSON envelope. Bump when a field is renamed or removed;
+/// adding fields is not a breaking change.
+pub const SCHEMA_VERSION: u32 = 1;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// `println!` for progress and confirmation messages, silenced by `--quiet`.
+/// Command results (listings, JSON, values) are printed unconditionally.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use status;
+
+/// `status!` for a completed action, highlighted when color is enabled.
+macro_rules! success {
+    ($($arg:tt)*) => {
+        $crate::output::status!("{}", $crate::color::success(&format!($($arg)*)))
+    };
+}
+pub(crate) use success;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn is_json(self) -> bool {
+        self == OutputFormat::Json
+    }
+}
+
+/// Print `data` wrapped in the versioned envelope shared by every command:
+/// `{"schema_version": 1, "command": "<name>", "data": ...}`.
+pub fn print_json<T: Serialize>(command: &str, data: &T) -> Result<()> {
+    let document = json!({
+        "schema_version": SCHEMA_VERSION,
+        "command": command,
+        "data": data,
+    });
+    println!("{}", serde_json::to_string_pretty(&document)?);
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::nix;
+use anyhow::{Context, Result, anyhow};
+use nix_flake_generator_core::suggest;
+use nix_parser::AttrPathPart;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// A package attribute the pinned nixpkgs does not have.
+#[derive(Debug, PartialEq)]
+pub struct MissingPackage {
+    pub name: String,
+    /// Attribute names close enough to be what was meant, best first
+    pub suggestions: Vec<String>,
+}
+
+/// The package attributes a flake takes from nixpkgs: the names in its
+/// devShell packages lists, less those its overlays or let bindings define.
+/// Attributes of package sets, like `nodePackages.prettier`, are left out as
+/// only top-level names are known.
+pub fn nixpkgs_attributes(flake: &str) -> Result<Vec<String>> {
+    let fragments = nix_parser::extract_flake_fragments(flake)?;
+    let mut defined: BTreeSet<&str> = fragments.let_bindings.keys().map(String::as_str).collect();
+    for bindings in fragments.overlays.values() {
+        for binding in bindings {
+            if let Some(AttrPathPart::Identifier(name)) = binding.path.parts.first() {
+                defined.insert(name.as_str());
+            }
+        }
+    }
+
+    let attributes: BTreeSet<&str> = fragments
+        .packages
+        .iter()
+        .map(String::as_str)
+        .filter(|name| !name.contains('.') && !defined.contains(name))
+        .collect();
+    Ok(attributes.into_iter().map(str::to_string).collect())
+}
+
+/// The entries of `attributes` that are not among `known`.
+pub fn missing(attributes: &[String], known: &BTreeSet<String>) -> Vec<MissingPackage> {
+    attributes
+        .iter()
+        .filter(|name| !known.contains(*name))
+        .map(|name| MissingPackage {
+            name: name.clone(),
+            suggestions: suggest::close_matches(name, known.iter().map(String::as_str))
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Check that every package the flake takes from nixpkgs exists there. The
+/// attribute names come from `nixpkgs` through nix when it is available,
+/// and otherwise from `index`, a file with one name per line.
+pub fn check(flake: &str, nixpkgs: &str, index: Option<&Path>) -> Result<()> {
+    let attributes = nixpkgs_attributes(flake)?;
+    let known: BTreeSet<String> = if nix::is_available() {
+        nix::attribute_names(nixpkgs)?.into_iter().collect()
+    } else if let Some(index) = index {
+        fs::read_to_string(index)
+            .with_context(|| format!("Failed to read package index {}", index.display()))?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    } else {
+        tracing::warn!(
+            "nix is not available and no package-index is configured; packages were not checked"
+        );
+        return Ok(());
+    };
+
+    let missing = missing(&attributes, &known);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let described: Vec<String> = missing
+        .iter()
+        .map(|package| {
+            if package.suggestions.is_empty() {
+                package.name.clone()
+            } else {
+                let choices: Vec<&str> = package.suggestions.iter().map(String::as_str).collect();
+                format!("{} (did you mean {}?)", package.name, suggest::format_choices(&choices))
+            }
+        })
+        .collect();
+    Err(anyhow!("Packages not found in {nixpkgs}: {}", described.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAKE: &str = r#"{
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+
+  outputs =
+    { self, nixpkgs }:
+    let
+      pkgs = import nixpkgs {
+        system = "x86_64-linux";
+        overlays = [ self.overlays.default ];
+      };
+    in
+    {
+      overlays.default = final: prev: {
+        rustToolchain = prev.rust-bin.stable.latest.default;
+      };
+
+      devShells.x86_64-linux.default = pkgs.mkShell {
+        packages = with pkgs; [
+          rustToolchain
+          cargo-deny
+          cargo-edti
+        ];
+      };
+    };
+}
+"#;
+
+    #[test]
+    fn test_missing_packages() {
+        let attributes = nixpkgs_attributes(FLAKE).unwrap();
+        assert_eq!(attributes, vec!["cargo-deny", "cargo-edti"]);
+
+        let known: BTreeSet<String> = ["cargo-deny", "cargo-edit", "cargo-watch"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(
+            missing(&attributes, &known),
+            vec![MissingPackage {
+                name: "cargo-edti".to_string(),
+                suggestions: vec!["cargo-edit".to_string()],
+            }]
+        );
+    }
+}
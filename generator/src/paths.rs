@@ -0,0 +1,132 @@
+use crate::errors::{self, ErrorKind};
+use anyhow::{Context, Result, anyhow};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Expand a leading `~`, make the path absolute and resolve `.` and `..`
+/// lexically, so paths that don't exist yet can still be normalized.
+pub fn normalize(path: &Path) -> Result<PathBuf> {
+    let expanded = match path.to_str().and_then(translate_windows_path) {
+        Some(translated) => translated,
+        None => expand_home(path)?,
+    };
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        env::current_dir()?.join(expanded)
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Outside of Windows (typically inside WSL), map Windows-style paths onto
+/// the Linux view of the same location: `C:\src\app` becomes
+/// `/mnt/c/src/app` and `\\wsl$\Ubuntu\home\me` becomes `/home/me`.
+fn translate_windows_path(raw: &str) -> Option<PathBuf> {
+    if cfg!(windows) {
+        return None;
+    }
+
+    for prefix in [r"\\wsl$\", r"\\wsl.localhost\"] {
+        if let Some(rest) = raw.strip_prefix(prefix) {
+            // Drop the distribution name
+            let inner = rest.split_once('\\').map(|(_, inner)| inner).unwrap_or("");
+            return Some(PathBuf::from(format!("/{}", inner.replace('\\', "/"))));
+        }
+    }
+
+    let bytes = raw.as_bytes();
+    let is_drive = bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes.len() == 2 || bytes[2] == b'\\' || bytes[2] == b'/');
+    if !is_drive {
+        return None;
+    }
+    let drive = (bytes[0] as char).to_ascii_lowercase();
+    let rest = raw[2..].replace('\\', "/");
+    Some(PathBuf::from(format!("/mnt/{}/{}", drive, rest.trim_start_matches('/'))))
+}
+
+fn expand_home(path: &Path) -> Result<PathBuf> {
+    let mut components = path.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first == "~" => {
+            let home = env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .filter(|h| !h.is_empty())
+                .ok_or_else(|| anyhow!("Cannot expand '~': HOME is not set"))?;
+            Ok(PathBuf::from(home).join(components.as_path()))
+        }
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// Normalize a target directory for generation and create it, including any
+/// missing parents. Fails clearly when the path names an existing file.
+pub fn prepare_target_dir(path: &Path) -> Result<PathBuf> {
+    let target = normalize(path)?;
+    if target.exists() && !target.is_dir() {
+        return Err(errors::coded(
+            ErrorKind::Io,
+            format!("{} is a file, not a directory", target.display()),
+        ));
+    }
+    fs::create_dir_all(&target)
+        .with_context(|| format!("Failed to create directory {}", target.display()))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_resolves_dots() {
+        assert_eq!(
+            normalize(Path::new("/tmp/a/./b/../c")).unwrap(),
+            PathBuf::from("/tmp/a/c")
+        );
+        assert_eq!(
+            normalize(Path::new("nested/..")).unwrap(),
+            env::current_dir().unwrap()
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_translate_windows_path() {
+        assert_eq!(
+            translate_windows_path(r"C:\Users\me\app"),
+            Some(PathBuf::from("/mnt/c/Users/me/app"))
+        );
+        assert_eq!(
+            translate_windows_path(r"\\wsl$\Ubuntu\home\me"),
+            Some(PathBuf::from("/home/me"))
+        );
+        assert_eq!(translate_windows_path("/home/me"), None);
+    }
+
+    #[test]
+    fn test_prepare_target_dir_rejects_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("flake.nix");
+        fs::write(&file, "").unwrap();
+        assert!(prepare_target_dir(&file).is_err());
+
+        let nested = dir.path().join("a/b/c");
+        assert_eq!(prepare_target_dir(&nested).unwrap(), nested);
+        assert!(nested.is_dir());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::remote::Remote;
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+
+const NIXPKGS_REPOSITORY: &str = "https://github.com/NixOS/nixpkgs";
+
+/// Newest stable branch when this release was made, used with `--offline`.
+const KNOWN_STABLE_BRANCH: &str = "nixos-24.11";
+
+/// The nixpkgs branch whose current commit `--pin-nixpkgs` pins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// The newest nixos-YY.MM release branch
+    LatestStable,
+    /// nixos-unstable
+    LatestUnstable,
+}
+
+/// A branch and the commit it pointed at.
+#[derive(Debug, PartialEq)]
+pub struct Pin {
+    pub branch: String,
+    pub rev: Option<String>,
+}
+
+impl Pin {
+    /// The nixpkgs reference to generate: the commit, or the branch when
+    /// offline.
+    pub fn reference(&self) -> &str {
+        self.rev.as_deref().unwrap_or(&self.branch)
+    }
+}
+
+/// Look up the current commit of the channel's branch with `git ls-remote`.
+/// Offline, the branch itself is used instead of a commit.
+pub fn resolve(channel: Channel, offline: bool, remote: &Remote) -> Result<Pin> {
+    if offline {
+        let branch = match channel {
+            Channel::LatestStable => KNOWN_STABLE_BRANCH,
+            Channel::LatestUnstable => "nixos-unstable",
+        };
+        return Ok(Pin {
+            branch: branch.to_string(),
+            rev: None,
+        });
+    }
+
+    let output = remote
+        .git(NIXPKGS_REPOSITORY)
+        .args(["ls-remote", "--heads", NIXPKGS_REPOSITORY, "nixos-*"])
+        .output()
+        .context("Failed to run git, which --pin-nixpkgs requires (or pass --offline)")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Looking up the nixpkgs branches failed (pass --offline to use branch names): {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    latest(&String::from_utf8_lossy(&output.stdout), channel).ok_or_else(|| {
+        anyhow!("No {} branch found in {NIXPKGS_REPOSITORY}", channel_name(channel))
+    })
+}
+
+fn channel_name(channel: Channel) -> &'static str {
+    match channel {
+        Channel::LatestStable => "stable",
+        Channel::LatestUnstable => "nixos-unstable",
+    }
+}
+
+/// Pick the channel's branch from `git ls-remote` output
+/// (`<rev>\trefs/heads/<branch>` per line).
+fn latest(listing: &str, channel: Channel) -> Option<Pin> {
+    let mut branches = listing.lines().filter_map(|line| {
+        let (rev, reference) = line.split_once('\t')?;
+        Some((reference.strip_prefix("refs/heads/")?, rev))
+    });
+
+    let (branch, rev) = match channel {
+        Channel::LatestUnstable => branches.find(|(branch, _)| *branch == "nixos-unstable")?,
+        Channel::LatestStable => branches
+            .filter_map(|(branch, rev)| Some((release(branch)?, branch, rev)))
+            .max()
+            .map(|(_, branch, rev)| (branch, rev))?,
+    };
+    Some(Pin {
+        branch: branch.to_string(),
+        rev: Some(rev.to_string()),
+    })
+}
+
+/// The year and month of a `nixos-YY.MM` release branch, skipping the
+/// `-small` and `-darwin` variants.
+fn release(branch: &str) -> Option<(u32, u32)> {
+    let (year, month) = branch.strip_prefix("nixos-")?.split_once('.')?;
+    Some((year.parse().ok()?, month.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "\
+1111111111111111111111111111111111111111\trefs/heads/nixos-23.11
+2222222222222222222222222222222222222222\trefs/heads/nixos-24.05
+3333333333333333333333333333333333333333\trefs/heads/nixos-24.11
+4444444444444444444444444444444444444444\trefs/heads/nixos-24.11-small
+5555555555555555555555555555555555555555\trefs/heads/nixos-unstable
+6666666666666666666666666666666666666666\trefs/heads/nixos-unstable-small
+";
+
+    #[test]
+    fn test_latest() {
+        let stable = latest(LISTING, Channel::LatestStable).unwrap();
+        assert_eq!(stable.branch, "nixos-24.11");
+        assert_eq!(stable.reference(), "3333333333333333333333333333333333333333");
+
+        let unstable = latest(LISTING, Channel::LatestUnstable).unwrap();
+        assert_eq!(unstable.branch, "nixos-unstable");
+        assert_eq!(unstable.reference(), "5555555555555555555555555555555555555555");
+    }
+
+    #[test]
+    fn test_resolve_offline() {
+        let pin = resolve(Channel::LatestStable, true, &Remote::default()).unwrap();
+        assert_eq!(pin.reference(), KNOWN_STABLE_BRANCH);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::metadata;
+use crate::template::{self, Template};
+use anyhow::{Context, Result};
+use nix_flake_generator_core::{ConflictStrategy, merger};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Nix source from a file, or from stdin for `None` or `-`, along with a
+/// name to refer to it by.
+pub fn read_source(path: Option<&Path>) -> Result<(String, String)> {
+    match path.filter(|p| p.as_os_str() != "-") {
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok((path.display().to_string(), content))
+        }
+        None => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)?;
+            Ok(("stdin".to_string(), content))
+        }
+    }
+}
+
+/// Format a file in place, or stdin to stdout when no path is given.
+pub fn fmt(path: Option<&Path>) -> Result<()> {
+    let (_, content) = read_source(path)?;
+    let formatted = template::nixfmt(&content)?;
+    match path.filter(|p| p.as_os_str() != "-") {
+        Some(path) if formatted != content => crate::writer::write_atomic(path, formatted),
+        Some(_) => Ok(()),
+        None => {
+            print!("{formatted}");
+            Ok(())
+        }
+    }
+}
+
+/// Merge flakes with the multi-language engine. The first source provides
+/// anything the engine doesn't combine, such as the description.
+pub fn merge_sources(
+    sources: &[(String, String)],
+    strategy: ConflictStrategy,
+) -> Result<String> {
+    let templates: Vec<Template> = sources
+        .iter()
+        .map(|(name, content)| Template {
+            name: name.clone(),
+            description: name.clone(),
+            flake_content: metadata::strip(content).to_string(),
+            ..Default::default()
+        })
+        .collect();
+
+    let merged = merger::merge_templates(&templates, strategy)?;
+    nix_parser::validate_flake(&merged)?;
+    Ok(merged)
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Whether we are running inside the Windows Subsystem for Linux.
+pub fn is_wsl() -> bool {
+    if env::var_os("WSL_DISTRO_NAME").is_some() {
+        return true;
+    }
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|release| release.to_lowercase().contains("microsoft"))
+        .unwrap_or(false)
+}
+
+/// Advice for running nix on a project in `target`, when the platform makes
+/// the usual `nix develop` instructions misleading.
+pub fn nix_advice(target: &Path) -> Option<String> {
+    if cfg!(windows) {
+        return Some(format!(
+            "nix does not run natively on Windows; open {} from WSL (e.g. `wsl --cd \"{}\"`) and run `nix develop` there",
+            target.display(),
+            target.display()
+        ));
+    }
+
+    if is_wsl() && is_windows_mount(target) {
+        return Some(format!(
+            "{} is on a Windows drive mounted into WSL; nix works there but is much slower, \
+             consider keeping the project in the Linux filesystem (e.g. under ~)",
+            target.display()
+        ));
+    }
+
+    None
+}
+
+/// Paths under `/mnt/<drive letter>` are Windows drives mounted by WSL.
+fn is_windows_mount(path: &Path) -> bool {
+    let mut components = path.components().skip(1);
+    let is_mnt = components.next().is_some_and(|c| c.as_os_str() == "mnt");
+    let is_drive = components.next().is_some_and(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        name.len() == 1 && name.chars().all(|ch| ch.is_ascii_alphabetic())
+    });
+    is_mnt && is_drive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_windows_mount() {
+        assert!(is_windows_mount(Path::new("/mnt/c/Users/me/app")));
+        assert!(!is_windows_mount(Path::new("/mnt/data/app")));
+        assert!(!is_windows_mount(Path::new("/home/me/app")));
+    }
+}
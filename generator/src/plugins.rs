@@ -0,0 +1,223 @@
+use crate::hooks;
+use crate::remote::Remote;
+use crate::signing::{self, Signature};
+use anyhow::{Result, anyhow};
+use nix_flake_generator_core::error::{Result as CoreResult, TemplateError};
+use nix_flake_generator_core::{Template, TemplateProvider, suggest};
+use std::collections::BTreeSet;
+use std::env;
+use std::io::ErrorKind as IoErrorKind;
+use std::process::Command;
+
+/// External subcommands are executables named `nix-flake-generator-<name>`.
+pub const PREFIX: &str = "nix-flake-generator-";
+
+/// Run `nix-flake-generator-<name>` with the remaining arguments and return
+/// its exit code. The plugin finds this binary through `NIX_FLAKE_GENERATOR`.
+pub fn run_external(args: &[String], builtin: &[String]) -> Result<i32> {
+    let (name, rest) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("No command given"))?;
+
+    let mut command = Command::new(format!("{PREFIX}{name}"));
+    command.args(rest);
+    if let Ok(exe) = env::current_exe() {
+        command.env("NIX_FLAKE_GENERATOR", exe);
+    }
+
+    match command.status() {
+        // A missing code means the plugin was killed by a signal.
+        Ok(status) => Ok(status.code().unwrap_or(1)),
+        Err(e) if e.kind() == IoErrorKind::NotFound => {
+            let mut known: Vec<String> = builtin.to_vec();
+            known.extend(discover());
+            let matches = suggest::close_matches(name, known.iter().map(String::as_str));
+            if matches.is_empty() {
+                Err(anyhow!("Unknown command '{name}'"))
+            } else {
+                Err(anyhow!(
+                    "Unknown command '{}', did you mean {}?",
+                    name,
+                    suggest::format_choices(&matches)
+                ))
+            }
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to run {PREFIX}{name}"))),
+    }
+}
+
+/// Names of the external subcommands found on PATH, sorted.
+pub fn discover() -> Vec<String> {
+    let Some(path) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    let mut names = BTreeSet::new();
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = std::fs::read_dir(dir) else { continue };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let stem = file_name.strip_suffix(env::consts::EXE_SUFFIX).unwrap_or(&file_name);
+            if let Some(name) = stem.strip_prefix(PREFIX) {
+                if !name.is_empty() && entry.path().is_file() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+    }
+    names.into_iter().collect()
+}
+
+/// Templates printed as a JSON array by a shell command, configured with
+/// `template-providers` in the global config. Each entry has `name`,
+/// `description`, `flake_content` and optionally `additional_files`, or
+/// `extends` and `delta` in place of `flake_content`.
+///
+/// The command may instead print a manifest, `{"templates": [...],
+/// "signature": {"format": "minisign" | "ssh", "signature": "..."}}`, whose
+/// detached signature covers the templates array.
+pub struct CommandProvider {
+    command: String,
+    signing_keys: Vec<String>,
+    allow_unsigned: bool,
+    remote: Remote,
+}
+
+impl CommandProvider {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            signing_keys: Vec::new(),
+            allow_unsigned: false,
+            remote: Remote::default(),
+        }
+    }
+
+    /// Run the command with the configured proxy when the environment sets
+    /// none.
+    pub fn fetch_with(mut self, remote: Remote) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Require the templates to be signed by one of `keys` (minisign or ssh
+    /// public keys), or only warn with `allow_unsigned`. Without keys nothing
+    /// is checked.
+    pub fn verify_with(mut self, keys: Vec<String>, allow_unsigned: bool) -> Self {
+        self.signing_keys = keys;
+        self.allow_unsigned = allow_unsigned;
+        self
+    }
+
+    fn check_signature(
+        &self,
+        templates: &serde_json::Value,
+        signature: Option<&Signature>,
+    ) -> CoreResult<()> {
+        if self.signing_keys.is_empty() {
+            return Ok(());
+        }
+        let problem = match signature {
+            None => "the templates are unsigned".to_string(),
+            Some(signature) => {
+                let payload = signing::signed_payload(templates);
+                match signing::verify(&payload, signature, &self.signing_keys) {
+                    Ok(()) => return Ok(()),
+                    Err(problem) => problem,
+                }
+            }
+        };
+
+        if self.allow_unsigned {
+            tracing::warn!("Using templates from {} anyway: {}", self.command, problem);
+            Ok(())
+        } else {
+            Err(self
+                .failure(format!("{problem}; pass --allow-unsigned to use them anyway"))
+                .into())
+        }
+    }
+
+    fn failure(&self, message: impl Into<String>) -> TemplateError {
+        TemplateError::Provider {
+            provider: self.command.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+impl TemplateProvider for CommandProvider {
+    fn name(&self) -> &str {
+        &self.command
+    }
+
+    fn templates(&self) -> CoreResult<Vec<Template>> {
+        let mut command = hooks::shell_command(&self.command);
+        self.remote.apply_proxy_env(&mut command);
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(self
+                .failure(format!(
+                    "exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+                .into());
+        }
+        let manifest: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| self.failure(format!("invalid template list: {e}")))?;
+        let (templates, signature) = match manifest {
+            serde_json::Value::Object(mut manifest) => {
+                let templates = manifest
+                    .remove("templates")
+                    .ok_or_else(|| self.failure("the manifest has no templates"))?;
+                let signature = manifest
+                    .remove("signature")
+                    .map(serde_json::from_value::<Signature>)
+                    .transpose()
+                    .map_err(|e| self.failure(format!("invalid signature: {e}")))?;
+                (templates, signature)
+            }
+            templates => (templates, None),
+        };
+
+        self.check_signature(&templates, signature.as_ref())?;
+        serde_json::from_value(templates)
+            .map_err(|e| self.failure(format!("invalid template list: {e}")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_provider_reads_json() {
+        let provider = CommandProvider::new(
+            r#"echo '[{"name": "internal", "description": "Internal stack", "flake_content": "{ }"}]'"#,
+        );
+        let templates = provider.templates().unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].name, "internal");
+        assert!(templates[0].additional_files.is_empty());
+
+        assert!(CommandProvider::new("exit 3").templates().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_provider_refuses_unsigned_templates() {
+        let command = r#"echo '{"templates": [{"name": "internal", "description": "", "flake_content": "{ }"}]}'"#;
+        let keys = vec!["RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string()];
+
+        assert_eq!(CommandProvider::new(command).templates().unwrap().len(), 1);
+        let error = CommandProvider::new(command)
+            .verify_with(keys.clone(), false)
+            .templates()
+            .unwrap_err();
+        assert!(error.to_string().contains("unsigned"));
+        let allowed = CommandProvider::new(command).verify_with(keys, true).templates();
+        assert_eq!(allowed.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::config::Config;
+use crate::errors::{self, ErrorKind};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Environment variable naming the policy file, taking precedence over the
+/// `policy` config key.
+pub const POLICY_ENV: &str = "NIX_FLAKE_GENERATOR_POLICY";
+
+/// Organisation rules every generated or merged flake must follow. Lists
+/// left out of the file place no restriction.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Policy {
+    /// Hosts, optionally with a path prefix (`github.com/NixOS`), that flake
+    /// inputs may be fetched from
+    pub allowed_inputs: Option<Vec<String>>,
+    /// Flake registry names (`nixpkgs`) that inputs may refer to
+    pub allowed_registries: Option<Vec<String>>,
+    /// Binary caches the flake's nixConfig must list
+    pub required_substituters: Vec<String>,
+    /// Templates that may not be used
+    pub banned_templates: Vec<String>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Where a flake input is fetched from.
+#[derive(Debug, PartialEq)]
+enum Source {
+    /// A local path, always allowed
+    Local,
+    /// An indirect reference resolved through the flake registry
+    Registry(String),
+    /// `host/path`, with shorthands like `github:` expanded
+    Remote(String),
+}
+
+/// Load the policy file named by `NIX_FLAKE_GENERATOR_POLICY` or the
+/// config's `policy` key, if any.
+pub fn load(config: &Config) -> Result<Option<Policy>> {
+    let Some(path) = env::var_os(POLICY_ENV)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| config.policy.clone())
+    else {
+        return Ok(None);
+    };
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Failed to read policy file {}: {}", path.display(), e))?;
+    let mut policy: Policy = toml::from_str(&content)
+        .map_err(|e| anyhow!("Invalid policy file {}: {}", path.display(), e))?;
+    policy.path = path;
+    Ok(Some(policy))
+}
+
+impl Policy {
+    /// Fail with every rule the templates or the flake break.
+    pub fn check(&self, templates: &[&str], flake_content: &str) -> Result<()> {
+        let violations = self.violations(templates, flake_content)?;
+        if violations.is_empty() {
+            return Ok(());
+        }
+        Err(errors::coded(
+            ErrorKind::PolicyViolation,
+            format!(
+                "Blocked by the policy in {}:\n  - {}",
+                self.path.display(),
+                violations.join("\n  - ")
+            ),
+        ))
+    }
+
+    fn violations(&self, templates: &[&str], flake_content: &str) -> Result<Vec<String>> {
+        let mut violations = Vec::new();
+
+        for template in templates {
+            if self.banned_templates.iter().any(|banned| banned == template) {
+                violations.push(format!("template '{template}' is banned; leave it out"));
+            }
+        }
+
+        let fragments = nix_parser::extract_flake_fragments(flake_content)?;
+        let mut inputs: Vec<_> = fragments.inputs.iter().collect();
+        inputs.sort();
+        for (name, url) in inputs {
+            match source(url) {
+                Source::Local => {}
+                Source::Registry(entry) => match &self.allowed_registries {
+                    Some(allowed) if !allowed.contains(&entry) => violations.push(format!(
+                        "input '{name}' uses the registry name '{entry}', which is not allowed \
+                         ({}); give it a full URL instead",
+                        list(allowed)
+                    )),
+                    _ => {}
+                },
+                Source::Remote(location) => match &self.allowed_inputs {
+                    Some(allowed) if !allowed.iter().any(|p| is_within(&location, p)) => {
+                        violations.push(format!(
+                            "input '{name}' comes from {location}, which is not allowed ({}); \
+                             use a mirror on an allowed host",
+                            list(allowed)
+                        ))
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        for substituter in &self.required_substituters {
+            if !flake_content.contains(&format!("\"{substituter}\"")) {
+                violations.push(format!(
+                    "substituter {substituter} is required; add it to `substituters` (and its key \
+                     to `trusted-public-keys`) in the config"
+                ));
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+fn list(entries: &[String]) -> String {
+    if entries.is_empty() {
+        "the allow-list is empty".to_string()
+    } else {
+        format!("allowed: {}", entries.join(", "))
+    }
+}
+
+/// Classify a flake input URL, expanding `github:`, `gitlab:` and
+/// `sourcehut:` shorthands to their hosts.
+fn source(url: &str) -> Source {
+    let url = url.split('?').next().unwrap_or(url);
+    if url.starts_with('/') || url.starts_with('.') || url.starts_with("path:") {
+        return Source::Local;
+    }
+
+    let shorthands = [
+        ("github:", "github.com/"),
+        ("gitlab:", "gitlab.com/"),
+        ("sourcehut:", "git.sr.ht/"),
+    ];
+    for (prefix, host) in shorthands {
+        if let Some(path) = url.strip_prefix(prefix) {
+            return Source::Remote(format!("{host}{path}"));
+        }
+    }
+
+    let registry_name = |reference: &str| {
+        Source::Registry(reference.split('/').next().unwrap_or(reference).to_string())
+    };
+    if let Some(reference) = url.strip_prefix("flake:") {
+        return registry_name(reference);
+    }
+    match url.split_once("://") {
+        Some((_, rest)) => {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let host = authority.rsplit('@').next().unwrap_or(authority);
+            Source::Remote(format!("{host}/{path}").trim_end_matches('/').to_string())
+        }
+        None => registry_name(url),
+    }
+}
+
+/// Whether `location` is `prefix` or lies below it, ignoring case.
+fn is_within(location: &str, prefix: &str) -> bool {
+    let location = location.to_ascii_lowercase();
+    let prefix = prefix.trim_end_matches('/').to_ascii_lowercase();
+    location == prefix || location.starts_with(&format!("{prefix}/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        assert_eq!(
+            source("github:NixOS/nixpkgs/nixos-unstable"),
+            Source::Remote("github.com/NixOS/nixpkgs/nixos-unstable".to_string())
+        );
+        assert_eq!(
+            source("git+ssh://git@git.example.com/team/flake?ref=main"),
+            Source::Remote("git.example.com/team/flake".to_string())
+        );
+        assert_eq!(source("nixpkgs/nixos-24.05"), Source::Registry("nixpkgs".to_string()));
+        assert_eq!(source("path:./nix"), Source::Local);
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = Policy {
+            allowed_inputs: Some(vec!["github.com/NixOS".to_string()]),
+            allowed_registries: Some(Vec::new()),
+            required_substituters: vec!["https://cache.example.com".to_string()],
+            banned_templates: vec!["php".to_string()],
+            path: PathBuf::from("policy.toml"),
+        };
+        let flake = r#"{
+  inputs = {
+    nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+    flake-utils.url = "github:numtide/flake-utils";
+    devenv.url = "devenv";
+  };
+  outputs = { self, nixpkgs, ... }: { };
+}"#;
+
+        let violations = policy.violations(&["rust", "php"], flake).unwrap();
+        assert_eq!(violations.len(), 4);
+        assert!(violations[0].starts_with("template 'php' is banned"));
+        assert!(violations[1].starts_with("input 'devenv' uses the registry name 'devenv'"));
+        assert!(violations[2].starts_with("input 'flake-utils' comes from github.com/numtide"));
+        assert!(violations[3].starts_with("substituter https://cache.example.com is required"));
+
+        let error = policy.check(&["php"], flake).unwrap_err();
+        assert_eq!(errors::classify(&error), ErrorKind::PolicyViolation);
+        assert!(Policy::default().check(&["php"], flake).is_ok());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config;
+use crate::errors::{ErrorKind, USAGE_EXIT_CODE};
+use crate::output::status;
+use anyhow::Result;
+use clap::Command;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Long help for the tool and every subcommand, followed by the config keys,
+/// environment variables and exit codes, as printed by `help --all`.
+pub fn help_all(mut command: Command) -> String {
+    command.build();
+    let mut out = String::new();
+    append_help(&mut command, &mut out);
+
+    out.push_str("Configuration keys:\n");
+    for (key, description) in config::KEYS {
+        let _ = writeln!(out, "  {key:<45} {description}");
+    }
+
+    out.push_str("\nEnvironment variables (override config files, not flags):\n");
+    for (name, description) in config::ENV_VARS {
+        let _ = writeln!(out, "  {name:<45} {description}");
+    }
+
+    out.push_str("\nExit codes:\n");
+    for (code, description) in exit_codes() {
+        let _ = writeln!(out, "  {code:<3} {description}");
+    }
+    out
+}
+
+fn append_help(command: &mut Command, out: &mut String) {
+    let _ = writeln!(out, "{}\n", command.render_long_help());
+    for sub in command.get_subcommands_mut() {
+        let _ = writeln!(out, "{}\n", "=".repeat(72));
+        append_help(sub, out);
+    }
+}
+
+fn exit_codes() -> Vec<(i32, &'static str)> {
+    let mut codes: Vec<(i32, &str)> = ErrorKind::ALL
+        .iter()
+        .map(|kind| (kind.code(), kind.description()))
+        .collect();
+    codes.push((USAGE_EXIT_CODE, "Invalid command-line usage"));
+    codes.sort();
+    codes
+}
+
+/// Render the man page for the tool to stdout, or one page per subcommand
+/// into `out_dir`.
+pub fn man(mut command: Command, out_dir: Option<&Path>) -> Result<()> {
+    command.build();
+
+    let mut page = Vec::new();
+    clap_mangen::Man::new(command.clone()).render(&mut page)?;
+    page.extend_from_slice(extra_sections().as_bytes());
+
+    let Some(out_dir) = out_dir else {
+        print!("{}", String::from_utf8_lossy(&page));
+        return Ok(());
+    };
+
+    fs::create_dir_all(out_dir)?;
+    let root = command.get_name().to_string();
+    fs::write(out_dir.join(format!("{root}.1")), page)?;
+    for sub in command.get_subcommands() {
+        let name = format!("{}-{}", root, sub.get_name());
+        let mut page = Vec::new();
+        clap_mangen::Man::new(sub.clone().name(name.clone())).render(&mut page)?;
+        fs::write(out_dir.join(format!("{name}.1")), page)?;
+    }
+    status!("Wrote man pages to {}", out_dir.display());
+    Ok(())
+}
+
+fn extra_sections() -> String {
+    let mut roff = String::from(".SH CONFIGURATION\n");
+    for (key, description) in config::KEYS {
+        let _ = writeln!(roff, ".TP\n\\fB{}\\fR\n{}", escape(key), escape(description));
+    }
+    roff.push_str(".SH ENVIRONMENT\n");
+    for (name, description) in config::ENV_VARS {
+        let _ = writeln!(roff, ".TP\n\\fB{}\\fR\n{}", escape(name), escape(description));
+    }
+    roff.push_str(".SH \"EXIT STATUS\"\n");
+    for (code, description) in exit_codes() {
+        let _ = writeln!(roff, ".TP\n\\fB{code}\\fR\n{}", escape(description));
+    }
+    roff
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
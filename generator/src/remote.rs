@@ -0,0 +1,205 @@
+use crate::config::Config;
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable the inline git credential helper reads the token
+/// from, so it never appears on a command line.
+const TOKEN_ENV: &str = "NIX_FLAKE_GENERATOR_GIT_TOKEN";
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbid network access for the rest of the run (`--offline`).
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Fail when `feature` is used in offline mode.
+pub fn require_network(feature: &str) -> Result<()> {
+    if is_offline() {
+        Err(anyhow!(
+            "{feature} needs network access, which --offline (or the offline config key) forbids"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Proxy and credentials for fetches from remote hosts: cloning with
+/// `--from-repo`, looking up nixpkgs with `--pin-nixpkgs` and running
+/// template providers.
+#[derive(Debug, Clone, Default)]
+pub struct Remote {
+    /// Used when the proxy environment variables are unset
+    proxy: Option<String>,
+    /// Tokens by host, or `env:NAME` to read one from the environment
+    tokens: BTreeMap<String, String>,
+}
+
+impl Remote {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            proxy: config.proxy.clone(),
+            tokens: config.tokens.clone(),
+        }
+    }
+
+    /// A `git` command set up to reach `url`: through the proxy unless the
+    /// host is in `NO_PROXY`, and authenticated with the host's token.
+    /// Without a token git falls back to `~/.netrc` and its credential
+    /// helpers as usual.
+    pub fn git(&self, url: &str) -> Command {
+        let mut command = Command::new("git");
+        let host = host(url);
+        if let Some(proxy) = host.and_then(|host| self.proxy_for(host)) {
+            command.arg("-c").arg(format!("http.proxy={proxy}"));
+        }
+        if let Some(token) = host.and_then(|host| self.token_for(host)) {
+            command
+                .args(["-c", "credential.helper="])
+                .arg("-c")
+                .arg(format!(
+                    "credential.helper=!f() {{ echo username=x-access-token; echo password=\"${TOKEN_ENV}\"; }}; f"
+                ))
+                .env(TOKEN_ENV, token);
+        }
+        command
+    }
+
+    /// Clone the default branch of `url` into `dir` without history. With
+    /// `trees_only` file contents are left on the server, which is enough
+    /// to list the files.
+    pub fn shallow_clone(&self, url: &str, dir: &Path, trees_only: bool) -> Result<()> {
+        require_network(&format!("Fetching {url}"))?;
+        let mut command = self.git(url);
+        command.args(["clone", "--quiet", "--depth", "1"]);
+        if trees_only {
+            command.args(["--filter=blob:none", "--no-checkout"]);
+        }
+        let output = command
+            .arg(url)
+            .arg(dir)
+            .output()
+            .context("Failed to run git, which fetching repositories requires")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Cloning {url} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The proxy for `host`: `HTTPS_PROXY` (or `ALL_PROXY`) from the
+    /// environment, else the configured one, unless `NO_PROXY` covers the
+    /// host.
+    fn proxy_for(&self, host: &str) -> Option<String> {
+        let no_proxy = env_var(&["NO_PROXY", "no_proxy"]).unwrap_or_default();
+        if bypasses_proxy(&no_proxy, host) {
+            return None;
+        }
+        env_var(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"])
+            .or_else(|| self.proxy.clone())
+    }
+
+    /// The token for `host`: `NIX_FLAKE_GENERATOR_TOKEN_<HOST>` (e.g.
+    /// `..._TOKEN_GITHUB_COM`), else `tokens.<host>` in the config.
+    fn token_for(&self, host: &str) -> Option<String> {
+        let variable: String = host
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        if let Some(token) = env_var(&[&format!("NIX_FLAKE_GENERATOR_TOKEN_{variable}")]) {
+            return Some(token);
+        }
+
+        let configured = self.tokens.get(host)?;
+        match configured.strip_prefix("env:") {
+            Some(name) => env_var(&[name]),
+            None => Some(configured.clone()),
+        }
+    }
+
+    /// Environment for commands that fetch on their own, such as template
+    /// providers: the configured proxy, when the environment has none.
+    pub fn apply_proxy_env(&self, command: &mut Command) {
+        if let (Some(proxy), None) = (&self.proxy, env_var(&["HTTPS_PROXY", "https_proxy"])) {
+            command.env("HTTPS_PROXY", proxy).env("https_proxy", proxy);
+        }
+    }
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// The host of an https, ssh or scp-like git URL, or of a `github:` style
+/// shorthand.
+fn host(url: &str) -> Option<&str> {
+    for (prefix, host) in [("github:", "github.com"), ("gitlab:", "gitlab.com")] {
+        if url.starts_with(prefix) {
+            return Some(host);
+        }
+    }
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest,
+        // `git@host:path`
+        None => url.split_once(':')?.0,
+    };
+    let authority = rest.split(['/', '?']).next()?;
+    let host = authority.rsplit('@').next()?;
+    Some(host.split(':').next().unwrap_or(host))
+}
+
+/// Whether a `NO_PROXY` list (`*`, hosts, `.domain` suffixes) covers `host`.
+fn bypasses_proxy(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{entry}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        assert_eq!(host("https://github.com/org/project"), Some("github.com"));
+        assert_eq!(
+            host("https://user@git.example.com:8443/team/repo.git"),
+            Some("git.example.com")
+        );
+        assert_eq!(host("git@git.example.com:team/repo.git"), Some("git.example.com"));
+        assert_eq!(host("github:NixOS/nixpkgs"), Some("github.com"));
+    }
+
+    #[test]
+    fn test_bypasses_proxy() {
+        assert!(bypasses_proxy("localhost, .example.com", "git.example.com"));
+        assert!(bypasses_proxy("example.com", "example.com"));
+        assert!(bypasses_proxy("*", "github.com"));
+        assert!(!bypasses_proxy("example.com", "notexample.com"));
+        assert!(!bypasses_proxy("", "github.com"));
+    }
+
+    #[test]
+    fn test_configured_token() {
+        let remote = Remote {
+            proxy: None,
+            tokens: BTreeMap::from([("git.example.com".to_string(), "secret".to_string())]),
+        };
+        assert_eq!(remote.token_for("git.example.com").as_deref(), Some("secret"));
+        assert_eq!(remote.token_for("github.com"), None);
+    }
+}
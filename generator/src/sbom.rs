@@ -0,0 +1,309 @@
+use crate::nix;
+use crate::timestamp;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SbomFormat {
+    Cyclonedx,
+    Spdx,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct License {
+    spdx_id: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Component {
+    name: String,
+    pname: Option<String>,
+    version: Option<String>,
+    licenses: Vec<License>,
+    out_path: Option<String>,
+}
+
+const COMPONENTS_EXPR: &str = r#"shell: map (p:
+  let
+    license = p.meta.license or [ ];
+    licenses = if builtins.isList license then license else [ license ];
+  in
+  {
+    name = p.name or "unknown";
+    pname = p.pname or null;
+    version = p.version or null;
+    out_path = p.outPath or null;
+    licenses = map (l:
+      if builtins.isAttrs l then
+        { spdx_id = l.spdxId or null; name = l.shortName or l.fullName or null; }
+      else
+        { spdx_id = null; name = toString l; }
+    ) licenses;
+  }
+) ((shell.nativeBuildInputs or [ ]) ++ (shell.buildInputs or [ ]))"#;
+
+pub fn generate(target_path: &Path, format: SbomFormat) -> Result<String> {
+    let flake = nix::flake_ref(target_path)?;
+    nix::require_nix()?;
+    let installable = nix::dev_shell_installable(&flake)?;
+
+    let components: Vec<Component> =
+        serde_json::from_value(nix::eval_json(&installable, COMPONENTS_EXPR)?)?;
+    let closure = closure_paths(&installable)?;
+
+    let document_name = target_path
+        .canonicalize()?
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "devshell".to_string());
+
+    let document = match format {
+        SbomFormat::Cyclonedx => cyclonedx_document(&document_name, &components, &closure),
+        SbomFormat::Spdx => spdx_document(&document_name, &components, &closure),
+    };
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Store paths in the runtime closure of the devShell. This realises the
+/// shell derivation, substituting or building it if necessary.
+fn closure_paths(installable: &str) -> Result<Vec<String>> {
+    let output = nix::nix_command()
+        .args(["path-info", "--json", "--recursive", installable])
+        .output()
+        .map_err(|e| anyhow!("Failed to run nix: {}", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nix path-info failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    // Newer nix versions return an object keyed by store path, older ones a
+    // list of objects with a `path` field.
+    let value: Value = serde_json::from_slice(&output.stdout)?;
+    let mut paths: Vec<String> = match value {
+        Value::Object(map) => map.keys().cloned().collect(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item.get("path").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    paths.sort();
+    Ok(paths)
+}
+
+/// Split `/nix/store/<hash>-<name>-<version>` into name and version. The
+/// version starts at the first dash followed by a digit, as in nixpkgs.
+fn split_store_path(path: &str) -> (String, Option<String>) {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    let name = base.split_once('-').map(|(_, rest)| rest).unwrap_or(base);
+
+    let bytes = name.as_bytes();
+    for i in 0..bytes.len().saturating_sub(1) {
+        if bytes[i] == b'-' && bytes[i + 1].is_ascii_digit() {
+            return (name[..i].to_string(), Some(name[i + 1..].to_string()));
+        }
+    }
+    (name.to_string(), None)
+}
+
+fn component_name(component: &Component) -> &str {
+    component.pname.as_deref().unwrap_or(&component.name)
+}
+
+/// Closure paths that are not already described by a direct component.
+fn transitive_paths<'a>(components: &[Component], closure: &'a [String]) -> Vec<&'a String> {
+    let direct: HashSet<&str> = components
+        .iter()
+        .filter_map(|c| c.out_path.as_deref())
+        .collect();
+    closure
+        .iter()
+        .filter(|path| !direct.contains(path.as_str()))
+        .collect()
+}
+
+fn purl(name: &str, version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("pkg:nix/{name}@{version}"),
+        None => format!("pkg:nix/{name}"),
+    }
+}
+
+fn cyclonedx_document(document_name: &str, components: &[Component], closure: &[String]) -> Value {
+    let mut entries = Vec::new();
+
+    for component in components {
+        let name = component_name(component);
+        let licenses: Vec<Value> = component
+            .licenses
+            .iter()
+            .map(|license| match (&license.spdx_id, &license.name) {
+                (Some(id), _) => json!({ "license": { "id": id } }),
+                (None, Some(name)) => json!({ "license": { "name": name } }),
+                (None, None) => json!({ "license": { "name": "unknown" } }),
+            })
+            .collect();
+
+        let mut entry = json!({
+            "type": "application",
+            "name": name,
+            "purl": purl(name, component.version.as_deref()),
+            "licenses": licenses,
+        });
+        if let Some(version) = &component.version {
+            entry["version"] = json!(version);
+        }
+        if let Some(out_path) = &component.out_path {
+            entry["properties"] = json!([{ "name": "nix:store_path", "value": out_path }]);
+        }
+        entries.push(entry);
+    }
+
+    for path in transitive_paths(components, closure) {
+        let (name, version) = split_store_path(path);
+        let mut entry = json!({
+            "type": "library",
+            "name": name,
+            "purl": purl(&name, version.as_deref()),
+            "properties": [{ "name": "nix:store_path", "value": path }],
+        });
+        if let Some(version) = version {
+            entry["version"] = json!(version);
+        }
+        entries.push(entry);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp::rfc3339_now(),
+            "tools": [{ "name": "nix-flake-generator", "version": env!("CARGO_PKG_VERSION") }],
+            "component": { "type": "application", "name": document_name },
+        },
+        "components": entries,
+    })
+}
+
+fn spdx_license_expression(component: &Component) -> String {
+    let ids: Vec<&str> = component
+        .licenses
+        .iter()
+        .filter_map(|license| license.spdx_id.as_deref())
+        .collect();
+
+    // Anything without an SPDX identifier can't be expressed faithfully.
+    if ids.is_empty() || ids.len() != component.licenses.len() {
+        "NOASSERTION".to_string()
+    } else {
+        ids.join(" OR ")
+    }
+}
+
+fn spdx_document(document_name: &str, components: &[Component], closure: &[String]) -> Value {
+    let mut packages = Vec::new();
+    let mut relationships = Vec::new();
+
+    let mut push_package = |name: &str, version: Option<&str>, license: String| {
+        let id = format!("SPDXRef-Package-{}", packages.len() + 1);
+        let mut package = json!({
+            "SPDXID": id,
+            "name": name,
+            "downloadLocation": "NOASSERTION",
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license,
+            "filesAnalyzed": false,
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": purl(name, version),
+            }],
+        });
+        if let Some(version) = version {
+            package["versionInfo"] = json!(version);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": id,
+        }));
+    };
+
+    for component in components {
+        push_package(
+            component_name(component),
+            component.version.as_deref(),
+            spdx_license_expression(component),
+        );
+    }
+    for path in transitive_paths(components, closure) {
+        let (name, version) = split_store_path(path);
+        push_package(&name, version.as_deref(), "NOASSERTION".to_string());
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": document_name,
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/nix-flake-generator/{}-{}",
+            document_name,
+            timestamp::rfc3339_now()
+        ),
+        "creationInfo": {
+            "created": timestamp::rfc3339_now(),
+            "creators": [format!("Tool: nix-flake-generator-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_store_path() {
+        assert_eq!(
+            split_store_path("/nix/store/abc123-openssl-3.0.13"),
+            ("openssl".to_string(), Some("3.0.13".to_string()))
+        );
+        assert_eq!(
+            split_store_path("/nix/store/abc123-rust-analyzer-2024-05-06"),
+            ("rust-analyzer".to_string(), Some("2024-05-06".to_string()))
+        );
+        assert_eq!(
+            split_store_path("/nix/store/abc123-source"),
+            ("source".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_spdx_license_expression() {
+        let component = Component {
+            name: "foo-1.0".to_string(),
+            pname: Some("foo".to_string()),
+            version: Some("1.0".to_string()),
+            licenses: vec![
+                License { spdx_id: Some("MIT".to_string()), name: None },
+                License { spdx_id: Some("Apache-2.0".to_string()), name: None },
+            ],
+            out_path: None,
+        };
+        assert_eq!(spdx_license_expression(&component), "MIT OR Apache-2.0");
+    }
+}
@@ -0,0 +1,49 @@
+use crate::nix::{self, NixError};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Replace the current process with `nix develop` on the flake in `flake_dir`.
+pub fn develop(flake_dir: &Path) -> Result<()> {
+    let flake = nix::flake_ref(flake_dir)?;
+    nix::require_nix()?;
+
+    let mut command = nix::nix_command();
+    command.arg("develop").arg(flake);
+    exec(command)
+}
+
+/// Run `args` inside the devShell of the flake in `flake_dir` and return its
+/// exit code.
+pub fn run(flake_dir: &Path, args: &[String]) -> Result<i32> {
+    let flake = nix::flake_ref(flake_dir)?;
+    nix::require_nix()?;
+
+    let status = nix::nix_command()
+        .arg("develop")
+        .arg(flake)
+        .arg("--command")
+        .args(args)
+        .status()
+        .map_err(NixError::Spawn)
+        .context("Failed to run nix develop")?;
+
+    // A missing code means the child was killed by a signal.
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(unix)]
+fn exec(mut command: Command) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    let err = command.exec();
+    Err(NixError::Spawn(err)).context("Failed to run nix develop")
+}
+
+#[cfg(not(unix))]
+fn exec(mut command: Command) -> Result<()> {
+    let status = command
+        .status()
+        .map_err(NixError::Spawn)
+        .context("Failed to run nix develop")?;
+    std::process::exit(status.code().unwrap_or(1))
+}
@@ -0,0 +1,183 @@
+use serde::Deserialize;
+use std::fs;
+use std::io::{ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Namespace ssh signatures over template lists are made in
+/// (`ssh-keygen -Y sign -n nix-flake-generator`).
+pub const SSH_NAMESPACE: &str = "nix-flake-generator";
+
+/// Principal the trusted ssh keys are listed under in the allowed signers
+/// file handed to `ssh-keygen`.
+const SSH_PRINCIPAL: &str = "template-publisher";
+
+/// A detached signature over a provider's template list, declared next to
+/// the templates in its manifest.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Signature {
+    pub format: SignatureFormat,
+    /// The signature file's content
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    Minisign,
+    Ssh,
+}
+
+impl SignatureFormat {
+    /// Whether a configured public key is of this format: minisign keys are
+    /// bare base64 (`RW...`), ssh keys start with their algorithm.
+    fn accepts(self, key: &str) -> bool {
+        let is_ssh = ["ssh-", "ecdsa-", "sk-"]
+            .iter()
+            .any(|prefix| key.starts_with(prefix));
+        match self {
+            SignatureFormat::Minisign => !is_ssh,
+            SignatureFormat::Ssh => is_ssh,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SignatureFormat::Minisign => "minisign",
+            SignatureFormat::Ssh => "ssh",
+        }
+    }
+
+    fn tool(self) -> &'static str {
+        match self {
+            SignatureFormat::Minisign => "minisign",
+            SignatureFormat::Ssh => "ssh-keygen",
+        }
+    }
+}
+
+/// The bytes a manifest's signature covers: its `templates` array as
+/// compact JSON with sorted keys, as printed by `jq -cjS .templates`.
+pub fn signed_payload(templates: &serde_json::Value) -> Vec<u8> {
+    // serde_json's maps are ordered by key
+    serde_json::to_vec(templates).expect("JSON values always serialize")
+}
+
+/// Check `signature` over `payload` against the trusted keys of its format,
+/// with minisign or ssh-keygen. Fails when none of them made it.
+pub fn verify(payload: &[u8], signature: &Signature, keys: &[String]) -> Result<(), String> {
+    let keys: Vec<&str> = keys
+        .iter()
+        .map(|key| key.trim())
+        .filter(|key| signature.format.accepts(key))
+        .collect();
+    if keys.is_empty() {
+        return Err(format!(
+            "signed with {} but template-signing-keys has no {} key",
+            signature.format.name(),
+            signature.format.name()
+        ));
+    }
+
+    match verify_files(payload, signature, &keys) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(TAMPERED.to_string()),
+        Err(e) if e.kind() == IoErrorKind::NotFound => Err(format!(
+            "{} is required to verify its signature but was not found on PATH",
+            signature.format.tool()
+        )),
+        Err(e) => Err(format!("failed to run {}: {e}", signature.format.tool())),
+    }
+}
+
+const TAMPERED: &str =
+    "the signature does not match any trusted key; the templates may have been tampered with";
+
+/// Write the payload, signature and signers into a temporary directory for
+/// the verifiers to read, and check them against `keys`.
+fn verify_files(payload: &[u8], signature: &Signature, keys: &[&str]) -> std::io::Result<bool> {
+    let dir = tempfile::Builder::new()
+        .prefix("nix-flake-generator-signing-")
+        .tempdir()?;
+    let file = |name: &str, content: &[u8]| -> std::io::Result<PathBuf> {
+        let path = dir.path().join(name);
+        fs::write(&path, content)?;
+        Ok(path)
+    };
+    let payload_path = file("templates.json", payload)?;
+    let signature_path = file("templates.json.sig", signature.signature.as_bytes())?;
+
+    match signature.format {
+        SignatureFormat::Minisign => {
+            for key in keys {
+                if minisign_verifies(&payload_path, &signature_path, key)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        SignatureFormat::Ssh => {
+            let signers: String = keys
+                .iter()
+                .map(|key| format!("{SSH_PRINCIPAL} {key}\n"))
+                .collect();
+            let signers_path = file("allowed_signers", signers.as_bytes())?;
+            ssh_verifies(payload, &signature_path, &signers_path)
+        }
+    }
+}
+
+fn minisign_verifies(payload: &Path, signature: &Path, key: &str) -> std::io::Result<bool> {
+    let status = Command::new("minisign")
+        .args(["-V", "-q", "-P", key, "-m"])
+        .arg(payload)
+        .arg("-x")
+        .arg(signature)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    Ok(status.success())
+}
+
+fn ssh_verifies(payload: &[u8], signature: &Path, signers: &Path) -> std::io::Result<bool> {
+    let mut child = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-I", SSH_PRINCIPAL, "-n", SSH_NAMESPACE, "-f"])
+        .arg(signers)
+        .arg("-s")
+        .arg(signature)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(payload)?;
+    }
+    Ok(child.wait()?.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_payload_sorts_keys() {
+        let templates: serde_json::Value =
+            serde_json::from_str(r#"[{"name": "a", "description": "b", "flake_content": "{ }"}]"#)
+                .unwrap();
+        assert_eq!(
+            String::from_utf8(signed_payload(&templates)).unwrap(),
+            r#"[{"description":"b","flake_content":"{ }","name":"a"}]"#
+        );
+    }
+
+    #[test]
+    fn test_verify_needs_a_key_of_the_format() {
+        let signature = Signature {
+            format: SignatureFormat::Ssh,
+            signature: String::new(),
+        };
+        let keys = vec!["RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string()];
+        assert!(verify(b"[]", &signature, &keys).unwrap_err().contains("has no ssh key"));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::ClosureBudget;
+use crate::nix::{self, NixError};
+use crate::output::{self, OutputFormat, status};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a devShell costs to download or build.
+#[derive(Debug, Serialize)]
+pub struct ShellStats {
+    /// Packages the shell puts on PATH
+    pub packages: usize,
+    /// Bytes of the packages and everything they depend on
+    pub closure_size: u64,
+    /// The packages with the largest closures, largest first
+    pub largest: Vec<PackageSize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageSize {
+    pub name: String,
+    pub path: String,
+    pub closure_size: u64,
+}
+
+#[derive(Deserialize)]
+struct ShellPackage {
+    name: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PathInfo {
+    #[serde(default)]
+    path: Option<String>,
+    nar_size: u64,
+    closure_size: u64,
+}
+
+// The same inputs `deps` reads, with their store paths
+const OUTPUTS_EXPR: &str = r#"shell: map (p: {
+  name = p.name or "unknown";
+  path = p.outPath;
+}) ((shell.nativeBuildInputs or [ ]) ++ (shell.buildInputs or [ ]))"#;
+
+/// Build the devShell of the flake in `target_path` and measure its
+/// packages' closures with `nix path-info`, keeping the `top` largest.
+pub fn measure(target_path: &Path, top: usize) -> Result<ShellStats> {
+    let flake = nix::flake_ref(target_path)?;
+    nix::require_nix()?;
+    let installable = nix::dev_shell_installable(&flake)?;
+
+    let mut packages: Vec<ShellPackage> =
+        serde_json::from_value(nix::eval_json(&installable, OUTPUTS_EXPR)?)?;
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+    packages.dedup_by(|a, b| a.path == b.path);
+
+    // path-info only knows paths that are in the store
+    status!("Building the devShell of {}", target_path.display());
+    run(nix::nix_command().args(["build", "--no-link", &installable]), "nix build")?;
+
+    let paths: Vec<&str> = packages.iter().map(|package| package.path.as_str()).collect();
+    let output = run(
+        nix::nix_command()
+            .args(["path-info", "--json", "--recursive", "--closure-size"])
+            .args(&paths),
+        "nix path-info",
+    )?;
+    let closure = parse_path_info(&output)?;
+
+    let mut largest: Vec<PackageSize> = packages
+        .into_iter()
+        .map(|package| PackageSize {
+            closure_size: closure.get(&package.path).map_or(0, |info| info.closure_size),
+            name: package.name,
+            path: package.path,
+        })
+        .collect();
+    largest.sort_by(|a, b| b.closure_size.cmp(&a.closure_size).then(a.name.cmp(&b.name)));
+    let package_count = largest.len();
+    largest.truncate(top);
+
+    Ok(ShellStats {
+        packages: package_count,
+        closure_size: closure.values().map(|info| info.nar_size).sum(),
+        largest,
+    })
+}
+
+fn run(command: &mut std::process::Command, name: &str) -> Result<Vec<u8>> {
+    let output = command.output().map_err(NixError::Spawn)?;
+    if !output.status.success() {
+        return Err(NixError::Failed {
+            command: name.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+/// `nix path-info --json` output by store path: a list of objects with a
+/// `path` before nix 2.19, an object keyed by path since.
+fn parse_path_info(json: &[u8]) -> Result<BTreeMap<String, PathInfo>> {
+    let value: serde_json::Value = serde_json::from_slice(json)?;
+    if value.is_array() {
+        let infos: Vec<PathInfo> = serde_json::from_value(value)?;
+        return Ok(infos
+            .into_iter()
+            .filter_map(|info| Some((info.path.clone()?, info)))
+            .collect());
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+pub fn print_stats(stats: &ShellStats, format: OutputFormat) -> Result<()> {
+    if format.is_json() {
+        return output::print_json("stats", stats);
+    }
+
+    println!("Packages: {}", stats.packages);
+    println!("Closure size: {}", human_size(stats.closure_size));
+    if !stats.largest.is_empty() {
+        println!("Largest packages (with their dependencies):");
+        for package in &stats.largest {
+            println!("  {:>10}  {}", human_size(package.closure_size), package.name);
+        }
+    }
+    Ok(())
+}
+
+/// Measure the devShell of the flake in `target_path` against `budget` and
+/// report the largest packages when it is over. Skipped with a warning
+/// without nix.
+pub fn check_budget(target_path: &Path, budget: &ClosureBudget) -> Result<()> {
+    let max = parse_size(&budget.max)?;
+    if !nix::is_available() {
+        tracing::warn!("nix is not available; the closure size budget was not checked");
+        return Ok(());
+    }
+
+    let stats = measure(target_path, 5)?;
+    let Some(message) = over_budget(&stats, max) else {
+        return Ok(());
+    };
+    if budget.fail {
+        return Err(anyhow!(message));
+    }
+    tracing::warn!("{message}");
+    Ok(())
+}
+
+fn over_budget(stats: &ShellStats, max: u64) -> Option<String> {
+    if stats.closure_size <= max {
+        return None;
+    }
+    let largest: Vec<String> = stats
+        .largest
+        .iter()
+        .map(|package| format!("{} ({})", package.name, human_size(package.closure_size)))
+        .collect();
+    Some(format!(
+        "The devShell closure is {}, over the budget of {}; largest: {}",
+        human_size(stats.closure_size),
+        human_size(max),
+        largest.join(", ")
+    ))
+}
+
+/// A size such as `2 GiB`, `500MB` or `1.5G`; units are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{size}' (expected e.g. '2 GiB')"))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let exponent = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => {
+            return Err(anyhow!(
+                "Unknown unit in size '{size}' (expected B, KiB, MiB, GiB or TiB)"
+            ));
+        }
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path_info_formats() {
+        let old = br#"[{"path": "/nix/store/a-go", "narSize": 10, "closureSize": 30}]"#;
+        let new = br#"{"/nix/store/a-go": {"narSize": 10, "closureSize": 30}}"#;
+        for json in [&old[..], &new[..]] {
+            let infos = parse_path_info(json).unwrap();
+            assert_eq!(infos["/nix/store/a-go"].closure_size, 30);
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("2 GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("500MB").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("3 PB").is_err());
+    }
+
+    #[test]
+    fn test_over_budget() {
+        let stats = ShellStats {
+            packages: 2,
+            closure_size: 3 * 1024 * 1024,
+            largest: vec![PackageSize {
+                name: "ghc-9.8.2".to_string(),
+                path: "/nix/store/a-ghc-9.8.2".to_string(),
+                closure_size: 2 * 1024 * 1024,
+            }],
+        };
+        assert!(over_budget(&stats, 4 * 1024 * 1024).is_none());
+        assert_eq!(
+            over_budget(&stats, 1024 * 1024).as_deref(),
+            Some("The devShell closure is 3.0 MiB, over the budget of 1.0 MiB; largest: ghc-9.8.2 (2.0 MiB)")
+        );
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
@@ -0,0 +1,117 @@
+use crate::dirs;
+use crate::remote::Remote;
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use nix_flake_generator_core::{EmbeddedProvider, TemplateProvider};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The repository the embedded templates were derived from; each template
+/// lives in `<name>/flake.nix` there.
+pub const UPSTREAM: &str = "https://github.com/the-nix-way/dev-templates";
+
+/// Where `template sync` installs updated templates, in the tool's data
+/// directory. Templates here replace the embedded ones, and `template-dirs`
+/// replace these in turn.
+pub fn user_templates_dir() -> Result<PathBuf> {
+    Ok(dirs::dir(dirs::Kind::Data)?.join("templates"))
+}
+
+/// How an embedded template compares with its upstream version.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    /// Upstream matches what is bundled or already installed
+    UpToDate,
+    /// Upstream differs; the diff is against the installed or bundled copy
+    Changed { diff: String },
+    /// Upstream has no template of this name
+    NotUpstream,
+}
+
+/// Templates compared with upstream, by name.
+pub struct SyncReport {
+    pub dir: PathBuf,
+    pub templates: Vec<(String, Status)>,
+}
+
+impl SyncReport {
+    pub fn changed(&self) -> impl Iterator<Item = &str> {
+        self.templates
+            .iter()
+            .filter(|(_, status)| matches!(status, Status::Changed { .. }))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Fetch `upstream` and compare every embedded template that has a flake
+/// of its own with it. Changed templates are written to `dir` with their
+/// bundled metadata unless `dry_run`.
+pub fn sync(remote: &Remote, upstream: &str, dir: &Path, dry_run: bool) -> Result<SyncReport> {
+    let scratch = tempfile::Builder::new()
+        .prefix("nix-flake-generator-sync-")
+        .tempdir()?;
+    remote.shallow_clone(upstream, scratch.path(), false)?;
+
+    let embedded = EmbeddedProvider;
+    let mut templates = embedded.templates()?;
+    templates.retain(|template| template.extends.is_none());
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut report = SyncReport {
+        dir: dir.to_path_buf(),
+        templates: Vec::new(),
+    };
+    let mut transaction = FileTransaction::new();
+    for template in templates {
+        let Ok(latest) = fs::read_to_string(scratch.path().join(&template.name).join("flake.nix"))
+        else {
+            report.templates.push((template.name, Status::NotUpstream));
+            continue;
+        };
+
+        let installed_path = dir.join(format!("{}.nix", template.name));
+        let current = fs::read_to_string(&installed_path).unwrap_or(template.flake_content);
+        let status = compare(&template.name, &current, &latest);
+        if matches!(status, Status::Changed { .. }) {
+            transaction.write(&installed_path, latest.as_str());
+            if let Some(metadata) = embedded.metadata(&template.name) {
+                transaction.write(dir.join(format!("{}.toml", template.name)), metadata);
+            }
+        }
+        report.templates.push((template.name, status));
+    }
+
+    if !dry_run {
+        transaction.commit()?;
+    }
+    Ok(report)
+}
+
+fn compare(name: &str, current: &str, latest: &str) -> Status {
+    if current.trim_end() == latest.trim_end() {
+        return Status::UpToDate;
+    }
+    Status::Changed {
+        diff: crate::diff::unified(
+            current,
+            latest,
+            &format!("{name}.nix (current)"),
+            &format!("{name}.nix (upstream)"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare("go", "{ }\n", "{ }"), Status::UpToDate);
+        let Status::Changed { diff } = compare("go", "{ a = 1; }\n", "{ a = 2; }\n") else {
+            panic!("expected a change");
+        };
+        assert!(diff.contains("go.nix (upstream)"));
+        assert!(diff.contains("+{ a = 2; }"));
+    }
+}
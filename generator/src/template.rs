@@ -1,112 +1,362 @@
-use crate::embedded_templates::EMBEDDED_TEMPLATES;
-use anyhow::{Result, anyhow};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::errors::{self, ErrorKind};
+use crate::metadata::{self, GenerationRecord, Header};
+use crate::output::{self, OutputFormat, status};
+use crate::policy::Policy;
+use crate::writer::FileTransaction;
+use anyhow::{Context, Result, anyhow};
+use nix_flake_generator_core::merger;
+use nix_flake_generator_core::options::{self, ConflictStrategy, GenerationOptions};
+use nix_flake_generator_core::registry::{self, Registry, TemplateGroup};
+use nix_flake_generator_core::TemplateProvider;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Template {
-    pub name: String,
-    pub description: String,
-    pub flake_content: String,
-    pub additional_files: HashMap<String, String>,
+pub use nix_flake_generator_core::{RenderedFlake, Template};
+
+/// Result of writing a rendered flake into a project.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    Written,
+    /// The project already matched the rendered output; nothing was touched.
+    UpToDate,
+}
+
+/// How to treat an existing flake.nix that differs from the generated one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Show the diff and fail
+    Refuse,
+    /// Show the diff and ask on the terminal
+    Prompt,
+    /// Replace it after saving a timestamped backup
+    Backup,
 }
 
 pub struct TemplateManager {
-    templates: HashMap<String, Template>,
+    registry: Registry,
+    policy: Option<Policy>,
+    header: Option<Header>,
 }
 
 impl TemplateManager {
     pub async fn new() -> Result<Self> {
-        let mut manager = Self {
-            templates: HashMap::new(),
-        };
+        Ok(Self {
+            registry: Registry::try_new()?,
+            policy: None,
+            header: None,
+        })
+    }
 
-        manager.load_embedded_templates().await?;
-        Ok(manager)
+    /// Put `header` at the top of every file written from now on.
+    pub fn set_header(&mut self, header: Header) {
+        self.header = Some(header);
     }
 
-    async fn load_embedded_templates(&mut self) -> Result<()> {
-        for (template_name, (description, flake_content)) in EMBEDDED_TEMPLATES.iter() {
-            let mut additional_files = HashMap::new();
+    /// Check every flake rendered or merged from now on against `policy`.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = Some(policy);
+    }
 
-            // Special case for rust-toolchain template - add sample rust-toolchain.toml
-            if *template_name == "rust-toolchain" {
-                additional_files.insert(
-                    "rust-toolchain.toml".to_string(),
-                    r#"[toolchain]
-channel = "stable"
-components = ["rustfmt", "rust-analyzer"]
-"#
-                    .to_string(),
-                );
-            }
+    /// Fail when the organisation policy forbids any of the templates or
+    /// anything in the flake.
+    pub fn check_policy(&self, template_names: &[&str], flake_content: &str) -> Result<()> {
+        match &self.policy {
+            Some(policy) => policy.check(template_names, flake_content),
+            None => Ok(()),
+        }
+    }
 
-            let template = Template {
-                name: template_name.to_string(),
-                description: description.to_string(),
-                flake_content: flake_content.to_string(),
-                additional_files,
-            };
+    /// Load `<name>.toml` + `<name>.nix` template pairs from a directory.
+    /// Templates found here replace embedded templates of the same name.
+    pub fn load_template_dir(&mut self, dir: &Path) -> Result<()> {
+        Ok(self.registry.load_template_dir(dir)?)
+    }
 
-            self.templates.insert(template_name.to_string(), template);
-        }
+    /// Add the templates of an external provider, replacing existing
+    /// templates of the same name.
+    pub fn add_provider(&mut self, provider: &dyn TemplateProvider) -> Result<()> {
+        Ok(self.registry.add_provider(provider)?)
+    }
 
-        Ok(())
+    pub fn get(&self, template_name: &str) -> Result<&Template> {
+        Ok(self.registry.get(template_name)?)
+    }
+
+    /// Render the flake and auxiliary files for the given templates without
+    /// touching the filesystem.
+    pub fn render(
+        &self,
+        template_names: &[&str],
+        options: &GenerationOptions,
+    ) -> Result<RenderedFlake> {
+        let rendered = self.registry.render(template_names, options)?;
+        self.check_policy(template_names, &rendered.flake_content)?;
+        Ok(rendered)
     }
 
-    pub async fn init_single(&self, template_name: &str, target_path: &Path) -> Result<()> {
-        let template = self
-            .templates
-            .get(template_name)
-            .ok_or_else(|| anyhow!("Template '{}' not found", template_name))?;
+    pub async fn init_single(
+        &self,
+        template_name: &str,
+        target_path: &Path,
+        options: &GenerationOptions,
+        policy: OverwritePolicy,
+    ) -> Result<WriteOutcome> {
+        let mut rendered = self.render(&[template_name], options)?;
+        rendered.flake_content = GenerationRecord::new(self, &[template_name], options)?
+            .prepend_to(&rendered.flake_content)?;
+        self.write_rendered(&rendered, target_path, true, policy)
+    }
 
-        fs::create_dir_all(target_path)?;
+    pub async fn init_multi(
+        &self,
+        template_names: &[&str],
+        target_path: &Path,
+        options: &GenerationOptions,
+        policy: OverwritePolicy,
+    ) -> Result<WriteOutcome> {
+        let mut rendered = self.render(template_names, options)?;
+        rendered.flake_content = GenerationRecord::new(self, template_names, options)?
+            .prepend_to(&rendered.flake_content)?;
+        self.write_rendered(&rendered, target_path, false, policy)
+    }
 
+    /// Render one flake with a named devShell per set of templates.
+    pub fn render_shells(
+        &self,
+        shells: &[(String, Vec<String>)],
+        options: &GenerationOptions,
+    ) -> Result<RenderedFlake> {
+        let rendered = self.registry.render_shells(shells, options)?;
+        self.check_policy(&shell_templates(shells), &rendered.flake_content)?;
+        Ok(rendered)
+    }
+
+    pub async fn init_shells(
+        &self,
+        shells: &[(String, Vec<String>)],
+        target_path: &Path,
+        options: &GenerationOptions,
+        policy: OverwritePolicy,
+    ) -> Result<WriteOutcome> {
+        let mut rendered = self.render_shells(shells, options)?;
+        let mut record = GenerationRecord::new(self, &shell_templates(shells), options)?;
+        record.shells = shells.iter().cloned().collect();
+        rendered.flake_content = record.prepend_to(&rendered.flake_content)?;
+        self.write_rendered(&rendered, target_path, false, policy)
+    }
+
+    /// Merge templates into the flake already present in `target_path`,
+    /// keeping its inputs, overlays and devShell contents. Without an existing
+    /// flake this is the same as a regular init.
+    pub async fn init_merge(
+        &self,
+        template_names: &[&str],
+        target_path: &Path,
+        options: &GenerationOptions,
+    ) -> Result<WriteOutcome> {
         let flake_path = target_path.join("flake.nix");
-        fs::write(&flake_path, &template.flake_content)?;
+        if !flake_path.exists() {
+            return self
+                .init_multi(template_names, target_path, options, OverwritePolicy::Refuse)
+                .await;
+        }
 
-        self.format_with_nixfmt(&flake_path)?;
+        let existing = fs::read_to_string(&flake_path)?;
+        let previous = GenerationRecord::read(&existing)?;
 
-        for (filename, content) in &template.additional_files {
-            fs::write(target_path.join(filename), content)?;
+        let mut templates = vec![Template {
+            name: "local".to_string(),
+            description: "Existing flake".to_string(),
+            flake_content: crate::metadata::strip(&existing).to_string(),
+            ..Default::default()
+        }];
+        templates.extend(self.registry.select(template_names, options)?);
+
+        let mut recorded: Vec<&str> = previous
+            .as_ref()
+            .map(|record| record.templates.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        for name in template_names {
+            if !recorded.contains(name) {
+                recorded.push(*name);
+            }
         }
 
-        Ok(())
+        let merged = merger::merge_templates_with(&templates, options, |template| {
+            nix_parser::extract_flake_fragments(&template.flake_content)
+        })?;
+        let mut rendered = RenderedFlake {
+            flake_content: options::apply(&merged.flake_content, options),
+            additional_files: registry::collect_additional_files(&templates),
+            provenance: merged.provenance,
+        };
+        registry::validate_rendered(&rendered, &recorded)?;
+        self.check_policy(&recorded, &rendered.flake_content)?;
+        rendered.flake_content = GenerationRecord::new(self, &recorded, options)?
+            .prepend_to(&rendered.flake_content)?;
+
+        self.write_rendered(&rendered, target_path, false, OverwritePolicy::Backup)
     }
 
-    pub async fn init_multi(&self, template_names: &[&str], target_path: &Path) -> Result<()> {
-        let mut templates = Vec::new();
-        for name in template_names {
-            let template = self
-                .templates
-                .get(*name)
-                .ok_or_else(|| anyhow!("Template '{}' not found", name))?;
-            templates.push(template.clone());
+    /// Merge arbitrary flakes into the flake in `target_path`. The result no
+    /// longer corresponds to a set of templates, so any generation record is
+    /// dropped rather than left to make `regenerate` discard the merged parts.
+    pub fn merge_flakes(
+        &self,
+        sources: &[(String, String)],
+        target_path: &Path,
+        strategy: ConflictStrategy,
+    ) -> Result<WriteOutcome> {
+        let flake_path = target_path.join("flake.nix");
+        let existing = fs::read_to_string(&flake_path)
+            .with_context(|| format!("No flake.nix to merge into at {}", flake_path.display()))?;
+
+        let mut base = vec![("local".to_string(), existing.clone())];
+        base.extend(sources.iter().cloned());
+        let merged = crate::pipe::merge_sources(&base, strategy)?;
+        self.check_policy(&[], &merged)?;
+
+        if GenerationRecord::read(&existing)?.is_some() {
+            tracing::warn!(
+                "{} will no longer carry a generation record; regenerate cannot reproduce merged flakes",
+                flake_path.display()
+            );
         }
 
-        let merged = crate::merger::merge_templates(&templates)?;
+        let rendered = RenderedFlake {
+            flake_content: merged,
+            additional_files: HashMap::new(),
+            provenance: Default::default(),
+        };
+        self.write_rendered(&rendered, target_path, false, OverwritePolicy::Backup)
+    }
 
-        fs::create_dir_all(target_path)?;
+    fn write_rendered(
+        &self,
+        rendered: &RenderedFlake,
+        target_path: &Path,
+        overwrite_additional: bool,
+        policy: OverwritePolicy,
+    ) -> Result<WriteOutcome> {
         let flake_path = target_path.join("flake.nix");
-        fs::write(&flake_path, merged)?;
+        let existing = fs::read_to_string(&flake_path).ok();
 
-        self.format_with_nixfmt(&flake_path)?;
+        // The configured header replaces the file's; without one the file's
+        // header is kept
+        let header = self
+            .header
+            .clone()
+            .or_else(|| existing.as_deref().and_then(Header::read));
+        let mut rendered = rendered.clone();
+        if let Some(header) = &header {
+            rendered.flake_content = header.prepend_to(&rendered.flake_content);
+            for (filename, content) in rendered.additional_files.iter_mut() {
+                if Header::fits(filename) {
+                    *content = header.prepend_to(content);
+                }
+            }
+        }
 
-        for template in &templates {
-            for (filename, content) in &template.additional_files {
+        let additional_to_write: Vec<_> = rendered
+            .additional_files
+            .iter()
+            .filter(|(filename, content)| {
                 let target_file = target_path.join(filename);
-                if !target_file.exists() {
-                    fs::write(target_file, content)?;
+                if overwrite_additional {
+                    fs::read_to_string(&target_file).ok().as_ref() != Some(*content)
+                } else {
+                    !target_file.exists()
                 }
+            })
+            .collect();
+
+        let flake_unchanged = existing
+            .as_deref()
+            .is_some_and(|existing| is_semantically_equal(existing, &rendered.flake_content));
+
+        let _span = tracing::debug_span!("write", path = %target_path.display()).entered();
+        if flake_unchanged && additional_to_write.is_empty() {
+            tracing::debug!("flake.nix and additional files unchanged, nothing to write");
+            return Ok(WriteOutcome::UpToDate);
+        }
+
+        if let (Some(existing), false) = (&existing, flake_unchanged) {
+            self.confirm_overwrite(&flake_path, existing, &rendered.flake_content, policy)?;
+        }
+
+        tracing::debug!(
+            flake_changed = !flake_unchanged,
+            additional_files = additional_to_write.len(),
+            "writing files"
+        );
+        let mut transaction = FileTransaction::new();
+        if !flake_unchanged {
+            transaction.write(&flake_path, rendered.flake_content.as_str());
+        }
+        for (filename, content) in additional_to_write {
+            transaction.write(target_path.join(filename), content.as_str());
+        }
+        transaction.commit()?;
+
+        if !flake_unchanged {
+            self.format_with_nixfmt(&flake_path)?;
+        }
+
+        Ok(WriteOutcome::Written)
+    }
+
+    /// Decide whether an existing, different flake.nix may be replaced,
+    /// showing what would change and backing it up when allowed.
+    fn confirm_overwrite(
+        &self,
+        flake_path: &Path,
+        existing: &str,
+        rendered: &str,
+        policy: OverwritePolicy,
+    ) -> Result<()> {
+        let allowed = match policy {
+            OverwritePolicy::Backup => true,
+            OverwritePolicy::Refuse | OverwritePolicy::Prompt => {
+                let proposed = self.format_content(rendered);
+                eprint!(
+                    "{}",
+                    crate::color::diff(&crate::diff::unified(
+                        existing,
+                        &proposed,
+                        "flake.nix (existing)",
+                        "flake.nix (generated)"
+                    ))
+                );
+                policy == OverwritePolicy::Prompt && prompt_yes_no("Overwrite flake.nix? [y/N] ")?
             }
+        };
+
+        if !allowed {
+            return Err(errors::coded(
+                ErrorKind::MergeConflict,
+                format!(
+                    "{} already exists; use --force to replace it (a backup is kept) or --merge to merge into it",
+                    flake_path.display()
+                ),
+            ));
         }
 
+        let backup_path =
+            flake_path.with_file_name(format!("flake.nix.{}.bak", crate::timestamp::compact_now()));
+        fs::copy(flake_path, &backup_path)?;
+        status!("Backed up existing flake.nix to {}", backup_path.display());
         Ok(())
     }
 
+    /// Format Nix source with nixfmt via stdin, returning it unchanged when
+    /// nixfmt is unavailable or fails.
+    fn format_content(&self, content: &str) -> String {
+        nixfmt(content).unwrap_or_else(|_| content.to_string())
+    }
+
     fn format_with_nixfmt(&self, file_path: &Path) -> Result<()> {
         if Command::new("nixfmt").arg("--version").output().is_ok() {
             let output = Command::new("nixfmt")
@@ -115,29 +365,145 @@ components = ["rustfmt", "rust-analyzer"]
             
             match output {
                 Ok(result) if result.status.success() => {
-                    println!("Formatted {} with nixfmt", file_path.display());
+                    status!("Formatted {} with nixfmt", file_path.display());
                 }
                 Ok(result) => {
-                    eprintln!("Warning: nixfmt failed to format {}: {}", 
-                        file_path.display(), 
-                        String::from_utf8_lossy(&result.stderr));
+                    tracing::warn!(
+                        "nixfmt failed to format {}: {}",
+                        file_path.display(),
+                        String::from_utf8_lossy(&result.stderr)
+                    );
                 }
                 Err(_) => {
-                    eprintln!("Warning: Failed to run nixfmt on {}", file_path.display());
+                    tracing::warn!("Failed to run nixfmt on {}", file_path.display());
                 }
             }
         }
         Ok(())
     }
 
-    pub fn list_templates(&self) {
-        println!("Available templates:");
-        let mut sorted: Vec<_> = self.templates.values().collect();
-        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    /// Names of all registered templates, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        self.registry.names()
+    }
+
+    /// The groups offered to every template, by name.
+    pub fn groups(&self) -> &BTreeMap<String, TemplateGroup> {
+        self.registry.groups()
+    }
+
+    pub fn list_templates(&self, format: OutputFormat) -> Result<()> {
+        let sorted = self.registry.templates();
+
+        if format.is_json() {
+            let entries: Vec<_> = sorted
+                .iter()
+                .map(|t| {
+                    json!({
+                        "name": t.name,
+                        "description": t.description,
+                        "groups": t.groups.keys().collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+            return output::print_json("list", &entries);
+        }
 
+        println!("Available templates:");
         for template in sorted {
-            println!("  {} - {}", template.name, template.description);
+            if template.groups.is_empty() {
+                println!("  {} - {}", template.name, template.description);
+            } else {
+                let groups: Vec<&str> = template.groups.keys().map(String::as_str).collect();
+                println!(
+                    "  {} - {} (--with {})",
+                    template.name,
+                    template.description,
+                    groups.join(", ")
+                );
+            }
         }
+        let shared: Vec<&str> = self.registry.groups().keys().map(String::as_str).collect();
+        if !shared.is_empty() {
+            println!("\nWith any template: --with {}", shared.join(", "));
+        }
+        Ok(())
     }
 
 }
+
+/// Format Nix source by piping it through nixfmt.
+pub fn nixfmt(content: &str) -> Result<String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new("nixfmt")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run nixfmt (is it installed?): {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(errors::coded(
+            ErrorKind::ParseFailure,
+            format!("nixfmt failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Every template of the shells, each once, in order of first use.
+fn shell_templates(shells: &[(String, Vec<String>)]) -> Vec<&str> {
+    let mut templates: Vec<&str> = Vec::new();
+    for name in shells.iter().flat_map(|(_, names)| names) {
+        if !templates.contains(&name.as_str()) {
+            templates.push(name);
+        }
+    }
+    templates
+}
+
+/// Whether writing `rendered` over `existing` would change nothing but
+/// formatting. The header and generation record have to match, and the
+/// flakes below them have to be the same text, or parse to the same syntax
+/// tree with the same comments. Falls back to exact comparison when either
+/// side doesn't parse.
+fn is_semantically_equal(existing: &str, rendered: &str) -> bool {
+    if Header::read(existing) != Header::read(rendered) {
+        return false;
+    }
+    match (GenerationRecord::read(existing), GenerationRecord::read(rendered)) {
+        (Ok(a), Ok(b)) if a == b => {}
+        _ => return false,
+    }
+
+    let (existing, rendered) = (metadata::strip(existing), metadata::strip(rendered));
+    if existing == rendered {
+        return true;
+    }
+    match (
+        nix_parser::parse_nix_expr(existing),
+        nix_parser::parse_nix_expr(rendered),
+    ) {
+        (Ok(a), Ok(b)) => {
+            a == b && nix_parser::comments(existing) == nix_parser::comments(rendered)
+        }
+        _ => false,
+    }
+}
+
+fn prompt_yes_no(question: &str) -> Result<bool> {
+    use std::io::Write;
+
+    eprint!("{question}");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
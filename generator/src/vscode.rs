@@ -0,0 +1,162 @@
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions every generated workspace recommends: Nix language support
+/// and direnv, which loads the devShell into VS Code's environment.
+const COMMON_EXTENSIONS: [&str; 2] = ["jnoortheen.nix-ide", "mkhl.direnv"];
+
+/// The VS Code extensions for a template's language.
+fn extensions(template: &str) -> &'static [&'static str] {
+    match template {
+        "bun" => &["oven.bun-vscode"],
+        "c-cpp" => &["llvm-vs-code-extensions.vscode-clangd"],
+        "clojure" => &["betterthantomorrow.calva"],
+        "csharp" => &["ms-dotnettools.csharp"],
+        "elixir" => &["jakebecker.elixir-ls"],
+        "elm" => &["elmtooling.elm-ls-vscode"],
+        "gleam" => &["gleam.gleam"],
+        "go" => &["golang.go"],
+        "hashi" => &["hashicorp.terraform"],
+        "haskell" => &["haskell.haskell"],
+        "java" => &["redhat.java"],
+        "kotlin" => &["fwcd.kotlin"],
+        "latex" => &["james-yu.latex-workshop"],
+        "ocaml" => &["ocamllabs.ocaml-platform"],
+        "php" => &["bmewburn.vscode-intelephense-client"],
+        "protobuf" => &["bufbuild.vscode-buf"],
+        "python" => &["ms-python.python"],
+        "r" => &["reditorsupport.r"],
+        "ruby" => &["shopify.ruby-lsp"],
+        "rust" | "rust-toolchain" => &["rust-lang.rust-analyzer"],
+        "scala" => &["scalameta.metals"],
+        "shell" => &["timonwong.shellcheck"],
+        "zig" => &["ziglang.vscode-zig"],
+        _ => &[],
+    }
+}
+
+/// Settings making a template's extensions use the tools on the devShell's
+/// PATH instead of downloading their own.
+fn settings(template: &str) -> Value {
+    match template {
+        "c-cpp" => json!({ "clangd.path": "clangd" }),
+        "go" => json!({ "go.toolsManagement.autoUpdate": false }),
+        "haskell" => json!({ "haskell.manageHLS": "PATH" }),
+        "nix" => json!({ "nix.formatterPath": "nixfmt" }),
+        "python" => json!({ "python.defaultInterpreterPath": "python3" }),
+        "rust" | "rust-toolchain" => json!({ "rust-analyzer.server.path": "rust-analyzer" }),
+        "shell" => json!({ "shellcheck.executablePath": "shellcheck" }),
+        "zig" => json!({ "zig.path": "zig", "zig.zls.path": "zls" }),
+        _ => json!({}),
+    }
+}
+
+/// Write `.vscode/extensions.json` and `.vscode/settings.json` for the
+/// templates into `target_path`. Existing files are merged into: their
+/// recommendations are kept and their settings win. Files that aren't
+/// plain JSON (VS Code also accepts comments) are left alone with a
+/// warning. Returns the files that changed.
+pub fn write(target_path: &Path, templates: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut recommended: Vec<&str> = COMMON_EXTENSIONS.to_vec();
+    let mut wanted = Map::new();
+    for template in templates {
+        recommended.extend(extensions(template));
+        if let Value::Object(template_settings) = settings(template) {
+            wanted.extend(template_settings);
+        }
+    }
+
+    let dir = target_path.join(".vscode");
+    let mut transaction = FileTransaction::new();
+    let mut written = Vec::new();
+
+    let extensions_path = dir.join("extensions.json");
+    if let Some(mut existing) = read_object(&extensions_path) {
+        let recommendations = existing
+            .entry("recommendations")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(recommendations) = recommendations {
+            for extension in recommended {
+                if !recommendations.iter().any(|r| r.as_str() == Some(extension)) {
+                    recommendations.push(Value::from(extension));
+                }
+            }
+        }
+        stage(&mut transaction, &mut written, extensions_path, &existing)?;
+    }
+
+    let settings_path = dir.join("settings.json");
+    if let Some(mut existing) = read_object(&settings_path) {
+        for (key, value) in wanted {
+            existing.entry(key).or_insert(value);
+        }
+        stage(&mut transaction, &mut written, settings_path, &existing)?;
+    }
+
+    transaction.commit()?;
+    Ok(written)
+}
+
+/// The JSON object in `path`, an empty one when the file doesn't exist,
+/// or `None` when it can't be merged into.
+fn read_object(path: &Path) -> Option<Map<String, Value>> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Some(Map::new());
+    };
+    match serde_json::from_str(&content) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => {
+            tracing::warn!(
+                "Leaving {} unchanged: it is not a plain JSON object",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+fn stage(
+    transaction: &mut FileTransaction,
+    written: &mut Vec<PathBuf>,
+    path: PathBuf,
+    object: &Map<String, Value>,
+) -> Result<()> {
+    let content = format!("{}\n", serde_json::to_string_pretty(object)?);
+    if fs::read_to_string(&path).ok().as_deref() != Some(content.as_str()) {
+        transaction.write(&path, content);
+        written.push(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_merges_existing_files() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".vscode")).unwrap();
+        fs::write(
+            dir.path().join(".vscode/settings.json"),
+            r#"{ "rust-analyzer.server.path": "/opt/ra", "editor.tabSize": 2 }"#,
+        )
+        .unwrap();
+
+        let written = write(dir.path(), &["rust", "go"]).unwrap();
+        let settings = fs::read_to_string(dir.path().join(".vscode/settings.json")).unwrap();
+        let extensions = fs::read_to_string(dir.path().join(".vscode/extensions.json")).unwrap();
+        let unchanged = write(dir.path(), &["rust", "go"]).unwrap();
+
+        assert_eq!(written.len(), 2);
+        assert!(settings.contains(r#""rust-analyzer.server.path": "/opt/ra""#));
+        assert!(settings.contains(r#""go.toolsManagement.autoUpdate": false"#));
+        assert!(extensions.contains("mkhl.direnv"));
+        assert!(extensions.contains("rust-lang.rust-analyzer"));
+        assert!(unchanged.is_empty());
+    }
+}
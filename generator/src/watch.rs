@@ -0,0 +1,92 @@
+use crate::config::{self, PROJECT_CONFIG_FILE};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Language manifests whose edits usually mean the environment should change.
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "go.mod",
+    "package.json",
+    "pyproject.toml",
+    "requirements.txt",
+    "Gemfile",
+    "mix.exs",
+    "composer.json",
+    "build.zig",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+];
+
+/// Config files that affect generation, plus the manifests present in
+/// `target_path`. Missing config files are included so creating them counts
+/// as a change.
+pub fn watched_files(target_path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![config::project_config_path()];
+    let target_config = target_path.join(PROJECT_CONFIG_FILE);
+    if !files.contains(&target_config) {
+        files.push(target_config);
+    }
+    if let Ok(global) = config::global_config_path() {
+        files.push(global);
+    }
+    files.extend(
+        MANIFESTS
+            .iter()
+            .map(|name| target_path.join(name))
+            .filter(|path| path.exists()),
+    );
+    files
+}
+
+fn snapshot(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Poll until one of `files` is created, modified or removed, returning the
+/// first one that changed. Waits for a quiet interval so an editor's
+/// write-then-rename counts as one change.
+pub async fn wait_for_change(files: &[PathBuf]) -> PathBuf {
+    let mut before = snapshot(files);
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let now = snapshot(files);
+        if let Some(index) = first_difference(&before, &now) {
+            let mut settled = now;
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let latest = snapshot(files);
+                if latest == settled {
+                    break;
+                }
+                settled = latest;
+            }
+            return files[index].clone();
+        }
+        before = now;
+    }
+}
+
+fn first_difference(before: &[Option<SystemTime>], after: &[Option<SystemTime>]) -> Option<usize> {
+    before.iter().zip(after).position(|(a, b)| a != b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_detects_creation() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![dir.path().join(PROJECT_CONFIG_FILE)];
+        let before = snapshot(&files);
+        fs::write(&files[0], "nixpkgs-ref = \"nixos-24.05\"\n").unwrap();
+        assert_eq!(first_difference(&before, &snapshot(&files)), Some(0));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::writer::FileTransaction;
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A script entering the devShell for contributors who have never used nix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Script {
+    /// `dev.sh` for Linux, macOS and WSL
+    Sh,
+    /// `dev.ps1` for PowerShell, running nix through WSL on Windows
+    Ps1,
+}
+
+impl Script {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Script::Sh => "dev.sh",
+            Script::Ps1 => "dev.ps1",
+        }
+    }
+
+    fn content(self) -> &'static str {
+        match self {
+            Script::Sh => DEV_SH,
+            Script::Ps1 => DEV_PS1,
+        }
+    }
+}
+
+// Flakes are enabled per invocation, so the scripts work whatever nix.conf says
+const DEV_SH: &str = r#"#!/usr/bin/env sh
+# Enter the development shell of this project: ./dev.sh
+# Run a single command in it instead: ./dev.sh cargo test
+set -eu
+cd "$(dirname "$0")"
+
+if ! command -v nix >/dev/null 2>&1; then
+  # Installed but not yet on PATH in this terminal
+  for profile in /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh \
+    "$HOME/.nix-profile/etc/profile.d/nix.sh"; do
+    if [ -e "$profile" ]; then
+      . "$profile"
+      break
+    fi
+  done
+fi
+
+if ! command -v nix >/dev/null 2>&1; then
+  echo "nix is not installed. Install it from https://nixos.org/download, then run $0 again." >&2
+  exit 1
+fi
+
+if [ "$#" -gt 0 ]; then
+  exec nix --extra-experimental-features "nix-command flakes" develop --command "$@"
+fi
+exec nix --extra-experimental-features "nix-command flakes" develop
+"#;
+
+const DEV_PS1: &str = r#"# Enter the development shell of this project: ./dev.ps1
+# Run a single command in it instead: ./dev.ps1 cargo test
+Set-Location $PSScriptRoot
+
+$features = 'nix-command flakes'
+$command = @('develop')
+if ($args.Count -gt 0) {
+    $command += @('--command') + $args
+}
+
+if (Get-Command nix -ErrorAction SilentlyContinue) {
+    & nix --extra-experimental-features $features @command
+    exit $LASTEXITCODE
+}
+
+# nix does not run natively on Windows; use it from WSL
+if (Get-Command wsl -ErrorAction SilentlyContinue) {
+    & wsl --cd $PSScriptRoot -- sh -lc 'command -v nix' > $null
+    if ($LASTEXITCODE -ne 0) {
+        [Console]::Error.WriteLine('nix is not installed in WSL. Install it there from https://nixos.org/download, then run this script again.')
+        exit 1
+    }
+    # A login shell, so nix's profile script has put it on PATH
+    & wsl --cd $PSScriptRoot -- sh -lc 'exec nix "$@"' sh --extra-experimental-features $features @command
+    exit $LASTEXITCODE
+}
+
+[Console]::Error.WriteLine('nix is not installed. Install WSL (wsl --install), then nix inside it from https://nixos.org/download.')
+exit 1
+"#;
+
+/// Write the scripts into `target_path`, making `dev.sh` executable.
+/// Returns the files that changed.
+pub fn write(target_path: &Path, scripts: &[Script]) -> Result<Vec<PathBuf>> {
+    let mut transaction = FileTransaction::new();
+    let mut written = Vec::new();
+    for script in scripts {
+        let path = target_path.join(script.file_name());
+        if fs::read_to_string(&path).ok().as_deref() != Some(script.content()) {
+            transaction.write(&path, script.content());
+            written.push(path);
+        }
+    }
+    transaction.commit()?;
+
+    #[cfg(unix)]
+    if scripts.contains(&Script::Sh) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(
+            target_path.join(Script::Sh.file_name()),
+            fs::Permissions::from_mode(0o755),
+        )?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_scripts() {
+        let dir = TempDir::new().unwrap();
+
+        let written = write(dir.path(), &[Script::Sh, Script::Ps1]).unwrap();
+        let unchanged = write(dir.path(), &[Script::Sh, Script::Ps1]).unwrap();
+        let script = fs::read_to_string(dir.path().join("dev.sh")).unwrap();
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::metadata(dir.path().join("dev.sh")).unwrap().permissions().mode()
+        };
+
+        assert_eq!(written.len(), 2);
+        assert!(unchanged.is_empty());
+        assert!(script.contains("develop --command \"$@\""));
+        #[cfg(unix)]
+        assert_eq!(mode & 0o111, 0o111);
+    }
+}
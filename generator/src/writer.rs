@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A set of file writes applied all-or-nothing. Every file is first written
+/// to a temporary sibling and then renamed into place; if any step fails,
+/// files already replaced are restored and new ones removed, so a project is
+/// never left half-generated.
+#[derive(Debug, Default)]
+pub struct FileTransaction {
+    writes: Vec<(PathBuf, Vec<u8>)>,
+}
+
+enum Applied {
+    Replaced { path: PathBuf, original: Vec<u8> },
+    Created(PathBuf),
+}
+
+impl FileTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a file. Line endings are normalized to LF so a template checked
+    /// out with CRLF on Windows still produces files nix and git agree on.
+    pub fn write(&mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) {
+        self.writes.push((path.into(), to_lf(content.into())));
+    }
+
+    pub fn commit(self) -> Result<()> {
+        let mut created_dirs = Vec::new();
+        let mut staged = Vec::new();
+
+        let result = self
+            .stage(&mut created_dirs, &mut staged)
+            .and_then(|()| Self::apply(&staged));
+
+        if result.is_err() {
+            for temp in &staged {
+                let _ = fs::remove_file(temp.0.as_path());
+            }
+            // Deepest directories were pushed last
+            for dir in created_dirs.iter().rev() {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+        result
+    }
+
+    fn stage(
+        &self,
+        created_dirs: &mut Vec<PathBuf>,
+        staged: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> Result<()> {
+        for (path, content) in &self.writes {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                create_dirs_tracked(parent, created_dirs)?;
+            }
+            let temp = temp_path(path);
+            fs::write(&temp, content)
+                .with_context(|| format!("Failed to write {}", temp.display()))?;
+            staged.push((temp, path.clone()));
+        }
+        Ok(())
+    }
+
+    fn apply(staged: &[(PathBuf, PathBuf)]) -> Result<()> {
+        let mut applied = Vec::new();
+
+        for (temp, path) in staged {
+            let step = match fs::read(path) {
+                Ok(original) => Applied::Replaced {
+                    path: path.clone(),
+                    original,
+                },
+                Err(_) => Applied::Created(path.clone()),
+            };
+
+            if let Err(e) = fs::rename(temp, path) {
+                rollback(&applied);
+                return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+            }
+            applied.push(step);
+        }
+
+        Ok(())
+    }
+}
+
+fn rollback(applied: &[Applied]) {
+    for step in applied.iter().rev() {
+        match step {
+            Applied::Replaced { path, original } => {
+                let _ = fs::write(path, original);
+            }
+            Applied::Created(path) => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn create_dirs_tracked(dir: &Path, created: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+        create_dirs_tracked(parent, created)?;
+    }
+    fs::create_dir(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    created.push(dir.to_path_buf());
+    Ok(())
+}
+
+fn to_lf(content: Vec<u8>) -> Vec<u8> {
+    if !content.contains(&b'\r') {
+        return content;
+    }
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        normalized.push(byte);
+    }
+    normalized
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp-{}", name, std::process::id()))
+}
+
+/// Atomically replace a single file.
+pub fn write_atomic(path: &Path, content: impl Into<Vec<u8>>) -> Result<()> {
+    let mut transaction = FileTransaction::new();
+    transaction.write(path, content);
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_commit_writes_all_files() {
+        let dir = TempDir::new().unwrap();
+        let mut transaction = FileTransaction::new();
+        transaction.write(dir.path().join("a.txt"), "a");
+        transaction.write(dir.path().join("nested/b.txt"), "b");
+        transaction.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.path().join("nested/b.txt")).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_failed_commit_rolls_back() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("existing.txt"), "original").unwrap();
+        // A directory where a file should go makes the rename fail
+        fs::create_dir(dir.path().join("blocked")).unwrap();
+        fs::write(dir.path().join("blocked/keep"), "").unwrap();
+
+        let mut transaction = FileTransaction::new();
+        transaction.write(dir.path().join("existing.txt"), "replaced");
+        transaction.write(dir.path().join("new.txt"), "new");
+        transaction.write(dir.path().join("blocked"), "oops");
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("existing.txt")).unwrap(),
+            "original"
+        );
+        assert!(!dir.path().join("new.txt").exists());
+        let leftovers = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp-"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn test_write_normalizes_line_endings() {
+        let dir = TempDir::new().unwrap();
+        let mut transaction = FileTransaction::new();
+        transaction.write(dir.path().join("flake.nix"), "{\r\n  a = 1;\r\n}\r\n");
+        transaction.commit().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("flake.nix")).unwrap(),
+            "{\n  a = 1;\n}\n"
+        );
+    }
+}
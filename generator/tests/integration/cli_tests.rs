@@ -35,4 +35,555 @@ fn test_missing_template_argument() {
         .assert()
         .failure()
         .stderr(predicate::str::contains("required"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_cache_list_and_clean_empty() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CACHE_HOME", &temp_path)
+        .arg("cache")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No cached environments"));
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CACHE_HOME", &temp_path)
+        .arg("cache")
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 cached environment(s)"));
+}
+
+#[test]
+fn test_config_set_and_get() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["config", "set", "nixpkgs-ref", "nixos-24.05"])
+        .assert()
+        .success();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["config", "get", "nixpkgs-ref"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nixos-24.05"));
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["config", "set", "no-such-key", "1"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_config_path_honors_env() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let config_file = temp_dir.path().join("ci.toml");
+    std::fs::write(&config_file, "nixpkgs-ref = \"nixos-24.05\"\n").unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("NFG_CONFIG", &config_file)
+        .args(["config", "path"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ci.toml"));
+
+    // The environment overrides the file it names
+    let mut cmd = create_cargo_command();
+    cmd.env("NFG_CONFIG", &config_file)
+        .env("NFG_NIXPKGS_REF", "nixos-24.11")
+        .env("XDG_CONFIG_HOME", &temp_path)
+        .args(["config", "get", "nixpkgs-ref"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nixos-24.11"));
+}
+
+#[test]
+fn test_init_uses_config_nixpkgs_ref() {
+    let (config_dir, config_path) = crate::integration::common::create_temp_dir_with_path();
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let config_file = config_dir.path().join("nix-flake-generator").join("config.toml");
+    std::fs::create_dir_all(config_file.parent().unwrap()).unwrap();
+    std::fs::write(&config_file, "nixpkgs-ref = \"nixos-24.05\"\n").unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &config_path)
+        .args(["init", "go", "--path", &temp_path])
+        .assert()
+        .success();
+
+    crate::integration::common::assert_flake_exists_and_contains(
+        &temp_dir,
+        &["github:NixOS/nixpkgs/nixos-24.05"],
+    );
+}
+
+#[test]
+fn test_alias_list_empty() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["alias", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No aliases configured"));
+}
+
+#[test]
+fn test_regenerate_replays_record() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "rust,go", "--path", &temp_path])
+        .assert()
+        .success();
+
+    let flake_content = crate::integration::common::assert_flake_exists_and_contains(
+        &temp_dir,
+        &["# @nix-flake-generator", "templates = [\"rust\", \"go\"]"],
+    );
+
+    // Replace the flake so regenerate has something to restore
+    std::fs::write(temp_dir.path().join("flake.nix"), flake_content.replace("gotools", "hello"))
+        .unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["regenerate", "--path", &temp_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Regenerated rust,go"));
+
+    let regenerated = crate::integration::common::assert_flake_exists_and_contains(&temp_dir, &[]);
+    assert_eq!(flake_content, regenerated);
+}
+
+#[test]
+fn test_generate_writes_configured_shells() {
+    let (_config_dir, config_path) = crate::integration::common::create_temp_dir_with_path();
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    std::fs::write(
+        temp_dir.path().join(".nix-flake-generator.toml"),
+        r#"
+[shells.frontend]
+languages = ["node", "elm"]
+
+[shells.backend]
+languages = ["go"]
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &config_path)
+        .current_dir(&temp_path)
+        .arg("generate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Generated devShells backend, frontend"));
+
+    let flake_content = crate::integration::common::assert_flake_exists_and_contains(
+        &temp_dir,
+        &["frontend = pkgs.mkShell", "backend = pkgs.mkShell", "backend = [\"go\"]"],
+    );
+    std::fs::write(temp_dir.path().join("flake.nix"), flake_content.replace("gotools", "hello"))
+        .unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &config_path)
+        .args(["regenerate", "--path", &temp_path])
+        .assert()
+        .success();
+    let regenerated = crate::integration::common::assert_flake_exists_and_contains(&temp_dir, &[]);
+    assert_eq!(flake_content, regenerated);
+}
+
+#[test]
+fn test_init_twice_is_up_to_date() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "rust-toolchain", "--path", &temp_path])
+        .assert()
+        .success();
+    let modified = std::fs::metadata(temp_dir.path().join("flake.nix"))
+        .and_then(|m| m.modified())
+        .unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "rust-toolchain", "--path", &temp_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already up to date"));
+
+    let modified_again = std::fs::metadata(temp_dir.path().join("flake.nix"))
+        .and_then(|m| m.modified())
+        .unwrap();
+    assert_eq!(modified, modified_again);
+}
+
+#[test]
+fn test_init_rewrites_comment_only_changes() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["init", "rust,go", "--path", &temp_path])
+        .assert()
+        .success();
+
+    // Provenance comments, a header and the options in the generation
+    // record live only in comments, which the syntax tree doesn't keep
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .env("NFG_HEADER", "Copyright Example Corp")
+        .args(["init", "rust,go", "--provenance", "--force", "--path", &temp_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already up to date").not());
+
+    let flake = crate::integration::common::assert_flake_exists_and_contains(
+        &temp_dir,
+        &["# @header\n# Copyright Example Corp\n", "# from rust ", "provenance-comments = true"],
+    );
+
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .env("NFG_HEADER", "Copyright Example Corp")
+        .args(["init", "rust,go", "--provenance", "--path", &temp_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already up to date"));
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("flake.nix")).unwrap(), flake);
+}
+
+#[test]
+fn test_init_refuses_to_overwrite_and_force_backs_up() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &temp_path])
+        .assert()
+        .success();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "zig", "--path", &temp_path])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"))
+        .stderr(predicate::str::contains("+++ flake.nix (generated)"));
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "zig", "--path", &temp_path, "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Backed up existing flake.nix"));
+
+    let backups = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".bak"))
+        .count();
+    assert_eq!(backups, 1);
+}
+
+#[test]
+fn test_init_merge_into_existing() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &temp_path])
+        .assert()
+        .success();
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "zig", "--path", &temp_path, "--merge"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Merged zig"));
+
+    crate::integration::common::assert_flake_exists_and_contains(
+        &temp_dir,
+        &["gotools", "zig", "templates = [\"go\", \"zig\"]"],
+    );
+}
+
+#[test]
+fn test_list_json_output() {
+    let mut cmd = create_cargo_command();
+    let output = cmd.args(["--output", "json", "list"]).output().unwrap();
+    assert!(output.status.success());
+
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["schema_version"], 1);
+    assert_eq!(document["command"], "list");
+    let templates = document["data"].as_array().unwrap();
+    assert!(templates.iter().any(|t| t["name"] == "rust"));
+}
+
+#[test]
+fn test_init_creates_nested_path() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let nested = format!("{temp_path}/a/./b/../c");
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &nested]).assert().success();
+    assert!(temp_dir.path().join("a/c/flake.nix").exists());
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &format!("{temp_path}/a/c/flake.nix")])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is a file, not a directory"));
+}
+
+#[test]
+fn test_completions_include_template_names() {
+    let mut cmd = create_cargo_command();
+    cmd.args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nix-flake-generator"))
+        .stdout(predicate::str::contains("python"));
+}
+
+#[test]
+fn test_help_all_reference() {
+    let mut cmd = create_cargo_command();
+    cmd.args(["help", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Target directory"))
+        .stdout(predicate::str::contains("defaults.path"))
+        .stdout(predicate::str::contains("Exit codes:"));
+}
+
+#[test]
+fn test_man_page() {
+    let mut cmd = create_cargo_command();
+    cmd.arg("man")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".TH"))
+        .stdout(predicate::str::contains("EXIT STATUS"));
+}
+
+#[test]
+fn test_quiet_and_verbose() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["--quiet", "init", "go", "--path", &temp_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["-v", "init", "rust,go", "--path", &temp_path])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("merged fragments"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_init_runs_hooks() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args([
+        "init",
+        "go",
+        "--path",
+        &temp_path,
+        "--hook",
+        "echo \"$NFG_TEMPLATES\" > hook-ran",
+    ])
+    .assert()
+    .success();
+
+    let marker = std::fs::read_to_string(temp_dir.path().join("hook-ran")).unwrap();
+    assert_eq!(marker.trim(), "go");
+}
+
+#[test]
+fn test_merge_reads_stdin_and_writes_stdout() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    for template in ["go", "zig"] {
+        let mut cmd = create_cargo_command();
+        cmd.args(["init", template, "--path", &format!("{temp_path}/{template}")])
+            .assert()
+            .success();
+    }
+
+    let go_flake = std::fs::read_to_string(temp_dir.path().join("go/flake.nix")).unwrap();
+    let mut cmd = create_cargo_command();
+    cmd.args(["merge", "--stdout", "-", &format!("{temp_path}/zig/flake.nix")])
+        .write_stdin(go_flake)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("go"))
+        .stdout(predicate::str::contains("zig"));
+}
+
+#[test]
+fn test_lint_reads_stdin() {
+    let source = "let\n  unused = 1;\n  used = 2;\nin\n[\n  used\n  used\n]\n";
+
+    let mut cmd = create_cargo_command();
+    cmd.arg("lint")
+        .write_stdin(source)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("stdin:2: warning:"))
+        .stdout(predicate::str::contains("[unused-binding]"))
+        .stdout(predicate::str::contains("[duplicate-entry]"));
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["lint", "--fix", "-"])
+        .write_stdin(source)
+        .assert()
+        .success()
+        .stdout("let\n  used = 2;\nin\n[\n  used\n]\n");
+}
+
+#[test]
+fn test_explain_reads_stdin() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &temp_path]).assert().success();
+
+    let flake = std::fs::read_to_string(temp_dir.path().join("flake.nix")).unwrap();
+    let mut cmd = create_cargo_command();
+    cmd.args(["explain", "--path", "-"])
+        .write_stdin(flake.as_str())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("from: go"))
+        .stdout(predicate::str::contains("  nixpkgs "));
+
+    let mut cmd = create_cargo_command();
+    cmd.args(["explain", "--lock"])
+        .write_stdin(flake)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs --path <directory>"));
+}
+
+#[test]
+fn test_merge_other_flake_into_project() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    for template in ["go", "zig"] {
+        let mut cmd = create_cargo_command();
+        cmd.args(["init", template, "--path", &format!("{temp_path}/{template}")])
+            .assert()
+            .success();
+    }
+
+    let mut cmd = create_cargo_command();
+    cmd.args([
+        "merge",
+        &format!("{temp_path}/zig/flake.nix"),
+        "--path",
+        &format!("{temp_path}/go"),
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Merged"));
+
+    let merged = std::fs::read_to_string(temp_dir.path().join("go/flake.nix")).unwrap();
+    assert!(merged.contains("zig"));
+    assert!(merged.contains("go"));
+}
+
+#[test]
+fn test_template_test_reports_failures() {
+    let (temp_dir, _temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let templates = temp_dir.path().join("templates");
+    std::fs::create_dir(&templates).unwrap();
+    let spec = temp_dir.path().join("template-tests.toml");
+    std::fs::write(
+        &spec,
+        r#"
+[[case]]
+templates = ["go"]
+contains = ["go"]
+
+[[case]]
+name = "missing package"
+templates = ["zig"]
+contains = ["not-in-any-flake"]
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = create_cargo_command();
+    cmd.arg("template")
+        .arg("test")
+        .arg(&templates)
+        .arg(&spec)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("PASS go"))
+        .stdout(predicate::str::contains("FAIL missing package"))
+        .stderr(predicate::str::contains("1 of 2 template test(s) failed"));
+}
+
+#[test]
+fn test_input_dedupe_writes_follows() {
+    let (temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    std::fs::write(
+        temp_dir.path().join("flake.nix"),
+        "{\n  inputs.nixpkgs.url = \"github:NixOS/nixpkgs/nixos-unstable\";\n  inputs.devenv.url = \"github:cachix/devenv\";\n\n  outputs = { self, nixpkgs, devenv }: { };\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        temp_dir.path().join("flake.lock"),
+        r#"{
+  "nodes": {
+    "devenv": {
+      "inputs": { "nixpkgs": "nixpkgs_2" },
+      "locked": { "lastModified": 1710000000, "owner": "cachix", "repo": "devenv", "rev": "d0", "type": "github" },
+      "original": { "owner": "cachix", "repo": "devenv", "type": "github" }
+    },
+    "nixpkgs": {
+      "locked": { "lastModified": 1714521600, "owner": "NixOS", "repo": "nixpkgs", "rev": "a0", "type": "github" },
+      "original": { "owner": "NixOS", "ref": "nixos-unstable", "repo": "nixpkgs", "type": "github" }
+    },
+    "nixpkgs_2": {
+      "locked": { "lastModified": 1700000000, "owner": "NixOS", "repo": "nixpkgs", "rev": "a1", "type": "github" },
+      "original": { "owner": "NixOS", "ref": "nixpkgs-unstable", "repo": "nixpkgs", "type": "github" }
+    },
+    "root": {
+      "inputs": { "devenv": "devenv", "nixpkgs": "nixpkgs" }
+    }
+  },
+  "root": "root",
+  "version": 7
+}"#,
+    )
+    .unwrap();
+
+    // Without nix on PATH the flake is still rewritten, only the relock is skipped
+    let mut cmd = create_cargo_command();
+    cmd.env("PATH", "")
+        .arg("input")
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("devenv/nixpkgs follows nixpkgs"));
+
+    let flake = std::fs::read_to_string(temp_dir.path().join("flake.nix")).unwrap();
+    assert!(flake.contains(
+        "  inputs.nixpkgs.url = \"github:NixOS/nixpkgs/nixos-unstable\";\n  inputs.devenv.inputs.nixpkgs.follows = \"nixpkgs\";\n"
+    ));
+    assert_eq!(flake.matches("follows").count(), 1);
+}
@@ -21,4 +21,115 @@ fn test_nonexistent_multi_template() {
         .assert()
         .failure()
         .stderr(predicate::str::contains("Template 'nonexistent' not found"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_deps_without_flake() {
+    let mut cmd = create_cargo_command();
+    let temp_dir = tempfile::TempDir::new().expect("Should create temp directory");
+    cmd.arg("deps")
+        .arg("--path")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No flake.nix found"));
+}
+
+#[test]
+fn test_sbom_invalid_format() {
+    let mut cmd = create_cargo_command();
+    cmd.arg("sbom")
+        .arg("--format")
+        .arg("xml")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn test_shell_nonexistent_template() {
+    let mut cmd = create_cargo_command();
+    cmd.arg("shell")
+        .arg("rust,nonexistent")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Template 'nonexistent' not found"));
+}
+
+#[test]
+fn test_run_requires_command() {
+    let mut cmd = create_cargo_command();
+    cmd.arg("run")
+        .arg("go")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("required"));
+}
+
+#[test]
+fn test_unknown_profile() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.env("XDG_CONFIG_HOME", &temp_path)
+        .args(["init", "--profile", "webapp"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Profile 'webapp' not found"));
+}
+
+#[test]
+fn test_init_invalid_verify_level() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "go", "--path", &temp_path, "--verify=everything"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn test_init_unsupported_dotnet_sdk() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "csharp", "--path", &temp_path, "--dotnet-sdks", "8,42"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unsupported .NET SDK '42'"));
+}
+
+#[test]
+fn test_unknown_template_exit_code_and_json_error() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    let output = cmd
+        .args(["--output", "json", "init", "nonexistent-template", "--path", &temp_path])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["schema_version"], 1);
+    assert_eq!(error["error"]["kind"], "unknown_template");
+    assert_eq!(error["error"]["code"], 3);
+}
+
+#[test]
+fn test_unknown_template_suggestion() {
+    let (_temp_dir, temp_path) = crate::integration::common::create_temp_dir_with_path();
+    let mut cmd = create_cargo_command();
+    cmd.args(["init", "rust,pyton", "--path", &temp_path])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unknown template 'pyton', did you mean 'python'?",
+        ));
+}
+
+#[test]
+fn test_unknown_command_suggests_builtin() {
+    let mut cmd = create_cargo_command();
+    cmd.arg("lisst")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown command 'lisst', did you mean 'list'?"));
+}
@@ -22,7 +22,7 @@ fn test_rust_go_combination() {
     let flake_content = assert_flake_exists_and_contains(
         &temp_dir,
         &[
-            "Multi-language development environment (rust, go)",
+            "Multi-language development environment (go, rust)",
             "rust-overlay",
             "rustToolchain",
             "go",
@@ -0,0 +1,253 @@
+{
+  description = "NixOS machines of a small home lab";
+
+  inputs = {
+    nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.11";
+    home-manager = {
+      url = "github:nix-community/home-manager/release-24.11";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    disko = {
+      url = "github:nix-community/disko";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    sops-nix = {
+      url = "github:Mic92/sops-nix";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+  };
+
+  outputs =
+    {
+      self,
+      nixpkgs,
+      home-manager,
+      disko,
+      sops-nix,
+    }:
+    let
+      lib = nixpkgs.lib;
+      users = {
+        alice = {
+          uid = 1000;
+          keys = [ "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHo5mgoC2H0Kzq3Z6d3c alice@laptop" ];
+          admin = true;
+        };
+        bob = {
+          uid = 1001;
+          keys = [ "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJ8f5mXv2Yz6c1Kq0Lr4 bob@desktop" ];
+          admin = false;
+        };
+      };
+      common =
+        { pkgs, ... }:
+        {
+          nix.settings = {
+            experimental-features = [
+              "nix-command"
+              "flakes"
+            ];
+            auto-optimise-store = true;
+            trusted-users = [ "@wheel" ];
+          };
+          nix.gc = {
+            automatic = true;
+            dates = "weekly";
+            options = "--delete-older-than 14d";
+          };
+          time.timeZone = "Europe/Berlin";
+          i18n.defaultLocale = "en_GB.UTF-8";
+          services.openssh = {
+            enable = true;
+            settings = {
+              PasswordAuthentication = false;
+              PermitRootLogin = "no";
+            };
+          };
+          networking.firewall.enable = true;
+          environment.systemPackages = with pkgs; [
+            git
+            htop
+            vim
+            tmux
+            curl
+            rsync
+          ];
+          users.users = lib.mapAttrs (name: user: {
+            isNormalUser = true;
+            inherit (user) uid;
+            extraGroups = if user.admin then [ "wheel" ] else [ ];
+            openssh.authorizedKeys.keys = user.keys;
+          }) users;
+          system.stateVersion = "24.11";
+        };
+      mkHost =
+        name: system: modules:
+        lib.nixosSystem {
+          inherit system;
+          specialArgs = { inherit self; };
+          modules = [
+            common
+            disko.nixosModules.disko
+            sops-nix.nixosModules.sops
+            home-manager.nixosModules.home-manager
+            {
+              networking.hostName = name;
+              home-manager.useGlobalPkgs = true;
+              home-manager.useUserPackages = true;
+              sops.defaultSopsFile = ./secrets + "/${name}.yaml";
+            }
+          ] ++ modules;
+        };
+    in
+    {
+      nixosConfigurations = {
+        router = mkHost "router" "x86_64-linux" [
+          (
+            { config, ... }:
+            {
+              boot.kernel.sysctl = {
+                "net.ipv4.conf.all.forwarding" = true;
+                "net.ipv6.conf.all.forwarding" = true;
+              };
+              networking = {
+                nat = {
+                  enable = true;
+                  externalInterface = "wan0";
+                  internalInterfaces = [ "lan0" ];
+                };
+                interfaces.lan0.ipv4.addresses = [
+                  {
+                    address = "10.0.0.1";
+                    prefixLength = 24;
+                  }
+                ];
+              };
+              services.kea.dhcp4 = {
+                enable = true;
+                settings = {
+                  interfaces-config.interfaces = [ "lan0" ];
+                  subnet4 = [
+                    {
+                      subnet = "10.0.0.0/24";
+                      pools = [ { pool = "10.0.0.100 - 10.0.0.200"; } ];
+                    }
+                  ];
+                };
+              };
+              services.unbound = {
+                enable = true;
+                settings.server = {
+                  interface = [ "10.0.0.1" ];
+                  access-control = [ "10.0.0.0/24 allow" ];
+                };
+              };
+              sops.secrets."wireguard/private" = { };
+              networking.wireguard.interfaces.wg0 = {
+                ips = [ "10.100.0.1/24" ];
+                listenPort = 51820;
+                privateKeyFile = config.sops.secrets."wireguard/private".path;
+                peers = [
+                  {
+                    publicKey = "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=";
+                    allowedIPs = [ "10.100.0.2/32" ];
+                  }
+                ];
+              };
+            }
+          )
+        ];
+
+        nas = mkHost "nas" "x86_64-linux" [
+          (
+            { pkgs, ... }:
+            {
+              boot.supportedFilesystems = [ "zfs" ];
+              networking.hostId = "8425e349";
+              services.zfs.autoScrub.enable = true;
+              services.samba = {
+                enable = true;
+                openFirewall = true;
+                settings.media = {
+                  path = "/tank/media";
+                  "read only" = "no";
+                  "valid users" = "alice bob";
+                };
+              };
+              services.jellyfin = {
+                enable = true;
+                openFirewall = true;
+              };
+              services.restic.backups.offsite = {
+                paths = [
+                  "/tank/documents"
+                  "/tank/photos"
+                ];
+                repository = "s3:https://s3.example.com/backups";
+                passwordFile = "/run/secrets/restic";
+                timerConfig.OnCalendar = "daily";
+              };
+              environment.systemPackages = with pkgs; [
+                zfs
+                smartmontools
+                restic
+              ];
+            }
+          )
+        ];
+
+        pi = mkHost "pi" "aarch64-linux" [
+          {
+            services.home-assistant = {
+              enable = true;
+              extraComponents = [
+                "met"
+                "mqtt"
+                "zha"
+              ];
+              config.http.server_port = 8123;
+            };
+            services.mosquitto = {
+              enable = true;
+              listeners = [
+                {
+                  port = 1883;
+                  settings.allow_anonymous = false;
+                }
+              ];
+            };
+          }
+        ];
+      };
+
+      homeConfigurations = lib.mapAttrs (
+        name: user:
+        home-manager.lib.homeManagerConfiguration {
+          pkgs = nixpkgs.legacyPackages.x86_64-linux;
+          modules = [
+            {
+              home.username = name;
+              home.homeDirectory = "/home/${name}";
+              home.stateVersion = "24.11";
+              programs.git.enable = true;
+              programs.starship.enable = user.admin;
+            }
+          ];
+        }
+      ) users;
+
+      devShells.x86_64-linux.default =
+        let
+          pkgs = nixpkgs.legacyPackages.x86_64-linux;
+        in
+        pkgs.mkShell {
+          packages = with pkgs; [
+            sops
+            age
+            ssh-to-age
+            nixos-rebuild
+            disko.packages.x86_64-linux.default
+          ];
+        };
+    };
+}
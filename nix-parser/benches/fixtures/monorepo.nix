@@ -0,0 +1,291 @@
+{
+  description = "Services, tools and documentation of a polyglot monorepo";
+
+  nixConfig = {
+    extra-substituters = [ "https://cache.example.com" ];
+    extra-trusted-public-keys = [ "cache.example.com-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=" ];
+  };
+
+  inputs = {
+    nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.11";
+    nixpkgs-unstable.url = "github:NixOS/nixpkgs/nixos-unstable";
+    rust-overlay = {
+      url = "github:oxalica/rust-overlay";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    gomod2nix = {
+      url = "github:nix-community/gomod2nix";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    treefmt-nix = {
+      url = "github:numtide/treefmt-nix";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+  };
+
+  outputs =
+    {
+      self,
+      nixpkgs,
+      nixpkgs-unstable,
+      rust-overlay,
+      gomod2nix,
+      treefmt-nix,
+    }:
+    let
+      supportedSystems = [
+        "x86_64-linux"
+        "aarch64-linux"
+        "x86_64-darwin"
+        "aarch64-darwin"
+      ];
+      forEachSupportedSystem =
+        f:
+        nixpkgs.lib.genAttrs supportedSystems (
+          system:
+          f {
+            inherit system;
+            pkgs = import nixpkgs {
+              inherit system;
+              overlays = [
+                rust-overlay.overlays.default
+                gomod2nix.overlays.default
+                self.overlays.default
+              ];
+              config.allowUnfree = true;
+            };
+            unstable = import nixpkgs-unstable { inherit system; };
+          }
+        );
+      version = "2024.11.${toString self.lastModified}";
+      commonEnv = {
+        RUST_BACKTRACE = "1";
+        GOFLAGS = "-mod=mod";
+        NODE_OPTIONS = "--max-old-space-size=4096";
+        PYTHONDONTWRITEBYTECODE = "1";
+      };
+    in
+    {
+      overlays.default = final: prev: {
+        rustToolchain = prev.rust-bin.stable.latest.default.override {
+          extensions = [
+            "rust-src"
+            "rust-analyzer"
+            "clippy"
+          ];
+          targets = [ "wasm32-unknown-unknown" ];
+        };
+        nodejs = prev.nodejs_22;
+        python = prev.python312;
+        go = prev.go_1_23;
+        postgresql = prev.postgresql_16;
+      };
+
+      packages = forEachSupportedSystem (
+        { pkgs, system, ... }:
+        let
+          rustPlatform = pkgs.makeRustPlatform {
+            cargo = pkgs.rustToolchain;
+            rustc = pkgs.rustToolchain;
+          };
+        in
+        {
+          api = rustPlatform.buildRustPackage {
+            pname = "api";
+            inherit version;
+            src = ./services/api;
+            cargoLock.lockFile = ./services/api/Cargo.lock;
+            nativeBuildInputs = with pkgs; [
+              pkg-config
+              protobuf
+            ];
+            buildInputs =
+              with pkgs;
+              [
+                openssl
+                postgresql.lib
+              ]
+              ++ pkgs.lib.optionals pkgs.stdenv.isDarwin [
+                darwin.apple_sdk.frameworks.Security
+                darwin.apple_sdk.frameworks.SystemConfiguration
+              ];
+            doCheck = false;
+            meta.mainProgram = "api";
+          };
+
+          gateway = pkgs.buildGoApplication {
+            pname = "gateway";
+            inherit version;
+            src = ./services/gateway;
+            modules = ./services/gateway/gomod2nix.toml;
+            subPackages = [ "cmd/gateway" ];
+            ldflags = [
+              "-s"
+              "-w"
+              "-X main.version=${version}"
+            ];
+          };
+
+          web = pkgs.buildNpmPackage {
+            pname = "web";
+            inherit version;
+            src = ./apps/web;
+            npmDepsHash = "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+            nodejs = pkgs.nodejs;
+            installPhase = ''
+              runHook preInstall
+              mkdir -p $out/share/web
+              cp -r dist/* $out/share/web
+              runHook postInstall
+            '';
+          };
+
+          worker = pkgs.python.pkgs.buildPythonApplication {
+            pname = "worker";
+            inherit version;
+            pyproject = true;
+            src = ./services/worker;
+            build-system = with pkgs.python.pkgs; [ hatchling ];
+            dependencies = with pkgs.python.pkgs; [
+              celery
+              redis
+              sqlalchemy
+              psycopg
+              pydantic
+              httpx
+            ];
+          };
+
+          docs = pkgs.stdenvNoCC.mkDerivation {
+            pname = "docs";
+            inherit version;
+            src = ./docs;
+            nativeBuildInputs = with pkgs; [
+              mdbook
+              mdbook-mermaid
+              mdbook-linkcheck
+            ];
+            buildPhase = "mdbook build";
+            installPhase = "cp -r book $out";
+          };
+
+          default = self.packages.${system}.api;
+        }
+      );
+
+      devShells = forEachSupportedSystem (
+        { pkgs, unstable, ... }:
+        let
+          shared = with pkgs; [
+            git
+            just
+            jq
+            yq-go
+            pre-commit
+            self.formatter.${pkgs.system}
+          ];
+          mkShell =
+            name: packages: hook:
+            pkgs.mkShell {
+              inherit name;
+              packages = shared ++ packages;
+              env = commonEnv;
+              shellHook = ''
+                echo "${name} shell, ${version}"
+                ${hook}
+              '';
+            };
+        in
+        {
+          api = mkShell "api" (
+            with pkgs;
+            [
+              rustToolchain
+              cargo-nextest
+              cargo-deny
+              cargo-watch
+              sqlx-cli
+              protobuf
+              pkg-config
+              openssl
+            ]
+          ) "export DATABASE_URL=postgres://localhost/api";
+
+          gateway = mkShell "gateway" (
+            with pkgs;
+            [
+              go
+              gopls
+              gotools
+              golangci-lint
+              gomod2nix.packages.${pkgs.system}.default
+            ]
+          ) "export GOPATH=$PWD/.go";
+
+          web = mkShell "web" (
+            with pkgs;
+            [
+              nodejs
+              nodePackages.pnpm
+              nodePackages.typescript-language-server
+              playwright-driver.browsers
+            ]
+          ) "export PLAYWRIGHT_BROWSERS_PATH=${pkgs.playwright-driver.browsers}";
+
+          worker = mkShell "worker" (
+            with pkgs;
+            [
+              python
+              uv
+              ruff
+              pyright
+              redis
+            ]
+          ) "export UV_PYTHON=${pkgs.python}/bin/python";
+
+          docs = mkShell "docs" (
+            with pkgs;
+            [
+              mdbook
+              mdbook-mermaid
+              unstable.vale
+            ]
+          ) "";
+
+          default = pkgs.mkShell {
+            inputsFrom = [
+              self.devShells.${pkgs.system}.api
+              self.devShells.${pkgs.system}.gateway
+              self.devShells.${pkgs.system}.web
+              self.devShells.${pkgs.system}.worker
+            ];
+            packages = with pkgs; [
+              postgresql
+              docker-compose
+              kubectl
+              kubernetes-helm
+            ];
+            env = commonEnv // {
+              COMPOSE_PROJECT_NAME = "monorepo";
+            };
+          };
+        }
+      );
+
+      checks = forEachSupportedSystem (
+        { pkgs, system, ... }:
+        {
+          api = self.packages.${system}.api.overrideAttrs { doCheck = true; };
+          formatting = (treefmt-nix.lib.evalModule pkgs ./treefmt.nix).config.build.check self;
+          gateway-lint = pkgs.runCommand "gateway-lint" { nativeBuildInputs = [ pkgs.golangci-lint ]; } ''
+            cd ${./services/gateway}
+            golangci-lint run ./... > $out
+          '';
+        }
+      );
+
+      formatter = forEachSupportedSystem (
+        { pkgs, ... }: (treefmt-nix.lib.evalModule pkgs ./treefmt.nix).config.build.wrapper
+      );
+    };
+}
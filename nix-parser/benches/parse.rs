@@ -0,0 +1,65 @@
+//! Parser benchmarks: every embedded template, as `generator-core` parses
+//! them on each multi-language render, and larger flakes of the shapes found
+//! in real projects (`benches/fixtures`).
+//!
+//! Compare a change against the main branch with
+//! `cargo bench -p nix-parser -- --save-baseline main` on main and
+//! `cargo bench -p nix-parser -- --baseline main` on the change.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+
+fn nix_files(dir: &Path) -> Vec<(String, String)> {
+    let mut files: Vec<(String, String)> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "nix"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read_to_string(&path).unwrap())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn templates(c: &mut Criterion) {
+    let templates = nix_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src/templates"));
+    let bytes: usize = templates.iter().map(|(_, content)| content.len()).sum();
+
+    let mut group = c.benchmark_group("templates");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for (_, content) in &templates {
+                nix_parser::parse_nix_expr(black_box(content)).unwrap();
+            }
+        })
+    });
+    group.bench_function("extract_fragments", |b| {
+        b.iter(|| {
+            for (_, content) in &templates {
+                nix_parser::extract_flake_fragments(black_box(content)).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn fixtures(c: &mut Criterion) {
+    let fixtures = nix_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/fixtures"));
+
+    let mut group = c.benchmark_group("fixtures");
+    for (name, content) in &fixtures {
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_function(name.as_str(), |b| {
+            b.iter(|| nix_parser::parse_nix_expr(black_box(content)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, templates, fixtures);
+criterion_main!(benches);
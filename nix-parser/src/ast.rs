@@ -1,13 +1,35 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+/// Failure to turn source text into a usable expression. Positions are
+/// 1-based line and column numbers in the original input.
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum ParseError {
-    #[error("Parse error: {0}")]
-    Parse(String),
-    #[error("Invalid syntax: {0}")]
-    InvalidSyntax(String),
+    #[error("Parse error at line {line}, column {column}: {message}")]
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("Parse error at line {line}, column {column}: unexpected input '{snippet}'")]
+    TrailingInput {
+        line: usize,
+        column: usize,
+        /// Up to 100 characters of the input that could not be parsed
+        snippet: String,
+    },
+    #[error(transparent)]
+    Analysis(#[from] AnalysisError),
+}
+
+/// The input parsed, but does not have the shape expected of a flake.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum AnalysisError {
+    #[error("Expected attribute set for flake")]
+    NotAnAttrSet,
+    #[error("Invalid flake: {}", .problems.join("; "))]
+    InvalidFlake { problems: Vec<String> },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -97,10 +119,12 @@ pub enum NixExpr {
 impl NixExpr {
     pub fn to_nix_string(&self) -> String {
         match self {
-            NixExpr::String(s) => format!("\"{}\"", s.replace("\"", "\\\"")),
+            NixExpr::String(s) => format!("\"{}\"", quote_unescaped(s)),
             NixExpr::Path(p) => p.clone(),
             NixExpr::Uri(u) => u.clone(),
             NixExpr::Integer(i) => i.to_string(),
+            // Keep the point, so the number parses back as a float
+            NixExpr::Float(f) if f.fract() == 0.0 => format!("{f:.1}"),
             NixExpr::Float(f) => f.to_string(),
             NixExpr::Bool(b) => b.to_string(),
             NixExpr::Null => "null".to_string(),
@@ -117,14 +141,7 @@ impl NixExpr {
                             result.push_str(&format!("  inherit {attr_list};\n"));
                         }
                     } else {
-                        let path_str = binding.path.parts.iter()
-                            .map(|part| match part {
-                                AttrPathPart::Identifier(id) => id.clone(),
-                                AttrPathPart::String(s) => format!("\"{s}\""),
-                                AttrPathPart::Interpolation(expr) => format!("${{{}}}", expr.to_nix_string()),
-                            })
-                            .collect::<Vec<_>>()
-                            .join(".");
+                        let path_str = binding.path.to_nix_string();
                         result.push_str(&format!("  {} = {};\n", path_str, binding.value.to_nix_string()));
                     }
                 }
@@ -133,7 +150,7 @@ impl NixExpr {
             }
             NixExpr::List(items) => {
                 let items_str = items.iter()
-                    .map(|item| item.to_nix_string())
+                    .map(|item| item.to_nix_list_item())
                     .collect::<Vec<_>>()
                     .join(" ");
                 format!("[ {items_str} ]")
@@ -160,20 +177,20 @@ impl NixExpr {
                 format!("{}: {}", param_str, body.to_nix_string())
             }
             NixExpr::FunctionCall { function, argument } => {
-                format!("{} {}", function.to_nix_string(), argument.to_nix_string())
+                let function = match function.as_ref() {
+                    NixExpr::FunctionCall { .. } | NixExpr::Select { .. } => function.to_nix_string(),
+                    _ => function.to_nix_primary(),
+                };
+                format!("{} {}", function, argument.to_nix_primary())
             }
             NixExpr::Select { expr, path, default } => {
-                let path_str = path.parts.iter()
-                    .map(|part| match part {
-                        AttrPathPart::Identifier(id) => id.clone(),
-                        AttrPathPart::String(s) => format!("\"{s}\""),
-                        AttrPathPart::Interpolation(expr) => format!("${{{}}}", expr.to_nix_string()),
-                    })
-                    .collect::<Vec<_>>()
-                    .join(".");
-                let base = format!("{}.{}", expr.to_nix_string(), path_str);
+                let base = match expr.as_ref() {
+                    NixExpr::Select { default: None, .. } => expr.to_nix_string(),
+                    _ => expr.to_nix_primary(),
+                };
+                let base = format!("{}.{}", base, path.to_nix_string());
                 if let Some(def) = default {
-                    format!("{} or {}", base, def.to_nix_string())
+                    format!("{} or {}", base, def.to_nix_primary())
                 } else {
                     base
                 }
@@ -196,7 +213,17 @@ impl NixExpr {
                     BinaryOperator::Implication => "->",
                     BinaryOperator::Update => "//",
                 };
-                format!("{} {} {}", left.to_nix_string(), op_str, right.to_nix_string())
+                // Operators are parsed left to right, so only a right-hand
+                // operation needs parentheses
+                let left = match left.as_ref() {
+                    NixExpr::BinaryOp { .. } => left.to_nix_string(),
+                    _ => left.to_nix_operand(),
+                };
+                let right = match right.as_ref() {
+                    NixExpr::BinaryOp { .. } => format!("({})", right.to_nix_string()),
+                    _ => right.to_nix_operand(),
+                };
+                format!("{left} {op_str} {right}")
             }
             NixExpr::If { condition, then_expr, else_expr } => {
                 format!("if {} then {} else {}", 
@@ -216,15 +243,12 @@ impl NixExpr {
             NixExpr::LetIn { bindings, body } => {
                 let mut result = "let\n".to_string();
                 for binding in bindings {
-                    let path_str = binding.path.parts.iter()
-                        .map(|part| match part {
-                            AttrPathPart::Identifier(id) => id.clone(),
-                            AttrPathPart::String(s) => format!("\"{s}\""),
-                            AttrPathPart::Interpolation(expr) => format!("${{{}}}", expr.to_nix_string()),
-                        })
-                        .collect::<Vec<_>>()
-                        .join(".");
-                    result.push_str(&format!("  {} = {};\n", path_str, binding.value.to_nix_string()));
+                    if let NixExpr::Inherit { .. } = &binding.value {
+                        result.push_str(&format!("  {};\n", binding.value.to_nix_string()));
+                    } else {
+                        let path_str = binding.path.to_nix_string();
+                        result.push_str(&format!("  {} = {};\n", path_str, binding.value.to_nix_string()));
+                    }
                 }
                 result.push_str(&format!("in\n{}", body.to_nix_string()));
                 result
@@ -240,18 +264,164 @@ impl NixExpr {
                     format!("inherit {attr_list}")
                 }
             }
-            // Add other cases as needed - for now, fall back to debug for unhandled cases
-            _ => format!("(* unhandled: {self:?} *)"),
+            NixExpr::Assert { condition, body } => {
+                format!("assert {};\n{}", condition.to_nix_string(), body.to_nix_string())
+            }
+            NixExpr::UnaryOp { op, expr } => {
+                let op_str = match op {
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::Negate => "-",
+                };
+                let operand = match expr.as_ref() {
+                    NixExpr::FunctionCall { .. } | NixExpr::Select { .. } | NixExpr::HasAttr { .. } => {
+                        expr.to_nix_string()
+                    }
+                    _ => expr.to_nix_primary(),
+                };
+                format!("{op_str}{operand}")
+            }
+            NixExpr::HasAttr { expr, path } => {
+                format!("{} ? {}", expr.to_nix_primary(), path.to_nix_string())
+            }
+        }
+    }
+
+    /// A double-quoted string of `parts`, merging adjacent literals. Like
+    /// the parser, this gives a plain [`NixExpr::String`] when nothing is
+    /// interpolated.
+    pub fn interpolated(parts: impl IntoIterator<Item = StringPart>) -> NixExpr {
+        let mut merged: Vec<StringPart> = Vec::new();
+        for part in parts {
+            if let StringPart::Literal(text) = &part {
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(StringPart::Literal(previous)) = merged.last_mut() {
+                    previous.push_str(text);
+                    continue;
+                }
+            }
+            merged.push(part);
+        }
+
+        match merged.as_slice() {
+            [] => NixExpr::String(String::new()),
+            [StringPart::Literal(text)] => NixExpr::String(text.clone()),
+            _ => NixExpr::InterpolatedString(merged),
+        }
+    }
+
+    /// Whether the expression parses on its own as a function argument or
+    /// the subject of a selection, without parentheses.
+    pub fn is_primary(&self) -> bool {
+        matches!(
+            self,
+            NixExpr::String(_)
+                | NixExpr::Path(_)
+                | NixExpr::Uri(_)
+                | NixExpr::Integer(_)
+                | NixExpr::Float(_)
+                | NixExpr::Bool(_)
+                | NixExpr::Null
+                | NixExpr::Identifier(_)
+                | NixExpr::AttrSet { .. }
+                | NixExpr::List(_)
+                | NixExpr::InterpolatedString(_)
+        )
+    }
+
+    /// [`to_nix_string`](Self::to_nix_string), parenthesized unless the
+    /// expression is primary.
+    fn to_nix_primary(&self) -> String {
+        if self.is_primary() {
+            self.to_nix_string()
+        } else {
+            format!("({})", self.to_nix_string())
+        }
+    }
+
+    /// The expression as a list item: list items are primary or a selection
+    /// from a variable, anything else is parenthesized.
+    fn to_nix_list_item(&self) -> String {
+        let mut root = self;
+        while let NixExpr::Select { expr, default: None, .. } = root {
+            root = expr;
+        }
+        if matches!(root, NixExpr::Identifier(_)) {
+            self.to_nix_string()
+        } else {
+            self.to_nix_primary()
+        }
+    }
+
+    /// The expression as an operand of a binary operator: keywords such as
+    /// `if` and `let` would otherwise take in the rest of the operation.
+    fn to_nix_operand(&self) -> String {
+        match self {
+            NixExpr::Lambda { .. }
+            | NixExpr::LetIn { .. }
+            | NixExpr::With { .. }
+            | NixExpr::If { .. }
+            | NixExpr::Assert { .. } => format!("({})", self.to_nix_string()),
+            _ => self.to_nix_string(),
         }
     }
 }
 
+/// A piece of a double-quoted string. Literals hold the text as written
+/// in the source, escapes included.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum StringPart {
     Literal(String),
     Interpolation(Box<NixExpr>),
 }
 
+impl StringPart {
+    /// Literal text, escaped so that it reads back as exactly `text`.
+    pub fn text(text: &str) -> Self {
+        StringPart::Literal(escape_string(text))
+    }
+
+    pub fn interpolation(expr: NixExpr) -> Self {
+        StringPart::Interpolation(Box::new(expr))
+    }
+}
+
+/// Escape `text` for use inside a double-quoted Nix string: backslashes,
+/// quotes, `${` and control characters. A trailing `$` is escaped as well,
+/// since an interpolation may follow it.
+pub fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '$' if matches!(chars.peek(), Some('{') | None) => escaped.push_str("\\$"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape the quotes of `text` that are not escaped already, for strings
+/// that were written between `''` and are printed between `"`.
+fn quote_unescaped(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len());
+    let mut escaping = false;
+    for c in text.chars() {
+        if c == '"' && !escaping {
+            quoted.push('\\');
+        }
+        escaping = c == '\\' && !escaping;
+        quoted.push(c);
+    }
+    quoted
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LambdaParam {
     Identifier(String),
@@ -278,6 +448,20 @@ pub struct AttrPath {
     pub parts: Vec<AttrPathPart>,
 }
 
+impl AttrPath {
+    pub fn to_nix_string(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                AttrPathPart::Identifier(id) => id.clone(),
+                AttrPathPart::String(s) => format!("\"{s}\""),
+                AttrPathPart::Interpolation(expr) => format!("${{{}}}", expr.to_nix_string()),
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AttrPathPart {
     Identifier(String),
@@ -319,6 +503,107 @@ pub struct FlakeFragments {
     pub packages: Vec<String>,
     pub env_vars: HashMap<String, String>,
     pub shell_hooks: Vec<String>,
-    pub allow_unfree: bool,
+    /// The `config` nixpkgs is imported with
+    #[serde(default)]
+    pub nixpkgs_config: NixpkgsConfig,
     pub let_bindings: HashMap<String, String>,
+    /// Source comments keyed by the attribute path or list item they belong to.
+    #[serde(default)]
+    pub comments: HashMap<String, Comments>,
+}
+
+/// The `config` argument nixpkgs is imported with, as in
+/// `import nixpkgs { config.allowUnfree = true; }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NixpkgsConfig {
+    /// `allowUnfree`
+    pub allow_unfree: bool,
+    /// `allowBroken`
+    pub allow_broken: bool,
+    /// `cudaSupport`
+    pub cuda_support: bool,
+    /// `permittedInsecurePackages`, such as `openssl-1.1.1w`
+    pub permitted_insecure: Vec<String>,
+    /// Any other option, keyed by its path below `config`
+    pub extra: BTreeMap<String, NixExpr>,
+}
+
+impl NixpkgsConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Record the option at `path` below `config`. Known options with a
+    /// value of another type than expected are kept in `extra`.
+    pub fn set(&mut self, path: &str, value: &NixExpr) {
+        match (path, value) {
+            ("allowUnfree", NixExpr::Bool(enabled)) => self.allow_unfree = *enabled,
+            ("allowBroken", NixExpr::Bool(enabled)) => self.allow_broken = *enabled,
+            ("cudaSupport", NixExpr::Bool(enabled)) => self.cuda_support = *enabled,
+            ("permittedInsecurePackages", NixExpr::List(items))
+                if items.iter().all(|item| matches!(item, NixExpr::String(_))) =>
+            {
+                self.permitted_insecure = items
+                    .iter()
+                    .filter_map(|item| match item {
+                        NixExpr::String(name) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            _ => {
+                self.extra.insert(path.to_string(), value.clone());
+            }
+        }
+    }
+
+    /// Combine with the config of another flake: an option either enables
+    /// is enabled, insecure packages permitted by either are permitted, and
+    /// `other`'s extra options replace those set here.
+    pub fn merge(&mut self, other: NixpkgsConfig) {
+        self.allow_unfree |= other.allow_unfree;
+        self.allow_broken |= other.allow_broken;
+        self.cuda_support |= other.cuda_support;
+        for package in other.permitted_insecure {
+            if !self.permitted_insecure.contains(&package) {
+                self.permitted_insecure.push(package);
+            }
+        }
+        self.extra.extend(other.extra);
+    }
+
+    /// One `config.<option> = <value>;` binding per option that is set.
+    pub fn to_nix_bindings(&self) -> Vec<String> {
+        let mut bindings = Vec::new();
+        for (enabled, option) in [
+            (self.allow_unfree, "allowUnfree"),
+            (self.allow_broken, "allowBroken"),
+            (self.cuda_support, "cudaSupport"),
+        ] {
+            if enabled {
+                bindings.push(format!("config.{option} = true;"));
+            }
+        }
+        if !self.permitted_insecure.is_empty() {
+            let packages = NixExpr::List(
+                self.permitted_insecure.iter().cloned().map(NixExpr::String).collect(),
+            );
+            bindings.push(format!(
+                "config.permittedInsecurePackages = {};",
+                packages.to_nix_string()
+            ));
+        }
+        for (path, value) in &self.extra {
+            bindings.push(format!("config.{path} = {};", value.to_nix_string()));
+        }
+        bindings
+    }
+}
+
+/// Comments on the lines above a binding and at the end of its line, without
+/// the leading `#`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Comments {
+    pub leading: Vec<String>,
+    pub trailing: Option<String>,
 }
\ No newline at end of file
@@ -35,7 +35,65 @@ pub fn extract_flake_data(expr: &NixExpr) -> Result<FlakeData, ParseError> {
             
             Ok(flake)
         }
-        _ => Err(ParseError::InvalidSyntax("Expected attribute set for flake".to_string())),
+        _ => Err(AnalysisError::NotAnAttrSet.into()),
+    }
+}
+
+/// Check that an expression has the shape nix expects of a flake: an
+/// attribute set with an optional string description, an attribute set of
+/// inputs, an optional nixConfig and an outputs function.
+pub fn validate_flake_schema(expr: &NixExpr) -> Result<(), ParseError> {
+    let NixExpr::AttrSet { bindings, .. } = expr else {
+        return Err(AnalysisError::InvalidFlake {
+            problems: vec!["a flake must be an attribute set".to_string()],
+        }
+        .into());
+    };
+
+    let mut problems = Vec::new();
+    let mut has_outputs = false;
+
+    for binding in bindings {
+        let Some(AttrPathPart::Identifier(name)) = binding.path.parts.first() else {
+            problems.push("top-level attribute names must be plain identifiers".to_string());
+            continue;
+        };
+        let is_whole = binding.path.parts.len() == 1;
+
+        match name.as_str() {
+            "description" => {
+                if !is_whole || !matches!(binding.value, NixExpr::String(_)) {
+                    problems.push("description must be a string".to_string());
+                }
+            }
+            "inputs" => {
+                if is_whole && !matches!(binding.value, NixExpr::AttrSet { .. }) {
+                    problems.push("inputs must be an attribute set".to_string());
+                }
+            }
+            "nixConfig" => {
+                if is_whole && !matches!(binding.value, NixExpr::AttrSet { .. }) {
+                    problems.push("nixConfig must be an attribute set".to_string());
+                }
+            }
+            "outputs" => {
+                has_outputs = true;
+                if !is_whole || !matches!(binding.value, NixExpr::Lambda { .. }) {
+                    problems.push("outputs must be a function".to_string());
+                }
+            }
+            other => problems.push(format!("unexpected top-level attribute '{other}'")),
+        }
+    }
+
+    if !has_outputs {
+        problems.push("missing outputs".to_string());
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AnalysisError::InvalidFlake { problems }.into())
     }
 }
 
@@ -47,8 +105,9 @@ pub fn extract_fragments_from_expr(expr: &NixExpr) -> Result<FlakeFragments, Par
         packages: Vec::new(),
         env_vars: HashMap::new(),
         shell_hooks: Vec::new(),
-        allow_unfree: false,
+        nixpkgs_config: NixpkgsConfig::default(),
         let_bindings: HashMap::new(),
+        comments: HashMap::new(),
     };
     
     if let NixExpr::AttrSet { bindings, .. } = expr {
@@ -69,11 +128,11 @@ pub fn extract_fragments_from_expr(expr: &NixExpr) -> Result<FlakeFragments, Par
                     _ => {}
                 },
                 // Handle multi-part paths like "inputs.nixpkgs.url"
-                [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second), AttrPathPart::Identifier(third)] => {
-                    if first == "inputs" && third == "url" {
-                        if let NixExpr::String(url) = &binding.value {
-                            fragments.inputs.insert(second.clone(), url.clone());
-                        }
+                [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second), AttrPathPart::Identifier(third)]
+                    if first == "inputs" && third == "url" =>
+                {
+                    if let NixExpr::String(url) = &binding.value {
+                        fragments.inputs.insert(second.clone(), url.clone());
                     }
                 },
                 _ => {}
@@ -88,20 +147,17 @@ fn extract_inputs_from_expr(expr: &NixExpr, inputs: &mut HashMap<String, String>
     if let NixExpr::AttrSet { bindings, .. } = expr {
         for binding in bindings {
             if let [AttrPathPart::Identifier(input_name)] = &binding.path.parts[..] {
-                match &binding.value {
-                    // Simple format: nixpkgs.url = "...";
-                    NixExpr::AttrSet { bindings, .. } => {
-                        for url_binding in bindings {
-                            if let [AttrPathPart::Identifier(attr)] = &url_binding.path.parts[..] {
-                                if attr == "url" {
-                                    if let NixExpr::String(url) = &url_binding.value {
-                                        inputs.insert(input_name.clone(), url.clone());
-                                    }
+                // Simple format: nixpkgs.url = "...";
+                if let NixExpr::AttrSet { bindings, .. } = &binding.value {
+                    for url_binding in bindings {
+                        if let [AttrPathPart::Identifier(attr)] = &url_binding.path.parts[..] {
+                            if attr == "url" {
+                                if let NixExpr::String(url) = &url_binding.value {
+                                    inputs.insert(input_name.clone(), url.clone());
                                 }
                             }
                         }
                     }
-                    _ => {}
                 }
             } else if binding.path.parts.len() == 2 {
                 // Handle nixpkgs.url format
@@ -123,53 +179,58 @@ fn extract_outputs_from_expr(expr: &NixExpr, fragments: &mut FlakeFragments) {
         extract_outputs_body(body, fragments);
     }
     
-    // Also search for config.allowUnfree patterns anywhere in the expression
-    if detect_allow_unfree(expr) {
-        fragments.allow_unfree = true;
-    }
+    // Also search for the nixpkgs config, usually set where nixpkgs is imported
+    collect_nixpkgs_config(expr, &mut fragments.nixpkgs_config);
 }
 
-fn detect_allow_unfree(expr: &NixExpr) -> bool {
+fn collect_nixpkgs_config(expr: &NixExpr, config: &mut NixpkgsConfig) {
     match expr {
         NixExpr::AttrSet { bindings, .. } => {
             for binding in bindings {
-                // Check for config.allowUnfree = true pattern
-                if let [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second)] = &binding.path.parts[..] {
-                    if first == "config" && second == "allowUnfree" {
-                        if let NixExpr::Bool(true) = &binding.value {
-                            return true;
+                match &binding.path.parts[..] {
+                    // config = { allowUnfree = true; ... }
+                    [AttrPathPart::Identifier(first)] if first == "config" => {
+                        if let NixExpr::AttrSet { bindings: options, .. } = &binding.value {
+                            for option in options {
+                                config.set(&option.path.to_nix_string(), &option.value);
+                            }
                         }
                     }
-                }
-                // Recursively check the binding value
-                if detect_allow_unfree(&binding.value) {
-                    return true;
+                    // config.allowUnfree = true
+                    [AttrPathPart::Identifier(first), rest @ ..] if first == "config" => {
+                        let path = AttrPath { parts: rest.to_vec() };
+                        config.set(&path.to_nix_string(), &binding.value);
+                    }
+                    _ => collect_nixpkgs_config(&binding.value, config),
                 }
             }
-            false
         }
-        NixExpr::Lambda { body, .. } => detect_allow_unfree(body),
+        NixExpr::Lambda { body, .. } => collect_nixpkgs_config(body, config),
         NixExpr::LetIn { bindings, body } => {
             for binding in bindings {
-                if detect_allow_unfree(&binding.value) {
-                    return true;
-                }
+                collect_nixpkgs_config(&binding.value, config);
             }
-            detect_allow_unfree(body)
+            collect_nixpkgs_config(body, config);
         }
         NixExpr::FunctionCall { function, argument } => {
-            detect_allow_unfree(function) || detect_allow_unfree(argument)
+            collect_nixpkgs_config(function, config);
+            collect_nixpkgs_config(argument, config);
         }
         NixExpr::List(items) => {
-            items.iter().any(detect_allow_unfree)
+            for item in items {
+                collect_nixpkgs_config(item, config);
+            }
         }
         NixExpr::With { env, body } => {
-            detect_allow_unfree(env) || detect_allow_unfree(body)
+            collect_nixpkgs_config(env, config);
+            collect_nixpkgs_config(body, config);
         }
         NixExpr::If { condition, then_expr, else_expr } => {
-            detect_allow_unfree(condition) || detect_allow_unfree(then_expr) || detect_allow_unfree(else_expr)
+            collect_nixpkgs_config(condition, config);
+            collect_nixpkgs_config(then_expr, config);
+            collect_nixpkgs_config(else_expr, config);
         }
-        _ => false,
+        _ => {}
     }
 }
 
@@ -197,21 +258,23 @@ fn extract_outputs_body(expr: &NixExpr, fragments: &mut FlakeFragments) {
                         _ => {}
                     },
                     // Handle nested paths like "overlays.default" and "inputs.nixpkgs.url"
-                    [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second)] => {
-                        if first == "overlays" {
-                            // Extract the overlay body bindings (inside the lambda)
-                            let overlay_bindings = extract_overlay_bindings(&binding.value);
-                            fragments.overlays.insert(second.clone(), overlay_bindings);
-                        }
+                    [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second)] if first == "overlays" => {
+                        // Extract the overlay body bindings (inside the lambda)
+                        let overlay_bindings = extract_overlay_bindings(&binding.value);
+                        fragments.overlays.insert(second.clone(), overlay_bindings);
                     },
                     // Handle inputs.nixpkgs.url format
-                    [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second), AttrPathPart::Identifier(third)] => {
-                        if first == "inputs" && third == "url" {
-                            if let NixExpr::String(url) = &binding.value {
-                                fragments.inputs.insert(second.clone(), url.clone());
-                            }
+                    [AttrPathPart::Identifier(first), AttrPathPart::Identifier(second), AttrPathPart::Identifier(third)]
+                        if first == "inputs" && third == "url" =>
+                    {
+                        if let NixExpr::String(url) = &binding.value {
+                            fragments.inputs.insert(second.clone(), url.clone());
                         }
                     },
+                    // Handle devShells.x86_64-linux.default format
+                    [AttrPathPart::Identifier(first), ..] if first == "devShells" => {
+                        extract_devshells_from_expr(&binding.value, fragments);
+                    },
                     _ => {}
                 }
             }
@@ -336,8 +399,13 @@ fn extract_packages_from_value(expr: &NixExpr, packages: &mut Vec<String>) {
         }
         NixExpr::List(items) => {
             for item in items {
-                if let NixExpr::Identifier(name) = item {
-                    packages.push(name.clone());
+                // Attributes of package sets like "python311Packages.pip" count too
+                let mut root = item;
+                while let NixExpr::Select { expr, default: None, .. } = root {
+                    root = expr;
+                }
+                if let NixExpr::Identifier(_) = root {
+                    packages.push(item.to_nix_string());
                 }
             }
         }
@@ -357,23 +425,37 @@ fn extract_packages_from_value(expr: &NixExpr, packages: &mut Vec<String>) {
 
 
 fn find_env_in_expr(expr: &NixExpr, env_vars: &mut HashMap<String, String>) {
-    if let NixExpr::AttrSet { bindings, .. } = expr {
-        for binding in bindings {
-            if let [AttrPathPart::Identifier(name)] = &binding.path.parts[..] {
-                if name == "env" {
-                    if let NixExpr::AttrSet { bindings, .. } = &binding.value {
-                        for env_binding in bindings {
-                            if let [AttrPathPart::Identifier(env_name)] = &env_binding.path.parts[..] {
-                                if let NixExpr::String(env_value) = &env_binding.value {
-                                    env_vars.insert(env_name.clone(), env_value.clone());
+    match expr {
+        NixExpr::AttrSet { bindings, .. } => {
+            for binding in bindings {
+                if let [AttrPathPart::Identifier(name)] = &binding.path.parts[..] {
+                    if name == "env" {
+                        if let NixExpr::AttrSet { bindings, .. } = &binding.value {
+                            for env_binding in bindings {
+                                if let [AttrPathPart::Identifier(env_name)] = &env_binding.path.parts[..] {
+                                    // Kept as written, quotes and interpolations included
+                                    env_vars.insert(env_name.clone(), env_binding.value.to_nix_string());
                                 }
                             }
                         }
                     }
                 }
+                find_env_in_expr(&binding.value, env_vars);
             }
-            find_env_in_expr(&binding.value, env_vars);
         }
+        NixExpr::Lambda { body, .. } => {
+            find_env_in_expr(body, env_vars);
+        }
+        NixExpr::FunctionCall { argument, .. } => {
+            find_env_in_expr(argument, env_vars);
+        }
+        NixExpr::LetIn { body, .. } => {
+            find_env_in_expr(body, env_vars);
+        }
+        NixExpr::With { body, .. } => {
+            find_env_in_expr(body, env_vars);
+        }
+        _ => {}
     }
 }
 
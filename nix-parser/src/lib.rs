@@ -1,37 +1,124 @@
 mod ast;
 mod parser;
 mod flake_analysis;
+mod trivia;
+mod roundtrip;
+pub mod lint;
 
 pub use ast::*;
-use parser::nix_expr;
-use flake_analysis::{extract_flake_data, extract_fragments_from_expr};
+pub use roundtrip::{Mismatch, RoundtripError, verify_roundtrip};
+pub use trivia::comments;
+use nom::Offset;
+use nom::IResult;
+use parser::{binding, list_items, nix_expr, nix_lambda};
+use flake_analysis::{extract_flake_data, extract_fragments_from_expr, validate_flake_schema};
 
 
 
 // Main parsing functions
 pub fn parse_nix_expr(input: &str) -> Result<NixExpr, ParseError> {
-    match nix_expr(input.trim()) {
+    parse_all(input, input.trim(), nix_expr)
+}
+
+/// Parse like [`parse_nix_expr`], also returning where each list item starts
+/// and ends in `input`, in source order.
+pub(crate) fn parse_nix_expr_with_list_items(
+    input: &str,
+) -> Result<(NixExpr, Vec<(usize, usize)>), ParseError> {
+    // Spans are measured from where the parsed text ends
+    let (expr, items) = parser::record_list_items(input.trim_end(), || parse_nix_expr(input));
+    Ok((expr?, items))
+}
+
+/// Parse a single binding such as `packages = [ pkgs.jq ];` or
+/// `inherit (pkgs) hello;`, as typed by a user. The trailing semicolon is
+/// optional.
+pub fn parse_binding(input: &str) -> Result<Binding, ParseError> {
+    let trimmed = input.trim();
+    parse_all(input, trimmed.strip_suffix(';').unwrap_or(trimmed), binding)
+}
+
+/// Parse a list of packages, either bracketed like `[ pkgs.jq ripgrep ]`
+/// or as bare items like `pkgs.jq ripgrep`, with the same rules as items
+/// inside a list: function calls have to be parenthesized.
+pub fn parse_list_items(input: &str) -> Result<Vec<NixExpr>, ParseError> {
+    if input.trim_start().starts_with('[') {
+        match parse_nix_expr(input)? {
+            NixExpr::List(items) => Ok(items),
+            _ => Err(syntax_error(input, input.trim_start(), "expected a list")),
+        }
+    } else {
+        parse_all(input, input.trim(), list_items)
+    }
+}
+
+/// Parse a function such as `final: prev: { ... }` or `{ pkgs, ... }: pkgs.hello`.
+pub fn parse_lambda(input: &str) -> Result<NixExpr, ParseError> {
+    parse_all(input, input.trim(), nix_lambda)
+}
+
+/// Run `parser` on `fragment`, a subslice of `input`, requiring it to
+/// consume everything but trailing whitespace.
+fn parse_all<'a, O>(
+    input: &'a str,
+    fragment: &'a str,
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> Result<O, ParseError> {
+    match parser(fragment) {
         Ok((remaining, expr)) => {
             let remaining_trimmed = remaining.trim();
             if remaining_trimmed.is_empty() {
                 Ok(expr)
             } else {
-                Err(ParseError::Parse(format!("Unexpected remaining input: '{}' (first 100 chars)", 
-                    &remaining_trimmed[..remaining_trimmed.len().min(100)])))
+                let (line, column) = position(input, remaining_trimmed);
+                Err(ParseError::TrailingInput {
+                    line,
+                    column,
+                    snippet: remaining_trimmed.chars().take(100).collect(),
+                })
             }
         }
-        Err(e) => Err(ParseError::Parse(format!("Parsing Error: {e}"))),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(syntax_error(input, e.input, &format!("{:?}", e.code)))
+        }
+        Err(nom::Err::Incomplete(_)) => {
+            Err(syntax_error(input, &input[input.len()..], "unexpected end of input"))
+        }
     }
 }
 
+fn syntax_error(input: &str, rest: &str, message: &str) -> ParseError {
+    let (line, column) = position(input, rest);
+    ParseError::Syntax { line, column, message: message.to_string() }
+}
+
+/// 1-based line and column of `rest`, a subslice of `input`.
+fn position(input: &str, rest: &str) -> (usize, usize) {
+    let consumed = &input[..input.offset(rest)];
+    let line = consumed.matches('\n').count() + 1;
+    let column = consumed.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
 pub fn parse_flake(input: &str) -> Result<FlakeData, ParseError> {
     let expr = parse_nix_expr(input)?;
     extract_flake_data(&expr)
 }
 
+/// Extract the mergeable parts of a flake, along with the comments attached
+/// to its bindings and packages.
 pub fn extract_flake_fragments(input: &str) -> Result<FlakeFragments, ParseError> {
     let expr = parse_nix_expr(input)?;
-    extract_fragments_from_expr(&expr)
+    let mut fragments = extract_fragments_from_expr(&expr)?;
+    fragments.comments = trivia::collect_comments(input);
+    Ok(fragments)
+}
+
+/// Parse a flake and check it against the flake schema.
+pub fn validate_flake(input: &str) -> Result<FlakeData, ParseError> {
+    let expr = parse_nix_expr(input)?;
+    validate_flake_schema(&expr)?;
+    extract_flake_data(&expr)
 }
 
 
@@ -161,6 +248,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_if_with_application_condition() {
+        let input = r#"if builtins.pathExists ./a then rust.x else rust.y"#;
+        let result = parse_nix_expr(input).unwrap();
+
+        match result {
+            NixExpr::If { condition, .. } => {
+                assert!(matches!(*condition, NixExpr::FunctionCall { .. }));
+            }
+            _ => panic!("Expected If"),
+        }
+        assert_eq!(parse_nix_expr(input).unwrap().to_nix_string(), input);
+    }
+
+    #[test]
+    fn test_parse_inherit_from_several_names() {
+        let input = r#"{ inherit (texlive) scheme-small biber; }"#;
+        let result = parse_nix_expr(input).unwrap();
+
+        match result {
+            NixExpr::AttrSet { bindings, .. } => match &bindings[0].value {
+                NixExpr::Inherit { from, attrs } => {
+                    assert!(from.is_some());
+                    assert_eq!(attrs, &vec!["scheme-small".to_string(), "biber".to_string()]);
+                }
+                _ => panic!("Expected Inherit"),
+            },
+            _ => panic!("Expected AttrSet"),
+        }
+    }
+
     #[test]
     fn test_extract_flake_fragments_rust() {
         let input = include_str!("templates/rust.nix");
@@ -203,8 +321,7 @@ mod tests {
             }
             Err(e) => {
                 eprintln!("Failed to parse go.nix template: {e:#?}");
-                // For now, let's not panic so we can see what's happening
-                assert!(false, "Failed to parse go.nix template");
+                panic!("Failed to parse go.nix template");
             }
         }
     }
@@ -340,7 +457,10 @@ mod tests {
         
         assert!(result.inputs.contains_key("nixpkgs"));
         assert!(!result.packages.is_empty());
-        assert!(result.allow_unfree, "Hashi template should set allow_unfree = true");
+        assert!(
+            result.nixpkgs_config.allow_unfree,
+            "Hashi template should set allow_unfree = true"
+        );
     }
 
     #[test]
@@ -1111,6 +1231,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_validate_flake_templates() {
+        for input in [
+            include_str!("templates/rust.nix"),
+            include_str!("templates/python.nix"),
+            include_str!("templates/go.nix"),
+        ] {
+            assert!(validate_flake(input).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_validate_flake_rejects_invalid_schema() {
+        let missing_outputs = r#"{ description = "x"; inputs = { }; }"#;
+        assert!(matches!(
+            validate_flake(missing_outputs),
+            Err(ParseError::Analysis(AnalysisError::InvalidFlake { problems }))
+                if problems.iter().any(|p| p == "missing outputs")
+        ));
+
+        let unknown_attr = r#"{ outputs = { self }: { }; packages = { }; }"#;
+        assert!(matches!(
+            validate_flake(unknown_attr),
+            Err(ParseError::Analysis(AnalysisError::InvalidFlake { problems }))
+                if problems.iter().any(|p| p.contains("'packages'"))
+        ));
+
+        let non_function_outputs = r#"{ outputs = { }; }"#;
+        assert!(validate_flake(non_function_outputs).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_positions() {
+        match parse_nix_expr("{\n  a = 1;\n} }") {
+            Err(ParseError::TrailingInput { line, column, snippet }) => {
+                assert_eq!((line, column), (3, 3));
+                assert_eq!(snippet, "}");
+            }
+            other => panic!("expected TrailingInput, got {other:?}"),
+        }
+
+        assert!(matches!(
+            parse_nix_expr(";"),
+            Err(ParseError::Syntax { line: 1, column: 1, .. })
+        ));
+    }
+
     #[test]
     fn test_binary_operator_parsing() {
         let input = r#"a ++ b"#;
@@ -1160,4 +1327,175 @@ mod tests {
             _ => panic!("Expected Select expression"),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_comments_in_order() {
+        let input = "# top\n{\n  a = \"# not one\"; # trailing\n  /* block # */ b = 1;\n  c = ''\n    # nor this\n  '';\n}\n";
+        assert_eq!(comments(input), vec!["top", "trailing"]);
+    }
+
+    #[test]
+    fn test_fragments_keep_comments() {
+        let input = r#"{
+  outputs = { self, nixpkgs }:
+    let
+      goVersion = 24; # Change this to update the whole stack
+    in
+    {
+      devShells.default = pkgs.mkShell {
+        packages = [
+          # Linters
+          golangci-lint
+        ];
+        shellHook = ''
+          # not a nix comment
+          echo hi
+        '';
+        env = {
+          # Required by rust-analyzer
+          RUST_SRC_PATH = "/src"; # keep in sync
+        };
+      };
+    };
+}"#;
+        let fragments = extract_flake_fragments(input).unwrap();
+        let comments = &fragments.comments;
+
+        assert_eq!(
+            comments["goVersion"].trailing.as_deref(),
+            Some("Change this to update the whole stack")
+        );
+        assert_eq!(comments["golangci-lint"].leading, vec!["Linters".to_string()]);
+        assert_eq!(comments["RUST_SRC_PATH"].leading, vec!["Required by rust-analyzer".to_string()]);
+        assert_eq!(comments["RUST_SRC_PATH"].trailing.as_deref(), Some("keep in sync"));
+        assert_eq!(comments.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_binding_fragment() {
+        let parsed = parse_binding("env.GOFLAGS = \"-mod=mod\";").unwrap();
+        assert_eq!(parsed.path.to_nix_string(), "env.GOFLAGS");
+        assert_eq!(parsed.value, NixExpr::String("-mod=mod".to_string()));
+
+        let inherit = parse_binding("inherit (pkgs) hello").unwrap();
+        assert!(matches!(inherit.value, NixExpr::Inherit { .. }));
+
+        assert!(matches!(
+            parse_binding("a = 1; b = 2;"),
+            Err(ParseError::TrailingInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_list_items_fragment() {
+        let items = "pkgs.jq ripgrep (python3.withPackages (ps: [ ps.numpy ]))";
+        let bare = parse_list_items(items).unwrap();
+        let bracketed = parse_list_items(&format!(" [ {items} ]")).unwrap();
+        assert_eq!(bare, bracketed);
+        assert_eq!(bare.len(), 3);
+        assert!(matches!(bare[2], NixExpr::FunctionCall { .. }));
+
+        assert!(parse_list_items("").unwrap().is_empty());
+        assert!(parse_list_items("[ a ] ++ b").is_err());
+    }
+
+    #[test]
+    fn test_parse_lambda_fragment() {
+        let overlay = parse_lambda("final: prev: { hello = prev.hello; }").unwrap();
+        match overlay {
+            NixExpr::Lambda { param: LambdaParam::Identifier(name), body } => {
+                assert_eq!(name, "final");
+                assert!(matches!(*body, NixExpr::Lambda { .. }));
+            }
+            other => panic!("Expected Lambda, got {other:?}"),
+        }
+
+        assert!(parse_lambda("{ pkgs, ... }: pkgs.hello").is_ok());
+        assert!(parse_lambda("pkgs.hello").is_err());
+    }
+
+    #[test]
+    fn test_interpolated_string_escapes_text() {
+        let hook = NixExpr::interpolated([
+            StringPart::text("echo \"cost: $"),
+            StringPart::interpolation(NixExpr::Identifier("price".to_string())),
+            StringPart::text(""),
+            StringPart::text("\" ${HOME}\\\n"),
+        ]);
+        let printed = hook.to_nix_string();
+        assert_eq!(printed, r#""echo \"cost: \$${price}\" \${HOME}\\\n""#);
+        assert_eq!(parse_nix_expr(&printed).unwrap(), hook);
+
+        match hook {
+            NixExpr::InterpolatedString(parts) => assert_eq!(parts.len(), 3),
+            other => panic!("Expected InterpolatedString, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_interpolated_string_without_interpolation_is_plain() {
+        let plain = NixExpr::interpolated([StringPart::text("a "), StringPart::text("\"b\"")]);
+        assert_eq!(plain, NixExpr::String(r#"a \"b\""#.to_string()));
+        assert_eq!(parse_nix_expr(&plain.to_nix_string()).unwrap(), plain);
+        assert_eq!(NixExpr::interpolated([]), NixExpr::String(String::new()));
+        assert_eq!(escape_string("100$"), r"100\$");
+        assert_eq!(escape_string("$HOME"), "$HOME");
+    }
+
+    #[test]
+    fn test_extract_nixpkgs_config() {
+        let input = r#"{
+  outputs = { nixpkgs, ... }:
+    let
+      pkgs = import nixpkgs {
+        system = "x86_64-linux";
+        config = {
+          allowUnfree = true;
+          permittedInsecurePackages = [ "openssl-1.1.1w" ];
+          android_sdk.accept_license = true;
+        };
+        config.cudaSupport = true;
+      };
+    in {
+      devShells.x86_64-linux.default = pkgs.mkShell { packages = [ pkgs.jq ]; };
+    };
+}"#;
+        let config = extract_flake_fragments(input).unwrap().nixpkgs_config;
+        assert!(config.allow_unfree);
+        assert!(config.cuda_support);
+        assert!(!config.allow_broken);
+        assert_eq!(config.permitted_insecure, vec!["openssl-1.1.1w".to_string()]);
+        assert_eq!(config.extra["android_sdk.accept_license"], NixExpr::Bool(true));
+        assert_eq!(
+            config.to_nix_bindings(),
+            vec![
+                "config.allowUnfree = true;",
+                "config.cudaSupport = true;",
+                "config.permittedInsecurePackages = [ \"openssl-1.1.1w\" ];",
+                "config.android_sdk.accept_license = true;",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_nixpkgs_config() {
+        let mut config = NixpkgsConfig {
+            allow_unfree: true,
+            permitted_insecure: vec!["openssl-1.1.1w".to_string()],
+            ..Default::default()
+        };
+        let mut other = NixpkgsConfig {
+            allow_broken: true,
+            permitted_insecure: vec!["python-2.7.18".to_string(), "openssl-1.1.1w".to_string()],
+            ..Default::default()
+        };
+        other.set("rocmSupport", &NixExpr::Bool(true));
+        config.merge(other);
+
+        assert!(config.allow_unfree && config.allow_broken && !config.cuda_support);
+        assert_eq!(config.permitted_insecure, vec!["openssl-1.1.1w", "python-2.7.18"]);
+        assert_eq!(config.extra.len(), 1);
+        assert!(!config.is_empty());
+        assert!(NixpkgsConfig::default().is_empty());
+    }
+}
@@ -0,0 +1,779 @@
+//! Scope analysis of nix source: bindings nothing uses, variables nothing
+//! binds, and attributes or list entries given twice.
+//!
+//! The AST carries no source positions, so bindings are placed by looking
+//! for them in the text, and list entries by where the parser recorded the
+//! list items. A finding that can't be placed unambiguously has no line and
+//! no fix; an entry sharing its lines with other code has a line but no fix.
+
+use crate::ast::*;
+use crate::{ParseError, parse_nix_expr_with_list_items};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    UnusedBinding,
+    DuplicateEntry,
+    DuplicateAttribute,
+    UndefinedVariable,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 4] = [
+        Rule::UnusedBinding,
+        Rule::DuplicateEntry,
+        Rule::DuplicateAttribute,
+        Rule::UndefinedVariable,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedBinding => "unused-binding",
+            Rule::DuplicateEntry => "duplicate-entry",
+            Rule::DuplicateAttribute => "duplicate-attribute",
+            Rule::UndefinedVariable => "undefined-variable",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::UnusedBinding => "A let binding that nothing refers to",
+            Rule::DuplicateEntry => "A list entry that is already in the list",
+            Rule::DuplicateAttribute => "An attribute defined twice, which nix rejects",
+            Rule::UndefinedVariable => "A variable that no enclosing scope binds",
+        }
+    }
+
+    /// Whether findings of the rule can have a fix
+    pub fn fixable(self) -> bool {
+        matches!(self, Rule::UnusedBinding | Rule::DuplicateEntry)
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Rule::UnusedBinding | Rule::DuplicateEntry => Severity::Warning,
+            Rule::DuplicateAttribute | Rule::UndefinedVariable => Severity::Error,
+        }
+    }
+}
+
+/// A problem found in nix source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based line, when the finding could be placed in the source
+    pub line: Option<usize>,
+    /// Lines, 1-based, whose removal fixes the finding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<RangeInclusive<usize>>,
+}
+
+/// Names every nix expression can use without binding them.
+const GLOBALS: &[&str] = &[
+    "abort", "baseNameOf", "builtins", "derivation", "dirOf", "false", "fetchGit",
+    "fetchTarball", "fetchTree", "fetchurl", "import", "isNull", "map", "null", "placeholder",
+    "removeAttrs", "scopedImport", "throw", "toString", "true",
+];
+
+/// Analyze `input`, returning its findings ordered by line.
+pub fn lint(input: &str) -> Result<Vec<Diagnostic>, ParseError> {
+    let (expr, items) = parse_nix_expr_with_list_items(input)?;
+    let mut analyzer = Analyzer::default();
+    analyzer.expr(&expr);
+
+    // Items the parser read but the AST doesn't keep would misplace entries
+    let items = if items.len() == analyzer.entries { items } else { Vec::new() };
+    let source = Source::new(input, items);
+    let mut diagnostics: Vec<Diagnostic> = analyzer
+        .findings
+        .iter()
+        .map(|finding| source.place(finding, &analyzer.counts))
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line.unwrap_or(usize::MAX));
+    Ok(diagnostics)
+}
+
+/// Remove the lines the fixes of `diagnostics` name.
+pub fn apply_fixes(input: &str, diagnostics: &[Diagnostic]) -> String {
+    let removed: HashSet<usize> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.fix.clone())
+        .flatten()
+        .collect();
+    input
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(index, _)| !removed.contains(&(index + 1)))
+        .map(|(_, line)| line)
+        .collect()
+}
+
+/// What a finding is about, to find it in the source by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Anchor {
+    /// The `ordinal`th binding of `path`, written `path = ...`
+    Binding { path: String, ordinal: usize },
+    /// The list entry at this index among all list items, in source order
+    Entry(usize),
+    /// The first use of a name
+    Variable(String),
+}
+
+#[derive(Debug)]
+struct Finding {
+    rule: Rule,
+    message: String,
+    anchor: Anchor,
+    /// Removing the binding or entry's lines fixes it, when nothing else
+    /// shares them
+    fixable: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    names: Vec<String>,
+    used: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Analyzer {
+    scopes: Vec<Scope>,
+    /// Enclosing `with` expressions, which may bind any name
+    with_depth: usize,
+    findings: Vec<Finding>,
+    /// Occurrences of each anchor (with ordinal 0) in source order
+    counts: HashMap<Anchor, usize>,
+    /// List items seen so far, which walking in source order numbers like
+    /// the parser
+    entries: usize,
+    reported: HashSet<String>,
+}
+
+impl Analyzer {
+    fn expr(&mut self, expr: &NixExpr) {
+        match expr {
+            NixExpr::Identifier(name) => self.resolve(name, true),
+            NixExpr::String(text) => {
+                // Indented strings aren't parsed into parts; their
+                // interpolations still use variables
+                for name in interpolated_names(text) {
+                    self.resolve(&name, false);
+                }
+            }
+            NixExpr::AttrSet { recursive, bindings } => {
+                self.inherits(bindings);
+                if *recursive {
+                    self.scopes.push(Scope {
+                        names: bound_names(bindings),
+                        used: HashSet::new(),
+                    });
+                }
+                let ordinals = self.bindings(bindings);
+                if *recursive {
+                    self.scopes.pop();
+                }
+                self.duplicate_attributes(bindings, &ordinals);
+            }
+            NixExpr::LetIn { bindings, body } => {
+                self.inherits(bindings);
+                self.scopes.push(Scope {
+                    names: bound_names(bindings),
+                    used: HashSet::new(),
+                });
+                let ordinals = self.bindings(bindings);
+                self.expr(body);
+                let scope = self.scopes.pop().unwrap_or_default();
+                self.duplicate_attributes(bindings, &ordinals);
+
+                let unused: Vec<(String, Option<usize>)> = bindings
+                    .iter()
+                    .zip(ordinals)
+                    .filter_map(|(binding, ordinal)| Some((single_name(binding)?, ordinal)))
+                    .filter(|(name, _)| !scope.used.contains(name))
+                    .collect();
+                // Removing every binding would leave an empty let
+                let fixable = unused.len() < bindings.len();
+                for (name, ordinal) in unused {
+                    self.findings.push(Finding {
+                        rule: Rule::UnusedBinding,
+                        message: format!("'{name}' is bound but never used"),
+                        anchor: Anchor::Binding {
+                            path: name,
+                            ordinal: ordinal.unwrap_or_default(),
+                        },
+                        fixable: fixable && ordinal.is_some(),
+                    });
+                }
+            }
+            NixExpr::List(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    let entry = self.entries;
+                    self.entries += 1;
+                    if items[..index].contains(item) {
+                        self.findings.push(Finding {
+                            rule: Rule::DuplicateEntry,
+                            message: format!("{} is already in the list", item.to_nix_string()),
+                            anchor: Anchor::Entry(entry),
+                            fixable: true,
+                        });
+                    }
+                    self.expr(item);
+                }
+            }
+            NixExpr::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Interpolation(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            NixExpr::Lambda { param, body } => {
+                let (names, defaults): (Vec<String>, Vec<&NixExpr>) = match param {
+                    LambdaParam::Identifier(name) => (vec![name.clone()], Vec::new()),
+                    LambdaParam::Pattern { params, .. } => (
+                        params.iter().map(|p| p.name.clone()).collect(),
+                        params.iter().filter_map(|p| p.default.as_deref()).collect(),
+                    ),
+                };
+                self.scopes.push(Scope {
+                    names,
+                    used: HashSet::new(),
+                });
+                for default in defaults {
+                    self.expr(default);
+                }
+                self.expr(body);
+                self.scopes.pop();
+            }
+            NixExpr::FunctionCall { function, argument } => {
+                self.expr(function);
+                self.expr(argument);
+            }
+            NixExpr::With { env, body } => {
+                self.expr(env);
+                self.with_depth += 1;
+                self.expr(body);
+                self.with_depth -= 1;
+            }
+            NixExpr::If { condition, then_expr, else_expr } => {
+                self.expr(condition);
+                self.expr(then_expr);
+                self.expr(else_expr);
+            }
+            NixExpr::Assert { condition, body } => {
+                self.expr(condition);
+                self.expr(body);
+            }
+            NixExpr::BinaryOp { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            NixExpr::UnaryOp { expr, .. } => self.expr(expr),
+            NixExpr::Select { expr, path, default } => {
+                self.expr(expr);
+                self.path(path);
+                if let Some(default) = default {
+                    self.expr(default);
+                }
+            }
+            NixExpr::HasAttr { expr, path } => {
+                self.expr(expr);
+                self.path(path);
+            }
+            NixExpr::Inherit { from, attrs } => match from {
+                Some(from) => self.expr(from),
+                None => {
+                    for attr in attrs {
+                        self.resolve(attr, true);
+                    }
+                }
+            },
+            NixExpr::Path(_)
+            | NixExpr::Uri(_)
+            | NixExpr::Integer(_)
+            | NixExpr::Float(_)
+            | NixExpr::Bool(_)
+            | NixExpr::Null => {}
+        }
+    }
+
+    /// Walk the bindings of a let or attribute set in source order,
+    /// returning the ordinal of each binding's anchor.
+    fn bindings(&mut self, bindings: &[Binding]) -> Vec<Option<usize>> {
+        let mut ordinals = Vec::new();
+        for binding in bindings {
+            match &binding.value {
+                // Resolved in the enclosing scope by `inherits`
+                NixExpr::Inherit { from: None, .. } => ordinals.push(None),
+                NixExpr::Inherit { from: Some(from), .. } => {
+                    ordinals.push(None);
+                    self.expr(from);
+                }
+                value => {
+                    let ordinal = path_text(&binding.path)
+                        .map(|path| self.next(binding_anchor(&path)));
+                    ordinals.push(ordinal);
+                    self.path(&binding.path);
+                    self.expr(value);
+                }
+            }
+        }
+        ordinals
+    }
+
+    /// `inherit name;` takes `name` from the scope around a let or rec set,
+    /// so those names are resolved before the set's own scope is entered.
+    fn inherits(&mut self, bindings: &[Binding]) {
+        for binding in bindings {
+            if let NixExpr::Inherit { from: None, attrs } = &binding.value {
+                for attr in attrs {
+                    self.resolve(attr, true);
+                }
+            }
+        }
+    }
+
+    fn path(&mut self, path: &AttrPath) {
+        for part in &path.parts {
+            if let AttrPathPart::Interpolation(expr) = part {
+                self.expr(expr);
+            }
+        }
+    }
+
+    fn duplicate_attributes(&mut self, bindings: &[Binding], ordinals: &[Option<usize>]) {
+        let mut seen = HashSet::new();
+        for (binding, ordinal) in bindings.iter().zip(ordinals) {
+            let paths: Vec<String> = match &binding.value {
+                NixExpr::Inherit { attrs, .. } => attrs.clone(),
+                _ => path_text(&binding.path).into_iter().collect(),
+            };
+            for path in paths {
+                if seen.insert(path.clone()) {
+                    continue;
+                }
+                let anchor = match ordinal {
+                    Some(ordinal) => Anchor::Binding {
+                        path: path.clone(),
+                        ordinal: *ordinal,
+                    },
+                    None => Anchor::Variable(path.clone()),
+                };
+                self.findings.push(Finding {
+                    rule: Rule::DuplicateAttribute,
+                    message: format!("attribute '{path}' is already defined"),
+                    anchor,
+                    fixable: false,
+                });
+            }
+        }
+    }
+
+    /// Count an occurrence of `key`, returning how many came before it.
+    fn next(&mut self, key: Anchor) -> usize {
+        let count = self.counts.entry(key).or_default();
+        *count += 1;
+        *count - 1
+    }
+
+    /// Mark `name` used in the innermost scope binding it, and report it
+    /// when none does and `report` is set.
+    fn resolve(&mut self, name: &str, report: bool) {
+        for scope in self.scopes.iter_mut().rev() {
+            if scope.names.iter().any(|bound| bound == name) {
+                scope.used.insert(name.to_string());
+                return;
+            }
+        }
+        let known = GLOBALS.contains(&name) || name.starts_with("__");
+        if report && !known && self.with_depth == 0 && self.reported.insert(name.to_string()) {
+            self.findings.push(Finding {
+                rule: Rule::UndefinedVariable,
+                message: format!("undefined variable '{name}'"),
+                anchor: Anchor::Variable(name.to_string()),
+                fixable: false,
+            });
+        }
+    }
+}
+
+fn binding_anchor(path: &str) -> Anchor {
+    Anchor::Binding {
+        path: path.to_string(),
+        ordinal: 0,
+    }
+}
+
+/// Names a let or rec set binds.
+fn bound_names(bindings: &[Binding]) -> Vec<String> {
+    bindings
+        .iter()
+        .flat_map(|binding| match &binding.value {
+            NixExpr::Inherit { attrs, .. } => attrs.clone(),
+            _ => binding.path.parts.first().and_then(part_name).into_iter().collect(),
+        })
+        .collect()
+}
+
+/// The name of a `name = ...` binding.
+fn single_name(binding: &Binding) -> Option<String> {
+    match (&binding.value, binding.path.parts.as_slice()) {
+        (NixExpr::Inherit { .. }, _) => None,
+        (_, [part]) => part_name(part),
+        _ => None,
+    }
+}
+
+fn part_name(part: &AttrPathPart) -> Option<String> {
+    match part {
+        AttrPathPart::Identifier(name) => Some(name.clone()),
+        AttrPathPart::String(_) | AttrPathPart::Interpolation(_) => None,
+    }
+}
+
+/// A path as written, e.g. `devShells.default`; `None` with interpolations.
+fn path_text(path: &AttrPath) -> Option<String> {
+    let parts: Option<Vec<String>> = path
+        .parts
+        .iter()
+        .map(|part| match part {
+            AttrPathPart::Identifier(name) => Some(name.clone()),
+            AttrPathPart::String(text) => Some(format!("\"{text}\"")),
+            AttrPathPart::Interpolation(_) => None,
+        })
+        .collect();
+    Some(parts?.join("."))
+}
+
+/// Variables the `${...}` interpolations in unparsed string text refer to.
+fn interpolated_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        rest = &rest[start + 2..];
+        let end = rest.find('}').unwrap_or(rest.len());
+        let mut previous = ' ';
+        let mut word = String::new();
+        for c in rest[..end].chars().chain([' ']) {
+            if c.is_alphanumeric() || c == '_' || (c == '-' && !word.is_empty()) {
+                word.push(c);
+                continue;
+            }
+            // `pkgs.go` refers to `pkgs` only
+            let selected = previous == '.';
+            if !word.is_empty() && !selected && !word.starts_with(|c: char| c.is_ascii_digit()) {
+                names.push(word.clone());
+            }
+            word.clear();
+            previous = c;
+        }
+        rest = &rest[end..];
+    }
+    names
+}
+
+/// The source text, for placing findings.
+struct Source<'a> {
+    text: &'a str,
+    lines: Vec<&'a str>,
+    /// Where each list item starts and ends, in source order
+    items: Vec<(usize, usize)>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: &'a str, items: Vec<(usize, usize)>) -> Self {
+        Self {
+            text,
+            lines: text.split_inclusive('\n').collect(),
+            items,
+        }
+    }
+
+    fn place(&self, finding: &Finding, counts: &HashMap<Anchor, usize>) -> Diagnostic {
+        let (line, fix) = match &finding.anchor {
+            Anchor::Binding { path, ordinal } => {
+                let total = counts.get(&binding_anchor(path)).copied().unwrap_or(0);
+                let line = self.nth_line(total, *ordinal, |code| {
+                    code.strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.trim_start().starts_with('='))
+                });
+                let fix = line.filter(|_| finding.fixable).and_then(|line| self.statement(line));
+                (line, fix)
+            }
+            Anchor::Entry(index) => match self.items.get(*index) {
+                Some(&span) => {
+                    let (line, lines) = self.item_lines(span);
+                    (Some(line), lines.filter(|_| finding.fixable))
+                }
+                None => (None, None),
+            },
+            Anchor::Variable(name) => (self.first_use(name), None),
+        };
+
+        Diagnostic {
+            rule: finding.rule,
+            severity: finding.rule.severity(),
+            message: finding.message.clone(),
+            line,
+            fix,
+        }
+    }
+
+    /// The line of the `ordinal`th of `total` occurrences, provided exactly
+    /// `total` lines match.
+    fn nth_line(
+        &self,
+        total: usize,
+        ordinal: usize,
+        matches: impl Fn(&str) -> bool,
+    ) -> Option<usize> {
+        let found: Vec<usize> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches(code(line)))
+            .map(|(index, _)| index + 1)
+            .collect();
+        if found.len() != total {
+            return None;
+        }
+        found.get(ordinal).copied()
+    }
+
+    /// The lines of the binding starting on `line`, with the comment lines
+    /// directly above it, provided nothing else shares its lines.
+    fn statement(&self, line: usize) -> Option<RangeInclusive<usize>> {
+        let start: usize = self.lines[..line - 1].iter().map(|l| l.len()).sum();
+        let end = start + statement_end(&self.text[start..])?;
+        let after = self.text[end + 1..].split('\n').next().unwrap_or("");
+        if !code(after).is_empty() {
+            return None;
+        }
+        let last = self.text[..end].matches('\n').count() + 1;
+
+        let mut first = line;
+        while first > 1 && self.lines[first - 2].trim_start().starts_with('#') {
+            first -= 1;
+        }
+        Some(first..=last)
+    }
+
+    /// The line a list item starts on, and its lines when nothing else
+    /// shares them. The parser's span can run on over whitespace and
+    /// comments after the item, which aren't counted.
+    fn item_lines(&self, (start, end): (usize, usize)) -> (usize, Option<RangeInclusive<usize>>) {
+        let first = self.text[..start].matches('\n').count() + 1;
+        let mut item: Vec<&str> = self.text[start..end].split('\n').collect();
+        while item.len() > 1 && item.last().is_some_and(|line| code(line).is_empty()) {
+            item.pop();
+        }
+        let last = first + item.len() - 1;
+
+        let line_start = self.text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let alone_before = self.text[line_start..start].trim().is_empty();
+        let last_line = if item.len() == 1 {
+            &self.text[line_start..]
+        } else {
+            self.lines[last - 1]
+        };
+        let own = item.last().map(|line| code(line)).unwrap_or_default();
+        let alone_after = code(last_line.split('\n').next().unwrap_or_default()) == own;
+        (first, (alone_before && alone_after).then_some(first..=last))
+    }
+
+    fn first_use(&self, name: &str) -> Option<usize> {
+        let word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+        self.lines
+            .iter()
+            .position(|line| {
+                let line = code(line);
+                line.match_indices(name).any(|(i, _)| {
+                    let before = line[..i].chars().next_back();
+                    let after = line[i + name.len()..].chars().next();
+                    !before.is_some_and(|c| word(c) || c == '.') && !after.is_some_and(word)
+                })
+            })
+            .map(|index| index + 1)
+    }
+}
+
+/// A line without indentation, comment and line break.
+fn code(line: &str) -> &str {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return "";
+    }
+    match line.find(" #") {
+        Some(comment) if !line[..comment].contains('"') => line[..comment].trim_end(),
+        _ => line,
+    }
+}
+
+/// Offset of the `;` ending the statement at the start of `text`.
+fn statement_end(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0i32;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'\'' if bytes.get(i + 1) == Some(&b'\'') => {
+                i += 2;
+                loop {
+                    i += text[i..].find("''")? + 2;
+                    // `'''`, `''$` and `''\` escape rather than end the string
+                    match bytes.get(i) {
+                        Some(b'\'' | b'$' | b'\\') => i += 1,
+                        _ => break,
+                    }
+                }
+                continue;
+            }
+            b'#' => i = text[i..].find('\n').map_or(bytes.len(), |newline| i + newline),
+            b'[' | b'{' | b'(' => depth += 1,
+            b']' | b'}' | b')' => depth -= 1,
+            b';' if depth == 0 => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(input: &str) -> Vec<(Rule, Option<usize>)> {
+        lint(input)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_binding() {
+        let input = r#"{ pkgs }:
+let
+  # The go toolchain
+  go = pkgs.go;
+  name = "demo";
+in
+{ inherit name; }"#;
+        let diagnostics = lint(input).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, Rule::UnusedBinding);
+        assert_eq!(diagnostics[0].line, Some(4));
+        assert_eq!(diagnostics[0].fix, Some(3..=4));
+
+        let fixed = apply_fixes(input, &diagnostics);
+        assert!(!fixed.contains("go"));
+        assert!(fixed.contains("name = \"demo\";"));
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let input = "{ pkgs }:\npkgs.mkShell {\n  packages = [ pkgs.go gopls ];\n}";
+        assert_eq!(rules(input), vec![(Rule::UndefinedVariable, Some(3))]);
+    }
+
+    #[test]
+    fn test_uses_in_indented_strings() {
+        let input = r#"{ pkgs }:
+let
+  version = "1.22";
+in
+pkgs.mkShell {
+  shellHook = ''
+    echo "go ${version}"
+  '';
+}"#;
+        assert!(rules(input).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_entries_and_attributes() {
+        let input = r#"{ pkgs }:
+{
+  packages = [
+    pkgs.go
+    pkgs.gopls
+    pkgs.go
+  ];
+  name = "a";
+  name = "b";
+}"#;
+        let diagnostics = lint(input).unwrap();
+        assert_eq!(
+            diagnostics.iter().map(|d| (d.rule, d.line)).collect::<Vec<_>>(),
+            vec![(Rule::DuplicateEntry, Some(6)), (Rule::DuplicateAttribute, Some(9))]
+        );
+        assert_eq!(diagnostics[0].fix, Some(6..=6));
+        assert_eq!(diagnostics[1].fix, None);
+
+        let fixed = apply_fixes(input, &diagnostics);
+        assert_eq!(fixed.matches("pkgs.go\n").count(), 1);
+    }
+
+    #[test]
+    fn test_inline_duplicate_entries() {
+        let input = "{ x }:\n{\n  a = [ x x \"s\" \"s\" ];\n  b = [\n    x\n    { y = 1; } # kept\n    { y = 1; }\n  ];\n}\n";
+        let diagnostics = lint(input).unwrap();
+        let placed: Vec<_> = diagnostics.iter().map(|d| (d.line, d.fix.clone())).collect();
+        assert_eq!(
+            placed,
+            vec![(Some(3), None), (Some(3), None), (Some(7), Some(7..=7))]
+        );
+
+        let fixed = apply_fixes(input, &diagnostics);
+        assert!(fixed.contains("  a = [ x x \"s\" \"s\" ];\n"));
+        assert!(fixed.contains("    { y = 1; } # kept\n  ];"));
+    }
+
+    #[test]
+    fn test_with_and_rec_scopes() {
+        let input = r#"{ pkgs }:
+with pkgs;
+rec {
+  a = hello;
+  b = a;
+}"#;
+        assert!(rules(input).is_empty());
+    }
+
+    #[test]
+    fn test_multiline_binding_fix() {
+        let input = r#"let
+  unused = {
+    a = ";";
+  };
+  used = 1;
+in
+used"#;
+        let diagnostics = lint(input).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].fix, Some(2..=4));
+        assert_eq!(apply_fixes(input, &diagnostics), "let\n  used = 1;\nin\nused");
+    }
+}
@@ -2,12 +2,35 @@ use crate::ast::*;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until, take_while, take_while1},
-    character::complete::{alpha1, char, digit1, multispace1},
-    combinator::{map, opt, recognize, value},
+    character::complete::{alpha1, anychar, char, digit1, multispace1},
+    combinator::{map, opt, recognize, value, verify},
     multi::{many0, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+thread_local! {
+    /// The list items parsed while `record_list_items` runs, as the length
+    /// of the input left where each starts mapped to where it ends.
+    static LIST_ITEMS: RefCell<Option<BTreeMap<usize, usize>>> = const { RefCell::new(None) };
+}
+
+/// Run `parse` and return, besides its result, where the list items it
+/// parsed start and end in `input`, in source order. Items parsed by
+/// alternatives that backtracked are included once, at the same place.
+pub fn record_list_items<T>(input: &str, parse: impl FnOnce() -> T) -> (T, Vec<(usize, usize)>) {
+    LIST_ITEMS.with(|items| *items.borrow_mut() = Some(BTreeMap::new()));
+    let result = parse();
+    let recorded = LIST_ITEMS.with(|items| items.borrow_mut().take()).unwrap_or_default();
+    let spans = recorded
+        .into_iter()
+        .rev()
+        .map(|(start, end)| (input.len() - start, input.len() - end))
+        .collect();
+    (result, spans)
+}
 
 // Core parser combinators
 pub fn nix_expr(input: &str) -> IResult<&str, NixExpr> {
@@ -165,8 +188,12 @@ fn string_part(input: &str) -> IResult<&str, StringPart> {
             delimited(tag("${"), nix_expr, char('}')),
             |expr| StringPart::Interpolation(Box::new(expr)),
         ),
+        // Escapes are kept as written, so `\"` and `\${` stay literal text
         map(
-            take_while1(|c| c != '"' && c != '$'),
+            recognize(many1(alt((
+                take_while1(|c| c != '"' && c != '$' && c != '\\'),
+                recognize(pair(char('\\'), anychar)),
+            )))),
             |s: &str| StringPart::Literal(s.to_string()),
         ),
         map(
@@ -218,12 +245,18 @@ fn nix_null(input: &str) -> IResult<&str, NixExpr> {
     value(NixExpr::Null, tag("null"))(input)
 }
 
+// Words that start or continue a construct and so never name a variable
+const KEYWORDS: &[&str] = &["if", "then", "else", "assert", "with", "let", "in", "rec", "inherit", "or"];
+
 fn nix_identifier(input: &str) -> IResult<&str, NixExpr> {
     map(
-        recognize(pair(
-            alt((alpha1, tag("_"))),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-        )),
+        verify(
+            recognize(pair(
+                alt((alpha1, tag("_"))),
+                take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+            )),
+            |s: &str| !KEYWORDS.contains(&s),
+        ),
         |s: &str| NixExpr::Identifier(s.to_string()),
     )(input)
 }
@@ -258,8 +291,17 @@ fn nix_list(input: &str) -> IResult<&str, NixExpr> {
     Ok((input, NixExpr::List(items)))
 }
 
+/// The items of a list without its brackets, as in `pkgs.hello (f x)`.
+pub fn list_items(input: &str) -> IResult<&str, Vec<NixExpr>> {
+    many0(terminated(ws(nix_list_item), skip_whitespace_and_comments))(input)
+}
+
 fn nix_list_item(input: &str) -> IResult<&str, NixExpr> {
-    ws(alt((
+    ws(list_item_value)(input)
+}
+
+fn list_item_value(input: &str) -> IResult<&str, NixExpr> {
+    let (rest, item) = alt((
         nix_attrset,
         nix_list,
         nix_interpolated_string,
@@ -268,7 +310,13 @@ fn nix_list_item(input: &str) -> IResult<&str, NixExpr> {
         nix_select_expr,
         nix_identifier,
         delimited(char('('), nix_expr, char(')')),
-    )))(input)
+    ))(input)?;
+    LIST_ITEMS.with(|items| {
+        if let Some(items) = items.borrow_mut().as_mut() {
+            items.insert(input.len(), rest.len());
+        }
+    });
+    Ok((rest, item))
 }
 
 // Parse identifier with optional select operations (no function calls)
@@ -337,7 +385,7 @@ fn nix_assert(input: &str) -> IResult<&str, NixExpr> {
     }))
 }
 
-fn nix_lambda(input: &str) -> IResult<&str, NixExpr> {
+pub fn nix_lambda(input: &str) -> IResult<&str, NixExpr> {
     let (input, param) = lambda_param(input)?;
     let (input, _) = ws(char(':'))(input)?;
     let (input, body) = nix_expr(input)?;
@@ -397,8 +445,8 @@ pub fn binding(input: &str) -> IResult<&str, Binding> {
             tuple((
                 ws(tag("inherit")),
                 opt(delimited(ws(char('(')), nix_expr, ws(char(')')))),
-                // Fix: Parse identifiers separated by whitespace
-                separated_list1(multispace1, ws(identifier_string)),
+                // Each identifier consumes the whitespace after it
+                many1(ws(identifier_string)),
             )),
             |(_, from, attrs)| Binding {
                 path: AttrPath { parts: vec![AttrPathPart::Identifier("inherit".to_string())] },
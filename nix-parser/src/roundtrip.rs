@@ -0,0 +1,249 @@
+//! Checking that an expression survives printing: parse the source, print
+//! it with [`NixExpr::to_nix_string`], parse the result and compare.
+//!
+//! The comparison is semantic: formatting and comments are not part of the
+//! AST, and `a.b.c` equals `(a.b).c` although list items and other
+//! expressions parse selections into differently nested nodes.
+
+use crate::ast::*;
+use crate::{ParseError, parse_nix_expr};
+use thiserror::Error;
+
+/// A place where the printed expression means something else than the
+/// source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Where in the expression, such as `outputs.<body>.devShells[0]`
+    pub path: String,
+    /// The source's subexpression, printed
+    pub expected: String,
+    /// What the printed expression parsed back to there
+    pub found: String,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RoundtripError {
+    /// The source itself does not parse
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("The printed expression does not parse: {error}")]
+    Reparse { printed: String, error: ParseError },
+    #[error("The printed expression differs at {}", paths(.mismatches))]
+    Mismatch {
+        printed: String,
+        mismatches: Vec<Mismatch>,
+    },
+}
+
+/// Parse `src`, print it, parse the printed text and check that both mean
+/// the same. Returns the printed text.
+pub fn verify_roundtrip(src: &str) -> Result<String, RoundtripError> {
+    let expr = parse_nix_expr(src)?;
+    let printed = expr.to_nix_string();
+    let reparsed = match parse_nix_expr(&printed) {
+        Ok(reparsed) => reparsed,
+        Err(error) => return Err(RoundtripError::Reparse { printed, error }),
+    };
+
+    let mut mismatches = Vec::new();
+    compare(&expr, &reparsed, "", &mut mismatches);
+    if mismatches.is_empty() {
+        Ok(printed)
+    } else {
+        Err(RoundtripError::Mismatch { printed, mismatches })
+    }
+}
+
+fn paths(mismatches: &[Mismatch]) -> String {
+    mismatches.iter().map(|m| m.path.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn compare(expected: &NixExpr, found: &NixExpr, path: &str, mismatches: &mut Vec<Mismatch>) {
+    if expected == found {
+        return;
+    }
+    let child = |segment: &str| -> String {
+        if path.is_empty() || segment.starts_with('[') {
+            format!("{path}{segment}")
+        } else {
+            format!("{path}.{segment}")
+        }
+    };
+
+    match (expected, found) {
+        (
+            NixExpr::AttrSet { recursive: r1, bindings: b1 },
+            NixExpr::AttrSet { recursive: r2, bindings: b2 },
+        ) if r1 == r2 && b1.len() == b2.len() => compare_bindings(b1, b2, path, mismatches),
+        (
+            NixExpr::LetIn { bindings: b1, body: body1 },
+            NixExpr::LetIn { bindings: b2, body: body2 },
+        ) if b1.len() == b2.len() => {
+            compare_bindings(b1, b2, &child("<let>"), mismatches);
+            compare(body1, body2, &child("<body>"), mismatches);
+        }
+        (NixExpr::List(items1), NixExpr::List(items2)) if items1.len() == items2.len() => {
+            for (i, (item1, item2)) in items1.iter().zip(items2).enumerate() {
+                compare(item1, item2, &child(&format!("[{i}]")), mismatches);
+            }
+        }
+        (
+            NixExpr::FunctionCall { function: f1, argument: a1 },
+            NixExpr::FunctionCall { function: f2, argument: a2 },
+        ) => {
+            compare(f1, f2, &child("<function>"), mismatches);
+            compare(a1, a2, &child("<argument>"), mismatches);
+        }
+        (NixExpr::Lambda { param: p1, body: body1 }, NixExpr::Lambda { param: p2, body: body2 })
+            if p1 == p2 =>
+        {
+            compare(body1, body2, &child("<body>"), mismatches);
+        }
+        (
+            NixExpr::BinaryOp { left: l1, op: op1, right: r1 },
+            NixExpr::BinaryOp { left: l2, op: op2, right: r2 },
+        ) if op1 == op2 => {
+            compare(l1, l2, &child("<left>"), mismatches);
+            compare(r1, r2, &child("<right>"), mismatches);
+        }
+        (NixExpr::UnaryOp { op: op1, expr: e1 }, NixExpr::UnaryOp { op: op2, expr: e2 })
+            if op1 == op2 =>
+        {
+            compare(e1, e2, &child("<operand>"), mismatches);
+        }
+        (NixExpr::With { env: e1, body: body1 }, NixExpr::With { env: e2, body: body2 }) => {
+            compare(e1, e2, &child("<with>"), mismatches);
+            compare(body1, body2, &child("<body>"), mismatches);
+        }
+        (
+            NixExpr::If { condition: c1, then_expr: t1, else_expr: e1 },
+            NixExpr::If { condition: c2, then_expr: t2, else_expr: e2 },
+        ) => {
+            compare(c1, c2, &child("<if>"), mismatches);
+            compare(t1, t2, &child("<then>"), mismatches);
+            compare(e1, e2, &child("<else>"), mismatches);
+        }
+        (
+            NixExpr::Assert { condition: c1, body: body1 },
+            NixExpr::Assert { condition: c2, body: body2 },
+        ) => {
+            compare(c1, c2, &child("<assert>"), mismatches);
+            compare(body1, body2, &child("<body>"), mismatches);
+        }
+        (NixExpr::Select { default: d1, .. }, NixExpr::Select { default: d2, .. }) => {
+            let (root1, parts1) = flatten_select(expected);
+            let (root2, parts2) = flatten_select(found);
+            if parts1 != parts2 {
+                mismatches.push(mismatch(path, expected, found));
+                return;
+            }
+            compare(root1, root2, &child("<select>"), mismatches);
+            match (d1, d2) {
+                (Some(d1), Some(d2)) => compare(d1, d2, &child("<or>"), mismatches),
+                (None, None) => {}
+                _ => mismatches.push(mismatch(path, expected, found)),
+            }
+        }
+        _ => mismatches.push(mismatch(path, expected, found)),
+    }
+}
+
+fn compare_bindings(
+    expected: &[Binding],
+    found: &[Binding],
+    path: &str,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    for (b1, b2) in expected.iter().zip(found) {
+        let name = b1.path.to_nix_string();
+        let binding_path = if path.is_empty() { name } else { format!("{path}.{name}") };
+        if b1.path != b2.path {
+            mismatches.push(Mismatch {
+                path: binding_path,
+                expected: b1.path.to_nix_string(),
+                found: b2.path.to_nix_string(),
+            });
+        } else {
+            compare(&b1.value, &b2.value, &binding_path, mismatches);
+        }
+    }
+}
+
+/// The innermost subject of a chain of selections without defaults, and
+/// all the attribute names selected from it in order.
+fn flatten_select(expr: &NixExpr) -> (&NixExpr, Vec<&AttrPathPart>) {
+    match expr {
+        NixExpr::Select { expr: inner, path, .. } => {
+            let (root, mut parts) = match inner.as_ref() {
+                NixExpr::Select { default: None, .. } => flatten_select(inner),
+                _ => (inner.as_ref(), Vec::new()),
+            };
+            parts.extend(&path.parts);
+            (root, parts)
+        }
+        _ => (expr, Vec::new()),
+    }
+}
+
+fn mismatch(path: &str, expected: &NixExpr, found: &NixExpr) -> Mismatch {
+    Mismatch {
+        path: if path.is_empty() { "<root>".to_string() } else { path.to_string() },
+        expected: expected.to_nix_string(),
+        found: found.to_nix_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_keeps_precedence() {
+        let printed = verify_roundtrip(
+            r#"{
+  a = f (g x) [ (h y) pkgs.hello ];
+  b = (import ./x.nix).value;
+  c = 1 + (2 * 3);
+  e = 2.0;
+}"#,
+        )
+        .unwrap();
+        assert!(printed.contains("a = f (g x) [ (h y) pkgs.hello ];"));
+        assert!(printed.contains("b = (import ./x.nix).value;"));
+        assert!(printed.contains("c = 1 + (2 * 3);"));
+        assert!(printed.contains("e = 2.0;"));
+    }
+
+    #[test]
+    fn test_nested_selections_are_equal() {
+        let list_item = parse_nix_expr("[ a.b.c ]").unwrap();
+        let NixExpr::List(items) = &list_item else {
+            panic!("expected a list");
+        };
+        let expression = parse_nix_expr("a.b.c").unwrap();
+        let mut mismatches = Vec::new();
+        compare(&items[0], &expression, "", &mut mismatches);
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_mismatch_is_located() {
+        let expected = parse_nix_expr("{ outputs = { packages = [ a b ]; }; }").unwrap();
+        let found = parse_nix_expr("{ outputs = { packages = [ a c ]; }; }").unwrap();
+        let mut mismatches = Vec::new();
+        compare(&expected, &found, "", &mut mismatches);
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                path: "outputs.packages[1]".to_string(),
+                expected: "b".to_string(),
+                found: "c".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_source_errors_are_reported() {
+        assert!(matches!(verify_roundtrip("{ a = ; }"), Err(RoundtripError::Parse(_))));
+    }
+}
@@ -17,18 +17,30 @@
         nixpkgs.lib.genAttrs supportedSystems (
           system:
           f {
-            pkgs = import nixpkgs { inherit system; };
+            pkgs = import nixpkgs {
+              inherit system;
+              overlays = [ self.overlays.default ];
+            };
           }
         );
     in
     {
+      overlays.default = final: prev: {
+        dotnet-combined = final.dotnetCorePackages.combinePackages [ final.dotnetCorePackages.sdk_8_0 ];
+      };
+
       devShells = forEachSupportedSystem (
         { pkgs }:
         {
           default = pkgs.mkShell {
             packages = with pkgs; [
-              dotnet-sdk_8
+              dotnet-combined
             ];
+
+            env = {
+              # Where tools such as the C# language server look for the SDKs
+              DOTNET_ROOT = "${pkgs.dotnet-combined}/share/dotnet";
+            };
           };
         }
       );
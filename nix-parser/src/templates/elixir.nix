@@ -58,6 +58,13 @@
                   darwin.apple_sdk.frameworks.CoreServices
                 ]
               );
+
+            env = {
+              # Keep shell history between sessions
+              ERL_AFLAGS = "-kernel shell_history enabled";
+              # The VM takes its filename encoding from the locale
+              LANG = "C.UTF-8";
+            };
           };
         }
       );
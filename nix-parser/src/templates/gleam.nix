@@ -17,16 +17,36 @@
         nixpkgs.lib.genAttrs supportedSystems (
           system:
           f {
-            pkgs = import nixpkgs { inherit system; };
+            pkgs = import nixpkgs {
+              inherit system;
+              overlays = [ self.overlays.default ];
+            };
           }
         );
     in
     {
+      overlays.default = final: prev: rec {
+        erlang = final.beam.interpreters.erlang_27;
+        pkgs-beam = final.beam.packagesWith erlang;
+        rebar3 = pkgs-beam.rebar3;
+      };
+
       devShells = forEachSupportedSystem (
         { pkgs }:
         {
           default = pkgs.mkShell {
-            packages = with pkgs; [ gleam ];
+            packages = with pkgs; [
+              erlang
+              gleam
+              rebar3
+            ];
+
+            env = {
+              # Keep shell history between sessions
+              ERL_AFLAGS = "-kernel shell_history enabled";
+              # The VM takes its filename encoding from the locale
+              LANG = "C.UTF-8";
+            };
           };
         }
       );
@@ -19,7 +19,10 @@
         nixpkgs.lib.genAttrs supportedSystems (
           system:
           f {
-            pkgs = import nixpkgs { inherit system; };
+            pkgs = import nixpkgs {
+              inherit system;
+              overlays = [ self.overlays.default ];
+            };
           }
         );
     in
@@ -42,6 +45,11 @@
               patchelf
               zlib
             ];
+
+            env = {
+              # Build tools and IDEs pick up the overlay's JDK
+              JAVA_HOME = "${pkgs.jdk.home}";
+            };
           };
         }
       );
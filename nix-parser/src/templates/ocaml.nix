@@ -27,7 +27,7 @@
         {
           default = pkgs.mkShell {
             packages = with pkgs; [
-              ocaml
+              ocamlPackages.ocaml
               ocamlformat
               ocamlPackages.dune_3
               ocamlPackages.odoc
@@ -17,18 +17,26 @@
         nixpkgs.lib.genAttrs supportedSystems (
           system:
           f {
-            pkgs = import nixpkgs { inherit system; };
+            pkgs = import nixpkgs {
+              inherit system;
+              overlays = [ self.overlays.default ];
+            };
           }
         );
     in
     {
+      overlays.default = final: prev: {
+        php = prev.php.withExtensions ({ enabled, all }: enabled);
+        composer = final.php.packages.composer;
+      };
+
       devShells = forEachSupportedSystem (
         { pkgs }:
         {
           default = pkgs.mkShell {
             packages = with pkgs; [
+              composer
               php
-              phpPackages.composer
             ];
           };
         }
@@ -27,6 +27,11 @@
         {
           default = pkgs.mkShell {
             packages = with pkgs; [ ruby_3_3 ];
+
+            env = {
+              # Bundler installs gems under the project, relative to its Gemfile
+              BUNDLE_PATH = "vendor/bundle";
+            };
           };
         }
       );
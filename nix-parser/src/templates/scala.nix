@@ -42,6 +42,11 @@
               sbt
               coursier
             ];
+
+            env = {
+              # Build tools and IDEs pick up the overlay's JDK
+              JAVA_HOME = "${pkgs.jdk.home}";
+            };
           };
         }
       );
@@ -0,0 +1,139 @@
+use crate::ast::Comments;
+use std::collections::HashMap;
+
+const KEYWORDS: &[&str] = &["assert", "else", "if", "in", "inherit", "let", "rec", "then", "with"];
+
+/// Collect the `#` comments of a Nix source, attached to the binding or list
+/// item on the same line (trailing) or the line below (leading). Comments are
+/// keyed by the attribute path as written, e.g. `goVersion`, `nixpkgs.url`
+/// or `RUST_SRC_PATH`, or by the identifier of a list item. Comments inside
+/// strings, block comments and comments separated from the next line by a
+/// blank line are not attached to anything.
+pub fn collect_comments(input: &str) -> HashMap<String, Comments> {
+    let mut comments: HashMap<String, Comments> = HashMap::new();
+    let mut pending: Vec<String> = Vec::new();
+    let mut state = State::Code;
+
+    for line in input.lines() {
+        let started_in_code = state == State::Code;
+        let (code, comment) = split_line(line, &mut state);
+        let code = code.trim();
+
+        if !started_in_code {
+            pending.clear();
+            continue;
+        }
+        if code.is_empty() {
+            match comment {
+                Some(comment) => pending.push(comment),
+                None => pending.clear(),
+            }
+            continue;
+        }
+
+        let leading = std::mem::take(&mut pending);
+        let Some(key) = binding_key(code) else { continue };
+        if leading.is_empty() && comment.is_none() {
+            continue;
+        }
+        comments.entry(key).or_insert(Comments {
+            leading,
+            trailing: comment,
+        });
+    }
+
+    comments
+}
+
+/// The text of every `#` comment of a Nix source, in order. Comments inside
+/// strings and block comments are not included.
+pub fn comments(input: &str) -> Vec<String> {
+    let mut state = State::Code;
+    input
+        .lines()
+        .filter_map(|line| split_line(line, &mut state).1)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Code,
+    String,
+    IndentedString,
+    BlockComment,
+}
+
+/// Split a line into its code and the text of its `#` comment, carrying
+/// string and block comment state over to the next line.
+fn split_line(line: &str, state: &mut State) -> (String, Option<String>) {
+    let mut code = String::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match *state {
+            State::Code => match c {
+                '#' => {
+                    let text: String = chars.collect();
+                    return (code, Some(text.trim().to_string()));
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    *state = State::BlockComment;
+                }
+                '"' => {
+                    code.push(c);
+                    *state = State::String;
+                }
+                '\'' if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                    code.push_str("''");
+                    *state = State::IndentedString;
+                }
+                _ => code.push(c),
+            },
+            State::String => {
+                code.push(c);
+                match c {
+                    '\\' => code.extend(chars.next()),
+                    '"' => *state = State::Code,
+                    _ => {}
+                }
+            }
+            State::IndentedString => {
+                code.push(c);
+                if c == '\'' && chars.peek() == Some(&'\'') {
+                    chars.next();
+                    code.push('\'');
+                    // `'''`, `''$` and `''\` are escapes, not the closing quote.
+                    match chars.peek() {
+                        Some('\'' | '$' | '\\') => code.extend(chars.next()),
+                        _ => *state = State::Code,
+                    }
+                }
+            }
+            State::BlockComment => {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    *state = State::Code;
+                }
+            }
+        }
+    }
+
+    (code, None)
+}
+
+/// The attribute path bound on a line (`a.b = ...`), or the identifier
+/// making up a list item.
+fn binding_key(code: &str) -> Option<String> {
+    let candidate = match code.find('=') {
+        Some(i) if !code[i + 1..].starts_with('=') => code[..i].trim(),
+        _ => code,
+    };
+
+    let is_path = !candidate.is_empty()
+        && candidate
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '\'' | '.' | '"'));
+    (is_path && !KEYWORDS.contains(&candidate)).then(|| candidate.to_string())
+}